@@ -0,0 +1,31 @@
+import concurrent.futures
+
+import bm
+
+
+class RateLimiterThreads(bm.Scenario):
+    nthreads: int
+    rate_limit: int
+    calls_per_thread: int
+
+    def run(self):
+        from ddtrace.internal.core._core import SlidingWindowRateLimiter
+
+        rate_limiter = SlidingWindowRateLimiter(rate_limit=self.rate_limit)
+
+        def worker():
+            is_allowed = rate_limiter.is_allowed
+            for _ in range(self.calls_per_thread):
+                is_allowed()
+
+        def _(loops):
+            # DEV: On free-threaded builds the native limiter runs without a lock, so the time per
+            # loop should stay flat as the number of threads grows past the number of cores used
+            # by the GIL builds.
+            with concurrent.futures.ThreadPoolExecutor(max_workers=self.nthreads) as executor:
+                for _ in range(loops):
+                    tasks = [executor.submit(worker) for _ in range(self.nthreads)]
+                    for task in concurrent.futures.as_completed(tasks):
+                        task.result()
+
+        yield _
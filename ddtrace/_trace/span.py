@@ -41,8 +41,6 @@ from ddtrace.constants import VERSION_KEY
 from ddtrace.ext import http
 from ddtrace.ext import net
 from ddtrace.internal import core
-from ddtrace.internal._rand import rand64bits as _rand64bits
-from ddtrace.internal._rand import rand128bits as _rand128bits
 from ddtrace.internal.compat import NumericType
 from ddtrace.internal.compat import StringIO
 from ddtrace.internal.compat import ensure_text
@@ -189,10 +187,10 @@ class Span(object):
         if trace_id is not None:
             self.trace_id: int = trace_id
         elif config._128_bit_trace_id_enabled:
-            self.trace_id: int = _rand128bits()  # type: ignore[no-redef]
+            self.trace_id: int = core.gen_trace_id_128()  # type: ignore[no-redef]
         else:
-            self.trace_id: int = _rand64bits()  # type: ignore[no-redef]
-        self.span_id: int = span_id or _rand64bits()
+            self.trace_id: int = core.gen_span_id()  # type: ignore[no-redef]
+        self.span_id: int = span_id or core.gen_span_id()
         self.parent_id: Optional[int] = parent_id
         self._on_finish_callbacks = [] if on_finish is None else on_finish
 
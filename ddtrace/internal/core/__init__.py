@@ -117,6 +117,10 @@ from ddtrace.vendor.debtcollector import deprecate
 from ..utils.deprecations import DDTraceDeprecationWarning
 from . import event_hub  # noqa:F401
 from ._core import DDSketch  # noqa:F401
+from ._core import gen_span_id  # noqa:F401
+from ._core import gen_trace_id_128  # noqa:F401
+from ._core import sampling_decision  # noqa:F401
+from ._core import trace_id_lower_64_bits  # noqa:F401
 from .event_hub import EventResultDict  # noqa:F401
 from .event_hub import dispatch
 from .event_hub import dispatch_with_results  # noqa:F401
@@ -1,6 +1,643 @@
+from typing import Any
+from typing import Callable
+from typing import Dict
+from typing import List
+from typing import Mapping
+from typing import Optional
+from typing import Tuple
+from typing import Union
+
 class DDSketch:
     def __init__(self): ...
     def add(self, value: float) -> None: ...
     def to_proto(self) -> bytes: ...
     @property
     def count(self) -> float: ...
+
+def set_runtime_worker_threads(worker_threads: int) -> None: ...
+def is_runtime_started() -> bool: ...
+
+class SlidingWindowRateLimiter:
+    _now_ns: Optional[int]
+    def __init__(
+        self,
+        rate_limit: int,
+        time_window: float = 1e9,
+        metrics_callback: Optional[Callable[[int, int], None]] = None,
+        metrics_interval: int = 1000,
+    ): ...
+    def is_allowed(self, n: int = 1, timestamp_ns: Optional[int] = None) -> bool: ...
+    def __call__(self) -> bool: ...
+    def allow_many(self, count: int) -> int: ...
+    def set_rate(self, rate_limit: int) -> None: ...
+    @property
+    def allowed(self) -> int: ...
+    @property
+    def denied(self) -> int: ...
+    @property
+    def rate_limit(self) -> int: ...
+    @property
+    def time_window(self) -> float: ...
+    @property
+    def effective_rate(self) -> float: ...
+    @property
+    def current_window_rate(self) -> float: ...
+
+class TokenBucketRateLimiter:
+    _now_ns: Optional[int]
+    def __init__(
+        self,
+        rate: float,
+        burst: Optional[float] = None,
+        time_window: float = 1e9,
+        metrics_callback: Optional[Callable[[int, int], None]] = None,
+        metrics_interval: int = 1000,
+    ): ...
+    def is_allowed(self, n: int = 1, timestamp_ns: Optional[int] = None) -> bool: ...
+    def __call__(self) -> bool: ...
+    def allow_many(self, count: int) -> int: ...
+    def set_rate(self, rate: float, burst: Optional[float] = None) -> None: ...
+    @property
+    def allowed(self) -> int: ...
+    @property
+    def denied(self) -> int: ...
+    @property
+    def rate(self) -> float: ...
+    @property
+    def burst(self) -> float: ...
+    @property
+    def time_window(self) -> float: ...
+    @property
+    def tokens(self) -> float: ...
+    @property
+    def effective_rate(self) -> float: ...
+    @property
+    def current_window_rate(self) -> float: ...
+
+class KeyedRateLimiter:
+    _now_ns: Optional[int]
+    def __init__(
+        self,
+        rate: float,
+        burst: Optional[float] = None,
+        time_window: float = 1e9,
+        max_keys: int = 1024,
+        metrics_callback: Optional[Callable[[int, int], None]] = None,
+        metrics_interval: int = 1000,
+    ): ...
+    def is_allowed(self, key: str, n: int = 1, timestamp_ns: Optional[int] = None) -> bool: ...
+    def set_rate(self, rate: float, burst: Optional[float] = None) -> None: ...
+    @property
+    def allowed(self) -> int: ...
+    @property
+    def denied(self) -> int: ...
+    def forget(self, key: str) -> None: ...
+    def __len__(self) -> int: ...
+    def __contains__(self, key: str) -> bool: ...
+    @property
+    def rate(self) -> float: ...
+    @property
+    def burst(self) -> float: ...
+    @property
+    def time_window(self) -> float: ...
+    @property
+    def max_keys(self) -> int: ...
+
+class AdaptiveRateLimiter:
+    _now_ns: Optional[int]
+    def __init__(
+        self,
+        rate: float,
+        min_rate: float = 1.0,
+        max_rate: Optional[float] = None,
+        smoothing: float = 0.2,
+        metrics_callback: Optional[Callable[[int, int], None]] = None,
+        metrics_interval: int = 1000,
+    ): ...
+    def set_target_rate(self, rate: float) -> None: ...
+    def is_allowed(self, n: int = 1, timestamp_ns: Optional[int] = None) -> bool: ...
+    def __call__(self) -> bool: ...
+    def allow_many(self, count: int) -> int: ...
+    @property
+    def allowed(self) -> int: ...
+    @property
+    def denied(self) -> int: ...
+    @property
+    def rate(self) -> float: ...
+    @property
+    def target_rate(self) -> float: ...
+    @property
+    def min_rate(self) -> float: ...
+    @property
+    def max_rate(self) -> float: ...
+    @property
+    def effective_rate(self) -> float: ...
+    @property
+    def current_window_rate(self) -> float: ...
+
+class ConcurrencyLimiter:
+    def __init__(self, max_concurrent: int): ...
+    def acquire(self, timeout: Optional[float] = None) -> bool: ...
+    def release(self) -> None: ...
+    def __enter__(self) -> "ConcurrencyLimiter": ...
+    def __exit__(self, exc_type, exc_value, traceback) -> None: ...
+    @property
+    def max_concurrent(self) -> int: ...
+    @property
+    def in_use(self) -> int: ...
+
+# A span as a dict keyed like the v0.4 span fields, or as a tuple in the v0.5 field order optionally
+# followed by meta_struct, span_links and span_events. Span links are dicts in the SpanLink.to_dict()
+# form and span events in the SpanEvent.__dict__() form
+Span = Union[Dict[str, Any], Tuple[Any, ...]]
+
+class OverflowReason:
+    TRACE_TOO_BIG: "OverflowReason"
+    BUFFER_FULL: "OverflowReason"
+
+class Overflow:
+    @property
+    def reason(self) -> OverflowReason: ...
+    @property
+    def size(self) -> int: ...
+    @property
+    def spans(self) -> int: ...
+
+class Compression:
+    NONE: "Compression"
+    GZIP: "Compression"
+    ZSTD: "Compression"
+    @property
+    def content_encoding(self) -> Optional[str]: ...
+    @staticmethod
+    def negotiate(accept_encoding: str) -> "Compression": ...
+
+class EncoderStats:
+    @property
+    def traces(self) -> int: ...
+    @property
+    def spans(self) -> int: ...
+    @property
+    def dropped_traces(self) -> int: ...
+    @property
+    def dropped_spans(self) -> int: ...
+    @property
+    def errors(self) -> int: ...
+    @property
+    def invalid_strings(self) -> int: ...
+    @property
+    def truncated_strings(self) -> int: ...
+    @property
+    def dropped_tags(self) -> int: ...
+    @property
+    def payloads(self) -> int: ...
+    @property
+    def bytes(self) -> int: ...
+    @property
+    def encode_time_ns(self) -> int: ...
+    @property
+    def encode_time_histogram(self) -> List[Tuple[Optional[int], int]]: ...
+
+class InvalidUtf8:
+    REPLACE: "InvalidUtf8"
+    DROP_TAG: "InvalidUtf8"
+    ERROR: "InvalidUtf8"
+
+class GlobMatcher:
+    def __init__(self, pattern: str): ...
+    @property
+    def pattern(self) -> str: ...
+    def match(self, subject: str) -> bool: ...
+
+class PrioritySampler:
+    def __init__(self, sample_rate: float = 1.0, service: Optional[str] = None, env: Optional[str] = None): ...
+    @property
+    def sample_rate(self) -> float: ...
+    @property
+    def rates(self) -> Dict[str, float]: ...
+    def set_sample_rate(self, sample_rate: float, service: Optional[str] = None, env: Optional[str] = None) -> None: ...
+    def update_rate_by_service_sample_rates(self, rate_by_service: Dict[str, float]) -> None: ...
+    def sample(
+        self, trace_id: int, service: Optional[str] = None, env: Optional[str] = None
+    ) -> Tuple[int, int, float]: ...
+
+class SamplingRules:
+    def __init__(self, rules: Optional[str] = None, default_sample_rate: Optional[float] = None): ...
+    def __len__(self) -> int: ...
+    def sample(
+        self,
+        trace_id: int,
+        service: Optional[str] = None,
+        name: Optional[str] = None,
+        resource: Optional[str] = None,
+        meta: Optional[Dict[str, str]] = None,
+        metrics: Optional[Dict[str, float]] = None,
+    ) -> Optional[Tuple[int, int, float]]: ...
+
+class SpanSamplingRules:
+    def __init__(self, rules: Optional[str] = None): ...
+    def __len__(self) -> int: ...
+    def sample(
+        self, span_id: int, service: Optional[str] = None, name: Optional[str] = None
+    ) -> Optional[Dict[str, float]]: ...
+
+class TraceSampler:
+    def __init__(
+        self,
+        rules: Optional[str] = None,
+        default_sample_rate: Optional[float] = None,
+        rate_limit: Optional[float] = None,
+        rate_limit_window: float = 1e9,
+        rate_limit_always_on: bool = False,
+        service: Optional[str] = None,
+        env: Optional[str] = None,
+        adaptive: bool = False,
+    ): ...
+    @property
+    def rate_limit(self) -> float: ...
+    @property
+    def backpressure_factor(self) -> float: ...
+    @property
+    def agent_rates(self) -> Dict[str, float]: ...
+    def set_sample_rate(self, sample_rate: float, service: Optional[str] = None, env: Optional[str] = None) -> None: ...
+    def update_rate_by_service_sample_rates(self, rate_by_service: Dict[str, float]) -> None: ...
+    def __len__(self) -> int: ...
+    def update_rules(self, rc_payload: Optional[bytes] = None) -> None: ...
+    def report_backpressure(self) -> None: ...
+    def record_manual(self, sampled: bool) -> None: ...
+    def decisions(self, reset: bool = False) -> Dict[str, Tuple[int, int]]: ...
+    def sample(
+        self,
+        trace_id: int,
+        service: Optional[str] = None,
+        name: Optional[str] = None,
+        resource: Optional[str] = None,
+        env: Optional[str] = None,
+        meta: Optional[Dict[str, str]] = None,
+        metrics: Optional[Dict[str, float]] = None,
+    ) -> Tuple[bool, int, int, float, Optional[float]]: ...
+    def explain(
+        self,
+        trace_id: int,
+        service: Optional[str] = None,
+        name: Optional[str] = None,
+        resource: Optional[str] = None,
+        env: Optional[str] = None,
+        meta: Optional[Dict[str, str]] = None,
+        metrics: Optional[Dict[str, float]] = None,
+    ) -> Dict[str, Any]: ...
+
+class ExtractedContext:
+    @property
+    def trace_id(self) -> Optional[int]: ...
+    @property
+    def span_id(self) -> Optional[int]: ...
+    @property
+    def sampling_priority(self) -> Optional[int]: ...
+    @property
+    def dd_origin(self) -> Optional[str]: ...
+    @property
+    def meta(self) -> Dict[str, str]: ...
+
+class TagLimits:
+    def __init__(
+        self,
+        max_key_length: int = 200,
+        max_value_length: int = 25000,
+        max_resource_length: int = 5000,
+        max_meta_entries: Optional[int] = None,
+    ): ...
+    @property
+    def max_key_length(self) -> int: ...
+    @property
+    def max_value_length(self) -> int: ...
+    @property
+    def max_resource_length(self) -> int: ...
+    @property
+    def max_meta_entries(self) -> Optional[int]: ...
+
+class TraceEncoderV04:
+    content_type: str
+    def __init__(
+        self,
+        max_size: int = 20 << 20,
+        max_item_size: Optional[int] = None,
+        on_drop: Optional[Callable[["Overflow"], None]] = None,
+        top_level_span_events: bool = False,
+        compression: Compression = Compression.NONE,
+        invalid_utf8: InvalidUtf8 = InvalidUtf8.REPLACE,
+        tag_limits: Optional[TagLimits] = None,
+    ): ...
+    def __len__(self) -> int: ...
+    @property
+    def size(self) -> int: ...
+    def size_in_bytes(self) -> int: ...
+    def estimate(self, trace: List[Span]) -> int: ...
+    @property
+    def max_size(self) -> int: ...
+    @property
+    def max_item_size(self) -> int: ...
+    @property
+    def compression(self) -> Compression: ...
+    @property
+    def content_encoding(self) -> Optional[str]: ...
+    @property
+    def invalid_utf8(self) -> InvalidUtf8: ...
+    @property
+    def tag_limits(self) -> TagLimits: ...
+    def stats(self, reset: bool = False) -> EncoderStats: ...
+    @property
+    def top_level_span_events(self) -> bool: ...
+    def put(self, trace: List[Span]) -> Optional["Overflow"]: ...
+    def put_partial(self, trace: List[Span], min_spans: int) -> Tuple[Optional["Overflow"], List[Span]]: ...
+    def flush(self) -> Tuple[bytes, int]: ...
+    def encode(self) -> Tuple[Optional[bytes], int]: ...
+
+class TraceEncoderV05:
+    content_type: str
+    def __init__(
+        self,
+        max_size: int = 20 << 20,
+        max_item_size: Optional[int] = None,
+        on_drop: Optional[Callable[["Overflow"], None]] = None,
+        compression: Compression = Compression.NONE,
+        invalid_utf8: InvalidUtf8 = InvalidUtf8.REPLACE,
+        tag_limits: Optional[TagLimits] = None,
+    ): ...
+    def __len__(self) -> int: ...
+    @property
+    def size(self) -> int: ...
+    def size_in_bytes(self) -> int: ...
+    def estimate(self, trace: List[Span]) -> int: ...
+    @property
+    def max_size(self) -> int: ...
+    @property
+    def max_item_size(self) -> int: ...
+    @property
+    def compression(self) -> Compression: ...
+    @property
+    def content_encoding(self) -> Optional[str]: ...
+    @property
+    def invalid_utf8(self) -> InvalidUtf8: ...
+    @property
+    def tag_limits(self) -> TagLimits: ...
+    def stats(self, reset: bool = False) -> EncoderStats: ...
+    def put(self, trace: List[Span]) -> Optional["Overflow"]: ...
+    def put_partial(self, trace: List[Span], min_spans: int) -> Tuple[Optional["Overflow"], List[Span]]: ...
+    def flush(self) -> Tuple[bytes, int]: ...
+    def encode(self) -> Tuple[Optional[bytes], int]: ...
+
+class JsonEncoder:
+    content_type: str
+    def __init__(
+        self,
+        max_size: int = 20 << 20,
+        max_item_size: Optional[int] = None,
+        on_drop: Optional[Callable[["Overflow"], None]] = None,
+        compression: Compression = Compression.NONE,
+    ): ...
+    def __len__(self) -> int: ...
+    @property
+    def size(self) -> int: ...
+    @property
+    def max_size(self) -> int: ...
+    @property
+    def max_item_size(self) -> int: ...
+    @property
+    def compression(self) -> Compression: ...
+    @property
+    def content_encoding(self) -> Optional[str]: ...
+    def stats(self, reset: bool = False) -> EncoderStats: ...
+    def put(self, item: Any) -> Optional["Overflow"]: ...
+    def flush(self) -> Tuple[bytes, int]: ...
+    def encode(self) -> Tuple[Optional[bytes], int]: ...
+
+class OtlpEncoder:
+    content_type: str
+    def __init__(
+        self,
+        max_size: int = 20 << 20,
+        max_item_size: Optional[int] = None,
+        on_drop: Optional[Callable[["Overflow"], None]] = None,
+        compression: Compression = Compression.NONE,
+        resource_attributes: Optional[Dict[str, Any]] = None,
+        scope_name: str = "ddtrace",
+        scope_version: Optional[str] = None,
+    ): ...
+    def __len__(self) -> int: ...
+    @property
+    def size(self) -> int: ...
+    @property
+    def max_size(self) -> int: ...
+    @property
+    def max_item_size(self) -> int: ...
+    @property
+    def compression(self) -> Compression: ...
+    @property
+    def content_encoding(self) -> Optional[str]: ...
+    def stats(self, reset: bool = False) -> EncoderStats: ...
+    def put(self, trace: List[Span]) -> Optional["Overflow"]: ...
+    def flush(self) -> Tuple[bytes, int]: ...
+    def encode(self) -> Tuple[Optional[bytes], int]: ...
+
+class CiTestCycleEncoder:
+    content_type: str
+    def __init__(
+        self,
+        max_size: int = 20 << 20,
+        max_item_size: Optional[int] = None,
+        on_drop: Optional[Callable[["Overflow"], None]] = None,
+        compression: Compression = Compression.NONE,
+    ): ...
+    def __len__(self) -> int: ...
+    @property
+    def size(self) -> int: ...
+    @property
+    def max_size(self) -> int: ...
+    @property
+    def max_item_size(self) -> int: ...
+    @property
+    def compression(self) -> Compression: ...
+    @property
+    def content_encoding(self) -> Optional[str]: ...
+    def stats(self, reset: bool = False) -> EncoderStats: ...
+    def set_metadata(self, event_type: str, metadata: Dict[str, str]) -> None: ...
+    def put(self, trace: List[Span], dd_origin: Optional[str] = None) -> Optional["Overflow"]: ...
+    def flush(self) -> Tuple[bytes, int]: ...
+    def encode(self) -> Tuple[Optional[bytes], int]: ...
+
+class WriterStats:
+    @property
+    def payloads(self) -> int: ...
+    @property
+    def traces(self) -> int: ...
+    @property
+    def bytes(self) -> int: ...
+    @property
+    def http_errors(self) -> int: ...
+    @property
+    def connection_errors(self) -> int: ...
+    @property
+    def retries(self) -> int: ...
+    @property
+    def requeued_payloads(self) -> int: ...
+    @property
+    def split_payloads(self) -> int: ...
+    @property
+    def dropped_payloads(self) -> int: ...
+    @property
+    def dropped_traces(self) -> int: ...
+    @property
+    def dropped_spans(self) -> int: ...
+    @property
+    def dropped_bytes(self) -> int: ...
+    @property
+    def dropped(self) -> Dict[str, Dict[str, int]]: ...
+    @property
+    def queued_payloads(self) -> int: ...
+    @property
+    def last_error(self) -> Optional[str]: ...
+
+class RetryPolicy:
+    def __init__(
+        self,
+        max_attempts: int = 3,
+        initial_backoff: float = 0.1,
+        max_backoff: float = 2.0,
+        max_requeued_bytes: int = 20 << 20,
+    ): ...
+    @property
+    def max_attempts(self) -> int: ...
+    @property
+    def initial_backoff(self) -> float: ...
+    @property
+    def max_backoff(self) -> float: ...
+    @property
+    def max_requeued_bytes(self) -> int: ...
+
+class NativeTraceWriter:
+    def __init__(
+        self,
+        agent_url: str,
+        api_version: str = "v0.5",
+        content_type: str = "application/msgpack",
+        headers: Optional[Dict[str, str]] = None,
+        timeout: float = 2.0,
+        max_queued_payloads: int = 64,
+        retry: Optional[RetryPolicy] = None,
+        on_rate_by_service: Optional[Callable[[Dict[str, float]], None]] = None,
+        max_idle_connections: int = 2,
+        idle_timeout: float = 10.0,
+        encoder: Optional[Union[TraceEncoderV04, TraceEncoderV05, JsonEncoder, OtlpEncoder, CiTestCycleEncoder]] = None,
+        interval: Optional[float] = None,
+        on_stats: Optional[Callable[[WriterStats], None]] = None,
+        stats_interval: float = 10.0,
+        data_pipeline: bool = False,
+        compute_stats: bool = False,
+        max_payload_size: Optional[int] = None,
+        proxy: Optional[str] = None,
+        health_metrics: Optional[bool] = None,
+        dogstatsd_url: Optional[str] = None,
+    ): ...
+    @property
+    def agent_url(self) -> str: ...
+    @property
+    def api_version(self) -> str: ...
+    @property
+    def data_pipeline(self) -> bool: ...
+    @property
+    def client_computed_stats(self) -> bool: ...
+    @property
+    def retry(self) -> RetryPolicy: ...
+    @property
+    def rate_by_service(self) -> Dict[str, float]: ...
+    @property
+    def interval(self) -> float: ...
+    @property
+    def encoder(self) -> Optional[Any]: ...
+    def write(self, trace: List[Span]) -> bool: ...
+    def flush_queue(self, timeout: Optional[float] = None) -> bool: ...
+    def record_dropped_p0(self, traces: int, spans: int) -> None: ...
+    def send(self, payload: bytes, traces: int, content_encoding: Optional[str] = None, spans: int = 0) -> bool: ...
+    def flush(self, timeout: Optional[float] = None) -> bool: ...
+    def stop(self, timeout: Optional[float] = None) -> bool: ...
+    def shutdown(self, timeout: Optional[float] = None) -> bool: ...
+    def stats(self, reset: bool = False) -> WriterStats: ...
+
+class AgentInfo:
+    @property
+    def version(self) -> Optional[str]: ...
+    @property
+    def endpoints(self) -> List[str]: ...
+    @property
+    def feature_flags(self) -> List[str]: ...
+    @property
+    def client_drop_p0s(self) -> bool: ...
+    @property
+    def span_events(self) -> bool: ...
+    @property
+    def peer_tags(self) -> List[str]: ...
+    @property
+    def obfuscation_version(self) -> Optional[int]: ...
+    @property
+    def supports_v05(self) -> bool: ...
+    @property
+    def supports_stats(self) -> bool: ...
+    def supports_endpoint(self, path: str) -> bool: ...
+    def to_dict(self) -> Dict[str, Any]: ...
+
+class AgentInfoFetcher:
+    def __init__(self, agent_url: str, timeout: float = 2.0, ttl: float = 300.0, proxy: Optional[str] = None): ...
+    @property
+    def last_error(self) -> Optional[str]: ...
+    def fetch(self, force: bool = False) -> Optional[AgentInfo]: ...
+
+class NativeSpanStatsProcessor:
+    def __init__(
+        self,
+        agent_url: str,
+        interval: Optional[float] = None,
+        timeout: float = 1.0,
+        hostname: str = "",
+        env: Optional[str] = None,
+        version: Optional[str] = None,
+        retry: Optional[RetryPolicy] = None,
+        proxy: Optional[str] = None,
+    ): ...
+    @property
+    def agent_url(self) -> str: ...
+    @property
+    def interval(self) -> float: ...
+    @property
+    def enabled(self) -> bool: ...
+    @property
+    def last_error(self) -> Optional[str]: ...
+    def on_span_start(self, span: Any) -> None: ...
+    def on_span_finish(self, span: Any) -> None: ...
+    def flush(self, timeout: Optional[float] = None) -> bool: ...
+    def shutdown(self, timeout: Optional[float] = None) -> bool: ...
+
+def sampling_decision(trace_id: int, rate: float) -> bool: ...
+def sampling_decision_tags(
+    mechanism: int, sample_rate: Optional[float] = None, limit_rate: Optional[float] = None
+) -> Tuple[Dict[str, str], Dict[str, float]]: ...
+def trace_id_lower_64_bits(trace_id: int) -> int: ...
+def b3_extract(headers: Mapping[str, Union[str, bytes]]) -> Optional[ExtractedContext]: ...
+def b3_build_headers(context: Any) -> Dict[str, str]: ...
+def b3multi_extract(headers: Mapping[str, Union[str, bytes]]) -> Optional[ExtractedContext]: ...
+def b3multi_build_headers(context: Any) -> Dict[str, str]: ...
+def baggage_extract(headers: Mapping[str, Union[str, bytes]]) -> Dict[str, str]: ...
+def baggage_build_headers(context: Any, max_items: int = 64, max_bytes: int = 8192) -> Dict[str, str]: ...
+def datadog_extract(
+    headers: Mapping[str, Union[str, bytes]], trace_id_128_bit: bool = True, tags_max_size: int = 512
+) -> Optional[ExtractedContext]: ...
+def datadog_tags_decode(tags: str, max_size: int = 512) -> Dict[str, str]: ...
+def datadog_tags_encode(meta: Dict[str, str], max_size: int = 512) -> Optional[str]: ...
+def w3c_parse_traceparent(traceparent: str) -> Tuple[int, int, int]: ...
+def w3c_parse_tracestate(
+    tracestate: str, sampled: Optional[bool] = None
+) -> Optional[Tuple[str, Optional[int], Dict[str, str], Optional[str], Optional[str]]]: ...
+def w3c_build_headers(context: Any) -> Dict[str, str]: ...
+def gen_span_id() -> int: ...
+def gen_trace_id_128() -> int: ...
+def get_limiter(name: str, rate_limit: int, time_window: float = 1e9) -> SlidingWindowRateLimiter: ...
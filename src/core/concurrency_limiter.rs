@@ -0,0 +1,117 @@
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+
+/// Semaphore bounding the number of concurrent operations (e.g. in-flight payload uploads). The
+/// GIL is released while waiting for a slot, so blocked threads don't stall the interpreter.
+#[pyclass(
+    frozen,
+    name = "ConcurrencyLimiter",
+    module = "ddtrace.internal.core._core"
+)]
+pub struct ConcurrencyLimiterPy {
+    max_concurrent: usize,
+    in_use: Mutex<usize>,
+    released: Condvar,
+}
+
+impl ConcurrencyLimiterPy {
+    fn wait_for_slot(&self, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut in_use = self.in_use.lock().unwrap();
+        while *in_use >= self.max_concurrent {
+            in_use = match deadline {
+                None => self.released.wait(in_use).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    self.released
+                        .wait_timeout(in_use, deadline - now)
+                        .unwrap()
+                        .0
+                }
+            };
+        }
+        *in_use += 1;
+        true
+    }
+}
+
+#[pymethods]
+impl ConcurrencyLimiterPy {
+    #[new]
+    fn new(max_concurrent: usize) -> PyResult<Self> {
+        if max_concurrent == 0 {
+            return Err(PyValueError::new_err(
+                "max_concurrent must be greater than 0",
+            ));
+        }
+        Ok(ConcurrencyLimiterPy {
+            max_concurrent,
+            in_use: Mutex::new(0),
+            released: Condvar::new(),
+        })
+    }
+
+    #[getter]
+    fn max_concurrent(&self) -> usize {
+        self.max_concurrent
+    }
+
+    #[getter]
+    fn in_use(&self) -> usize {
+        *self.in_use.lock().unwrap()
+    }
+
+    /// Waits up to `timeout` seconds (forever if `None`) for a slot, and returns whether one was
+    /// acquired.
+    #[pyo3(signature = (timeout = None))]
+    fn acquire(&self, py: Python<'_>, timeout: Option<f64>) -> PyResult<bool> {
+        let timeout = match timeout {
+            None => None,
+            Some(timeout) => Some(
+                Duration::try_from_secs_f64(timeout.max(0.0))
+                    .map_err(|e| PyValueError::new_err(e.to_string()))?,
+            ),
+        };
+        // Don't give up the GIL when a slot is immediately available
+        if let Ok(mut in_use) = self.in_use.try_lock() {
+            if *in_use < self.max_concurrent {
+                *in_use += 1;
+                return Ok(true);
+            }
+        }
+        Ok(py.allow_threads(|| self.wait_for_slot(timeout)))
+    }
+
+    fn release(&self) -> PyResult<()> {
+        let mut in_use = self.in_use.lock().unwrap();
+        if *in_use == 0 {
+            return Err(PyRuntimeError::new_err(
+                "release() called more times than acquire()",
+            ));
+        }
+        *in_use -= 1;
+        self.released.notify_one();
+        Ok(())
+    }
+
+    fn __enter__<'py>(slf: &Bound<'py, Self>) -> Bound<'py, Self> {
+        let limiter = slf.get();
+        slf.py().allow_threads(|| limiter.wait_for_slot(None));
+        slf.clone()
+    }
+
+    fn __exit__(
+        &self,
+        _exc_type: &Bound<'_, PyAny>,
+        _exc_value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) -> PyResult<()> {
+        self.release()
+    }
+}
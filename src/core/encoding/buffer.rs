@@ -0,0 +1,125 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+/// Same default as `DD_TRACE_WRITER_BUFFER_SIZE_BYTES`.
+pub const DEFAULT_MAX_SIZE: usize = 20 << 20;
+
+#[pyclass(
+    eq,
+    eq_int,
+    name = "OverflowReason",
+    module = "ddtrace.internal.core._core"
+)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OverflowReasonPy {
+    /// The trace alone is bigger than `max_item_size`.
+    #[pyo3(name = "TRACE_TOO_BIG")]
+    TraceTooBig,
+    /// The trace would make the payload bigger than `max_size`.
+    #[pyo3(name = "BUFFER_FULL")]
+    BufferFull,
+}
+
+/// A trace rejected by an encoder buffer, returned by `put()` and passed to the drop callback.
+#[pyclass(frozen, name = "Overflow", module = "ddtrace.internal.core._core")]
+pub struct OverflowPy {
+    reason: OverflowReasonPy,
+    size: usize,
+    spans: usize,
+}
+
+#[pymethods]
+impl OverflowPy {
+    #[getter]
+    pub fn reason(&self) -> OverflowReasonPy {
+        self.reason
+    }
+
+    /// Size in bytes of the encoded trace.
+    #[getter]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    #[getter]
+    pub fn spans(&self) -> usize {
+        self.spans
+    }
+
+    fn __repr__(&self) -> String {
+        let reason = match self.reason {
+            OverflowReasonPy::TraceTooBig => "TRACE_TOO_BIG",
+            OverflowReasonPy::BufferFull => "BUFFER_FULL",
+        };
+        format!(
+            "Overflow(reason=OverflowReason.{}, size={}, spans={})",
+            reason, self.size, self.spans
+        )
+    }
+}
+
+/// Size bounds of an encoder buffer. Traces that don't fit are dropped rather than raised, and
+/// reported to an optional callback so that the writer can account for them.
+pub struct Limits {
+    max_size: usize,
+    max_item_size: usize,
+    on_drop: Option<PyObject>,
+}
+
+impl Limits {
+    pub fn new(
+        max_size: usize,
+        max_item_size: Option<usize>,
+        on_drop: Option<PyObject>,
+    ) -> PyResult<Self> {
+        if max_size == 0 {
+            return Err(PyValueError::new_err("max_size must be greater than 0"));
+        }
+        Ok(Limits {
+            max_size,
+            max_item_size: max_item_size.unwrap_or(max_size).min(max_size),
+            on_drop,
+        })
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    pub fn max_item_size(&self) -> usize {
+        self.max_item_size
+    }
+
+    /// Checks a trace of `spans` spans encoded in `size` bytes, which would make the payload
+    /// `payload_size` bytes long. Returns the overflow if the trace has to be dropped.
+    pub fn check(
+        &self,
+        py: Python<'_>,
+        spans: usize,
+        size: usize,
+        payload_size: usize,
+    ) -> PyResult<Option<Py<OverflowPy>>> {
+        let reason = if size > self.max_item_size {
+            OverflowReasonPy::TraceTooBig
+        } else if payload_size > self.max_size {
+            OverflowReasonPy::BufferFull
+        } else {
+            return Ok(None);
+        };
+        let overflow = Py::new(
+            py,
+            OverflowPy {
+                reason,
+                size,
+                spans,
+            },
+        )?;
+        if let Some(on_drop) = &self.on_drop {
+            // A failing callback must not fail the traced application
+            if let Err(err) = on_drop.call1(py, (overflow.clone_ref(py),)) {
+                err.write_unraisable_bound(py, Some(on_drop.bind(py)));
+            }
+        }
+        Ok(Some(overflow))
+    }
+}
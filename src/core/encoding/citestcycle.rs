@@ -0,0 +1,467 @@
+use std::time::Instant;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyString};
+
+use super::buffer::{self, Limits, OverflowPy};
+use super::compression::CompressionPy;
+use super::msgpack;
+use super::packer;
+use super::span::{text, write_number, write_text, Span};
+use super::span_link::SpanLink;
+use super::stats::{EncoderStatsPy, Stats};
+
+const PAYLOAD_FORMAT_VERSION: u64 = 1;
+const TEST_SUITE_EVENT_VERSION: u64 = 1;
+const TEST_EVENT_VERSION: u64 = 2;
+
+/// Tag holding the kind of test event, e.g. `test` or `test_suite_end`.
+const EVENT_TYPE_TAG: &str = "type";
+const SESSION_TYPE: &str = "test_session_end";
+const MODULE_TYPE: &str = "test_module_end";
+const SUITE_TYPE: &str = "test_suite_end";
+const TEST_TYPE: &str = "test";
+
+const SESSION_ID: &str = "test_session_id";
+const MODULE_ID: &str = "test_module_id";
+const SUITE_ID: &str = "test_suite_id";
+const COVERAGE_TAG: &str = "test.coverage";
+const ITR_CORRELATION_ID: &str = "itr_correlation_id";
+const ORIGIN_TAG: &str = "_dd.origin";
+
+/// Size of the payload map without its metadata and events: the map header, the three keys and
+/// the version.
+const PAYLOAD_KEYS_SIZE: usize = 1 + 8 + 1 + 9 + 7;
+
+/// Buffers test events in the citestcycle format of the CI Visibility intake:
+/// `{"version": 1, "metadata": {...}, "events": [{"version": ..., "type": ..., "content": ...}]}`.
+///
+/// Traces are lists of spans, given as dicts or in the compact tuple form, and every span becomes
+/// an event. Decoded payloads are the same as the ones of
+/// `ddtrace.internal.ci_visibility.encoder.CIVisibilityEncoderV01`: session, module and suite
+/// events carry no span ids, the test ids are moved from `meta` to the event content, and `meta`
+/// and `metrics` are sorted by key.
+#[pyclass(name = "CiTestCycleEncoder", module = "ddtrace.internal.core._core")]
+pub struct CiTestCycleEncoderPy {
+    events: Vec<u8>,
+    event_count: usize,
+    count: usize,
+    /// Metadata by event type, `*` applying to all of them.
+    metadata: Py<PyDict>,
+    encoded_metadata: Vec<u8>,
+    limits: Limits,
+    compression: CompressionPy,
+    stats: Stats,
+}
+
+/// Sorts the entries of a tag dict by key, leaving out the keys in `skip`.
+fn sorted_tags<'a, 'py>(
+    entries: &'a [(Bound<'py, PyString>, Bound<'py, PyAny>)],
+    skip: &[&str],
+) -> PyResult<Vec<(&'a str, &'a Bound<'py, PyAny>)>> {
+    let mut sorted = Vec::with_capacity(entries.len());
+    for (key, value) in entries {
+        let key = key.to_str()?;
+        if !skip.contains(&key) {
+            sorted.push((key, value));
+        }
+    }
+    sorted.sort_unstable_by_key(|(key, _)| *key);
+    Ok(sorted)
+}
+
+fn entries<'py>(
+    tags: Option<&Bound<'py, PyDict>>,
+) -> PyResult<Vec<(Bound<'py, PyString>, Bound<'py, PyAny>)>> {
+    tags.into_iter()
+        .flatten()
+        .map(|(key, value)| Ok((key.downcast_into::<PyString>()?, value)))
+        .collect()
+}
+
+/// Returns the value of a tag, which has to be a string.
+fn find_tag<'a>(
+    entries: &'a [(Bound<'_, PyString>, Bound<'_, PyAny>)],
+    key: &str,
+) -> PyResult<Option<&'a str>> {
+    for (name, value) in entries {
+        if name.to_str()? == key {
+            if value.is_none() {
+                return Ok(None);
+            }
+            return Ok(Some(value.downcast::<PyString>()?.to_str()?));
+        }
+    }
+    Ok(None)
+}
+
+/// Test ids are tagged as decimal strings and sent as integers.
+fn parse_id(key: &str, value: &str) -> PyResult<u64> {
+    value
+        .parse()
+        .map_err(|_| PyValueError::new_err(format!("invalid {}: {:?}", key, value)))
+}
+
+fn write_event(buf: &mut Vec<u8>, span: &Span<'_>, dd_origin: Option<&str>) -> PyResult<()> {
+    let meta = entries(span.meta.as_ref())?;
+    let metrics = entries(span.metrics.as_ref())?;
+    let tag = |key: &str| find_tag(&meta, key);
+    let event_type = tag(EVENT_TYPE_TAG)?.filter(|event_type| !event_type.is_empty());
+    let is_test = event_type == Some(TEST_TYPE);
+    let is_level =
+        |levels: &[&str]| event_type.is_some_and(|ty| ty == TEST_TYPE || levels.contains(&ty));
+
+    let without_ids =
+        event_type.is_some_and(|ty| [SESSION_TYPE, MODULE_TYPE, SUITE_TYPE].contains(&ty));
+    let session_id = match tag(SESSION_ID)? {
+        Some(id) if !id.is_empty() && is_level(&[SESSION_TYPE, MODULE_TYPE, SUITE_TYPE]) => {
+            Some(parse_id(SESSION_ID, id)?)
+        }
+        _ => None,
+    };
+    let module_id = match tag(MODULE_ID)? {
+        Some(id) if !id.is_empty() && is_level(&[MODULE_TYPE, SUITE_TYPE]) => {
+            Some(parse_id(MODULE_ID, id)?)
+        }
+        _ => None,
+    };
+    let suite_id = match tag(SUITE_ID)? {
+        Some(id) if !id.is_empty() && is_level(&[SUITE_TYPE]) => Some(parse_id(SUITE_ID, id)?),
+        _ => None,
+    };
+    let correlation_id = tag(ITR_CORRELATION_ID)?;
+
+    let mut skip = vec![COVERAGE_TAG, ITR_CORRELATION_ID];
+    skip.extend(session_id.map(|_| SESSION_ID));
+    skip.extend(module_id.map(|_| MODULE_ID));
+    skip.extend(suite_id.map(|_| SUITE_ID));
+    skip.extend(dd_origin.map(|_| ORIGIN_TAG));
+    let sorted_meta = sorted_tags(&meta, &skip)?;
+    let sorted_metrics = sorted_tags(&metrics, &[])?;
+
+    msgpack::write_map_len(buf, 3);
+    msgpack::write_str(buf, "version");
+    msgpack::write_uint(
+        buf,
+        if is_test {
+            TEST_EVENT_VERSION
+        } else {
+            TEST_SUITE_EVENT_VERSION
+        },
+    );
+    msgpack::write_str(buf, "type");
+    match &span.span_type {
+        Some(span_type) if span_type.to_str()? == TEST_TYPE => match event_type {
+            Some(event_type) => msgpack::write_str(buf, event_type),
+            None => msgpack::write_nil(buf),
+        },
+        _ => msgpack::write_str(buf, "span"),
+    }
+
+    msgpack::write_str(buf, "content");
+    let start = span.start.filter(|start| *start != 0);
+    let fields = 8
+        + if without_ids { 0 } else { 3 }
+        + usize::from(start.is_some())
+        + usize::from(!span.span_links.is_empty())
+        + usize::from(session_id.is_some())
+        + usize::from(module_id.is_some())
+        + usize::from(suite_id.is_some())
+        + usize::from(correlation_id.is_some());
+    msgpack::write_map_len(buf, fields);
+    if !without_ids {
+        // Missing ids are sent as 1, which the intake expects for events outside of any trace
+        msgpack::write_str(buf, "trace_id");
+        msgpack::write_uint(buf, span.trace_id.max(1));
+        msgpack::write_str(buf, "parent_id");
+        msgpack::write_uint(buf, span.parent_id.unwrap_or(0).max(1));
+        msgpack::write_str(buf, "span_id");
+        msgpack::write_uint(buf, span.span_id.max(1));
+    }
+    msgpack::write_str(buf, "service");
+    write_text(buf, span.service.as_ref())?;
+    msgpack::write_str(buf, "resource");
+    write_text(buf, span.resource.as_ref())?;
+    msgpack::write_str(buf, "name");
+    write_text(buf, span.name.as_ref())?;
+    msgpack::write_str(buf, "error");
+    msgpack::write_int(buf, i64::from(span.error));
+    if let Some(start) = start {
+        msgpack::write_str(buf, "start");
+        msgpack::write_int(buf, start);
+    }
+    msgpack::write_str(buf, "duration");
+    match span.duration {
+        None => msgpack::write_nil(buf),
+        Some(duration) => msgpack::write_int(buf, duration),
+    }
+
+    msgpack::write_str(buf, "meta");
+    msgpack::write_map_len(buf, sorted_meta.len() + usize::from(dd_origin.is_some()));
+    for (key, value) in &sorted_meta {
+        msgpack::write_str(buf, key);
+        write_text(buf, text(Some((*value).clone()))?.as_ref())?;
+    }
+    if let Some(dd_origin) = dd_origin {
+        msgpack::write_str(buf, ORIGIN_TAG);
+        msgpack::write_str(buf, dd_origin);
+    }
+    msgpack::write_str(buf, "metrics");
+    msgpack::write_map_len(buf, sorted_metrics.len());
+    for (key, value) in &sorted_metrics {
+        msgpack::write_str(buf, key);
+        write_number(buf, value)?;
+    }
+
+    msgpack::write_str(buf, "type");
+    match event_type {
+        Some(event_type) => msgpack::write_str(buf, event_type),
+        None => write_text(buf, span.span_type.as_ref())?,
+    }
+    if !span.span_links.is_empty() {
+        msgpack::write_str(buf, "span_links");
+        msgpack::write_array_len(buf, span.span_links.len());
+        for link in &span.span_links {
+            write_link(buf, link)?;
+        }
+    }
+    if let Some(session_id) = session_id {
+        msgpack::write_str(buf, SESSION_ID);
+        msgpack::write_uint(buf, session_id);
+    }
+    if let Some(module_id) = module_id {
+        msgpack::write_str(buf, MODULE_ID);
+        msgpack::write_uint(buf, module_id);
+    }
+    if let Some(suite_id) = suite_id {
+        msgpack::write_str(buf, SUITE_ID);
+        msgpack::write_uint(buf, suite_id);
+    }
+    if let Some(correlation_id) = correlation_id {
+        msgpack::write_str(buf, ITR_CORRELATION_ID);
+        msgpack::write_str(buf, correlation_id);
+    }
+    Ok(())
+}
+
+/// Links are maps in the `SpanLink.to_dict()` form, with hex string ids.
+fn write_link(buf: &mut Vec<u8>, link: &SpanLink<'_>) -> PyResult<()> {
+    let fields = 2
+        + usize::from(link.attributes.is_some())
+        + usize::from(link.dropped_attributes_count.is_some())
+        + usize::from(link.tracestate.is_some())
+        + usize::from(link.flags.is_some());
+    msgpack::write_map_len(buf, fields);
+
+    msgpack::write_str(buf, "trace_id");
+    msgpack::write_str(buf, &format!("{:032x}", link.trace_id));
+    msgpack::write_str(buf, "span_id");
+    msgpack::write_str(buf, &format!("{:016x}", link.span_id));
+    if let Some(attributes) = &link.attributes {
+        msgpack::write_str(buf, "attributes");
+        msgpack::write_map_len(buf, attributes.len());
+        for (key, value) in attributes {
+            write_text(buf, text(Some(key))?.as_ref())?;
+            write_text(buf, text(Some(value))?.as_ref())?;
+        }
+    }
+    if let Some(dropped_attributes_count) = link.dropped_attributes_count {
+        msgpack::write_str(buf, "dropped_attributes_count");
+        msgpack::write_int(buf, dropped_attributes_count);
+    }
+    if let Some(tracestate) = &link.tracestate {
+        msgpack::write_str(buf, "tracestate");
+        write_text(buf, Some(tracestate))?;
+    }
+    if let Some(flags) = link.flags {
+        msgpack::write_str(buf, "flags");
+        msgpack::write_uint(buf, u64::from(flags));
+    }
+    Ok(())
+}
+
+impl CiTestCycleEncoderPy {
+    fn payload_size(&self, event_count: usize) -> usize {
+        PAYLOAD_KEYS_SIZE
+            + self.encoded_metadata.len()
+            + msgpack::array_header_size(event_count)
+            + self.events.len()
+    }
+
+    fn take_payload<'py>(&mut self, py: Python<'py>) -> PyResult<(Bound<'py, PyBytes>, usize)> {
+        let mut payload = Vec::with_capacity(self.payload_size(self.event_count));
+        msgpack::write_map_len(&mut payload, 3);
+        msgpack::write_str(&mut payload, "version");
+        msgpack::write_uint(&mut payload, PAYLOAD_FORMAT_VERSION);
+        msgpack::write_str(&mut payload, "metadata");
+        payload.extend_from_slice(&self.encoded_metadata);
+        msgpack::write_str(&mut payload, "events");
+        msgpack::write_array_len(&mut payload, self.event_count);
+        payload.extend_from_slice(&self.events);
+
+        let count = self.reset();
+        let compression = self.compression;
+        let payload = py.allow_threads(|| compression.compress(payload))?;
+        self.stats.record_payload(payload.len());
+        Ok((PyBytes::new_bound(py, &payload), count))
+    }
+
+    /// Empties the buffer and returns the number of traces it held.
+    fn reset(&mut self) -> usize {
+        let count = self.count;
+        self.events.clear();
+        self.event_count = 0;
+        self.count = 0;
+        count
+    }
+}
+
+#[pymethods]
+impl CiTestCycleEncoderPy {
+    #[new]
+    #[pyo3(signature = (
+        max_size = buffer::DEFAULT_MAX_SIZE,
+        max_item_size = None,
+        on_drop = None,
+        compression = CompressionPy::None
+    ))]
+    fn new(
+        py: Python<'_>,
+        max_size: usize,
+        max_item_size: Option<usize>,
+        on_drop: Option<PyObject>,
+        compression: CompressionPy,
+    ) -> PyResult<Self> {
+        let mut encoded_metadata = Vec::new();
+        msgpack::write_map_len(&mut encoded_metadata, 0);
+        Ok(CiTestCycleEncoderPy {
+            events: Vec::new(),
+            event_count: 0,
+            count: 0,
+            metadata: PyDict::new_bound(py).unbind(),
+            encoded_metadata,
+            limits: Limits::new(max_size, max_item_size, on_drop)?,
+            compression,
+            stats: Stats::default(),
+        })
+    }
+
+    #[classattr]
+    fn content_type() -> &'static str {
+        "application/msgpack"
+    }
+
+    /// Number of traces in the buffer.
+    fn __len__(&self) -> usize {
+        self.count
+    }
+
+    /// Size in bytes of the payload that would be returned by `flush()`, before compression.
+    #[getter]
+    fn size(&self) -> usize {
+        self.payload_size(self.event_count)
+    }
+
+    #[getter]
+    fn max_size(&self) -> usize {
+        self.limits.max_size()
+    }
+
+    #[getter]
+    fn max_item_size(&self) -> usize {
+        self.limits.max_item_size()
+    }
+
+    #[getter]
+    fn compression(&self) -> CompressionPy {
+        self.compression
+    }
+
+    /// Value of the `Content-Encoding` header to send along with the payloads, if compressed.
+    #[getter]
+    fn content_encoding(&self) -> Option<&'static str> {
+        self.compression.content_encoding()
+    }
+
+    /// Counters of the traces and payloads handled so far, reset afterwards if `reset` is set.
+    #[pyo3(signature = (reset = false))]
+    fn stats(&mut self, reset: bool) -> EncoderStatsPy {
+        self.stats.snapshot(reset)
+    }
+
+    /// Adds metadata to the events of the given type, or of all types for `*`. Metadata set for a
+    /// type is merged with the one set before.
+    fn set_metadata(
+        &mut self,
+        py: Python<'_>,
+        event_type: &Bound<'_, PyString>,
+        metadata: &Bound<'_, PyDict>,
+    ) -> PyResult<()> {
+        let all_metadata = self.metadata.bind(py);
+        let type_metadata = match all_metadata.get_item(event_type)? {
+            Some(type_metadata) => type_metadata.downcast_into::<PyDict>()?,
+            None => {
+                let type_metadata = PyDict::new_bound(py);
+                all_metadata.set_item(event_type, &type_metadata)?;
+                type_metadata
+            }
+        };
+        type_metadata.update(metadata.as_mapping())?;
+
+        let mut encoded_metadata = Vec::new();
+        packer::write_value(&mut encoded_metadata, all_metadata.as_any())?;
+        self.encoded_metadata = encoded_metadata;
+        Ok(())
+    }
+
+    /// Adds the spans of a trace as events, with `dd_origin` set as their `_dd.origin` tag. A trace
+    /// that doesn't fit is dropped and its overflow returned, and nothing is buffered if any of its
+    /// spans fails to encode.
+    #[pyo3(signature = (trace, dd_origin = None))]
+    fn put<'py>(
+        &mut self,
+        py: Python<'py>,
+        trace: Vec<Span<'py>>,
+        dd_origin: Option<&str>,
+    ) -> PyResult<Option<Py<OverflowPy>>> {
+        let started = Instant::now();
+        let size_before = self.payload_size(self.event_count);
+        let events_len = self.events.len();
+        let overflow = trace
+            .iter()
+            .try_for_each(|span| write_event(&mut self.events, span, dd_origin))
+            .and_then(|()| {
+                let payload_size = self.payload_size(self.event_count + trace.len());
+                self.limits
+                    .check(py, trace.len(), payload_size - size_before, payload_size)
+            });
+        let result = match overflow {
+            Ok(None) => {
+                self.event_count += trace.len();
+                self.count += 1;
+                Ok(None)
+            }
+            result => {
+                self.events.truncate(events_len);
+                result
+            }
+        };
+        self.stats.record_put(&result, trace.len(), started);
+        result
+    }
+
+    /// Returns the payload along with the number of traces it contains and empties the buffer.
+    fn flush<'py>(&mut self, py: Python<'py>) -> PyResult<(Bound<'py, PyBytes>, usize)> {
+        self.take_payload(py)
+    }
+
+    /// Same as `flush()`, but returns no payload when no event was buffered.
+    fn encode<'py>(&mut self, py: Python<'py>) -> PyResult<(Option<Bound<'py, PyBytes>>, usize)> {
+        if self.event_count == 0 {
+            return Ok((None, self.reset()));
+        }
+        let (payload, count) = self.take_payload(py)?;
+        Ok((Some(payload), count))
+    }
+}
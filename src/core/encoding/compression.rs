@@ -0,0 +1,75 @@
+use std::io::Write;
+
+use flate2::write::GzEncoder;
+use pyo3::prelude::*;
+
+#[pyclass(
+    eq,
+    eq_int,
+    name = "Compression",
+    module = "ddtrace.internal.core._core"
+)]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompressionPy {
+    #[default]
+    #[pyo3(name = "NONE")]
+    None,
+    #[pyo3(name = "GZIP")]
+    Gzip,
+    #[pyo3(name = "ZSTD")]
+    Zstd,
+}
+
+impl CompressionPy {
+    /// Compresses a payload with the default level of the algorithm.
+    pub fn compress(self, payload: Vec<u8>) -> std::io::Result<Vec<u8>> {
+        match self {
+            CompressionPy::None => Ok(payload),
+            CompressionPy::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&payload)?;
+                encoder.finish()
+            }
+            CompressionPy::Zstd => zstd::encode_all(payload.as_slice(), 0),
+        }
+    }
+}
+
+#[pymethods]
+impl CompressionPy {
+    /// Value of the `Content-Encoding` header to send along with compressed payloads.
+    #[getter]
+    pub fn content_encoding(&self) -> Option<&'static str> {
+        match self {
+            CompressionPy::None => None,
+            CompressionPy::Gzip => Some("gzip"),
+            CompressionPy::Zstd => Some("zstd"),
+        }
+    }
+
+    /// Picks the best compression accepted by an intake, given the value of its `Accept-Encoding`
+    /// header. Codings with a zero quality value are not accepted.
+    #[staticmethod]
+    fn negotiate(accept_encoding: &str) -> CompressionPy {
+        let accepted = |coding: &str| {
+            accept_encoding.split(',').any(|item| {
+                let mut params = item.split(';').map(str::trim);
+                let name = params.next().unwrap_or_default();
+                let zero_quality = params.any(|param| {
+                    param
+                        .strip_prefix("q=")
+                        .and_then(|q| q.parse::<f64>().ok())
+                        .is_some_and(|q| q == 0.0)
+                });
+                (name.eq_ignore_ascii_case(coding) || name == "*") && !zero_quality
+            })
+        };
+        if accepted("zstd") {
+            CompressionPy::Zstd
+        } else if accepted("gzip") {
+            CompressionPy::Gzip
+        } else {
+            CompressionPy::None
+        }
+    }
+}
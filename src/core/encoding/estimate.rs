@@ -0,0 +1,144 @@
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyBytes, PyDict, PyFloat, PyList, PyLong, PyString, PyTuple};
+
+use super::span::Span;
+use super::span_event::SpanEvent;
+use super::span_link::SpanLink;
+
+// Upper bounds of the size of encoded traces, in either format. They are computed from the length
+// of the strings of the spans without encoding anything, so that the writer can tell whether a
+// trace fits in the current payload before buffering it.
+
+/// Field names, fixed-size fields and headers of a span.
+const SPAN_OVERHEAD: usize = 256;
+/// Field names, ids and flags of a span link.
+const LINK_OVERHEAD: usize = 160;
+/// Field names and timestamp of a span event.
+const EVENT_OVERHEAD: usize = 64;
+/// Header of a string, plus its index in the v0.5 string table.
+const STRING_OVERHEAD: usize = 10;
+/// Type tag and header of a nested value.
+const VALUE_OVERHEAD: usize = 48;
+/// Largest encoded number, as msgpack or as JSON.
+const NUMBER_SIZE: usize = 32;
+/// JSON escapes take up to 6 bytes per byte of UTF-8.
+const JSON_ESCAPE_FACTOR: usize = 6;
+
+pub fn trace_size(trace: &[Span<'_>]) -> PyResult<usize> {
+    let mut size = 5;
+    for span in trace {
+        size += span_size(span)?;
+    }
+    Ok(size)
+}
+
+fn span_size(span: &Span<'_>) -> PyResult<usize> {
+    let mut size = SPAN_OVERHEAD;
+    for text in [&span.service, &span.name, &span.resource, &span.span_type]
+        .into_iter()
+        .flatten()
+    {
+        size += text_size(text.as_any())?;
+    }
+    if let Some(meta) = &span.meta {
+        for (key, value) in meta {
+            size += text_size(&key)? + text_size(&value)?;
+        }
+    }
+    if let Some(metrics) = &span.metrics {
+        for (key, _) in metrics {
+            size += text_size(&key)? + NUMBER_SIZE;
+        }
+    }
+    if let Some(meta_struct) = &span.meta_struct {
+        for (key, value) in meta_struct {
+            size += text_size(&key)?;
+            size += match value.downcast::<PyBytes>() {
+                Ok(value) => value.as_bytes().len() + STRING_OVERHEAD,
+                Err(_) => value_size(&value)?,
+            };
+        }
+    }
+    for link in &span.span_links {
+        size += link_size(link)?;
+    }
+    for event in &span.span_events {
+        size += event_size(event)?;
+    }
+    Ok(size)
+}
+
+fn link_size(link: &SpanLink<'_>) -> PyResult<usize> {
+    let mut size = LINK_OVERHEAD;
+    if let Some(tracestate) = &link.tracestate {
+        size += json_text_size(tracestate.as_any())?;
+    }
+    if let Some(attributes) = &link.attributes {
+        for (key, value) in attributes {
+            size += json_text_size(&key)? + json_text_size(&value)?;
+        }
+    }
+    Ok(size)
+}
+
+fn event_size(event: &SpanEvent<'_>) -> PyResult<usize> {
+    let mut size = EVENT_OVERHEAD;
+    if let Some(name) = &event.name {
+        size += json_text_size(name.as_any())?;
+    }
+    if let Some(attributes) = &event.attributes {
+        size += value_size(attributes.as_any())?;
+    }
+    Ok(size)
+}
+
+fn text_size(text: &Bound<'_, PyAny>) -> PyResult<usize> {
+    match text.downcast::<PyString>() {
+        Ok(text) => Ok(utf8_len(text)? + STRING_OVERHEAD),
+        Err(_) => Ok(NUMBER_SIZE),
+    }
+}
+
+/// Strings that aren't valid UTF-8 are replaced or dropped when encoded, and take at most 4 bytes
+/// per character either way.
+fn utf8_len(text: &Bound<'_, PyString>) -> PyResult<usize> {
+    match text.to_str() {
+        Ok(text) => Ok(text.len()),
+        Err(_) => Ok(text.len()? * 4),
+    }
+}
+
+fn json_text_size(text: &Bound<'_, PyAny>) -> PyResult<usize> {
+    match text.downcast::<PyString>() {
+        Ok(text) => Ok(utf8_len(text)? * JSON_ESCAPE_FACTOR + STRING_OVERHEAD),
+        Err(_) => Ok(NUMBER_SIZE),
+    }
+}
+
+/// Size of a value packed as msgpack or serialized as JSON, whichever is the largest.
+fn value_size(value: &Bound<'_, PyAny>) -> PyResult<usize> {
+    if value.is_none() || value.is_instance_of::<PyBool>() {
+        Ok(VALUE_OVERHEAD)
+    } else if value.is_instance_of::<PyLong>() || value.is_instance_of::<PyFloat>() {
+        Ok(VALUE_OVERHEAD + NUMBER_SIZE)
+    } else if value.is_instance_of::<PyString>() {
+        Ok(VALUE_OVERHEAD + json_text_size(value)?)
+    } else if let Ok(value) = value.downcast::<PyBytes>() {
+        Ok(VALUE_OVERHEAD + value.as_bytes().len())
+    } else if let Ok(value) = value.downcast::<PyDict>() {
+        let mut size = VALUE_OVERHEAD;
+        for (key, value) in value {
+            size += value_size(&key)? + value_size(&value)?;
+        }
+        Ok(size)
+    } else if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+        let mut size = VALUE_OVERHEAD;
+        for value in value.iter()? {
+            size += value_size(&value?)?;
+        }
+        Ok(size)
+    } else {
+        // Encoding fails on anything else anyway
+        Ok(VALUE_OVERHEAD)
+    }
+}
@@ -0,0 +1,94 @@
+// Writers for JSON documents embedded in trace payloads. Output follows `json.dumps` with its
+// default settings, i.e. `", "` and `": "` separators and non-ASCII characters escaped, so that
+// tags holding JSON are the same whichever encoder produced them. `write_escaped` is also used for
+// intake payloads, which are sent as UTF-8.
+
+use pyo3::exceptions::PyTypeError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyList, PyLong, PyString, PyTuple};
+
+pub fn write_str(buf: &mut String, value: &str) {
+    write_escaped(buf, value, true);
+}
+
+/// Writes a string, escaping non-ASCII characters as well if `ascii` is set.
+pub fn write_escaped(buf: &mut String, value: &str, ascii: bool) {
+    buf.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            '\u{08}' => buf.push_str("\\b"),
+            '\u{0c}' => buf.push_str("\\f"),
+            ' '..='~' => buf.push(c),
+            _ if !ascii && c > '~' => buf.push(c),
+            _ => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    buf.push_str(&format!("\\u{:04x}", unit));
+                }
+            }
+        }
+    }
+    buf.push('"');
+}
+
+pub fn write_key(buf: &mut String, key: &str) {
+    write_str(buf, key);
+    buf.push_str(": ");
+}
+
+/// Writes the separator that goes before the item at `index` of an array or object.
+pub fn write_separator(buf: &mut String, index: usize) {
+    if index > 0 {
+        buf.push_str(", ");
+    }
+}
+
+/// Writes a Python object the way `json.dumps` would, for the types that can be found in span
+/// fields: None, bool, int, float, str, list, tuple and dicts with string keys.
+pub fn write_value(buf: &mut String, value: &Bound<'_, PyAny>) -> PyResult<()> {
+    if value.is_none() {
+        buf.push_str("null");
+    } else if let Ok(value) = value.downcast::<PyBool>() {
+        buf.push_str(if value.is_true() { "true" } else { "false" });
+    } else if value.is_instance_of::<PyLong>() {
+        buf.push_str(value.str()?.to_str()?);
+    } else if let Ok(value) = value.downcast::<PyFloat>() {
+        let float = value.value();
+        if float.is_nan() {
+            buf.push_str("NaN");
+        } else if float.is_infinite() {
+            buf.push_str(if float > 0.0 { "Infinity" } else { "-Infinity" });
+        } else {
+            // Python's shortest repr, which differs from Rust's for exponents
+            buf.push_str(value.repr()?.to_str()?);
+        }
+    } else if let Ok(value) = value.downcast::<PyString>() {
+        write_str(buf, value.to_str()?);
+    } else if let Ok(value) = value.downcast::<PyDict>() {
+        buf.push('{');
+        for (index, (key, value)) in value.iter().enumerate() {
+            write_separator(buf, index);
+            write_key(buf, key.downcast::<PyString>()?.to_str()?);
+            write_value(buf, &value)?;
+        }
+        buf.push('}');
+    } else if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+        buf.push('[');
+        for (index, value) in value.iter()?.enumerate() {
+            write_separator(buf, index);
+            write_value(buf, &value?)?;
+        }
+        buf.push(']');
+    } else {
+        return Err(PyTypeError::new_err(format!(
+            "Object of type {} is not JSON serializable",
+            value.get_type().name()?
+        )));
+    }
+    Ok(())
+}
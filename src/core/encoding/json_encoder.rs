@@ -0,0 +1,252 @@
+use std::time::Instant;
+
+use pyo3::exceptions::{PyOverflowError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{
+    PyBool, PyByteArray, PyBytes, PyDict, PyFloat, PyList, PyLong, PyString, PyTuple,
+};
+
+use super::buffer::{self, Limits, OverflowPy};
+use super::compression::CompressionPy;
+use super::json;
+use super::stats::{EncoderStatsPy, Stats};
+use super::utf8::sanitized;
+
+/// Same nesting limit as the msgpack packer.
+const MAX_DEPTH: usize = 511;
+
+/// Buffers items, e.g. span or test event dicts, into a JSON array for the intakes that take JSON
+/// bodies (agentless logs, EVP proxy, CI Visibility).
+///
+/// Unlike `json.dumps`, strings are written as UTF-8 and invalid sequences in them, such as lone
+/// surrogates, are replaced with U+FFFD instead of failing the whole payload. Non-finite floats,
+/// which aren't valid JSON, are written as `null` and integers have to fit in 64 bits.
+#[pyclass(name = "JsonEncoder", module = "ddtrace.internal.core._core")]
+pub struct JsonEncoderPy {
+    /// Comma separated items, without the enclosing brackets.
+    items: String,
+    count: usize,
+    limits: Limits,
+    compression: CompressionPy,
+    stats: Stats,
+}
+
+fn write_value(buf: &mut String, value: &Bound<'_, PyAny>, depth: usize) -> PyResult<()> {
+    if depth > MAX_DEPTH {
+        return Err(PyValueError::new_err("recursion limit exceeded"));
+    }
+    if value.is_none() {
+        buf.push_str("null");
+    } else if let Ok(value) = value.downcast::<PyBool>() {
+        buf.push_str(if value.is_true() { "true" } else { "false" });
+    } else if value.is_instance_of::<PyLong>() {
+        write_int(buf, value)?;
+    } else if let Ok(value) = value.downcast::<PyFloat>() {
+        write_float(buf, value.value());
+    } else if let Ok(value) = value.downcast::<PyString>() {
+        json::write_escaped(buf, &sanitized(value)?, false);
+    } else if let Ok(value) = value.downcast::<PyBytes>() {
+        json::write_escaped(buf, &String::from_utf8_lossy(value.as_bytes()), false);
+    } else if let Ok(value) = value.downcast::<PyByteArray>() {
+        json::write_escaped(buf, &String::from_utf8_lossy(&value.to_vec()), false);
+    } else if let Ok(value) = value.downcast::<PyDict>() {
+        buf.push('{');
+        for (index, (key, value)) in value.iter().enumerate() {
+            if index > 0 {
+                buf.push(',');
+            }
+            write_key(buf, &key)?;
+            buf.push(':');
+            write_value(buf, &value, depth + 1)?;
+        }
+        buf.push('}');
+    } else if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+        buf.push('[');
+        for (index, value) in value.iter()?.enumerate() {
+            if index > 0 {
+                buf.push(',');
+            }
+            write_value(buf, &value?, depth + 1)?;
+        }
+        buf.push(']');
+    } else {
+        return Err(PyTypeError::new_err(format!(
+            "Object of type {} is not JSON serializable",
+            value.get_type().name()?
+        )));
+    }
+    Ok(())
+}
+
+/// Keys that aren't strings are converted like `json.dumps` does.
+fn write_key(buf: &mut String, key: &Bound<'_, PyAny>) -> PyResult<()> {
+    if let Ok(key) = key.downcast::<PyString>() {
+        json::write_escaped(buf, &sanitized(key)?, false);
+        return Ok(());
+    }
+    let mut key_text = String::new();
+    if key.is_none() || key.is_instance_of::<PyBool>() || key.is_instance_of::<PyLong>() {
+        write_value(&mut key_text, key, 0)?;
+    } else if let Ok(key) = key.downcast::<PyFloat>() {
+        write_float(&mut key_text, key.value());
+    } else {
+        return Err(PyTypeError::new_err(format!(
+            "keys must be str, int, float, bool or None, not {}",
+            key.get_type().name()?
+        )));
+    }
+    json::write_escaped(buf, &key_text, false);
+    Ok(())
+}
+
+fn write_int(buf: &mut String, value: &Bound<'_, PyAny>) -> PyResult<()> {
+    if let Ok(value) = value.extract::<i64>() {
+        buf.push_str(&value.to_string());
+    } else {
+        let value = value
+            .extract::<u64>()
+            .map_err(|_| PyOverflowError::new_err("Integer value out of range"))?;
+        buf.push_str(&value.to_string());
+    }
+    Ok(())
+}
+
+fn write_float(buf: &mut String, value: f64) {
+    if value.is_finite() {
+        // The debug format is the shortest that round-trips and switches to exponents for large
+        // and small values, e.g. `1e16`
+        buf.push_str(&format!("{:?}", value));
+    } else {
+        buf.push_str("null");
+    }
+}
+
+impl JsonEncoderPy {
+    fn payload_size(&self) -> usize {
+        self.items.len() + 2
+    }
+
+    fn take_payload<'py>(&mut self, py: Python<'py>) -> PyResult<(Bound<'py, PyBytes>, usize)> {
+        let mut payload = Vec::with_capacity(self.payload_size());
+        payload.push(b'[');
+        payload.extend_from_slice(self.items.as_bytes());
+        payload.push(b']');
+
+        let count = self.count;
+        self.items.clear();
+        self.count = 0;
+        let compression = self.compression;
+        let payload = py.allow_threads(|| compression.compress(payload))?;
+        self.stats.record_payload(payload.len());
+        Ok((PyBytes::new_bound(py, &payload), count))
+    }
+}
+
+#[pymethods]
+impl JsonEncoderPy {
+    #[new]
+    #[pyo3(signature = (
+        max_size = buffer::DEFAULT_MAX_SIZE,
+        max_item_size = None,
+        on_drop = None,
+        compression = CompressionPy::None
+    ))]
+    fn new(
+        max_size: usize,
+        max_item_size: Option<usize>,
+        on_drop: Option<PyObject>,
+        compression: CompressionPy,
+    ) -> PyResult<Self> {
+        Ok(JsonEncoderPy {
+            items: String::new(),
+            count: 0,
+            limits: Limits::new(max_size, max_item_size, on_drop)?,
+            compression,
+            stats: Stats::default(),
+        })
+    }
+
+    #[classattr]
+    fn content_type() -> &'static str {
+        "application/json"
+    }
+
+    fn __len__(&self) -> usize {
+        self.count
+    }
+
+    /// Size in bytes of the payload that would be returned by `flush()`, before compression.
+    #[getter]
+    fn size(&self) -> usize {
+        self.payload_size()
+    }
+
+    #[getter]
+    fn max_size(&self) -> usize {
+        self.limits.max_size()
+    }
+
+    #[getter]
+    fn max_item_size(&self) -> usize {
+        self.limits.max_item_size()
+    }
+
+    #[getter]
+    fn compression(&self) -> CompressionPy {
+        self.compression
+    }
+
+    /// Value of the `Content-Encoding` header to send along with the payloads, if compressed.
+    #[getter]
+    fn content_encoding(&self) -> Option<&'static str> {
+        self.compression.content_encoding()
+    }
+
+    /// Counters of the traces and payloads handled so far, reset afterwards if `reset` is set.
+    #[pyo3(signature = (reset = false))]
+    fn stats(&mut self, reset: bool) -> EncoderStatsPy {
+        self.stats.snapshot(reset)
+    }
+
+    /// Adds an item to the array. An item that doesn't fit is dropped and its overflow returned,
+    /// with `spans` set to 1, and nothing is buffered if it fails to encode.
+    fn put(&mut self, py: Python<'_>, item: &Bound<'_, PyAny>) -> PyResult<Option<Py<OverflowPy>>> {
+        let started = Instant::now();
+        let size_before = self.payload_size();
+        let items_len = self.items.len();
+        if self.count > 0 {
+            self.items.push(',');
+        }
+        let overflow = write_value(&mut self.items, item, 0).and_then(|()| {
+            let payload_size = self.payload_size();
+            self.limits
+                .check(py, 1, payload_size - size_before, payload_size)
+        });
+        let result = match overflow {
+            Ok(None) => {
+                self.count += 1;
+                Ok(None)
+            }
+            result => {
+                self.items.truncate(items_len);
+                result
+            }
+        };
+        self.stats.record_put(&result, 1, started);
+        result
+    }
+
+    /// Returns the payload along with the number of items it contains and empties the buffer.
+    fn flush<'py>(&mut self, py: Python<'py>) -> PyResult<(Bound<'py, PyBytes>, usize)> {
+        self.take_payload(py)
+    }
+
+    /// Same as `flush()`, but returns `(None, 0)` when no item was buffered.
+    fn encode<'py>(&mut self, py: Python<'py>) -> PyResult<(Option<Bound<'py, PyBytes>>, usize)> {
+        if self.count == 0 {
+            return Ok((None, 0));
+        }
+        let (payload, count) = self.take_payload(py)?;
+        Ok((Some(payload), count))
+    }
+}
@@ -0,0 +1,31 @@
+mod buffer;
+mod citestcycle;
+mod compression;
+mod estimate;
+mod json;
+mod json_encoder;
+pub mod msgpack;
+mod normalize;
+mod otlp;
+mod packer;
+mod partial;
+mod protobuf;
+mod span;
+mod span_event;
+mod span_link;
+mod stats;
+mod string_table;
+mod utf8;
+mod v04;
+mod v05;
+
+pub use buffer::{OverflowPy, OverflowReasonPy};
+pub use citestcycle::CiTestCycleEncoderPy;
+pub use compression::CompressionPy;
+pub use json_encoder::JsonEncoderPy;
+pub use normalize::TagLimitsPy;
+pub use otlp::OtlpEncoderPy;
+pub use stats::EncoderStatsPy;
+pub use utf8::InvalidUtf8Py;
+pub use v04::TraceEncoderV04Py;
+pub use v05::TraceEncoderV05Py;
@@ -0,0 +1,117 @@
+// Writers for the subset of msgpack used by the trace encoders. Every writer picks the most compact
+// representation for its value, like the C packer used by `ddtrace.internal._encoding`, so that
+// both produce the same bytes for the same payload.
+
+pub fn write_nil(buf: &mut Vec<u8>) {
+    buf.push(0xc0);
+}
+
+pub fn write_bool(buf: &mut Vec<u8>, value: bool) {
+    buf.push(if value { 0xc3 } else { 0xc2 });
+}
+
+pub fn write_uint(buf: &mut Vec<u8>, value: u64) {
+    if value < 1 << 7 {
+        buf.push(value as u8);
+    } else if value < 1 << 8 {
+        buf.extend_from_slice(&[0xcc, value as u8]);
+    } else if value < 1 << 16 {
+        buf.push(0xcd);
+        buf.extend_from_slice(&(value as u16).to_be_bytes());
+    } else if value < 1 << 32 {
+        buf.push(0xce);
+        buf.extend_from_slice(&(value as u32).to_be_bytes());
+    } else {
+        buf.push(0xcf);
+        buf.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+pub fn write_int(buf: &mut Vec<u8>, value: i64) {
+    if value >= 0 {
+        write_uint(buf, value as u64);
+    } else if value >= -(1 << 5) {
+        buf.push(value as u8);
+    } else if value >= -(1 << 7) {
+        buf.extend_from_slice(&[0xd0, value as u8]);
+    } else if value >= -(1 << 15) {
+        buf.push(0xd1);
+        buf.extend_from_slice(&(value as i16).to_be_bytes());
+    } else if value >= -(1 << 31) {
+        buf.push(0xd2);
+        buf.extend_from_slice(&(value as i32).to_be_bytes());
+    } else {
+        buf.push(0xd3);
+        buf.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+pub fn write_f64(buf: &mut Vec<u8>, value: f64) {
+    buf.push(0xcb);
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+pub fn write_str(buf: &mut Vec<u8>, value: &str) {
+    let len = value.len();
+    if len < 32 {
+        buf.push(0xa0 | len as u8);
+    } else if len < 1 << 8 {
+        buf.extend_from_slice(&[0xd9, len as u8]);
+    } else if len < 1 << 16 {
+        buf.push(0xda);
+        buf.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        buf.push(0xdb);
+        buf.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+    buf.extend_from_slice(value.as_bytes());
+}
+
+pub fn write_bin(buf: &mut Vec<u8>, value: &[u8]) {
+    let len = value.len();
+    if len < 1 << 8 {
+        buf.extend_from_slice(&[0xc4, len as u8]);
+    } else if len < 1 << 16 {
+        buf.push(0xc5);
+        buf.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        buf.push(0xc6);
+        buf.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+    buf.extend_from_slice(value);
+}
+
+pub fn write_array_len(buf: &mut Vec<u8>, len: usize) {
+    if len < 16 {
+        buf.push(0x90 | len as u8);
+    } else if len < 1 << 16 {
+        buf.push(0xdc);
+        buf.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        buf.push(0xdd);
+        buf.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+}
+
+pub fn write_map_len(buf: &mut Vec<u8>, len: usize) {
+    if len < 16 {
+        buf.push(0x80 | len as u8);
+    } else if len < 1 << 16 {
+        buf.push(0xde);
+        buf.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        buf.push(0xdf);
+        buf.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+}
+
+/// Number of bytes taken by the header of an array (or map) of `len` items.
+pub fn array_header_size(len: usize) -> usize {
+    if len < 16 {
+        1
+    } else if len < 1 << 16 {
+        3
+    } else {
+        5
+    }
+}
@@ -0,0 +1,215 @@
+use std::borrow::Cow;
+
+use pyo3::prelude::*;
+use pyo3::types::PyString;
+
+use super::utf8::{sanitized, InvalidUtf8Py};
+
+/// Suffix of truncated tag keys and values, like the agent's.
+const TRUNCATED_SUFFIX: &str = "...";
+
+/// Length limits applied to spans while they are encoded. Defaults are the ones of the agent's
+/// truncator, which would otherwise trim oversized spans after receiving them. Lengths are in
+/// bytes of UTF-8.
+#[pyclass(frozen, name = "TagLimits", module = "ddtrace.internal.core._core")]
+#[derive(Clone)]
+pub struct TagLimitsPy {
+    max_key_length: usize,
+    max_value_length: usize,
+    max_resource_length: usize,
+    max_meta_entries: Option<usize>,
+}
+
+impl Default for TagLimitsPy {
+    fn default() -> Self {
+        TagLimitsPy {
+            max_key_length: 200,
+            max_value_length: 25_000,
+            max_resource_length: 5_000,
+            max_meta_entries: None,
+        }
+    }
+}
+
+#[pymethods]
+impl TagLimitsPy {
+    #[new]
+    #[pyo3(signature = (
+        max_key_length = 200,
+        max_value_length = 25_000,
+        max_resource_length = 5_000,
+        max_meta_entries = None
+    ))]
+    fn new(
+        max_key_length: usize,
+        max_value_length: usize,
+        max_resource_length: usize,
+        max_meta_entries: Option<usize>,
+    ) -> Self {
+        TagLimitsPy {
+            max_key_length,
+            max_value_length,
+            max_resource_length,
+            max_meta_entries,
+        }
+    }
+
+    /// Tag and metric keys longer than this are truncated, with a `...` suffix.
+    #[getter]
+    fn max_key_length(&self) -> usize {
+        self.max_key_length
+    }
+
+    /// Tag values longer than this are truncated, with a `...` suffix.
+    #[getter]
+    fn max_value_length(&self) -> usize {
+        self.max_value_length
+    }
+
+    #[getter]
+    fn max_resource_length(&self) -> usize {
+        self.max_resource_length
+    }
+
+    /// Tags past this number are dropped from `meta`, if set.
+    #[getter]
+    fn max_meta_entries(&self) -> Option<usize> {
+        self.max_meta_entries
+    }
+}
+
+/// Returns at most `max_len` bytes of `text`, cut at a character boundary and followed by
+/// `suffix` when truncated.
+fn truncated<'a>(text: Cow<'a, str>, max_len: usize, suffix: &str) -> Cow<'a, str> {
+    if text.len() <= max_len {
+        return text;
+    }
+    let mut len = max_len;
+    while !text.is_char_boundary(len) {
+        len -= 1;
+    }
+    let mut truncated = String::with_capacity(len + suffix.len());
+    truncated.push_str(&text[..len]);
+    truncated.push_str(suffix);
+    Cow::Owned(truncated)
+}
+
+/// Part of a tag a string is for, which picks its length limit.
+#[derive(Clone, Copy)]
+pub enum TagPart {
+    Key,
+    Value,
+}
+
+/// Applies an [`InvalidUtf8Py`] policy and [`TagLimitsPy`] to the strings of spans, and counts the
+/// strings and tags that had to be fixed so that the encoders can report them.
+pub struct TagNormalizer {
+    invalid_utf8: InvalidUtf8Py,
+    limits: TagLimitsPy,
+    invalid: u64,
+    truncated: u64,
+    dropped: u64,
+}
+
+/// Strings and tags fixed by a [`TagNormalizer`].
+pub struct Fixes {
+    pub invalid_strings: u64,
+    pub truncated_strings: u64,
+    pub dropped_tags: u64,
+}
+
+impl TagNormalizer {
+    pub fn new(invalid_utf8: InvalidUtf8Py, limits: Option<TagLimitsPy>) -> Self {
+        TagNormalizer {
+            invalid_utf8,
+            limits: limits.unwrap_or_default(),
+            invalid: 0,
+            truncated: 0,
+            dropped: 0,
+        }
+    }
+
+    pub fn invalid_utf8(&self) -> InvalidUtf8Py {
+        self.invalid_utf8
+    }
+
+    pub fn limits(&self) -> TagLimitsPy {
+        self.limits.clone()
+    }
+
+    /// Returns the string of a tag key or value as UTF-8, truncated if it is too long, or `None`
+    /// if the tag has to be dropped. `None` strings are kept as is. Strings are borrowed unless
+    /// they had to be fixed.
+    pub fn tag<'a>(
+        &mut self,
+        text: Option<&'a Bound<'_, PyString>>,
+        part: TagPart,
+    ) -> PyResult<Option<Option<Cow<'a, str>>>> {
+        let Some(text) = text else {
+            return Ok(Some(None));
+        };
+        let text = match text.to_str() {
+            Ok(text) => Cow::Borrowed(text),
+            Err(err) => {
+                self.invalid += 1;
+                match self.invalid_utf8 {
+                    InvalidUtf8Py::Replace => sanitized(text)?,
+                    InvalidUtf8Py::DropTag => {
+                        self.dropped += 1;
+                        return Ok(None);
+                    }
+                    InvalidUtf8Py::Error => return Err(err),
+                }
+            }
+        };
+        let max_len = match part {
+            TagPart::Key => self.limits.max_key_length,
+            TagPart::Value => self.limits.max_value_length,
+        };
+        Ok(Some(Some(self.truncate(text, max_len, TRUNCATED_SUFFIX))))
+    }
+
+    /// Returns the resource of a span, truncated if it is too long.
+    pub fn resource<'a>(
+        &mut self,
+        text: Option<&'a Bound<'_, PyString>>,
+    ) -> PyResult<Option<Cow<'a, str>>> {
+        let Some(text) = text else {
+            return Ok(None);
+        };
+        let max_len = self.limits.max_resource_length;
+        Ok(Some(self.truncate(
+            Cow::Borrowed(text.to_str()?),
+            max_len,
+            "",
+        )))
+    }
+
+    /// Returns whether a tag can be added to a `meta` that has `len` of them already, counting it
+    /// as dropped otherwise.
+    pub fn allow_meta_entry(&mut self, len: usize) -> bool {
+        match self.limits.max_meta_entries {
+            Some(max_entries) if len >= max_entries => {
+                self.dropped += 1;
+                false
+            }
+            _ => true,
+        }
+    }
+
+    fn truncate<'a>(&mut self, text: Cow<'a, str>, max_len: usize, suffix: &str) -> Cow<'a, str> {
+        if text.len() > max_len {
+            self.truncated += 1;
+        }
+        truncated(text, max_len, suffix)
+    }
+
+    /// Returns the fixes made since the last call.
+    pub fn take_fixes(&mut self) -> Fixes {
+        Fixes {
+            invalid_strings: std::mem::take(&mut self.invalid),
+            truncated_strings: std::mem::take(&mut self.truncated),
+            dropped_tags: std::mem::take(&mut self.dropped),
+        }
+    }
+}
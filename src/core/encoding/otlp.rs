@@ -0,0 +1,501 @@
+use std::time::Instant;
+
+use pyo3::exceptions::{PyOverflowError, PyTypeError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyBytes, PyDict, PyFloat, PyList, PyLong, PyString, PyTuple};
+
+use super::buffer::{self, Limits, OverflowPy};
+use super::compression::CompressionPy;
+use super::protobuf;
+use super::span::{text, Number, Span};
+use super::span_event::SpanEvent;
+use super::span_link::SpanLink;
+use super::stats::{EncoderStatsPy, Stats};
+
+// Field numbers of the OTLP messages, from opentelemetry/proto/{trace,common,resource}/v1.
+const REQUEST_RESOURCE_SPANS: u32 = 1;
+const RESOURCE_SPANS_RESOURCE: u32 = 1;
+const RESOURCE_SPANS_SCOPE_SPANS: u32 = 2;
+const RESOURCE_ATTRIBUTES: u32 = 1;
+const SCOPE_SPANS_SCOPE: u32 = 1;
+const SCOPE_SPANS_SPANS: u32 = 2;
+const SCOPE_NAME: u32 = 1;
+const SCOPE_VERSION: u32 = 2;
+const SPAN_TRACE_ID: u32 = 1;
+const SPAN_SPAN_ID: u32 = 2;
+const SPAN_PARENT_SPAN_ID: u32 = 4;
+const SPAN_NAME: u32 = 5;
+const SPAN_KIND: u32 = 6;
+const SPAN_START_TIME: u32 = 7;
+const SPAN_END_TIME: u32 = 8;
+const SPAN_ATTRIBUTES: u32 = 9;
+const SPAN_EVENTS: u32 = 11;
+const SPAN_LINKS: u32 = 13;
+const SPAN_STATUS: u32 = 15;
+const EVENT_TIME: u32 = 1;
+const EVENT_NAME: u32 = 2;
+const EVENT_ATTRIBUTES: u32 = 3;
+const LINK_TRACE_ID: u32 = 1;
+const LINK_SPAN_ID: u32 = 2;
+const LINK_TRACE_STATE: u32 = 3;
+const LINK_ATTRIBUTES: u32 = 4;
+const LINK_DROPPED_ATTRIBUTES_COUNT: u32 = 5;
+const LINK_FLAGS: u32 = 6;
+const STATUS_MESSAGE: u32 = 2;
+const STATUS_CODE: u32 = 3;
+const KEY_VALUE_KEY: u32 = 1;
+const KEY_VALUE_VALUE: u32 = 2;
+const ANY_VALUE_STRING: u32 = 1;
+const ANY_VALUE_BOOL: u32 = 2;
+const ANY_VALUE_INT: u32 = 3;
+const ANY_VALUE_DOUBLE: u32 = 4;
+const ANY_VALUE_ARRAY: u32 = 5;
+const ANY_VALUE_KVLIST: u32 = 6;
+const ANY_VALUE_BYTES: u32 = 7;
+const ARRAY_VALUES: u32 = 1;
+const KVLIST_VALUES: u32 = 1;
+
+const SPAN_KIND_INTERNAL: u64 = 1;
+const STATUS_CODE_ERROR: u64 = 2;
+
+/// Tag holding the highest 64 bits of 128-bit trace ids, in hex.
+const TRACE_ID_HIGH_TAG: &str = "_dd.p.tid";
+
+/// Buffers spans as an OTLP `ExportTraceServiceRequest`, in its protobuf encoding.
+///
+/// Traces are lists of spans, given as dicts or in the compact tuple form. Spans are grouped into
+/// one resource per service, tagged with `service.name` and the `resource_attributes` given to the
+/// constructor. Tags and metrics become span attributes, along with `resource.name` and
+/// `span.type`, `span.kind` gives the span kind and the `error.message` tag the status message of
+/// spans in error.
+#[pyclass(name = "OtlpEncoder", module = "ddtrace.internal.core._core")]
+pub struct OtlpEncoderPy {
+    /// Encoded `ScopeSpans.spans` fields grouped by service, in order of first appearance.
+    services: Vec<(Option<String>, Vec<u8>)>,
+    /// Encoded `Resource.attributes` fields shared by every resource.
+    resource_attributes: Vec<u8>,
+    /// Encoded `InstrumentationScope`.
+    scope: Vec<u8>,
+    count: usize,
+    limits: Limits,
+    compression: CompressionPy,
+    stats: Stats,
+}
+
+fn write_key_value<F>(buf: &mut Vec<u8>, field: u32, key: &str, write_value: F) -> PyResult<()>
+where
+    F: FnOnce(&mut Vec<u8>) -> PyResult<()>,
+{
+    protobuf::write_message(buf, field, |buf| {
+        protobuf::write_string(buf, KEY_VALUE_KEY, key);
+        protobuf::write_message(buf, KEY_VALUE_VALUE, write_value)
+    })
+}
+
+fn write_string_attribute(buf: &mut Vec<u8>, field: u32, key: &str, value: &str) -> PyResult<()> {
+    write_key_value(buf, field, key, |buf| {
+        protobuf::write_string(buf, ANY_VALUE_STRING, value);
+        Ok(())
+    })
+}
+
+/// Writes the fields of an `AnyValue` holding a Python value.
+fn write_any_value(buf: &mut Vec<u8>, value: &Bound<'_, PyAny>) -> PyResult<()> {
+    if let Ok(value) = value.downcast::<PyString>() {
+        protobuf::write_string(buf, ANY_VALUE_STRING, value.to_str()?);
+    } else if let Ok(value) = value.downcast::<PyBool>() {
+        protobuf::write_bool(buf, ANY_VALUE_BOOL, value.is_true());
+    } else if value.is_instance_of::<PyLong>() {
+        let value = value
+            .extract::<i64>()
+            .map_err(|_| PyOverflowError::new_err("Integer value out of range"))?;
+        protobuf::write_int(buf, ANY_VALUE_INT, value);
+    } else if let Ok(value) = value.downcast::<PyFloat>() {
+        protobuf::write_double(buf, ANY_VALUE_DOUBLE, value.value());
+    } else if let Ok(value) = value.downcast::<PyBytes>() {
+        protobuf::write_bytes(buf, ANY_VALUE_BYTES, value.as_bytes());
+    } else if let Ok(value) = value.downcast::<PyDict>() {
+        protobuf::write_message(buf, ANY_VALUE_KVLIST, |buf| {
+            for (key, value) in value {
+                let key = key.downcast::<PyString>()?;
+                write_key_value(buf, KVLIST_VALUES, key.to_str()?, |buf| {
+                    write_any_value(buf, &value)
+                })?;
+            }
+            Ok(())
+        })?;
+    } else if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+        protobuf::write_message(buf, ANY_VALUE_ARRAY, |buf| {
+            for value in value.iter()? {
+                let value = value?;
+                protobuf::write_message(buf, ARRAY_VALUES, |buf| write_any_value(buf, &value))?;
+            }
+            Ok(())
+        })?;
+    } else if !value.is_none() {
+        return Err(PyTypeError::new_err(format!(
+            "Unhandled attribute type: {}",
+            value.get_type().name()?
+        )));
+    }
+    Ok(())
+}
+
+fn write_attributes(buf: &mut Vec<u8>, field: u32, attributes: &Bound<'_, PyDict>) -> PyResult<()> {
+    for (key, value) in attributes {
+        let key = key.downcast::<PyString>()?;
+        write_key_value(buf, field, key.to_str()?, |buf| {
+            write_any_value(buf, &value)
+        })?;
+    }
+    Ok(())
+}
+
+fn span_kind(kind: Option<&str>) -> u64 {
+    match kind {
+        Some("server") => 2,
+        Some("client") => 3,
+        Some("producer") => 4,
+        Some("consumer") => 5,
+        _ => SPAN_KIND_INTERNAL,
+    }
+}
+
+fn tag<'py>(span: &Span<'py>, key: &str) -> PyResult<Option<Bound<'py, PyString>>> {
+    match &span.meta {
+        None => Ok(None),
+        Some(meta) => text(meta.get_item(key)?),
+    }
+}
+
+fn write_span(buf: &mut Vec<u8>, span: &Span<'_>) -> PyResult<()> {
+    let trace_id_high = match tag(span, TRACE_ID_HIGH_TAG)? {
+        Some(high) => u64::from_str_radix(high.to_str()?, 16).unwrap_or(0),
+        None => 0,
+    };
+    let trace_id = u128::from(trace_id_high) << 64 | u128::from(span.trace_id);
+    protobuf::write_bytes(buf, SPAN_TRACE_ID, &trace_id.to_be_bytes());
+    protobuf::write_bytes(buf, SPAN_SPAN_ID, &span.span_id.to_be_bytes());
+    if let Some(parent_id) = span.parent_id.filter(|&parent_id| parent_id != 0) {
+        protobuf::write_bytes(buf, SPAN_PARENT_SPAN_ID, &parent_id.to_be_bytes());
+    }
+    if let Some(name) = &span.name {
+        protobuf::write_string(buf, SPAN_NAME, name.to_str()?);
+    }
+    let kind = tag(span, "span.kind")?;
+    let kind = kind.as_ref().map(|kind| kind.to_str()).transpose()?;
+    protobuf::write_uint(buf, SPAN_KIND, span_kind(kind));
+    let start = span.start.unwrap_or(0);
+    protobuf::write_fixed64(buf, SPAN_START_TIME, start as u64);
+    protobuf::write_fixed64(
+        buf,
+        SPAN_END_TIME,
+        (start + span.duration.unwrap_or(0)) as u64,
+    );
+
+    if let Some(resource) = &span.resource {
+        write_string_attribute(buf, SPAN_ATTRIBUTES, "resource.name", resource.to_str()?)?;
+    }
+    if let Some(span_type) = &span.span_type {
+        write_string_attribute(buf, SPAN_ATTRIBUTES, "span.type", span_type.to_str()?)?;
+    }
+    if let Some(meta) = &span.meta {
+        for (key, value) in meta {
+            if let (Some(key), Some(value)) = (text(Some(key))?, text(Some(value))?) {
+                write_string_attribute(buf, SPAN_ATTRIBUTES, key.to_str()?, value.to_str()?)?;
+            }
+        }
+    }
+    if let Some(metrics) = &span.metrics {
+        for (key, value) in metrics {
+            let Some(key) = text(Some(key))? else {
+                continue;
+            };
+            if value.is_none() {
+                continue;
+            }
+            let value = value.extract::<Number>()?;
+            write_key_value(buf, SPAN_ATTRIBUTES, key.to_str()?, |buf| {
+                match value {
+                    Number::Int(value) => protobuf::write_int(buf, ANY_VALUE_INT, value),
+                    Number::UInt(value) => {
+                        protobuf::write_double(buf, ANY_VALUE_DOUBLE, value as f64)
+                    }
+                    Number::Float(value) => protobuf::write_double(buf, ANY_VALUE_DOUBLE, value),
+                }
+                Ok(())
+            })?;
+        }
+    }
+
+    for event in &span.span_events {
+        protobuf::write_message(buf, SPAN_EVENTS, |buf| write_event(buf, event))?;
+    }
+    for link in &span.span_links {
+        protobuf::write_message(buf, SPAN_LINKS, |buf| write_link(buf, link))?;
+    }
+    if span.error != 0 {
+        let message = tag(span, "error.message")?;
+        protobuf::write_message(buf, SPAN_STATUS, |buf| {
+            if let Some(message) = &message {
+                protobuf::write_string(buf, STATUS_MESSAGE, message.to_str()?);
+            }
+            protobuf::write_uint(buf, STATUS_CODE, STATUS_CODE_ERROR);
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+fn write_event(buf: &mut Vec<u8>, event: &SpanEvent<'_>) -> PyResult<()> {
+    protobuf::write_fixed64(buf, EVENT_TIME, event.time_unix_nano as u64);
+    if let Some(name) = &event.name {
+        protobuf::write_string(buf, EVENT_NAME, name.to_str()?);
+    }
+    if let Some(attributes) = &event.attributes {
+        write_attributes(buf, EVENT_ATTRIBUTES, attributes)?;
+    }
+    Ok(())
+}
+
+fn write_link(buf: &mut Vec<u8>, link: &SpanLink<'_>) -> PyResult<()> {
+    protobuf::write_bytes(buf, LINK_TRACE_ID, &link.trace_id.to_be_bytes());
+    protobuf::write_bytes(buf, LINK_SPAN_ID, &link.span_id.to_be_bytes());
+    if let Some(tracestate) = &link.tracestate {
+        protobuf::write_string(buf, LINK_TRACE_STATE, tracestate.to_str()?);
+    }
+    if let Some(attributes) = &link.attributes {
+        write_attributes(buf, LINK_ATTRIBUTES, attributes)?;
+    }
+    if let Some(count) = link.dropped_attributes_count.filter(|&count| count > 0) {
+        protobuf::write_uint(buf, LINK_DROPPED_ATTRIBUTES_COUNT, count as u64);
+    }
+    if let Some(flags) = link.flags {
+        protobuf::write_fixed32(buf, LINK_FLAGS, flags);
+    }
+    Ok(())
+}
+
+/// Encodes every span of a trace along with its service, which picks the resource it goes to.
+fn encode_spans(trace: &[Span<'_>]) -> PyResult<Vec<(Option<String>, Vec<u8>)>> {
+    let mut encoded = Vec::with_capacity(trace.len());
+    for span in trace {
+        let service = match &span.service {
+            Some(service) => Some(service.to_str()?.to_owned()),
+            None => None,
+        };
+        let mut spans = Vec::new();
+        protobuf::write_message(&mut spans, SCOPE_SPANS_SPANS, |buf| write_span(buf, span))?;
+        encoded.push((service, spans));
+    }
+    Ok(encoded)
+}
+
+impl OtlpEncoderPy {
+    fn write_resource(&self, buf: &mut Vec<u8>, service: Option<&str>) -> PyResult<()> {
+        if let Some(service) = service {
+            write_string_attribute(buf, RESOURCE_ATTRIBUTES, "service.name", service)?;
+        }
+        buf.extend_from_slice(&self.resource_attributes);
+        Ok(())
+    }
+
+    fn write_resource_spans(
+        &self,
+        buf: &mut Vec<u8>,
+        service: Option<&str>,
+        spans: &[u8],
+    ) -> PyResult<()> {
+        protobuf::write_message(buf, REQUEST_RESOURCE_SPANS, |buf| {
+            protobuf::write_message(buf, RESOURCE_SPANS_RESOURCE, |buf| {
+                self.write_resource(buf, service)
+            })?;
+            protobuf::write_message(buf, RESOURCE_SPANS_SCOPE_SPANS, |buf| {
+                protobuf::write_bytes(buf, SCOPE_SPANS_SCOPE, &self.scope);
+                buf.extend_from_slice(spans);
+                Ok(())
+            })
+        })
+    }
+
+    fn payload_size(&self) -> PyResult<usize> {
+        let mut size = 0;
+        for (service, spans) in &self.services {
+            let mut resource = Vec::new();
+            self.write_resource(&mut resource, service.as_deref())?;
+            let scope_spans =
+                protobuf::field_size(SCOPE_SPANS_SCOPE, self.scope.len()) + spans.len();
+            let resource_spans = protobuf::field_size(RESOURCE_SPANS_RESOURCE, resource.len())
+                + protobuf::field_size(RESOURCE_SPANS_SCOPE_SPANS, scope_spans);
+            size += protobuf::field_size(REQUEST_RESOURCE_SPANS, resource_spans);
+        }
+        Ok(size)
+    }
+
+    fn service_spans(&mut self, service: Option<String>) -> &mut Vec<u8> {
+        let index = match self.services.iter().position(|(s, _)| *s == service) {
+            Some(index) => index,
+            None => {
+                self.services.push((service, Vec::new()));
+                self.services.len() - 1
+            }
+        };
+        &mut self.services[index].1
+    }
+
+    fn take_payload<'py>(&mut self, py: Python<'py>) -> PyResult<(Bound<'py, PyBytes>, usize)> {
+        let mut payload = Vec::with_capacity(self.payload_size()?);
+        for (service, spans) in &self.services {
+            self.write_resource_spans(&mut payload, service.as_deref(), spans)?;
+        }
+
+        let count = self.count;
+        self.services.clear();
+        self.count = 0;
+        let compression = self.compression;
+        let payload = py.allow_threads(|| compression.compress(payload))?;
+        self.stats.record_payload(payload.len());
+        Ok((PyBytes::new_bound(py, &payload), count))
+    }
+}
+
+#[pymethods]
+impl OtlpEncoderPy {
+    #[new]
+    #[pyo3(signature = (
+        max_size = buffer::DEFAULT_MAX_SIZE,
+        max_item_size = None,
+        on_drop = None,
+        compression = CompressionPy::None,
+        resource_attributes = None,
+        scope_name = "ddtrace",
+        scope_version = None
+    ))]
+    fn new(
+        max_size: usize,
+        max_item_size: Option<usize>,
+        on_drop: Option<PyObject>,
+        compression: CompressionPy,
+        resource_attributes: Option<&Bound<'_, PyDict>>,
+        scope_name: &str,
+        scope_version: Option<&str>,
+    ) -> PyResult<Self> {
+        let mut attributes = Vec::new();
+        if let Some(resource_attributes) = resource_attributes {
+            write_attributes(&mut attributes, RESOURCE_ATTRIBUTES, resource_attributes)?;
+        }
+        let mut scope = Vec::new();
+        protobuf::write_string(&mut scope, SCOPE_NAME, scope_name);
+        if let Some(scope_version) = scope_version {
+            protobuf::write_string(&mut scope, SCOPE_VERSION, scope_version);
+        }
+        Ok(OtlpEncoderPy {
+            services: Vec::new(),
+            resource_attributes: attributes,
+            scope,
+            count: 0,
+            limits: Limits::new(max_size, max_item_size, on_drop)?,
+            compression,
+            stats: Stats::default(),
+        })
+    }
+
+    #[classattr]
+    fn content_type() -> &'static str {
+        "application/x-protobuf"
+    }
+
+    fn __len__(&self) -> usize {
+        self.count
+    }
+
+    /// Size in bytes of the payload that would be returned by `flush()`, before compression.
+    #[getter]
+    fn size(&self) -> PyResult<usize> {
+        self.payload_size()
+    }
+
+    #[getter]
+    fn max_size(&self) -> usize {
+        self.limits.max_size()
+    }
+
+    #[getter]
+    fn max_item_size(&self) -> usize {
+        self.limits.max_item_size()
+    }
+
+    #[getter]
+    fn compression(&self) -> CompressionPy {
+        self.compression
+    }
+
+    /// Value of the `Content-Encoding` header to send along with the payloads, if compressed.
+    #[getter]
+    fn content_encoding(&self) -> Option<&'static str> {
+        self.compression.content_encoding()
+    }
+
+    /// Counters of the traces and payloads handled so far, reset afterwards if `reset` is set.
+    #[pyo3(signature = (reset = false))]
+    fn stats(&mut self, reset: bool) -> EncoderStatsPy {
+        self.stats.snapshot(reset)
+    }
+
+    /// Adds a trace to the buffer. A trace that doesn't fit is dropped and its overflow returned,
+    /// and nothing is buffered if any of its spans fails to encode.
+    fn put<'py>(
+        &mut self,
+        py: Python<'py>,
+        trace: Vec<Span<'py>>,
+    ) -> PyResult<Option<Py<OverflowPy>>> {
+        let started = Instant::now();
+        let encoded = match encode_spans(&trace) {
+            Ok(encoded) => encoded,
+            Err(err) => {
+                let result = Err(err);
+                self.stats.record_put(&result, trace.len(), started);
+                return result;
+            }
+        };
+
+        let size_before = self.payload_size()?;
+        let lens: Vec<usize> = self.services.iter().map(|(_, spans)| spans.len()).collect();
+        for (service, spans) in encoded {
+            self.service_spans(service).extend_from_slice(&spans);
+        }
+        let overflow = self.payload_size().and_then(|payload_size| {
+            self.limits
+                .check(py, trace.len(), payload_size - size_before, payload_size)
+        });
+        let result = match overflow {
+            Ok(None) => {
+                self.count += 1;
+                Ok(None)
+            }
+            result => {
+                self.services.truncate(lens.len());
+                for ((_, spans), len) in self.services.iter_mut().zip(lens) {
+                    spans.truncate(len);
+                }
+                result
+            }
+        };
+        self.stats.record_put(&result, trace.len(), started);
+        result
+    }
+
+    /// Returns the payload along with the number of traces it contains and empties the buffer.
+    fn flush<'py>(&mut self, py: Python<'py>) -> PyResult<(Bound<'py, PyBytes>, usize)> {
+        self.take_payload(py)
+    }
+
+    /// Same as `flush()`, but returns `(None, 0)` when no trace was buffered.
+    fn encode<'py>(&mut self, py: Python<'py>) -> PyResult<(Option<Bound<'py, PyBytes>>, usize)> {
+        if self.count == 0 {
+            return Ok((None, 0));
+        }
+        let (payload, count) = self.take_payload(py)?;
+        Ok((Some(payload), count))
+    }
+}
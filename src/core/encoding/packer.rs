@@ -0,0 +1,59 @@
+use pyo3::exceptions::{PyOverflowError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyByteArray, PyBytes, PyDict, PyFloat, PyList, PyLong, PyString};
+
+use super::msgpack;
+
+/// Same nesting limit as the msgpack packer.
+const MAX_DEPTH: usize = 511;
+
+/// Packs a Python object the way `ddtrace.internal._encoding.packb` does: only the exact basic
+/// types (None, bool, int, float, str, bytes, list, dict) are supported, subclasses are not.
+pub fn write_value(buf: &mut Vec<u8>, value: &Bound<'_, PyAny>) -> PyResult<()> {
+    write_nested(buf, value, 0)
+}
+
+fn write_nested(buf: &mut Vec<u8>, value: &Bound<'_, PyAny>, depth: usize) -> PyResult<()> {
+    if depth > MAX_DEPTH {
+        return Err(PyValueError::new_err("recursion limit exceeded"));
+    }
+    if value.is_none() {
+        msgpack::write_nil(buf);
+    } else if value.is_exact_instance_of::<PyLong>() {
+        if let Ok(value) = value.extract::<i64>() {
+            msgpack::write_int(buf, value);
+        } else {
+            let value = value
+                .extract::<u64>()
+                .map_err(|_| PyOverflowError::new_err("Integer value out of range"))?;
+            msgpack::write_uint(buf, value);
+        }
+    } else if let Ok(value) = value.downcast_exact::<PyFloat>() {
+        msgpack::write_f64(buf, value.value());
+    } else if let Ok(value) = value.downcast_exact::<PyBytes>() {
+        msgpack::write_bin(buf, value.as_bytes());
+    } else if let Ok(value) = value.downcast_exact::<PyByteArray>() {
+        msgpack::write_bin(buf, &value.to_vec());
+    } else if let Ok(value) = value.downcast_exact::<PyString>() {
+        msgpack::write_str(buf, value.to_str()?);
+    } else if let Ok(value) = value.downcast_exact::<PyDict>() {
+        msgpack::write_map_len(buf, value.len());
+        for (key, value) in value {
+            write_nested(buf, &key, depth + 1)?;
+            write_nested(buf, &value, depth + 1)?;
+        }
+    } else if let Ok(value) = value.downcast_exact::<PyList>() {
+        msgpack::write_array_len(buf, value.len());
+        for value in value {
+            write_nested(buf, &value, depth + 1)?;
+        }
+    } else if let Ok(value) = value.downcast::<PyBool>() {
+        msgpack::write_bool(buf, value.is_true());
+    } else {
+        return Err(PyTypeError::new_err(format!(
+            "can not serialize '{}' object",
+            value.get_type().name()?
+        )));
+    }
+    Ok(())
+}
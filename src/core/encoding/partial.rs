@@ -0,0 +1,46 @@
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use super::span::Span;
+
+/// Metric set on the first span of a partially flushed chunk, like the Python span aggregator does.
+const PARTIAL_FLUSH_METRIC: &str = "_dd.py.partial_flush";
+
+/// Splits a trace in progress into the chunk of finished spans to encode now and the spans to keep
+/// until they finish. Spans without a duration are the ones still open.
+///
+/// This follows the `DD_TRACE_PARTIAL_FLUSH_ENABLED` semantics: finished spans are flushed once the
+/// whole trace is finished, or as soon as there are at least `min_spans` of them, in which case the
+/// first span of the chunk is tagged with the size of the chunk. Otherwise the chunk is empty and
+/// the whole trace is kept.
+pub fn split<'py>(
+    trace: &[Bound<'py, PyAny>],
+    min_spans: usize,
+) -> PyResult<(Vec<Span<'py>>, Vec<Bound<'py, PyAny>>)> {
+    let mut finished = Vec::new();
+    let mut open = Vec::new();
+    for span in trace {
+        let parsed: Span<'py> = span.extract()?;
+        if parsed.duration.is_some() {
+            finished.push(parsed);
+        } else {
+            open.push(span.clone());
+        }
+    }
+
+    let partial = finished.len() >= min_spans;
+    if !open.is_empty() && !partial {
+        return Ok((Vec::new(), trace.to_vec()));
+    }
+    let chunk_len = finished.len();
+    if let (true, Some(first)) = (partial, finished.first_mut()) {
+        // Tag a copy so that the caller's metrics are left untouched
+        let metrics = match &first.metrics {
+            Some(metrics) => metrics.copy()?,
+            None => PyDict::new_bound(trace[0].py()),
+        };
+        metrics.set_item(PARTIAL_FLUSH_METRIC, chunk_len)?;
+        first.metrics = Some(metrics);
+    }
+    Ok((finished, open))
+}
@@ -0,0 +1,79 @@
+// Writers for the subset of the protobuf wire format used by the OTLP encoder. Fields are written
+// in proto3 style: callers leave out fields that hold their default value.
+
+use pyo3::prelude::*;
+
+const VARINT: u8 = 0;
+const FIXED64: u8 = 1;
+const LEN: u8 = 2;
+const FIXED32: u8 = 5;
+
+pub fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+pub fn varint_size(value: u64) -> usize {
+    (64 - (value | 1).leading_zeros() as usize).div_ceil(7)
+}
+
+fn write_tag(buf: &mut Vec<u8>, field: u32, wire_type: u8) {
+    write_varint(buf, u64::from(field) << 3 | u64::from(wire_type));
+}
+
+/// Number of bytes taken by a length-delimited field of `len` bytes.
+pub fn field_size(field: u32, len: usize) -> usize {
+    varint_size(u64::from(field) << 3) + varint_size(len as u64) + len
+}
+
+pub fn write_uint(buf: &mut Vec<u8>, field: u32, value: u64) {
+    write_tag(buf, field, VARINT);
+    write_varint(buf, value);
+}
+
+/// Writes an `int64`, which takes 10 bytes when negative.
+pub fn write_int(buf: &mut Vec<u8>, field: u32, value: i64) {
+    write_uint(buf, field, value as u64);
+}
+
+pub fn write_bool(buf: &mut Vec<u8>, field: u32, value: bool) {
+    write_uint(buf, field, u64::from(value));
+}
+
+pub fn write_fixed64(buf: &mut Vec<u8>, field: u32, value: u64) {
+    write_tag(buf, field, FIXED64);
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_fixed32(buf: &mut Vec<u8>, field: u32, value: u32) {
+    write_tag(buf, field, FIXED32);
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_double(buf: &mut Vec<u8>, field: u32, value: f64) {
+    write_fixed64(buf, field, value.to_bits());
+}
+
+pub fn write_bytes(buf: &mut Vec<u8>, field: u32, value: &[u8]) {
+    write_tag(buf, field, LEN);
+    write_varint(buf, value.len() as u64);
+    buf.extend_from_slice(value);
+}
+
+pub fn write_string(buf: &mut Vec<u8>, field: u32, value: &str) {
+    write_bytes(buf, field, value.as_bytes());
+}
+
+/// Writes an embedded message, whose fields are written by `write`.
+pub fn write_message<F>(buf: &mut Vec<u8>, field: u32, write: F) -> PyResult<()>
+where
+    F: FnOnce(&mut Vec<u8>) -> PyResult<()>,
+{
+    let mut message = Vec::new();
+    write(&mut message)?;
+    write_bytes(buf, field, &message);
+    Ok(())
+}
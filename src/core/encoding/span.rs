@@ -0,0 +1,209 @@
+use pyo3::exceptions::{PyOverflowError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyFloat, PyLong, PyString, PyTuple};
+
+use super::msgpack;
+use super::span_event::SpanEvent;
+use super::span_link::SpanLink;
+
+/// Number of fields in the compact tuple form of a span, which follows the v0.5 field order:
+/// `(service, name, resource, trace_id, span_id, parent_id, start, duration, error, meta,
+/// metrics, type)`. Fields that v0.5 has no slot for may follow, in this order: `meta_struct`,
+/// `span_links`, `span_events`.
+const TUPLE_FIELDS: usize = 12;
+const MAX_TUPLE_FIELDS: usize = 15;
+
+/// A span as handed over by Python, either as a dict keyed like the v0.4 span fields or in the
+/// compact tuple form. Strings and tag dicts are borrowed from the Python objects and only read
+/// when the span is encoded.
+pub struct Span<'py> {
+    pub service: Option<Bound<'py, PyString>>,
+    pub name: Option<Bound<'py, PyString>>,
+    pub resource: Option<Bound<'py, PyString>>,
+    pub trace_id: u64,
+    pub span_id: u64,
+    pub parent_id: Option<u64>,
+    pub start: Option<i64>,
+    pub duration: Option<i64>,
+    pub error: i32,
+    pub meta: Option<Bound<'py, PyDict>>,
+    pub metrics: Option<Bound<'py, PyDict>>,
+    pub span_type: Option<Bound<'py, PyString>>,
+    /// Values are either msgpack encoded already, as `bytes`, or objects to encode.
+    pub meta_struct: Option<Bound<'py, PyDict>>,
+    pub span_links: Vec<SpanLink<'py>>,
+    pub span_events: Vec<SpanEvent<'py>>,
+}
+
+impl<'py> Span<'py> {
+    fn from_dict(span: &Bound<'py, PyDict>) -> PyResult<Self> {
+        Ok(Span {
+            service: text(span.get_item("service")?)?,
+            name: text(span.get_item("name")?)?,
+            resource: text(span.get_item("resource")?)?,
+            trace_id: id(span.get_item("trace_id")?)?,
+            span_id: id(span.get_item("span_id")?)?,
+            parent_id: optional_id(span.get_item("parent_id")?)?,
+            start: optional(span.get_item("start")?)?,
+            duration: optional(span.get_item("duration")?)?,
+            error: optional(span.get_item("error")?)?.unwrap_or(0),
+            meta: dict(span.get_item("meta")?)?,
+            metrics: dict(span.get_item("metrics")?)?,
+            span_type: text(span.get_item("type")?)?,
+            meta_struct: dict(span.get_item("meta_struct")?)?,
+            span_links: optional(span.get_item("span_links")?)?.unwrap_or_default(),
+            span_events: optional(span.get_item("span_events")?)?.unwrap_or_default(),
+        })
+    }
+
+    fn from_tuple(span: &Bound<'py, PyTuple>) -> PyResult<Self> {
+        if !(TUPLE_FIELDS..=MAX_TUPLE_FIELDS).contains(&span.len()) {
+            return Err(PyValueError::new_err(format!(
+                "span tuples must have {} to {} fields, got {}",
+                TUPLE_FIELDS,
+                MAX_TUPLE_FIELDS,
+                span.len()
+            )));
+        }
+        let field = |index| {
+            if index < span.len() {
+                span.get_item(index).map(Some)
+            } else {
+                Ok(None)
+            }
+        };
+        Ok(Span {
+            service: text(field(0)?)?,
+            name: text(field(1)?)?,
+            resource: text(field(2)?)?,
+            trace_id: id(field(3)?)?,
+            span_id: id(field(4)?)?,
+            parent_id: optional_id(field(5)?)?,
+            start: optional(field(6)?)?,
+            duration: optional(field(7)?)?,
+            error: optional(field(8)?)?.unwrap_or(0),
+            meta: dict(field(9)?)?,
+            metrics: dict(field(10)?)?,
+            span_type: text(field(11)?)?,
+            meta_struct: dict(field(12)?)?,
+            span_links: optional(field(13)?)?.unwrap_or_default(),
+            span_events: optional(field(14)?)?.unwrap_or_default(),
+        })
+    }
+}
+
+impl<'py> FromPyObject<'py> for Span<'py> {
+    fn extract_bound(span: &Bound<'py, PyAny>) -> PyResult<Self> {
+        if let Ok(span) = span.downcast::<PyDict>() {
+            Span::from_dict(span)
+        } else if let Ok(span) = span.downcast::<PyTuple>() {
+            Span::from_tuple(span)
+        } else {
+            Err(PyTypeError::new_err(format!(
+                "spans must be dicts or tuples, not {}",
+                span.get_type().name()?
+            )))
+        }
+    }
+}
+
+/// Returns the value unless it is missing or `None`.
+fn present(value: Option<Bound<'_, PyAny>>) -> Option<Bound<'_, PyAny>> {
+    value.filter(|value| !value.is_none())
+}
+
+pub fn text<'py>(value: Option<Bound<'py, PyAny>>) -> PyResult<Option<Bound<'py, PyString>>> {
+    match present(value) {
+        None => Ok(None),
+        Some(value) => match value.downcast_into::<PyString>() {
+            Ok(value) => Ok(Some(value)),
+            Err(e) => Err(PyTypeError::new_err(format!(
+                "Unhandled text type: {}",
+                e.into_inner().get_type().name()?
+            ))),
+        },
+    }
+}
+
+/// Span and trace ids wider than 64 bits are truncated to their lowest 64 bits, which is what the
+/// agent expects in the `trace_id` field.
+fn optional_id(value: Option<Bound<'_, PyAny>>) -> PyResult<Option<u64>> {
+    match present(value) {
+        None => Ok(None),
+        Some(value) => Ok(Some(value.extract::<u128>()? as u64)),
+    }
+}
+
+fn id(value: Option<Bound<'_, PyAny>>) -> PyResult<u64> {
+    Ok(optional_id(value)?.unwrap_or(0))
+}
+
+pub fn optional<'py, T: FromPyObject<'py>>(
+    value: Option<Bound<'py, PyAny>>,
+) -> PyResult<Option<T>> {
+    present(value).map(|value| value.extract()).transpose()
+}
+
+fn dict<'py>(value: Option<Bound<'py, PyAny>>) -> PyResult<Option<Bound<'py, PyDict>>> {
+    match present(value) {
+        None => Ok(None),
+        Some(value) => Ok(Some(value.downcast_into::<PyDict>()?)),
+    }
+}
+
+/// Writes an optional string, `None` being encoded as nil.
+pub fn write_text(buf: &mut Vec<u8>, text: Option<&Bound<'_, PyString>>) -> PyResult<()> {
+    match text {
+        None => msgpack::write_nil(buf),
+        Some(text) => msgpack::write_str(buf, text.to_str()?),
+    }
+    Ok(())
+}
+
+/// A metric value, encoded as an integer or a double depending on its Python type.
+pub enum Number {
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+}
+
+impl Number {
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        match *self {
+            Number::Int(value) => msgpack::write_int(buf, value),
+            Number::UInt(value) => msgpack::write_uint(buf, value),
+            Number::Float(value) => msgpack::write_f64(buf, value),
+        }
+    }
+}
+
+impl<'py> FromPyObject<'py> for Number {
+    fn extract_bound(value: &Bound<'py, PyAny>) -> PyResult<Self> {
+        if let Ok(value) = value.downcast::<PyFloat>() {
+            return Ok(Number::Float(value.value()));
+        }
+        if value.is_instance_of::<PyLong>() {
+            if let Ok(value) = value.extract::<i64>() {
+                return Ok(Number::Int(value));
+            }
+            return value
+                .extract::<u64>()
+                .map(Number::UInt)
+                .map_err(|_| PyOverflowError::new_err("Integer value out of range"));
+        }
+        Err(PyTypeError::new_err(format!(
+            "Unhandled numeric type: {}",
+            value.get_type().name()?
+        )))
+    }
+}
+
+/// Writes an optional metric value, `None` being encoded as nil.
+pub fn write_number(buf: &mut Vec<u8>, value: &Bound<'_, PyAny>) -> PyResult<()> {
+    if value.is_none() {
+        msgpack::write_nil(buf);
+    } else {
+        value.extract::<Number>()?.write(buf);
+    }
+    Ok(())
+}
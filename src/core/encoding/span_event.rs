@@ -0,0 +1,60 @@
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyString};
+
+use super::json;
+use super::span::{optional, text};
+
+/// Tag holding the JSON encoded events.
+pub const EVENTS_TAG: &str = "events";
+
+/// A span event, given as a dict in the `SpanEvent.__dict__()` form.
+pub struct SpanEvent<'py> {
+    pub name: Option<Bound<'py, PyString>>,
+    pub time_unix_nano: i64,
+    pub attributes: Option<Bound<'py, PyDict>>,
+}
+
+impl SpanEvent<'_> {
+    fn write_json(&self, buf: &mut String) -> PyResult<()> {
+        buf.push('{');
+        json::write_key(buf, "name");
+        match &self.name {
+            None => buf.push_str("null"),
+            Some(name) => json::write_str(buf, name.to_str()?),
+        }
+        buf.push_str(", ");
+        json::write_key(buf, "time_unix_nano");
+        buf.push_str(&self.time_unix_nano.to_string());
+        if let Some(attributes) = &self.attributes {
+            buf.push_str(", ");
+            json::write_key(buf, "attributes");
+            json::write_value(buf, attributes.as_any())?;
+        }
+        buf.push('}');
+        Ok(())
+    }
+}
+
+impl<'py> FromPyObject<'py> for SpanEvent<'py> {
+    fn extract_bound(event: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let event = event.downcast::<PyDict>()?;
+        Ok(SpanEvent {
+            name: text(event.get_item("name")?)?,
+            time_unix_nano: optional(event.get_item("time_unix_nano")?)?.unwrap_or(0),
+            attributes: optional(event.get_item("attributes")?)?,
+        })
+    }
+}
+
+/// Serializes events the way agents without native span events support expect them: as a JSON
+/// list in the `events` tag, with the same layout as `json.dumps([vars(event)() for event in
+/// events])`.
+pub fn to_json(events: &[SpanEvent<'_>]) -> PyResult<String> {
+    let mut buf = String::from("[");
+    for (index, event) in events.iter().enumerate() {
+        json::write_separator(&mut buf, index);
+        event.write_json(&mut buf)?;
+    }
+    buf.push(']');
+    Ok(buf)
+}
@@ -0,0 +1,103 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyString};
+
+use super::json;
+use super::span::{optional, text};
+
+/// A span link, given as a dict in the `SpanLink.to_dict()` form. Ids may be hex strings, as
+/// produced by `to_dict()`, or ints.
+pub struct SpanLink<'py> {
+    pub trace_id: u128,
+    pub span_id: u64,
+    pub attributes: Option<Bound<'py, PyDict>>,
+    pub dropped_attributes_count: Option<i64>,
+    pub tracestate: Option<Bound<'py, PyString>>,
+    pub flags: Option<u32>,
+}
+
+impl SpanLink<'_> {
+    /// Lowest 64 bits of the trace id.
+    pub fn trace_id_low(&self) -> u64 {
+        self.trace_id as u64
+    }
+
+    /// Highest 64 bits of the trace id, zero for 64-bit trace ids.
+    pub fn trace_id_high(&self) -> u64 {
+        (self.trace_id >> 64) as u64
+    }
+
+    fn write_json(&self, buf: &mut String) -> PyResult<()> {
+        buf.push('{');
+        json::write_key(buf, "trace_id");
+        json::write_str(buf, &format!("{:032x}", self.trace_id));
+        buf.push_str(", ");
+        json::write_key(buf, "span_id");
+        json::write_str(buf, &format!("{:016x}", self.span_id));
+        if let Some(attributes) = &self.attributes {
+            buf.push_str(", ");
+            json::write_key(buf, "attributes");
+            buf.push('{');
+            for (index, (key, value)) in attributes.iter().enumerate() {
+                json::write_separator(buf, index);
+                json::write_key(buf, key.downcast::<PyString>()?.to_str()?);
+                json::write_str(buf, value.downcast::<PyString>()?.to_str()?);
+            }
+            buf.push('}');
+        }
+        if let Some(dropped_attributes_count) = self.dropped_attributes_count {
+            buf.push_str(", ");
+            json::write_key(buf, "dropped_attributes_count");
+            buf.push_str(&dropped_attributes_count.to_string());
+        }
+        if let Some(tracestate) = &self.tracestate {
+            buf.push_str(", ");
+            json::write_key(buf, "tracestate");
+            json::write_str(buf, tracestate.to_str()?);
+        }
+        if let Some(flags) = self.flags {
+            buf.push_str(", ");
+            json::write_key(buf, "flags");
+            buf.push_str(&flags.to_string());
+        }
+        buf.push('}');
+        Ok(())
+    }
+}
+
+impl<'py> FromPyObject<'py> for SpanLink<'py> {
+    fn extract_bound(link: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let link = link.downcast::<PyDict>()?;
+        Ok(SpanLink {
+            trace_id: hex_id(link.get_item("trace_id")?)?,
+            span_id: hex_id(link.get_item("span_id")?)? as u64,
+            attributes: optional(link.get_item("attributes")?)?,
+            dropped_attributes_count: optional(link.get_item("dropped_attributes_count")?)?,
+            tracestate: text(link.get_item("tracestate")?)?,
+            flags: optional(link.get_item("flags")?)?,
+        })
+    }
+}
+
+fn hex_id(value: Option<Bound<'_, PyAny>>) -> PyResult<u128> {
+    match value {
+        None => Ok(0),
+        Some(value) => match value.downcast::<PyString>() {
+            Ok(value) => u128::from_str_radix(value.to_str()?, 16)
+                .map_err(|_| PyValueError::new_err(format!("invalid span link id: {}", value))),
+            Err(_) => value.extract(),
+        },
+    }
+}
+
+/// Serializes links the way the v0.5 format carries them: as a JSON list in the `_dd.span_links`
+/// tag, with the same layout as `json.dumps([link.to_dict() for link in links])`.
+pub fn to_json(links: &[SpanLink<'_>]) -> PyResult<String> {
+    let mut buf = String::from("[");
+    for (index, link) in links.iter().enumerate() {
+        json::write_separator(&mut buf, index);
+        link.write_json(&mut buf)?;
+    }
+    buf.push(']');
+    Ok(buf)
+}
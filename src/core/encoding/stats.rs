@@ -0,0 +1,212 @@
+use std::time::{Duration, Instant};
+
+use pyo3::prelude::*;
+
+use super::buffer::OverflowPy;
+use super::normalize::Fixes;
+
+/// Buckets of the `put()` duration histogram. Bucket `i` counts the calls that took less than
+/// `2**i` microseconds, the last one every call that took longer.
+const TIME_BUCKETS: usize = 20;
+
+/// Counters kept by an encoder over its lifetime, or since they were last reset, to track the
+/// cost of the native encoding path in health metrics and benchmarks.
+#[derive(Default)]
+pub struct Stats {
+    traces: u64,
+    spans: u64,
+    dropped_traces: u64,
+    dropped_spans: u64,
+    errors: u64,
+    invalid_strings: u64,
+    truncated_strings: u64,
+    dropped_tags: u64,
+    payloads: u64,
+    bytes: u64,
+    encode_time: Duration,
+    encode_time_buckets: [u64; TIME_BUCKETS],
+}
+
+impl Stats {
+    /// Accounts for a `put()` of `spans` spans started at `started`. Traces that failed to encode
+    /// only count as errors.
+    pub fn record_put(
+        &mut self,
+        result: &PyResult<Option<Py<OverflowPy>>>,
+        spans: usize,
+        started: Instant,
+    ) {
+        let elapsed = started.elapsed();
+        match result {
+            Ok(None) => {
+                self.traces += 1;
+                self.spans += spans as u64;
+            }
+            Ok(Some(_)) => {
+                self.dropped_traces += 1;
+                self.dropped_spans += spans as u64;
+            }
+            Err(_) => {
+                self.errors += 1;
+                return;
+            }
+        }
+        self.encode_time += elapsed;
+        let bucket = 128 - elapsed.as_micros().leading_zeros() as usize;
+        self.encode_time_buckets[bucket.min(TIME_BUCKETS - 1)] += 1;
+    }
+
+    /// Accounts for the strings and tags fixed while encoding spans.
+    pub fn record_fixes(&mut self, fixes: Fixes) {
+        self.invalid_strings += fixes.invalid_strings;
+        self.truncated_strings += fixes.truncated_strings;
+        self.dropped_tags += fixes.dropped_tags;
+    }
+
+    /// Accounts for a payload of `size` bytes returned by `flush()` or `encode()`.
+    pub fn record_payload(&mut self, size: usize) {
+        self.payloads += 1;
+        self.bytes += size as u64;
+    }
+
+    /// Returns a snapshot of the counters, and resets them if `reset` is set.
+    pub fn snapshot(&mut self, reset: bool) -> EncoderStatsPy {
+        let stats = EncoderStatsPy {
+            traces: self.traces,
+            spans: self.spans,
+            dropped_traces: self.dropped_traces,
+            dropped_spans: self.dropped_spans,
+            errors: self.errors,
+            invalid_strings: self.invalid_strings,
+            truncated_strings: self.truncated_strings,
+            dropped_tags: self.dropped_tags,
+            payloads: self.payloads,
+            bytes: self.bytes,
+            encode_time_ns: self.encode_time.as_nanos() as u64,
+            encode_time_buckets: self.encode_time_buckets,
+        };
+        if reset {
+            *self = Stats::default();
+        }
+        stats
+    }
+}
+
+/// Snapshot of the counters of an encoder, returned by its `stats()` method.
+#[pyclass(frozen, name = "EncoderStats", module = "ddtrace.internal.core._core")]
+pub struct EncoderStatsPy {
+    traces: u64,
+    spans: u64,
+    dropped_traces: u64,
+    dropped_spans: u64,
+    errors: u64,
+    invalid_strings: u64,
+    truncated_strings: u64,
+    dropped_tags: u64,
+    payloads: u64,
+    bytes: u64,
+    encode_time_ns: u64,
+    encode_time_buckets: [u64; TIME_BUCKETS],
+}
+
+#[pymethods]
+impl EncoderStatsPy {
+    /// Number of traces buffered.
+    #[getter]
+    fn traces(&self) -> u64 {
+        self.traces
+    }
+
+    /// Number of spans buffered.
+    #[getter]
+    fn spans(&self) -> u64 {
+        self.spans
+    }
+
+    /// Number of traces dropped because they didn't fit in the buffer.
+    #[getter]
+    fn dropped_traces(&self) -> u64 {
+        self.dropped_traces
+    }
+
+    #[getter]
+    fn dropped_spans(&self) -> u64 {
+        self.dropped_spans
+    }
+
+    /// Number of traces that failed to encode.
+    #[getter]
+    fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// Number of tag keys and values that weren't valid UTF-8, whether they were replaced, dropped
+    /// or failed their trace.
+    #[getter]
+    fn invalid_strings(&self) -> u64 {
+        self.invalid_strings
+    }
+
+    /// Number of tag keys and values, and resources, truncated because they were too long.
+    #[getter]
+    fn truncated_strings(&self) -> u64 {
+        self.truncated_strings
+    }
+
+    /// Number of tags left out because they weren't valid UTF-8 or `meta` had too many of them.
+    #[getter]
+    fn dropped_tags(&self) -> u64 {
+        self.dropped_tags
+    }
+
+    /// Number of payloads returned by `flush()` and `encode()`.
+    #[getter]
+    fn payloads(&self) -> u64 {
+        self.payloads
+    }
+
+    /// Size in bytes of the payloads, after compression.
+    #[getter]
+    fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Total time spent in `put()` for the traces buffered or dropped.
+    #[getter]
+    fn encode_time_ns(&self) -> u64 {
+        self.encode_time_ns
+    }
+
+    /// Histogram of the `put()` durations, as `(upper_bound_ns, count)` pairs where the upper
+    /// bound is exclusive, and `None` for the last bucket.
+    #[getter]
+    fn encode_time_histogram(&self) -> Vec<(Option<u64>, u64)> {
+        self.encode_time_buckets
+            .iter()
+            .enumerate()
+            .map(|(index, count)| {
+                let upper_bound = (index < TIME_BUCKETS - 1).then(|| 1000 << index);
+                (upper_bound, *count)
+            })
+            .collect()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "EncoderStats(traces={}, spans={}, dropped_traces={}, dropped_spans={}, errors={}, \
+             invalid_strings={}, truncated_strings={}, dropped_tags={}, \
+             payloads={}, bytes={}, encode_time_ns={})",
+            self.traces,
+            self.spans,
+            self.dropped_traces,
+            self.dropped_spans,
+            self.errors,
+            self.invalid_strings,
+            self.truncated_strings,
+            self.dropped_tags,
+            self.payloads,
+            self.bytes,
+            self.encode_time_ns
+        )
+    }
+}
@@ -0,0 +1,140 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use pyo3::prelude::*;
+use pyo3::types::PyString;
+
+use super::msgpack;
+use super::normalize::{TagNormalizer, TagPart};
+
+const ORIGIN_KEY: &str = "_dd.origin";
+
+/// The v0.5 string table: every string of the payload is written once, msgpack encoded, and spans
+/// refer to it by index. Index 0 is always the empty string and index 1 the `_dd.origin` key.
+///
+/// Strings longer than `max_string_length` bytes are replaced by a placeholder, like the Cython
+/// string table does, so that a single huge tag can't take up the whole payload.
+///
+/// Python strings are also looked up by identity first: service names, operation names and tag
+/// keys are usually the same objects from one span to the next, so this skips both the UTF-8
+/// conversion and the hashing of their contents. The table keeps a reference to these strings
+/// until it is reset, so that their address can't be reused by another string meanwhile.
+pub struct StringTable {
+    indices: HashMap<String, u32>,
+    objects: HashMap<usize, (Py<PyString>, u32)>,
+    encoded: Vec<u8>,
+    max_string_length: usize,
+}
+
+/// State of a [`StringTable`] to return to if encoding a trace fails midway.
+#[derive(Clone, Copy)]
+pub struct Savepoint {
+    len: u32,
+    encoded_len: usize,
+}
+
+impl StringTable {
+    pub fn new(max_string_length: usize) -> Self {
+        let mut table = StringTable {
+            indices: HashMap::new(),
+            objects: HashMap::new(),
+            encoded: Vec::new(),
+            max_string_length,
+        };
+        table.reset();
+        table
+    }
+
+    fn next_index(&self) -> u32 {
+        self.indices.len() as u32
+    }
+
+    /// Size of the table once encoded as a msgpack array.
+    pub fn size(&self) -> usize {
+        msgpack::array_header_size(self.indices.len()) + self.encoded.len()
+    }
+
+    pub fn index(&mut self, string: &str) -> u32 {
+        if let Some(&index) = self.indices.get(string) {
+            return index;
+        }
+        let index = self.next_index();
+        self.indices.insert(string.to_owned(), index);
+        if string.len() > self.max_string_length {
+            let placeholder = format!(
+                "<dropped string of length {} because it's too long (max allowed length {})>",
+                string.len(),
+                self.max_string_length
+            );
+            msgpack::write_str(&mut self.encoded, &placeholder);
+        } else {
+            msgpack::write_str(&mut self.encoded, string);
+        }
+        index
+    }
+
+    /// Like `index()`, with `None` standing for the empty string.
+    pub fn index_text(&mut self, text: Option<&Bound<'_, PyString>>) -> PyResult<u32> {
+        match text {
+            None => Ok(0),
+            Some(text) => {
+                let address = text.as_ptr() as usize;
+                if let Some((_, index)) = self.objects.get(&address) {
+                    return Ok(*index);
+                }
+                let index = self.index(text.to_str()?);
+                self.objects.insert(address, (text.clone().unbind(), index));
+                Ok(index)
+            }
+        }
+    }
+
+    /// Like `index_text()` for a tag key or value normalized by `normalizer`. Returns `None` if
+    /// the tag has to be dropped. Only strings that didn't need fixing are looked up by identity,
+    /// so that every use of the others is counted.
+    pub fn index_tag(
+        &mut self,
+        text: Option<&Bound<'_, PyString>>,
+        normalizer: &mut TagNormalizer,
+        part: TagPart,
+    ) -> PyResult<Option<u32>> {
+        if let Some(text) = text {
+            if let Some((_, index)) = self.objects.get(&(text.as_ptr() as usize)) {
+                return Ok(Some(*index));
+            }
+        }
+        match normalizer.tag(text, part)? {
+            None => Ok(None),
+            Some(Some(Cow::Owned(fixed))) => Ok(Some(self.index(&fixed))),
+            Some(_) => self.index_text(text).map(Some),
+        }
+    }
+
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        msgpack::write_array_len(buf, self.indices.len());
+        buf.extend_from_slice(&self.encoded);
+    }
+
+    pub fn savepoint(&self) -> Savepoint {
+        Savepoint {
+            len: self.next_index(),
+            encoded_len: self.encoded.len(),
+        }
+    }
+
+    /// Forgets every string added since `savepoint`, so that no index refers to a string that
+    /// isn't in the table anymore.
+    pub fn rollback(&mut self, savepoint: Savepoint) {
+        self.encoded.truncate(savepoint.encoded_len);
+        self.indices.retain(|_, index| *index < savepoint.len);
+        self.objects.retain(|_, (_, index)| *index < savepoint.len);
+    }
+
+    pub fn reset(&mut self) {
+        self.indices.clear();
+        self.objects.clear();
+        self.encoded.clear();
+        self.index("");
+        self.index(ORIGIN_KEY);
+    }
+}
@@ -0,0 +1,44 @@
+use std::borrow::Cow;
+
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyString};
+
+/// What the trace encoders do with tags whose key or value isn't valid UTF-8, e.g. a string with
+/// a lone surrogate decoded with `surrogateescape`.
+#[pyclass(
+    eq,
+    eq_int,
+    name = "InvalidUtf8",
+    module = "ddtrace.internal.core._core"
+)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum InvalidUtf8Py {
+    /// Replace every invalid sequence with U+FFFD.
+    #[pyo3(name = "REPLACE")]
+    Replace,
+    /// Leave the tag out of the span.
+    #[pyo3(name = "DROP_TAG")]
+    DropTag,
+    /// Fail the trace, like the Cython encoders do.
+    #[pyo3(name = "ERROR")]
+    Error,
+}
+
+/// Returns the string as is if it is valid UTF-8, or with every lone surrogate replaced with
+/// U+FFFD.
+pub fn sanitized<'a>(text: &'a Bound<'_, PyString>) -> PyResult<Cow<'a, str>> {
+    if let Ok(text) = text.to_str() {
+        return Ok(Cow::Borrowed(text));
+    }
+    let encoded = text.call_method1("encode", ("utf-16-le", "surrogatepass"))?;
+    let units = encoded
+        .downcast::<PyBytes>()?
+        .as_bytes()
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]));
+    Ok(Cow::Owned(
+        char::decode_utf16(units)
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect(),
+    ))
+}
@@ -0,0 +1,525 @@
+use std::time::Instant;
+
+use pyo3::exceptions::PyTypeError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyBytes, PyDict, PyFloat, PyList, PyLong, PyString, PyTuple};
+
+use super::buffer::{self, Limits, OverflowPy};
+use super::compression::CompressionPy;
+use super::estimate;
+use super::msgpack;
+use super::normalize::{TagLimitsPy, TagNormalizer, TagPart};
+use super::packer;
+use super::partial;
+use super::span::{text, write_number, write_text, Span};
+use super::span_event::{self, SpanEvent, EVENTS_TAG};
+use super::span_link::SpanLink;
+use super::stats::{EncoderStatsPy, Stats};
+use super::utf8::InvalidUtf8Py;
+
+/// Buffers traces in the v0.4 msgpack format, i.e. an array of traces where every span is a map
+/// keyed by field name.
+///
+/// Traces are lists of spans, given as dicts or in the compact tuple form. Output is byte for byte
+/// the same as `ddtrace.internal._encoding.MsgpackEncoderV04` for the same spans, so the writer
+/// can pick either format depending on what the agent supports.
+///
+/// Span events are encoded as JSON in the `events` tag unless `top_level_span_events` is set, in
+/// which case they go to the `span_events` field, which only newer agents understand.
+///
+/// Tag keys and values that aren't valid UTF-8 are handled as `invalid_utf8` says, replaced by
+/// default, and counted in `stats().invalid_strings`. Tags and resources longer than the agent
+/// accepts are truncated to `tag_limits`, and counted in `stats().truncated_strings`.
+#[pyclass(name = "TraceEncoderV04", module = "ddtrace.internal.core._core")]
+pub struct TraceEncoderV04Py {
+    traces: Vec<u8>,
+    count: usize,
+    limits: Limits,
+    compression: CompressionPy,
+    top_level_span_events: bool,
+    stats: Stats,
+    tags: TagNormalizer,
+}
+
+/// Encodes the entries of a tag dict, normalized and leaving out the dropped ones, and returns how
+/// many were written. `meta` entries are also subject to the limit of entries.
+fn write_tags(
+    buf: &mut Vec<u8>,
+    tags: Option<&Bound<'_, PyDict>>,
+    normalizer: &mut TagNormalizer,
+    is_meta: bool,
+    mut write_value: impl FnMut(&mut Vec<u8>, &Bound<'_, PyAny>, &mut TagNormalizer) -> PyResult<bool>,
+) -> PyResult<usize> {
+    let mut written = 0;
+    for (key, value) in tags.into_iter().flatten() {
+        if is_meta && !normalizer.allow_meta_entry(written) {
+            continue;
+        }
+        let key = text(Some(key))?;
+        let Some(key) = normalizer.tag(key.as_ref(), TagPart::Key)? else {
+            continue;
+        };
+        let len = buf.len();
+        match key {
+            None => msgpack::write_nil(buf),
+            Some(key) => msgpack::write_str(buf, &key),
+        }
+        if write_value(buf, &value, normalizer)? {
+            written += 1;
+        } else {
+            buf.truncate(len);
+        }
+    }
+    Ok(written)
+}
+
+/// Writes a meta value, returning false if the tag has to be dropped.
+fn write_meta_value(
+    buf: &mut Vec<u8>,
+    value: &Bound<'_, PyAny>,
+    normalizer: &mut TagNormalizer,
+) -> PyResult<bool> {
+    let value = text(Some(value.clone()))?;
+    match normalizer.tag(value.as_ref(), TagPart::Value)? {
+        None => Ok(false),
+        Some(None) => {
+            msgpack::write_nil(buf);
+            Ok(true)
+        }
+        Some(Some(value)) => {
+            msgpack::write_str(buf, &value);
+            Ok(true)
+        }
+    }
+}
+
+fn write_span(
+    buf: &mut Vec<u8>,
+    span: &Span<'_>,
+    top_level_events: bool,
+    normalizer: &mut TagNormalizer,
+) -> PyResult<()> {
+    let has_events = !span.span_events.is_empty();
+    let events_tag = if has_events && !top_level_events {
+        Some(span_event::to_json(&span.span_events)?)
+    } else {
+        None
+    };
+    let mut meta = Vec::new();
+    let meta_len = write_tags(
+        &mut meta,
+        span.meta.as_ref(),
+        normalizer,
+        true,
+        write_meta_value,
+    )? + usize::from(events_tag.is_some());
+    let mut metrics = Vec::new();
+    let metrics_len = write_tags(
+        &mut metrics,
+        span.metrics.as_ref(),
+        normalizer,
+        false,
+        |buf, value, _| write_number(buf, value).map(|()| true),
+    )?;
+    let meta_struct = span.meta_struct.as_ref().filter(|meta| !meta.is_empty());
+    let fields = 7
+        + usize::from(span.parent_id.is_some())
+        + usize::from(span.error != 0)
+        + usize::from(span.span_type.is_some())
+        + usize::from(meta_len > 0)
+        + usize::from(metrics_len > 0)
+        + usize::from(meta_struct.is_some())
+        + usize::from(!span.span_links.is_empty())
+        + usize::from(has_events && top_level_events);
+    msgpack::write_map_len(buf, fields);
+
+    msgpack::write_str(buf, "trace_id");
+    msgpack::write_uint(buf, span.trace_id);
+    if let Some(parent_id) = span.parent_id {
+        msgpack::write_str(buf, "parent_id");
+        msgpack::write_uint(buf, parent_id);
+    }
+    msgpack::write_str(buf, "span_id");
+    msgpack::write_uint(buf, span.span_id);
+    msgpack::write_str(buf, "service");
+    write_text(buf, span.service.as_ref())?;
+    msgpack::write_str(buf, "resource");
+    match normalizer.resource(span.resource.as_ref())? {
+        None => msgpack::write_nil(buf),
+        Some(resource) => msgpack::write_str(buf, &resource),
+    }
+    msgpack::write_str(buf, "name");
+    write_text(buf, span.name.as_ref())?;
+    msgpack::write_str(buf, "start");
+    write_optional_int(buf, span.start);
+    msgpack::write_str(buf, "duration");
+    write_optional_int(buf, span.duration);
+    if span.error != 0 {
+        msgpack::write_str(buf, "error");
+        msgpack::write_uint(buf, 1);
+    }
+    if let Some(span_type) = &span.span_type {
+        msgpack::write_str(buf, "type");
+        write_text(buf, Some(span_type))?;
+    }
+    if !span.span_links.is_empty() {
+        msgpack::write_str(buf, "span_links");
+        msgpack::write_array_len(buf, span.span_links.len());
+        for link in &span.span_links {
+            write_link(buf, link)?;
+        }
+    }
+    if meta_len > 0 {
+        msgpack::write_str(buf, "meta");
+        msgpack::write_map_len(buf, meta_len);
+        buf.extend_from_slice(&meta);
+        if let Some(events_tag) = &events_tag {
+            msgpack::write_str(buf, EVENTS_TAG);
+            msgpack::write_str(buf, events_tag);
+        }
+    }
+    if let Some(meta_struct) = meta_struct {
+        msgpack::write_str(buf, "meta_struct");
+        msgpack::write_map_len(buf, meta_struct.len());
+        let mut encoded = Vec::new();
+        for (key, value) in meta_struct {
+            write_text(buf, text(Some(key))?.as_ref())?;
+            match value.downcast::<PyBytes>() {
+                Ok(value) => msgpack::write_bin(buf, value.as_bytes()),
+                Err(_) => {
+                    encoded.clear();
+                    packer::write_value(&mut encoded, &value)?;
+                    msgpack::write_bin(buf, &encoded);
+                }
+            }
+        }
+    }
+    if has_events && top_level_events {
+        msgpack::write_str(buf, "span_events");
+        msgpack::write_array_len(buf, span.span_events.len());
+        for event in &span.span_events {
+            write_event(buf, event)?;
+        }
+    }
+    if metrics_len > 0 {
+        msgpack::write_str(buf, "metrics");
+        msgpack::write_map_len(buf, metrics_len);
+        buf.extend_from_slice(&metrics);
+    }
+    Ok(())
+}
+
+/// Links are maps keyed like `SpanLink.to_dict()`, except that ids are integers: the trace id is
+/// split into `trace_id` and `trace_id_high`, the latter being left out for 64-bit trace ids. The
+/// high bit of `flags` is set so that the agent can tell unset flags from zero.
+fn write_link(buf: &mut Vec<u8>, link: &SpanLink<'_>) -> PyResult<()> {
+    let fields = 2
+        + usize::from(link.attributes.is_some())
+        + usize::from(link.dropped_attributes_count.is_some())
+        + usize::from(link.tracestate.is_some())
+        + usize::from(link.flags.is_some())
+        + usize::from(link.trace_id_high() != 0);
+    msgpack::write_map_len(buf, fields);
+
+    msgpack::write_str(buf, "trace_id");
+    msgpack::write_uint(buf, link.trace_id_low());
+    msgpack::write_str(buf, "span_id");
+    msgpack::write_uint(buf, link.span_id);
+    if let Some(attributes) = &link.attributes {
+        msgpack::write_str(buf, "attributes");
+        msgpack::write_map_len(buf, attributes.len());
+        for (key, value) in attributes {
+            write_text(buf, text(Some(key))?.as_ref())?;
+            write_text(buf, text(Some(value))?.as_ref())?;
+        }
+    }
+    if let Some(dropped_attributes_count) = link.dropped_attributes_count {
+        msgpack::write_str(buf, "dropped_attributes_count");
+        msgpack::write_int(buf, dropped_attributes_count);
+    }
+    if let Some(tracestate) = &link.tracestate {
+        msgpack::write_str(buf, "tracestate");
+        write_text(buf, Some(tracestate))?;
+    }
+    if let Some(flags) = link.flags {
+        msgpack::write_str(buf, "flags");
+        msgpack::write_uint(buf, u64::from(flags | 1 << 31));
+    }
+    if link.trace_id_high() != 0 {
+        msgpack::write_str(buf, "trace_id_high");
+        msgpack::write_uint(buf, link.trace_id_high());
+    }
+    Ok(())
+}
+
+/// Events follow the agent's `SpanEvent` schema, where attribute values are typed unions:
+/// `{"type": <0 string, 1 bool, 2 int, 3 double, 4 array>, "<type>_value": value}`.
+fn write_event(buf: &mut Vec<u8>, event: &SpanEvent<'_>) -> PyResult<()> {
+    let attributes = event.attributes.as_ref().filter(|attrs| !attrs.is_empty());
+    msgpack::write_map_len(buf, 2 + usize::from(attributes.is_some()));
+    msgpack::write_str(buf, "name");
+    write_text(buf, event.name.as_ref())?;
+    msgpack::write_str(buf, "time_unix_nano");
+    msgpack::write_int(buf, event.time_unix_nano);
+    if let Some(attributes) = attributes {
+        msgpack::write_str(buf, "attributes");
+        msgpack::write_map_len(buf, attributes.len());
+        for (key, value) in attributes {
+            write_text(buf, text(Some(key))?.as_ref())?;
+            if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+                msgpack::write_map_len(buf, 2);
+                msgpack::write_str(buf, "type");
+                msgpack::write_uint(buf, 4);
+                msgpack::write_str(buf, "array_value");
+                msgpack::write_map_len(buf, 1);
+                msgpack::write_str(buf, "values");
+                msgpack::write_array_len(buf, value.len()?);
+                for value in value.iter()? {
+                    write_attribute_value(buf, &value?)?;
+                }
+            } else {
+                write_attribute_value(buf, &value)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_attribute_value(buf: &mut Vec<u8>, value: &Bound<'_, PyAny>) -> PyResult<()> {
+    msgpack::write_map_len(buf, 2);
+    msgpack::write_str(buf, "type");
+    if let Ok(value) = value.downcast::<PyString>() {
+        msgpack::write_uint(buf, 0);
+        msgpack::write_str(buf, "string_value");
+        msgpack::write_str(buf, value.to_str()?);
+    } else if let Ok(value) = value.downcast::<PyBool>() {
+        msgpack::write_uint(buf, 1);
+        msgpack::write_str(buf, "bool_value");
+        msgpack::write_bool(buf, value.is_true());
+    } else if value.is_instance_of::<PyLong>() {
+        msgpack::write_uint(buf, 2);
+        msgpack::write_str(buf, "int_value");
+        msgpack::write_int(buf, value.extract()?);
+    } else if let Ok(value) = value.downcast::<PyFloat>() {
+        msgpack::write_uint(buf, 3);
+        msgpack::write_str(buf, "double_value");
+        msgpack::write_f64(buf, value.value());
+    } else {
+        return Err(PyTypeError::new_err(format!(
+            "Unhandled span event attribute type: {}",
+            value.get_type().name()?
+        )));
+    }
+    Ok(())
+}
+
+fn write_optional_int(buf: &mut Vec<u8>, value: Option<i64>) {
+    match value {
+        None => msgpack::write_nil(buf),
+        Some(value) => msgpack::write_int(buf, value),
+    }
+}
+
+impl TraceEncoderV04Py {
+    fn write_trace(&mut self, trace: &[Span<'_>]) -> PyResult<()> {
+        msgpack::write_array_len(&mut self.traces, trace.len());
+        for span in trace {
+            write_span(
+                &mut self.traces,
+                span,
+                self.top_level_span_events,
+                &mut self.tags,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn payload_size(&self, count: usize) -> usize {
+        msgpack::array_header_size(count) + self.traces.len()
+    }
+
+    /// Buffers a trace, or drops it and returns its overflow if it doesn't fit. Nothing is buffered
+    /// if any of its spans fails to encode.
+    fn put_spans(
+        &mut self,
+        py: Python<'_>,
+        trace: &[Span<'_>],
+    ) -> PyResult<Option<Py<OverflowPy>>> {
+        let started = Instant::now();
+        let size_before = self.payload_size(self.count);
+        let traces_len = self.traces.len();
+        let overflow = self.write_trace(trace).and_then(|()| {
+            let payload_size = self.payload_size(self.count + 1);
+            self.limits
+                .check(py, trace.len(), payload_size - size_before, payload_size)
+        });
+        let result = match overflow {
+            Ok(None) => {
+                self.count += 1;
+                Ok(None)
+            }
+            result => {
+                self.traces.truncate(traces_len);
+                result
+            }
+        };
+        self.stats.record_put(&result, trace.len(), started);
+        self.stats.record_fixes(self.tags.take_fixes());
+        result
+    }
+
+    fn take_payload<'py>(&mut self, py: Python<'py>) -> PyResult<(Bound<'py, PyBytes>, usize)> {
+        let mut payload = Vec::with_capacity(self.payload_size(self.count));
+        msgpack::write_array_len(&mut payload, self.count);
+        payload.extend_from_slice(&self.traces);
+
+        let count = self.count;
+        self.traces.clear();
+        self.count = 0;
+        let compression = self.compression;
+        let payload = py.allow_threads(|| compression.compress(payload))?;
+        self.stats.record_payload(payload.len());
+        Ok((PyBytes::new_bound(py, &payload), count))
+    }
+}
+
+#[pymethods]
+impl TraceEncoderV04Py {
+    #[new]
+    #[pyo3(signature = (
+        max_size = buffer::DEFAULT_MAX_SIZE,
+        max_item_size = None,
+        on_drop = None,
+        top_level_span_events = false,
+        compression = CompressionPy::None,
+        invalid_utf8 = InvalidUtf8Py::Replace,
+        tag_limits = None
+    ))]
+    fn new(
+        max_size: usize,
+        max_item_size: Option<usize>,
+        on_drop: Option<PyObject>,
+        top_level_span_events: bool,
+        compression: CompressionPy,
+        invalid_utf8: InvalidUtf8Py,
+        tag_limits: Option<TagLimitsPy>,
+    ) -> PyResult<Self> {
+        Ok(TraceEncoderV04Py {
+            traces: Vec::new(),
+            count: 0,
+            limits: Limits::new(max_size, max_item_size, on_drop)?,
+            top_level_span_events,
+            compression,
+            stats: Stats::default(),
+            tags: TagNormalizer::new(invalid_utf8, tag_limits),
+        })
+    }
+
+    #[classattr]
+    fn content_type() -> &'static str {
+        "application/msgpack"
+    }
+
+    fn __len__(&self) -> usize {
+        self.count
+    }
+
+    /// Size in bytes of the payload that would be returned by `flush()`, before compression.
+    #[getter]
+    fn size(&self) -> usize {
+        self.payload_size(self.count)
+    }
+
+    /// Same as `size`.
+    fn size_in_bytes(&self) -> usize {
+        self.payload_size(self.count)
+    }
+
+    /// Upper bound of the number of bytes that `put(trace)` would add to the payload, computed
+    /// without encoding the trace, so that the buffer can be flushed first if it wouldn't fit.
+    fn estimate(&self, trace: Vec<Span<'_>>) -> PyResult<usize> {
+        estimate::trace_size(&trace)
+    }
+
+    #[getter]
+    fn max_size(&self) -> usize {
+        self.limits.max_size()
+    }
+
+    #[getter]
+    fn max_item_size(&self) -> usize {
+        self.limits.max_item_size()
+    }
+
+    #[getter]
+    fn compression(&self) -> CompressionPy {
+        self.compression
+    }
+
+    /// Value of the `Content-Encoding` header to send along with the payloads, if compressed.
+    #[getter]
+    fn content_encoding(&self) -> Option<&'static str> {
+        self.compression.content_encoding()
+    }
+
+    #[getter]
+    fn invalid_utf8(&self) -> InvalidUtf8Py {
+        self.tags.invalid_utf8()
+    }
+
+    #[getter]
+    fn tag_limits(&self) -> TagLimitsPy {
+        self.tags.limits()
+    }
+
+    /// Counters of the traces and payloads handled so far, reset afterwards if `reset` is set.
+    #[pyo3(signature = (reset = false))]
+    fn stats(&mut self, reset: bool) -> EncoderStatsPy {
+        self.stats.snapshot(reset)
+    }
+
+    #[getter]
+    fn top_level_span_events(&self) -> bool {
+        self.top_level_span_events
+    }
+
+    /// Adds a trace to the buffer. A trace that doesn't fit is dropped and its overflow returned,
+    /// and nothing is buffered if any of its spans fails to encode.
+    fn put<'py>(
+        &mut self,
+        py: Python<'py>,
+        trace: Vec<Span<'py>>,
+    ) -> PyResult<Option<Py<OverflowPy>>> {
+        self.put_spans(py, &trace)
+    }
+
+    /// Adds the finished spans of a trace that may still be in progress, once there are at least
+    /// `min_spans` of them or the whole trace is finished. Returns the overflow of the chunk, if
+    /// any, along with the spans to pass again once more of them are finished.
+    fn put_partial<'py>(
+        &mut self,
+        py: Python<'py>,
+        trace: Vec<Bound<'py, PyAny>>,
+        min_spans: usize,
+    ) -> PyResult<(Option<Py<OverflowPy>>, Vec<Bound<'py, PyAny>>)> {
+        let (chunk, open) = partial::split(&trace, min_spans)?;
+        if chunk.is_empty() {
+            return Ok((None, open));
+        }
+        Ok((self.put_spans(py, &chunk)?, open))
+    }
+
+    /// Returns the payload along with the number of traces it contains and empties the buffer.
+    fn flush<'py>(&mut self, py: Python<'py>) -> PyResult<(Bound<'py, PyBytes>, usize)> {
+        self.take_payload(py)
+    }
+
+    /// Same as `flush()`, but returns `(None, 0)` when no trace was buffered.
+    fn encode<'py>(&mut self, py: Python<'py>) -> PyResult<(Option<Bound<'py, PyBytes>>, usize)> {
+        if self.count == 0 {
+            return Ok((None, 0));
+        }
+        let (payload, count) = self.take_payload(py)?;
+        Ok((Some(payload), count))
+    }
+}
@@ -0,0 +1,314 @@
+use std::borrow::Cow;
+use std::time::Instant;
+
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use super::buffer::{self, Limits, OverflowPy};
+use super::compression::CompressionPy;
+use super::estimate;
+use super::msgpack;
+use super::normalize::{TagLimitsPy, TagNormalizer, TagPart};
+use super::partial;
+use super::span::{text, write_number, Span};
+use super::span_event::{self, EVENTS_TAG};
+use super::span_link;
+use super::stats::{EncoderStatsPy, Stats};
+use super::string_table::StringTable;
+use super::utf8::InvalidUtf8Py;
+
+const SPAN_LINKS_TAG: &str = "_dd.span_links";
+
+/// Buffers traces in the v0.5 msgpack format, i.e. `[strings, traces]` where every string of the
+/// payload is replaced by its index in the shared `strings` table.
+///
+/// Traces are lists of spans, given as dicts or in the compact tuple form. Output is byte for byte
+/// the same as `ddtrace.internal._encoding.MsgpackEncoderV05` for the same spans. The v0.5 span
+/// layout has no `meta_struct` field, so it is left out of the payload, and span links are carried
+/// as JSON in the `_dd.span_links` tag. Span events are always carried as JSON in the `events` tag.
+///
+/// Tag keys and values that aren't valid UTF-8 are handled as `invalid_utf8` says, replaced by
+/// default, and counted in `stats().invalid_strings`. Tags and resources longer than the agent
+/// accepts are truncated to `tag_limits`, and counted in `stats().truncated_strings`.
+#[pyclass(name = "TraceEncoderV05", module = "ddtrace.internal.core._core")]
+pub struct TraceEncoderV05Py {
+    strings: StringTable,
+    traces: Vec<u8>,
+    count: usize,
+    limits: Limits,
+    compression: CompressionPy,
+    stats: Stats,
+    tags: TagNormalizer,
+}
+
+impl TraceEncoderV05Py {
+    fn write_span(&mut self, span: &Span<'_>) -> PyResult<()> {
+        let strings = &mut self.strings;
+        let tags = &mut self.tags;
+        let buf = &mut self.traces;
+        msgpack::write_array_len(buf, 12);
+        msgpack::write_uint(buf, strings.index_text(span.service.as_ref())?.into());
+        msgpack::write_uint(buf, strings.index_text(span.name.as_ref())?.into());
+        let resource = match tags.resource(span.resource.as_ref())? {
+            Some(Cow::Owned(resource)) => strings.index(&resource),
+            _ => strings.index_text(span.resource.as_ref())?,
+        };
+        msgpack::write_uint(buf, resource.into());
+        msgpack::write_uint(buf, span.trace_id);
+        msgpack::write_uint(buf, span.span_id);
+        msgpack::write_uint(buf, span.parent_id.unwrap_or(0));
+        msgpack::write_int(buf, span.start.unwrap_or(0));
+        msgpack::write_int(buf, span.duration.unwrap_or(0));
+        msgpack::write_int(buf, span.error.into());
+
+        let span_links = if span.span_links.is_empty() {
+            None
+        } else {
+            Some(span_link::to_json(&span.span_links)?)
+        };
+        let span_events = if span.span_events.is_empty() {
+            None
+        } else {
+            Some(span_event::to_json(&span.span_events)?)
+        };
+        let mut meta = Vec::new();
+        for (key, value) in span.meta.iter().flatten() {
+            if !tags.allow_meta_entry(meta.len()) {
+                continue;
+            }
+            let key = strings.index_tag(text(Some(key))?.as_ref(), tags, TagPart::Key)?;
+            let value = strings.index_tag(text(Some(value))?.as_ref(), tags, TagPart::Value)?;
+            if let (Some(key), Some(value)) = (key, value) {
+                meta.push((key, value));
+            }
+        }
+        let meta_len =
+            meta.len() + usize::from(span_links.is_some()) + usize::from(span_events.is_some());
+        msgpack::write_map_len(buf, meta_len);
+        for (key, value) in meta {
+            msgpack::write_uint(buf, key.into());
+            msgpack::write_uint(buf, value.into());
+        }
+        if let Some(span_links) = span_links {
+            msgpack::write_uint(buf, strings.index(SPAN_LINKS_TAG).into());
+            msgpack::write_uint(buf, strings.index(&span_links).into());
+        }
+        if let Some(span_events) = span_events {
+            msgpack::write_uint(buf, strings.index(EVENTS_TAG).into());
+            msgpack::write_uint(buf, strings.index(&span_events).into());
+        }
+
+        let mut metrics = Vec::new();
+        for (key, value) in span.metrics.iter().flatten() {
+            if let Some(key) = strings.index_tag(text(Some(key))?.as_ref(), tags, TagPart::Key)? {
+                metrics.push((key, value));
+            }
+        }
+        msgpack::write_map_len(buf, metrics.len());
+        for (key, value) in metrics {
+            msgpack::write_uint(buf, key.into());
+            write_number(buf, &value)?;
+        }
+
+        msgpack::write_uint(buf, strings.index_text(span.span_type.as_ref())?.into());
+        Ok(())
+    }
+
+    fn write_trace(&mut self, trace: &[Span<'_>]) -> PyResult<()> {
+        msgpack::write_array_len(&mut self.traces, trace.len());
+        for span in trace {
+            self.write_span(span)?;
+        }
+        Ok(())
+    }
+
+    fn payload_size(&self, count: usize) -> usize {
+        msgpack::array_header_size(2)
+            + self.strings.size()
+            + msgpack::array_header_size(count)
+            + self.traces.len()
+    }
+
+    /// Buffers a trace, or drops it and returns its overflow if it doesn't fit. Nothing is buffered
+    /// if any of its spans fails to encode.
+    fn put_spans(
+        &mut self,
+        py: Python<'_>,
+        trace: &[Span<'_>],
+    ) -> PyResult<Option<Py<OverflowPy>>> {
+        let started = Instant::now();
+        let size_before = self.payload_size(self.count);
+        let traces_len = self.traces.len();
+        let savepoint = self.strings.savepoint();
+        let overflow = self.write_trace(trace).and_then(|()| {
+            let payload_size = self.payload_size(self.count + 1);
+            self.limits
+                .check(py, trace.len(), payload_size - size_before, payload_size)
+        });
+        let result = match overflow {
+            Ok(None) => {
+                self.count += 1;
+                Ok(None)
+            }
+            result => {
+                self.traces.truncate(traces_len);
+                self.strings.rollback(savepoint);
+                result
+            }
+        };
+        self.stats.record_put(&result, trace.len(), started);
+        self.stats.record_fixes(self.tags.take_fixes());
+        result
+    }
+
+    fn take_payload<'py>(&mut self, py: Python<'py>) -> PyResult<(Bound<'py, PyBytes>, usize)> {
+        let mut payload = Vec::with_capacity(self.payload_size(self.count));
+        msgpack::write_array_len(&mut payload, 2);
+        self.strings.write(&mut payload);
+        msgpack::write_array_len(&mut payload, self.count);
+        payload.extend_from_slice(&self.traces);
+
+        let count = self.count;
+        self.strings.reset();
+        self.traces.clear();
+        self.count = 0;
+        let compression = self.compression;
+        let payload = py.allow_threads(|| compression.compress(payload))?;
+        self.stats.record_payload(payload.len());
+        Ok((PyBytes::new_bound(py, &payload), count))
+    }
+}
+
+#[pymethods]
+impl TraceEncoderV05Py {
+    #[new]
+    #[pyo3(signature = (
+        max_size = buffer::DEFAULT_MAX_SIZE,
+        max_item_size = None,
+        on_drop = None,
+        compression = CompressionPy::None,
+        invalid_utf8 = InvalidUtf8Py::Replace,
+        tag_limits = None
+    ))]
+    fn new(
+        max_size: usize,
+        max_item_size: Option<usize>,
+        on_drop: Option<PyObject>,
+        compression: CompressionPy,
+        invalid_utf8: InvalidUtf8Py,
+        tag_limits: Option<TagLimitsPy>,
+    ) -> PyResult<Self> {
+        Ok(TraceEncoderV05Py {
+            strings: StringTable::new(max_size / 10),
+            traces: Vec::new(),
+            count: 0,
+            limits: Limits::new(max_size, max_item_size, on_drop)?,
+            compression,
+            stats: Stats::default(),
+            tags: TagNormalizer::new(invalid_utf8, tag_limits),
+        })
+    }
+
+    #[classattr]
+    fn content_type() -> &'static str {
+        "application/msgpack"
+    }
+
+    fn __len__(&self) -> usize {
+        self.count
+    }
+
+    /// Size in bytes of the payload that would be returned by `flush()`, before compression.
+    #[getter]
+    fn size(&self) -> usize {
+        self.payload_size(self.count)
+    }
+
+    /// Same as `size`.
+    fn size_in_bytes(&self) -> usize {
+        self.payload_size(self.count)
+    }
+
+    /// Upper bound of the number of bytes that `put(trace)` would add to the payload, computed
+    /// without encoding the trace, so that the buffer can be flushed first if it wouldn't fit.
+    fn estimate(&self, trace: Vec<Span<'_>>) -> PyResult<usize> {
+        estimate::trace_size(&trace)
+    }
+
+    #[getter]
+    fn max_size(&self) -> usize {
+        self.limits.max_size()
+    }
+
+    #[getter]
+    fn max_item_size(&self) -> usize {
+        self.limits.max_item_size()
+    }
+
+    #[getter]
+    fn compression(&self) -> CompressionPy {
+        self.compression
+    }
+
+    /// Value of the `Content-Encoding` header to send along with the payloads, if compressed.
+    #[getter]
+    fn content_encoding(&self) -> Option<&'static str> {
+        self.compression.content_encoding()
+    }
+
+    #[getter]
+    fn invalid_utf8(&self) -> InvalidUtf8Py {
+        self.tags.invalid_utf8()
+    }
+
+    #[getter]
+    fn tag_limits(&self) -> TagLimitsPy {
+        self.tags.limits()
+    }
+
+    /// Counters of the traces and payloads handled so far, reset afterwards if `reset` is set.
+    #[pyo3(signature = (reset = false))]
+    fn stats(&mut self, reset: bool) -> EncoderStatsPy {
+        self.stats.snapshot(reset)
+    }
+
+    /// Adds a trace to the buffer. A trace that doesn't fit is dropped and its overflow returned,
+    /// and nothing is buffered if any of its spans fails to encode.
+    fn put<'py>(
+        &mut self,
+        py: Python<'py>,
+        trace: Vec<Span<'py>>,
+    ) -> PyResult<Option<Py<OverflowPy>>> {
+        self.put_spans(py, &trace)
+    }
+
+    /// Adds the finished spans of a trace that may still be in progress, once there are at least
+    /// `min_spans` of them or the whole trace is finished. Returns the overflow of the chunk, if
+    /// any, along with the spans to pass again once more of them are finished.
+    fn put_partial<'py>(
+        &mut self,
+        py: Python<'py>,
+        trace: Vec<Bound<'py, PyAny>>,
+        min_spans: usize,
+    ) -> PyResult<(Option<Py<OverflowPy>>, Vec<Bound<'py, PyAny>>)> {
+        let (chunk, open) = partial::split(&trace, min_spans)?;
+        if chunk.is_empty() {
+            return Ok((None, open));
+        }
+        Ok((self.put_spans(py, &chunk)?, open))
+    }
+
+    /// Returns the payload along with the number of traces it contains and empties the buffer.
+    fn flush<'py>(&mut self, py: Python<'py>) -> PyResult<(Bound<'py, PyBytes>, usize)> {
+        self.take_payload(py)
+    }
+
+    /// Same as `flush()`, but returns `(None, 0)` when no trace was buffered.
+    fn encode<'py>(&mut self, py: Python<'py>) -> PyResult<(Option<Bound<'py, PyBytes>>, usize)> {
+        if self.count == 0 {
+            return Ok((None, 0));
+        }
+        let (payload, count) = self.take_payload(py)?;
+        Ok((Some(payload), count))
+    }
+}
@@ -0,0 +1,40 @@
+// Parsing of JSON documents, e.g. the responses of the agent and the sampling rules of the
+// configuration. Encoding spans to JSON is done by `encoding::json` instead.
+
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+
+pub use serde_json::Value;
+
+/// Parses a JSON document.
+pub fn parse(input: &[u8]) -> Result<Value, String> {
+    serde_json::from_slice(input).map_err(|err| err.to_string())
+}
+
+/// Converts a document the way `json.loads` does.
+pub fn to_python(value: &Value, py: Python<'_>) -> PyResult<PyObject> {
+    Ok(match value {
+        Value::Null => py.None(),
+        Value::Bool(value) => value.to_object(py),
+        Value::Number(value) => match (value.as_i64(), value.as_u64()) {
+            (Some(value), _) => value.to_object(py),
+            (None, Some(value)) => value.to_object(py),
+            _ => value.as_f64().to_object(py),
+        },
+        Value::String(value) => value.to_object(py),
+        Value::Array(items) => {
+            let list = PyList::empty_bound(py);
+            for item in items {
+                list.append(to_python(item, py)?)?;
+            }
+            list.into_any().unbind()
+        }
+        Value::Object(members) => {
+            let dict = PyDict::new_bound(py);
+            for (key, value) in members {
+                dict.set_item(key, to_python(value, py)?)?;
+            }
+            dict.into_any().unbind()
+        }
+    })
+}
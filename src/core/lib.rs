@@ -1,9 +1,66 @@
+mod concurrency_limiter;
 mod ddsketch;
+mod encoding;
+mod json;
+mod propagation;
+mod rand;
+mod rate_limiter;
+mod runtime;
+mod sampling;
+mod writer;
 
 use pyo3::prelude::*;
 
 #[pymodule]
 fn _core(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<ddsketch::DDSketchPy>()?;
+    m.add_class::<rate_limiter::SlidingWindowRateLimiterPy>()?;
+    m.add_class::<rate_limiter::TokenBucketRateLimiterPy>()?;
+    m.add_class::<rate_limiter::KeyedRateLimiterPy>()?;
+    m.add_class::<rate_limiter::AdaptiveRateLimiterPy>()?;
+    m.add_class::<concurrency_limiter::ConcurrencyLimiterPy>()?;
+    m.add_class::<sampling::GlobMatcherPy>()?;
+    m.add_class::<sampling::PrioritySamplerPy>()?;
+    m.add_class::<sampling::SamplingRulesPy>()?;
+    m.add_class::<sampling::SpanSamplingRulesPy>()?;
+    m.add_class::<sampling::TraceSamplerPy>()?;
+    m.add_class::<propagation::ExtractedContextPy>()?;
+    m.add_class::<encoding::TraceEncoderV04Py>()?;
+    m.add_class::<encoding::TraceEncoderV05Py>()?;
+    m.add_class::<encoding::JsonEncoderPy>()?;
+    m.add_class::<encoding::OtlpEncoderPy>()?;
+    m.add_class::<encoding::CiTestCycleEncoderPy>()?;
+    m.add_class::<encoding::OverflowPy>()?;
+    m.add_class::<encoding::OverflowReasonPy>()?;
+    m.add_class::<encoding::CompressionPy>()?;
+    m.add_class::<encoding::InvalidUtf8Py>()?;
+    m.add_class::<encoding::TagLimitsPy>()?;
+    m.add_class::<encoding::EncoderStatsPy>()?;
+    m.add_class::<writer::NativeTraceWriterPy>()?;
+    m.add_class::<writer::RetryPolicyPy>()?;
+    m.add_class::<writer::WriterStatsPy>()?;
+    m.add_class::<writer::AgentInfoFetcherPy>()?;
+    m.add_class::<writer::AgentInfoPy>()?;
+    m.add_class::<writer::NativeSpanStatsProcessorPy>()?;
+    m.add_function(wrap_pyfunction!(rate_limiter::get_limiter, m)?)?;
+    m.add_function(wrap_pyfunction!(sampling::sampling_decision, m)?)?;
+    m.add_function(wrap_pyfunction!(sampling::sampling_decision_tags, m)?)?;
+    m.add_function(wrap_pyfunction!(sampling::trace_id_lower_64_bits, m)?)?;
+    m.add_function(wrap_pyfunction!(propagation::b3_extract, m)?)?;
+    m.add_function(wrap_pyfunction!(propagation::b3_build_headers, m)?)?;
+    m.add_function(wrap_pyfunction!(propagation::b3multi_extract, m)?)?;
+    m.add_function(wrap_pyfunction!(propagation::b3multi_build_headers, m)?)?;
+    m.add_function(wrap_pyfunction!(propagation::baggage_extract, m)?)?;
+    m.add_function(wrap_pyfunction!(propagation::baggage_build_headers, m)?)?;
+    m.add_function(wrap_pyfunction!(propagation::datadog_extract, m)?)?;
+    m.add_function(wrap_pyfunction!(propagation::datadog_tags_decode, m)?)?;
+    m.add_function(wrap_pyfunction!(propagation::datadog_tags_encode, m)?)?;
+    m.add_function(wrap_pyfunction!(propagation::w3c_parse_traceparent, m)?)?;
+    m.add_function(wrap_pyfunction!(propagation::w3c_parse_tracestate, m)?)?;
+    m.add_function(wrap_pyfunction!(propagation::w3c_build_headers, m)?)?;
+    m.add_function(wrap_pyfunction!(rand::gen_span_id, m)?)?;
+    m.add_function(wrap_pyfunction!(rand::gen_trace_id_128, m)?)?;
+    m.add_function(wrap_pyfunction!(runtime::set_runtime_worker_threads, m)?)?;
+    m.add_function(wrap_pyfunction!(runtime::is_runtime_started, m)?)?;
     Ok(())
 }
@@ -0,0 +1,179 @@
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use super::context::ExtractedContextPy;
+use super::header;
+
+const SINGLE_HEADER: &str = "b3";
+const TRACE_ID_HEADER: &str = "x-b3-traceid";
+const SPAN_ID_HEADER: &str = "x-b3-spanid";
+const SAMPLED_HEADER: &str = "x-b3-sampled";
+const FLAGS_HEADER: &str = "x-b3-flags";
+
+const AUTO_REJECT: i32 = 0;
+const AUTO_KEEP: i32 = 1;
+const USER_KEEP: i32 = 2;
+
+/// Parses a hex ID like `_b3_id_to_dd_id()`, `0` being `None`. IDs longer than 128 bits are
+/// invalid.
+fn parse_id(value: &str) -> Result<Option<u128>, ()> {
+    let value = value.trim();
+    let value = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    if value.starts_with(['+', '-']) {
+        return Err(());
+    }
+    let id = u128::from_str_radix(value, 16).map_err(|_| ())?;
+    Ok((id != 0).then_some(id))
+}
+
+/// Parses a hex span ID like `parse_id()`. Span IDs longer than 64 bits are invalid.
+fn parse_span_id(value: &str) -> Result<Option<u64>, ()> {
+    match parse_id(value)? {
+        Some(id) => u64::try_from(id).map(Some).map_err(|_| ()),
+        None => Ok(None),
+    }
+}
+
+/// Formats an ID like `_dd_id_to_b3_id()`: 32 hex digits for 128-bit trace IDs, 16 otherwise.
+fn format_id(id: u128) -> String {
+    if id > u128::from(u64::MAX) {
+        format!("{id:032x}")
+    } else {
+        format!("{id:016x}")
+    }
+}
+
+/// Reads the IDs and the sampling priority of a context to inject, or `None` if it has no trace
+/// ID or no span ID.
+fn injected_fields(context: &Bound<'_, PyAny>) -> PyResult<Option<(u128, u64, Option<f64>)>> {
+    let trace_id: Option<u128> = context.getattr("trace_id")?.extract()?;
+    let span_id: Option<u64> = context.getattr("span_id")?.extract()?;
+    let (Some(trace_id), Some(span_id)) = (trace_id, span_id) else {
+        return Ok(None);
+    };
+    let sampling_priority = context.getattr("sampling_priority")?.extract()?;
+    Ok(Some((trace_id, span_id, sampling_priority)))
+}
+
+fn context(
+    trace_id: Option<u128>,
+    span_id: Option<u64>,
+    priority: Option<i32>,
+) -> ExtractedContextPy {
+    ExtractedContextPy {
+        trace_id,
+        span_id,
+        sampling_priority: priority,
+        dd_origin: None,
+        meta: Vec::new(),
+    }
+}
+
+/// Extracts a context from the `x-b3-*` headers, like `_B3MultiHeader._extract()`, or returns
+/// `None` if there is no `x-b3-traceid`, if an ID isn't hexadecimal or if the span ID is longer
+/// than 64 bits. `x-b3-flags: 1` takes precedence over `x-b3-sampled`.
+#[pyfunction]
+pub fn b3multi_extract(headers: &Bound<'_, PyAny>) -> PyResult<Option<ExtractedContextPy>> {
+    let Some(trace_id) = header(headers, TRACE_ID_HEADER)? else {
+        return Ok(None);
+    };
+    let Ok(trace_id) = parse_id(&trace_id) else {
+        return Ok(None);
+    };
+    let span_id = match header(headers, SPAN_ID_HEADER)? {
+        Some(span_id) => match parse_span_id(&span_id) {
+            Ok(span_id) => span_id,
+            Err(_) => return Ok(None),
+        },
+        None => None,
+    };
+    let mut priority = match header(headers, SAMPLED_HEADER)?.as_deref() {
+        Some("0") => Some(AUTO_REJECT),
+        Some("1") => Some(AUTO_KEEP),
+        _ => None,
+    };
+    if header(headers, FLAGS_HEADER)?.as_deref() == Some("1") {
+        priority = Some(USER_KEEP);
+    }
+    Ok(Some(context(trace_id, span_id, priority)))
+}
+
+/// Extracts a context from the `b3` header, like `_B3SingleHeader._extract()`, or returns `None`
+/// if it is missing or empty, if an ID isn't hexadecimal or if the span ID is longer than 64 bits.
+/// The header is either only the sampling state, or
+/// `{TraceId}-{SpanId}[-{SamplingState}[-{ParentSpanId}]]`.
+#[pyfunction]
+pub fn b3_extract(headers: &Bound<'_, PyAny>) -> PyResult<Option<ExtractedContextPy>> {
+    let Some(single_header) = header(headers, SINGLE_HEADER)?.filter(|value| !value.is_empty())
+    else {
+        return Ok(None);
+    };
+    let parts: Vec<&str> = single_header.split('-').collect();
+    let (ids, sampled) = match parts.as_slice() {
+        [sampled] => (None, Some(*sampled)),
+        [trace_id, span_id] => (Some((*trace_id, *span_id)), None),
+        [trace_id, span_id, sampled, ..] => (Some((*trace_id, *span_id)), Some(*sampled)),
+        [] => unreachable!("split always returns a part"),
+    };
+    let (trace_id, span_id) = match ids {
+        Some((trace_id, span_id)) => match (parse_id(trace_id), parse_span_id(span_id)) {
+            (Ok(trace_id), Ok(span_id)) => (trace_id, span_id),
+            _ => return Ok(None),
+        },
+        None => (None, None),
+    };
+    let priority = match sampled {
+        Some("0") => Some(AUTO_REJECT),
+        Some("1") => Some(AUTO_KEEP),
+        Some("d") => Some(USER_KEEP),
+        _ => None,
+    };
+    Ok(Some(context(trace_id, span_id, priority)))
+}
+
+/// Returns the `x-b3-*` headers of a context, like `_B3MultiHeader._inject()`: an empty dict if
+/// it has no trace ID or no span ID.
+#[pyfunction]
+pub fn b3multi_build_headers<'py>(
+    py: Python<'py>,
+    context: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let headers = PyDict::new_bound(py);
+    let Some((trace_id, span_id, priority)) = injected_fields(context)? else {
+        return Ok(headers);
+    };
+    headers.set_item(TRACE_ID_HEADER, format_id(trace_id))?;
+    headers.set_item(SPAN_ID_HEADER, format_id(span_id.into()))?;
+    match priority {
+        Some(priority) if priority <= 0.0 => headers.set_item(SAMPLED_HEADER, "0")?,
+        Some(priority) if priority == 1.0 => headers.set_item(SAMPLED_HEADER, "1")?,
+        Some(priority) if priority > 1.0 => headers.set_item(FLAGS_HEADER, "1")?,
+        _ => {}
+    }
+    Ok(headers)
+}
+
+/// Returns the `b3` header of a context, like `_B3SingleHeader._inject()`: an empty dict if it
+/// has no trace ID or no span ID.
+#[pyfunction]
+pub fn b3_build_headers<'py>(
+    py: Python<'py>,
+    context: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let headers = PyDict::new_bound(py);
+    let Some((trace_id, span_id, priority)) = injected_fields(context)? else {
+        return Ok(headers);
+    };
+    let mut single_header = format!("{}-{}", format_id(trace_id), format_id(span_id.into()));
+    match priority {
+        Some(priority) if priority <= 0.0 => single_header.push_str("-0"),
+        Some(priority) if priority == 1.0 => single_header.push_str("-1"),
+        Some(priority) if priority > 1.0 => single_header.push_str("-d"),
+        _ => {}
+    }
+    headers.set_item(SINGLE_HEADER, single_header)?;
+    Ok(headers)
+}
@@ -0,0 +1,157 @@
+// Codec of the W3C `baggage` header, like `_BaggageHeader`:
+//
+//     baggage = member, { ",", member };
+//     member = key, "=", value, { ";", property };
+//
+// Keys and values are percent-encoded, and the properties of the members are dropped.
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use super::header;
+
+const BAGGAGE_HEADER: &str = "baggage";
+/// Default limits of the injected header, as `DD_TRACE_BAGGAGE_MAX_ITEMS` and
+/// `DD_TRACE_BAGGAGE_MAX_BYTES`.
+const DEFAULT_MAX_ITEMS: usize = 64;
+const DEFAULT_MAX_BYTES: usize = 8192;
+
+/// Characters that aren't percent-encoded in keys, as `_BaggageHeader.SAFE_CHARACTERS_KEY`.
+fn is_safe_key_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// Characters that aren't percent-encoded in values, as `_BaggageHeader.SAFE_CHARACTERS_VALUE`.
+fn is_safe_value_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'()*+-./:<>?@[]^_`{|}~".contains(&b)
+}
+
+/// Percent-encodes the UTF-8 bytes of a string that aren't safe, like `urllib.parse.quote()`.
+fn quote(value: &str, is_safe: fn(u8) -> bool) -> String {
+    let mut quoted = String::with_capacity(value.len());
+    for b in value.bytes() {
+        if is_safe(b) {
+            quoted.push(b as char);
+        } else {
+            quoted.push_str(&format!("%{b:02X}"));
+        }
+    }
+    quoted
+}
+
+/// Decodes the `%XX` escapes of a string, like `urllib.parse.unquote()`: malformed escapes are
+/// kept as is, and invalid UTF-8 is replaced.
+fn unquote(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut unquoted = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(b) => {
+                unquoted.push(b);
+                i += 3;
+            }
+            None => {
+                unquoted.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&unquoted).into_owned()
+}
+
+/// Decodes a `baggage` header into its key/value pairs, in order, a later value of a key
+/// replacing the earlier one, or returns `None` if a member has no `=`, or an empty key or value.
+fn decode(baggage: &str) -> Option<Vec<(String, String)>> {
+    let mut items: Vec<(String, String)> = Vec::new();
+    for member in baggage.split(',') {
+        let (key, value) = member.split_once('=')?;
+        let value = value.split_once(';').map_or(value, |(value, _)| value);
+        let (key, value) = (unquote(key.trim()), unquote(value.trim()));
+        if key.is_empty() || value.is_empty() {
+            return None;
+        }
+        match items.iter_mut().find(|(existing, _)| *existing == key) {
+            Some((_, existing)) => *existing = value,
+            None => items.push((key, value)),
+        }
+    }
+    Some(items)
+}
+
+/// Encodes key/value pairs into a `baggage` header. Only the first `max_items` pairs are
+/// encoded, and the pairs that would make the header longer than `max_bytes` are dropped along
+/// with the following ones.
+fn encode<'a>(
+    items: impl IntoIterator<Item = (&'a str, &'a str)>,
+    max_items: usize,
+    max_bytes: usize,
+) -> String {
+    let mut baggage = String::new();
+    for (key, value) in items.into_iter().take(max_items) {
+        let member = format!(
+            "{}={}",
+            quote(key.trim(), is_safe_key_char),
+            quote(value.trim(), is_safe_value_char)
+        );
+        let separator = if baggage.is_empty() { "" } else { "," };
+        if baggage.len() + separator.len() + member.len() > max_bytes {
+            break;
+        }
+        baggage.push_str(separator);
+        baggage.push_str(&member);
+    }
+    baggage
+}
+
+/// Returns the baggage of the `baggage` header, like `_BaggageHeader._extract()`: an empty dict
+/// if the header is missing, empty or malformed.
+#[pyfunction]
+pub fn baggage_extract<'py>(
+    py: Python<'py>,
+    headers: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let baggage = PyDict::new_bound(py);
+    let header = header(headers, BAGGAGE_HEADER)?.unwrap_or_default();
+    if header.is_empty() {
+        return Ok(baggage);
+    }
+    for (key, value) in decode(&header).unwrap_or_default() {
+        baggage.set_item(key, value)?;
+    }
+    Ok(baggage)
+}
+
+/// Returns the `baggage` header of the baggage of a context, like `_BaggageHeader._inject()`:
+/// an empty dict if it has no baggage. Keys and values are converted with `str()`, and the items
+/// over the limits are dropped.
+#[pyfunction]
+#[pyo3(signature = (context, max_items = DEFAULT_MAX_ITEMS, max_bytes = DEFAULT_MAX_BYTES))]
+pub fn baggage_build_headers<'py>(
+    py: Python<'py>,
+    context: &Bound<'py, PyAny>,
+    max_items: usize,
+    max_bytes: usize,
+) -> PyResult<Bound<'py, PyDict>> {
+    let headers = PyDict::new_bound(py);
+    let baggage = context.getattr("_baggage")?;
+    let baggage = baggage.downcast::<PyDict>()?;
+    if baggage.is_empty() {
+        return Ok(headers);
+    }
+    let mut items = Vec::with_capacity(baggage.len().min(max_items));
+    // A snapshot of the items, as `str()` may run code changing the baggage.
+    for item in baggage.items().iter().take(max_items) {
+        let (key, value): (Bound<'_, PyAny>, Bound<'_, PyAny>) = item.extract()?;
+        items.push((key.str()?.to_string(), value.str()?.to_string()));
+    }
+    let items = items
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()));
+    headers.set_item(BAGGAGE_HEADER, encode(items, max_items, max_bytes))?;
+    Ok(headers)
+}
@@ -0,0 +1,89 @@
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+/// Trace context extracted from the headers of a request, with the arguments of
+/// `ddtrace.context.Context` as attributes. IDs of `0` are `None`, like in the Python propagators.
+#[pyclass(
+    frozen,
+    name = "ExtractedContext",
+    module = "ddtrace.internal.core._core"
+)]
+pub struct ExtractedContextPy {
+    pub trace_id: Option<u128>,
+    pub span_id: Option<u64>,
+    pub sampling_priority: Option<i32>,
+    pub dd_origin: Option<String>,
+    /// In order, for the tags to be propagated in the same order.
+    pub meta: Vec<(String, String)>,
+}
+
+impl ExtractedContextPy {
+    /// Sets a meta, replacing its value if it is already set.
+    pub fn set_meta(&mut self, key: &str, value: String) {
+        match self.meta.iter_mut().find(|(existing, _)| existing == key) {
+            Some((_, existing)) => *existing = value,
+            None => self.meta.push((key.to_owned(), value)),
+        }
+    }
+
+    pub fn get_meta(&self, key: &str) -> Option<&str> {
+        self.meta
+            .iter()
+            .find(|(existing, _)| existing == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn remove_meta(&mut self, key: &str) {
+        self.meta.retain(|(existing, _)| existing != key);
+    }
+}
+
+#[pymethods]
+impl ExtractedContextPy {
+    #[getter]
+    fn trace_id(&self) -> Option<u128> {
+        self.trace_id
+    }
+
+    #[getter]
+    fn span_id(&self) -> Option<u64> {
+        self.span_id
+    }
+
+    #[getter]
+    fn sampling_priority(&self) -> Option<i32> {
+        self.sampling_priority
+    }
+
+    #[getter]
+    fn dd_origin(&self) -> Option<&str> {
+        self.dd_origin.as_deref()
+    }
+
+    /// A new dict on every access, for the context to own it.
+    #[getter]
+    fn meta<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let meta = PyDict::new_bound(py);
+        for (key, value) in &self.meta {
+            meta.set_item(key, value)?;
+        }
+        Ok(meta)
+    }
+
+    fn __repr__(&self) -> String {
+        fn repr<T: ToString>(value: Option<T>) -> String {
+            value.map_or_else(|| "None".to_owned(), |value| value.to_string())
+        }
+        format!(
+            "ExtractedContext(trace_id={}, span_id={}, sampling_priority={}, dd_origin={})",
+            repr(self.trace_id),
+            repr(self.span_id),
+            repr(self.sampling_priority),
+            repr(
+                self.dd_origin
+                    .as_deref()
+                    .map(|origin| format!("'{origin}'"))
+            ),
+        )
+    }
+}
@@ -0,0 +1,179 @@
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use super::context::ExtractedContextPy;
+use super::tagset::{self, DEFAULT_MAX_SIZE};
+use super::{
+    header, HIGHER_ORDER_TRACE_ID_BITS_KEY, PROPAGATED_TAG_PREFIX, PROPAGATION_ERROR_KEY,
+    SAMPLING_DECISION_KEY,
+};
+
+const TRACE_ID_HEADER: &str = "x-datadog-trace-id";
+const PARENT_ID_HEADER: &str = "x-datadog-parent-id";
+const SAMPLING_PRIORITY_HEADER: &str = "x-datadog-sampling-priority";
+const ORIGIN_HEADER: &str = "x-datadog-origin";
+const TAGS_HEADER: &str = "x-datadog-tags";
+
+/// Tags that aren't extracted from `x-datadog-tags`.
+const REJECTED_TAGS: [&str; 1] = ["_dd.p.upstream_services"];
+/// Priority of the requests without `x-datadog-sampling-priority`.
+const USER_KEEP: i32 = 2;
+/// `_dd.p.dm` of the requests without one, for a decision made by a rule.
+const DEFAULT_DECISION_MAKER: &str = "-3";
+
+/// Returns whether the higher order bits of a trace ID in `_dd.p.tid` are valid, like
+/// `_DatadogMultiHeader._higher_order_is_valid()`: 16 hex digits, not all zeroes.
+fn higher_order_bits(tid: &str) -> Option<u64> {
+    if tid.len() != 16 {
+        return None;
+    }
+    u64::from_str_radix(tid, 16).ok().filter(|&bits| bits != 0)
+}
+
+/// Returns whether a `_dd.p.dm` value is valid, like `validate_sampling_decision()`.
+fn is_valid_decision_maker(value: &str) -> bool {
+    matches!(value.as_bytes(), [b'-', digit] if digit.is_ascii_digit())
+}
+
+/// Returns the propagated tags of `x-datadog-tags`, or only a `_dd.propagation_error` tag if it
+/// can't be decoded, like `_DatadogMultiHeader._extract_meta()`.
+fn extract_meta(tags: &str, max_size: usize) -> Vec<(String, String)> {
+    match tagset::decode(tags, max_size) {
+        Ok(tags) => tags
+            .into_iter()
+            .filter(|(key, _)| {
+                key.starts_with(PROPAGATED_TAG_PREFIX) && !REJECTED_TAGS.contains(&key.as_str())
+            })
+            .collect(),
+        Err(err) => vec![(
+            PROPAGATION_ERROR_KEY.to_owned(),
+            err.propagation_error().to_owned(),
+        )],
+    }
+}
+
+/// Extracts a context from the `x-datadog-*` headers, like `_DatadogMultiHeader._extract()`, or
+/// returns `None` if there is no valid `x-datadog-trace-id`, or if the other IDs or the sampling
+/// priority aren't integers.
+///
+/// The higher order bits of 128-bit trace IDs in `_dd.p.tid` are only added to the trace ID if
+/// `trace_id_128_bit`. A malformed `_dd.p.tid` is replaced by a `_dd.propagation_error` tag.
+#[pyfunction]
+#[pyo3(signature = (headers, trace_id_128_bit = true, tags_max_size = DEFAULT_MAX_SIZE))]
+pub fn datadog_extract(
+    headers: &Bound<'_, PyAny>,
+    trace_id_128_bit: bool,
+    tags_max_size: usize,
+) -> PyResult<Option<ExtractedContextPy>> {
+    let Some(trace_id) = header(headers, TRACE_ID_HEADER)? else {
+        return Ok(None);
+    };
+    let Some(mut trace_id) = trace_id
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|&trace_id| trace_id > 0)
+        .map(u128::from)
+    else {
+        return Ok(None);
+    };
+    let span_id = match header(headers, PARENT_ID_HEADER)? {
+        Some(span_id) => match span_id.trim().parse::<u64>() {
+            Ok(span_id) => span_id,
+            Err(_) => return Ok(None),
+        },
+        None => 0,
+    };
+    let sampling_priority = match header(headers, SAMPLING_PRIORITY_HEADER)? {
+        Some(priority) => match priority.trim().parse::<i32>() {
+            Ok(priority) => priority,
+            Err(_) => return Ok(None),
+        },
+        None => USER_KEEP,
+    };
+    let mut context = ExtractedContextPy {
+        trace_id: None,
+        span_id: (span_id != 0).then_some(span_id),
+        sampling_priority: Some(sampling_priority),
+        dd_origin: header(headers, ORIGIN_HEADER)?,
+        meta: match header(headers, TAGS_HEADER)? {
+            Some(tags) if !tags.is_empty() => extract_meta(&tags, tags_max_size),
+            _ => Vec::new(),
+        },
+    };
+
+    if let Some(tid) = context.get_meta(HIGHER_ORDER_TRACE_ID_BITS_KEY) {
+        match higher_order_bits(tid) {
+            Some(bits) if trace_id_128_bit => trace_id |= u128::from(bits) << 64,
+            Some(_) => {}
+            None => {
+                let error = format!("malformed_tid {tid}");
+                context.remove_meta(HIGHER_ORDER_TRACE_ID_BITS_KEY);
+                context.set_meta(PROPAGATION_ERROR_KEY, error);
+            }
+        }
+    }
+    context.trace_id = Some(trace_id);
+
+    match context.get_meta(SAMPLING_DECISION_KEY) {
+        Some(value) if !value.is_empty() => {
+            if !is_valid_decision_maker(value) {
+                context.remove_meta(SAMPLING_DECISION_KEY);
+                context.set_meta(PROPAGATION_ERROR_KEY, "decoding_error".to_owned());
+            }
+        }
+        _ => context.set_meta(SAMPLING_DECISION_KEY, DEFAULT_DECISION_MAKER.to_owned()),
+    }
+    Ok(Some(context))
+}
+
+/// Returns the propagated tags of an `x-datadog-tags` header, like
+/// `_DatadogMultiHeader._extract_meta()`: only a `_dd.propagation_error` tag if it is longer than
+/// `max_size` or malformed.
+#[pyfunction]
+#[pyo3(signature = (tags, max_size = DEFAULT_MAX_SIZE))]
+pub fn datadog_tags_decode<'py>(
+    py: Python<'py>,
+    tags: &str,
+    max_size: usize,
+) -> PyResult<Bound<'py, PyDict>> {
+    let meta = PyDict::new_bound(py);
+    for (key, value) in extract_meta(tags, max_size) {
+        meta.set_item(key, value)?;
+    }
+    Ok(meta)
+}
+
+/// Returns the `x-datadog-tags` header of the propagated tags of a context's meta, like
+/// `_DatadogMultiHeader._inject()`, or `None` if there are none. If they can't be encoded, a
+/// `_dd.propagation_error` tag is set in the meta and `None` is returned, and nothing is encoded
+/// if there is already one.
+#[pyfunction]
+#[pyo3(signature = (meta, max_size = DEFAULT_MAX_SIZE))]
+pub fn datadog_tags_encode(meta: &Bound<'_, PyDict>, max_size: usize) -> PyResult<Option<String>> {
+    if meta.contains(PROPAGATION_ERROR_KEY)? {
+        return Ok(None);
+    }
+    let mut tags = Vec::new();
+    // A snapshot of the items, as `str()` may run code changing the meta.
+    for item in meta.items() {
+        let (key, value): (Bound<'_, PyAny>, Bound<'_, PyAny>) = item.extract()?;
+        let key = key.str()?.to_string();
+        if key.starts_with(PROPAGATED_TAG_PREFIX) {
+            tags.push((key, value.str()?.to_string()));
+        }
+    }
+    if tags.is_empty() {
+        return Ok(None);
+    }
+    let tags = tags
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()));
+    match tagset::encode(tags, max_size) {
+        Ok(tagset) => Ok(Some(tagset)),
+        Err(err) => {
+            meta.set_item(PROPAGATION_ERROR_KEY, err.propagation_error())?;
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,49 @@
+// Parsing and building of the distributed tracing headers, like `ddtrace.propagation.http`.
+mod b3;
+mod baggage;
+mod context;
+mod datadog;
+mod tagset;
+mod w3c;
+
+use pyo3::exceptions::PyTypeError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+pub use b3::{b3_build_headers, b3_extract, b3multi_build_headers, b3multi_extract};
+pub use baggage::{baggage_build_headers, baggage_extract};
+pub use context::ExtractedContextPy;
+pub use datadog::{datadog_extract, datadog_tags_decode, datadog_tags_encode};
+pub use w3c::{w3c_build_headers, w3c_parse_traceparent, w3c_parse_tracestate};
+
+/// Keys of the meta of a context, like `ddtrace.internal.constants`.
+const SAMPLING_DECISION_KEY: &str = "_dd.p.dm";
+const USER_ID_KEY: &str = "_dd.p.usr.id";
+const LAST_DD_PARENT_ID_KEY: &str = "_dd.parent_id";
+const HIGHER_ORDER_TRACE_ID_BITS_KEY: &str = "_dd.p.tid";
+const PROPAGATION_ERROR_KEY: &str = "_dd.propagation_error";
+/// Prefix of the tags propagated along with the trace.
+const PROPAGATED_TAG_PREFIX: &str = "_dd.p.";
+
+/// Returns the value of a header, under its name or as a WSGI environ variable, like
+/// `_extract_header_value()`, from a mapping of lowercase names. Values that aren't valid UTF-8
+/// have their invalid bytes replaced.
+fn header(headers: &Bound<'_, PyAny>, name: &str) -> PyResult<Option<String>> {
+    let wsgi_name = format!("http_{}", name.replace('-', "_"));
+    for name in [name, wsgi_name.as_str()] {
+        if !headers.contains(name)? {
+            continue;
+        }
+        let value = headers.get_item(name)?;
+        if let Ok(value) = value.downcast::<PyBytes>() {
+            return Ok(Some(String::from_utf8_lossy(value.as_bytes()).into_owned()));
+        }
+        return match value.extract::<String>() {
+            Ok(value) => Ok(Some(value)),
+            Err(_) => Err(PyTypeError::new_err(format!(
+                "header {name} must be str or bytes"
+            ))),
+        };
+    }
+    Ok(None)
+}
@@ -0,0 +1,120 @@
+// Codec of the `x-datadog-tags` header, like `ddtrace.internal._tagset`:
+//
+//     tagset = tag, { ",", tag };
+//     tag = key, "=", value;
+//     key = { ? ASCII 32-126 ? - equal or comma or space };
+//     value = { ? ASCII 32-126 ? - comma };
+
+/// Default size limit of the header, as `DD_TRACE_X_DATADOG_TAGS_MAX_LENGTH`.
+pub const DEFAULT_MAX_SIZE: usize = 512;
+
+/// Why a tagset couldn't be decoded.
+pub enum DecodeError {
+    /// The tagset is longer than the limit.
+    MaxSize,
+    /// The tagset is malformed.
+    Invalid,
+}
+
+impl DecodeError {
+    /// Value of the `_dd.propagation_error` tag of the context.
+    pub fn propagation_error(&self) -> &'static str {
+        match self {
+            DecodeError::MaxSize => "extract_max_size",
+            DecodeError::Invalid => "decoding_error",
+        }
+    }
+}
+
+/// Why tags couldn't be encoded into a tagset.
+pub enum EncodeError {
+    /// The tagset would be longer than the limit.
+    MaxSize,
+    /// A key or a value has invalid characters, or is empty.
+    Invalid,
+}
+
+impl EncodeError {
+    /// Value of the `_dd.propagation_error` tag of the context.
+    pub fn propagation_error(&self) -> &'static str {
+        match self {
+            EncodeError::MaxSize => "inject_max_size",
+            EncodeError::Invalid => "encoding_error",
+        }
+    }
+}
+
+fn is_valid_key_char(c: char) -> bool {
+    matches!(c, '!'..='+' | '-'..='<' | '>'..='~')
+}
+
+fn is_valid_value_char(c: char) -> bool {
+    c == ' ' || c == '=' || is_valid_key_char(c)
+}
+
+/// Decodes a tagset into its key/value pairs, in order, a later value of a key replacing the
+/// earlier one. Spaces around the values are trimmed, and empty keys or values are invalid.
+pub fn decode(tagset: &str, max_size: usize) -> Result<Vec<(String, String)>, DecodeError> {
+    let mut tags: Vec<(String, String)> = Vec::new();
+    if tagset.is_empty() {
+        return Ok(tags);
+    }
+    if tagset.chars().count() > max_size {
+        return Err(DecodeError::MaxSize);
+    }
+    let mut insert = |key: &str, value: &str| {
+        let value = value.trim_matches(' ');
+        if value.is_empty() {
+            return Err(DecodeError::Invalid);
+        }
+        match tags.iter_mut().find(|(existing, _)| existing == key) {
+            Some((_, existing)) => *existing = value.to_owned(),
+            None => tags.push((key.to_owned(), value.to_owned())),
+        }
+        Ok(())
+    };
+    // A single trailing comma is allowed.
+    let tagset = tagset.strip_suffix(',').unwrap_or(tagset);
+    for tag in tagset.split(',') {
+        let Some((key, value)) = tag.split_once('=') else {
+            return Err(DecodeError::Invalid);
+        };
+        if key.is_empty()
+            || !key.chars().all(is_valid_key_char)
+            || !value.chars().all(is_valid_value_char)
+        {
+            return Err(DecodeError::Invalid);
+        }
+        insert(key, value)?;
+    }
+    Ok(tags)
+}
+
+/// Encodes key/value pairs into a tagset, in order, like `encode_tagset_values()`. Spaces around
+/// the keys and values are trimmed, and the pairs are checked in order, so the first error found
+/// is returned.
+pub fn encode<'a>(
+    tags: impl IntoIterator<Item = (&'a str, &'a str)>,
+    max_size: usize,
+) -> Result<String, EncodeError> {
+    let mut tagset = String::new();
+    for (i, (key, value)) in tags.into_iter().enumerate() {
+        let (key, value) = (key.trim_matches(' '), value.trim_matches(' '));
+        if key.is_empty()
+            || value.is_empty()
+            || !key.chars().all(is_valid_key_char)
+            || !value.chars().all(is_valid_value_char)
+        {
+            return Err(EncodeError::Invalid);
+        }
+        let separator = if i > 0 { "," } else { "" };
+        if tagset.len() + separator.len() + key.len() + 1 + value.len() > max_size {
+            return Err(EncodeError::MaxSize);
+        }
+        tagset.push_str(separator);
+        tagset.push_str(key);
+        tagset.push('=');
+        tagset.push_str(value);
+    }
+    Ok(tagset)
+}
@@ -0,0 +1,416 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use super::{LAST_DD_PARENT_ID_KEY, PROPAGATED_TAG_PREFIX, SAMPLING_DECISION_KEY, USER_ID_KEY};
+
+const TRACEPARENT_KEY: &str = "traceparent";
+const TRACESTATE_KEY: &str = "tracestate";
+/// Longest `dd` list member, without its `dd=` key.
+const MAX_DD_MEMBER_LEN: usize = 256;
+
+/// Values of a `traceparent` header.
+pub struct Traceparent {
+    pub trace_id: u128,
+    pub span_id: u64,
+    pub sampled: bool,
+}
+
+/// Returns whether a character can be in a `tracestate` header.
+fn is_printable(c: char) -> bool {
+    (' '..='~').contains(&c)
+}
+
+fn parse_hex(value: &str, len: usize) -> Option<u128> {
+    let lowercase = value
+        .bytes()
+        .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+    if value.len() != len || !lowercase {
+        return None;
+    }
+    u128::from_str_radix(value, 16).ok()
+}
+
+/// Parses a `traceparent` header like `_TraceContext._get_traceparent_values()`: versions other
+/// than `00` may have more fields, and `ff` is invalid, as are zero IDs.
+pub fn parse_traceparent(traceparent: &str) -> Result<Traceparent, String> {
+    let invalid = || format!("Invalid traceparent version: {traceparent}");
+    let mut fields = traceparent.trim().splitn(5, '-');
+    let mut field = |len: usize| fields.next().and_then(|field| parse_hex(field, len));
+    let version = field(2).ok_or_else(invalid)?;
+    let trace_id = field(32).ok_or_else(invalid)?;
+    let span_id = field(16).ok_or_else(invalid)? as u64;
+    let flags = field(2).ok_or_else(invalid)?;
+    let future = fields.next();
+    if future == Some("") {
+        return Err(invalid());
+    }
+    match version {
+        0xff => {
+            return Err(format!(
+                "ff is an invalid traceparent version: {traceparent}"
+            ))
+        }
+        0x00 if future.is_some() => {
+            return Err(format!(
+                "Traceparents with the version `00` should contain 4 values delimited by a dash: \
+                 {traceparent}"
+            ))
+        }
+        _ => {}
+    }
+    if trace_id == 0 {
+        return Err("0 value for trace_id is invalid".to_owned());
+    }
+    if span_id == 0 {
+        return Err("0 value for span_id is invalid".to_owned());
+    }
+    Ok(Traceparent {
+        trace_id,
+        span_id,
+        sampled: flags & 0x1 != 0,
+    })
+}
+
+/// Values of the `dd` list member of a `tracestate` header.
+#[derive(Default)]
+pub struct DdMember {
+    pub sampling_priority: Option<i32>,
+    pub origin: Option<String>,
+    /// Last Datadog parent ID, to reconnect traces with spans of other vendors.
+    pub last_parent_id: Option<String>,
+    /// `t.` members, as `_dd.p.` tags.
+    pub tags: Vec<(String, String)>,
+}
+
+/// Parses the `dd` list member of a `tracestate` header, or returns `None` if it is invalid.
+fn parse_dd_member(member: &str) -> Option<DdMember> {
+    let mut dd = DdMember::default();
+    for item in member.split(';') {
+        let (key, value) = item.split_once(':')?;
+        // `=` is encoded as `~` in tracestate
+        let decoded = || value.replace('~', "=");
+        match key {
+            "s" => dd.sampling_priority = Some(value.trim().parse().ok()?),
+            "o" => dd.origin = Some(decoded()),
+            "p" => dd.last_parent_id = Some(value.to_owned()),
+            _ => {
+                if let Some(key) = key.strip_prefix("t.") {
+                    let key = format!("{PROPAGATED_TAG_PREFIX}{key}");
+                    match dd.tags.iter_mut().find(|(existing, _)| *existing == key) {
+                        Some((_, existing)) => *existing = decoded(),
+                        None => dd.tags.push((key, decoded())),
+                    }
+                }
+            }
+        }
+    }
+    Some(dd)
+}
+
+/// Parses a `tracestate` header like `_TraceContext._get_context()`, returning it with the
+/// whitespace around its list members trimmed, for the other vendors' members to be propagated,
+/// along with its `dd` member if valid. Returns `None` if it has characters outside of `0x20` to
+/// `0x7E`.
+pub fn parse_tracestate(tracestate: &str) -> Option<(String, Option<DdMember>)> {
+    let members: Vec<&str> = tracestate.split(',').map(str::trim).collect();
+    let tracestate = members.join(",");
+    if !tracestate.chars().all(is_printable) {
+        return None;
+    }
+    let dd = match members.iter().rev().find_map(|m| m.strip_prefix("dd=")) {
+        Some(member) => parse_dd_member(member),
+        None => Some(DdMember::default()),
+    };
+    Some((tracestate, dd))
+}
+
+/// Returns the sampling priority of a context from the sampled flag of its `traceparent` and the
+/// priority of its `tracestate`, like `_TraceContext._get_sampling_priority()`: the latter is only
+/// kept if it agrees with the flag, or if the trace comes from RUM without one.
+pub fn sampling_priority(
+    sampled: bool,
+    priority: Option<i32>,
+    origin: Option<&str>,
+) -> Option<i32> {
+    let from_rum_without_priority = priority.unwrap_or(0) == 0 && origin == Some("rum");
+    match priority.filter(|&priority| priority != 0) {
+        _ if from_rum_without_priority => priority,
+        Some(priority) if (priority > 0) == sampled => Some(priority),
+        _ => Some(sampled as i32),
+    }
+}
+
+/// Replaces the characters that can't be in a `tracestate` key or value: runs of characters
+/// outside of `0x20` to `0x7E` and the `invalid` ones by `_`, then `=` by `~`, like
+/// `w3c_encode_tag()`.
+fn encode(value: &str, invalid: &[char]) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    let mut in_run = false;
+    for c in value.chars() {
+        if !is_printable(c) {
+            if !in_run {
+                encoded.push('_');
+            }
+            in_run = true;
+            continue;
+        }
+        in_run = false;
+        encoded.push(match c {
+            c if invalid.contains(&c) => '_',
+            '=' => '~',
+            c => c,
+        });
+    }
+    encoded
+}
+
+fn encode_key(key: &str) -> String {
+    encode(key, &[',', ' ', '='])
+}
+
+fn encode_value(value: &str) -> String {
+    encode(value, &[',', ';', '~'])
+}
+
+/// Removes the `dd` list members of a `tracestate` header, like
+/// `re.sub("dd=(.+?)(?:,|$)", "", tracestate)`.
+fn remove_dd_members(tracestate: &str) -> String {
+    let mut remaining = String::with_capacity(tracestate.len());
+    let mut rest = tracestate;
+    while let Some(start) = rest.find("dd=") {
+        let value = &rest[start + 3..];
+        // The value has at least one character, up to the next comma or the end.
+        let end = match value.char_indices().nth(1) {
+            Some((next, _)) => value[next..]
+                .find(',')
+                .map(|comma| start + 3 + next + comma + 1),
+            None => None,
+        }
+        .or_else(|| (!value.is_empty()).then_some(rest.len()));
+        match end {
+            Some(end) => {
+                remaining.push_str(&rest[..start]);
+                rest = &rest[end..];
+            }
+            None => {
+                remaining.push_str(&rest[..start + 1]);
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    remaining.push_str(rest);
+    remaining
+}
+
+/// Adds the last Datadog parent ID to a `tracestate` header, like `w3c_tracestate_add_p()`.
+fn add_last_parent_id(tracestate: &str, span_id: u64) -> String {
+    let member = format!("p:{span_id:016x}");
+    if tracestate.contains("dd=") {
+        tracestate.replace("dd=", &format!("dd={member};"))
+    } else if !tracestate.is_empty() {
+        format!("dd={member},{tracestate}")
+    } else {
+        format!("dd={member}")
+    }
+}
+
+/// Fields of a `ddtrace.context.Context` the headers are built from.
+struct ContextFields<'py> {
+    trace_id: Option<u128>,
+    span_id: Option<u64>,
+    sampling_priority: Option<Bound<'py, PyAny>>,
+    origin: Option<String>,
+    meta: Bound<'py, PyDict>,
+    is_remote: bool,
+}
+
+impl<'py> ContextFields<'py> {
+    fn extract(context: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let sampling_priority = context.getattr("sampling_priority")?;
+        Ok(ContextFields {
+            trace_id: context.getattr("trace_id")?.extract()?,
+            span_id: context.getattr("span_id")?.extract()?,
+            sampling_priority: (!sampling_priority.is_none()).then_some(sampling_priority),
+            origin: context.getattr("dd_origin")?.extract()?,
+            meta: context.getattr("_meta")?.downcast_into()?,
+            is_remote: context.getattr("_is_remote")?.is_truthy()?,
+        })
+    }
+
+    fn meta(&self, key: &str) -> PyResult<Option<String>> {
+        match self.meta.get_item(key)? {
+            Some(value) if !value.is_none() => Ok(Some(value.str()?.to_string())),
+            _ => Ok(None),
+        }
+    }
+
+    fn sampled(&self) -> PyResult<bool> {
+        match &self.sampling_priority {
+            Some(priority) => Ok(priority.extract::<f64>()? > 0.0),
+            None => Ok(false),
+        }
+    }
+
+    /// Returns the `traceparent` header, like `Context._traceparent`: the trace ID of the
+    /// incoming `traceparent` is kept as is.
+    fn traceparent(&self) -> PyResult<String> {
+        let traceparent = self.meta(TRACEPARENT_KEY)?;
+        let (Some(trace_id), Some(span_id)) = (self.trace_id, self.span_id) else {
+            return Ok(traceparent.unwrap_or_default());
+        };
+        let trace_id = match traceparent.as_deref().and_then(|tp| tp.split('-').nth(1)) {
+            Some(trace_id) => trace_id.to_owned(),
+            None => format!("{trace_id:032x}"),
+        };
+        let flags = if self.sampled()? { "01" } else { "00" };
+        Ok(format!("00-{trace_id}-{span_id:016x}-{flags}"))
+    }
+
+    /// Returns the `dd` list member, like `w3c_get_dd_list_member()`: propagated tags are only
+    /// added while the member is at most 256 characters long.
+    fn dd_member(&self) -> PyResult<String> {
+        let mut members = Vec::new();
+        if let Some(priority) = &self.sampling_priority {
+            members.push(format!("s:{}", priority.str()?));
+        }
+        if let Some(origin) = self.origin.as_deref().filter(|origin| !origin.is_empty()) {
+            members.push(format!("o:{}", encode_value(origin)));
+        }
+        if let Some(decision) = self.meta(SAMPLING_DECISION_KEY)?.filter(|v| !v.is_empty()) {
+            members.push(format!("t.dm:{}", encode_value(&decision)));
+        }
+        if let Some(user_id) = self.meta(USER_ID_KEY)?.filter(|v| !v.is_empty()) {
+            members.push(format!("t.usr.id:{}", encode_value(&user_id)));
+        }
+        let mut len: usize = members.iter().map(String::len).sum();
+        // A snapshot of the items, as `str()` may run code changing the meta.
+        for item in self.meta.items() {
+            let (key, value): (Bound<'_, PyAny>, Bound<'_, PyAny>) = item.extract()?;
+            let Ok(key) = key.extract::<&str>() else {
+                continue;
+            };
+            if !key.starts_with(PROPAGATED_TAG_PREFIX)
+                || key == SAMPLING_DECISION_KEY
+                || key == USER_ID_KEY
+            {
+                continue;
+            }
+            let key = key.replace(PROPAGATED_TAG_PREFIX, "t.");
+            let member = format!(
+                "{}:{}",
+                encode_key(&key),
+                encode_value(&value.str()?.to_string())
+            );
+            if len + member.len() <= MAX_DD_MEMBER_LEN {
+                len += member.len();
+                members.push(member);
+            }
+        }
+        Ok(members.join(";"))
+    }
+
+    /// Returns the `tracestate` header, like `Context._tracestate`: the `dd` member replaces the
+    /// incoming one, and the members of other vendors are kept.
+    fn tracestate(&self) -> PyResult<String> {
+        let member = self.dd_member()?;
+        let tracestate = self.meta(TRACESTATE_KEY)?.unwrap_or_default();
+        if member.is_empty() {
+            return Ok(tracestate);
+        }
+        let others = remove_dd_members(&tracestate);
+        if others.is_empty() {
+            Ok(format!("dd={member}"))
+        } else {
+            Ok(format!("dd={member},{others}"))
+        }
+    }
+}
+
+/// Parses a `traceparent` header, and returns its trace ID, span ID and sampled flag, or raises
+/// `ValueError` if it is invalid.
+#[pyfunction]
+pub fn w3c_parse_traceparent(traceparent: &str) -> PyResult<(u128, u64, u8)> {
+    let traceparent = parse_traceparent(traceparent).map_err(PyValueError::new_err)?;
+    Ok((
+        traceparent.trace_id,
+        traceparent.span_id,
+        traceparent.sampled as u8,
+    ))
+}
+
+/// Parses a `tracestate` header, and returns it trimmed, for it to be kept in the meta of the
+/// context, with the sampling priority, the propagated tags, the origin and the last Datadog
+/// parent ID of its `dd` member. The values of a missing or invalid `dd` member are `None` and an
+/// empty dict. Returns `None` if the header is invalid.
+///
+/// With the sampled flag of the `traceparent`, the sampling priority is the one of the context.
+#[pyfunction]
+#[pyo3(signature = (tracestate, sampled = None))]
+#[allow(clippy::type_complexity)]
+pub fn w3c_parse_tracestate<'py>(
+    py: Python<'py>,
+    tracestate: &str,
+    sampled: Option<bool>,
+) -> PyResult<
+    Option<(
+        String,
+        Option<i32>,
+        Bound<'py, PyDict>,
+        Option<String>,
+        Option<String>,
+    )>,
+> {
+    let Some((tracestate, dd)) = parse_tracestate(tracestate) else {
+        return Ok(None);
+    };
+    let dd = dd.unwrap_or_default();
+    let tags = PyDict::new_bound(py);
+    for (key, value) in &dd.tags {
+        tags.set_item(key, value)?;
+    }
+    let priority = match sampled {
+        Some(sampled) => sampling_priority(sampled, dd.sampling_priority, dd.origin.as_deref()),
+        None => dd.sampling_priority,
+    };
+    Ok(Some((
+        tracestate,
+        priority,
+        tags,
+        dd.origin,
+        dd.last_parent_id,
+    )))
+}
+
+/// Returns the `traceparent` and `tracestate` headers of a context, like
+/// `_TraceContext._inject()`: an empty dict if it can't have a `traceparent`.
+#[pyfunction]
+pub fn w3c_build_headers<'py>(
+    py: Python<'py>,
+    context: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let context = ContextFields::extract(context)?;
+    let headers = PyDict::new_bound(py);
+    let traceparent = context.traceparent()?;
+    if traceparent.is_empty() {
+        return Ok(headers);
+    }
+    headers.set_item(TRACEPARENT_KEY, traceparent)?;
+    let tracestate = context.tracestate()?;
+    let tracestate = if !context.is_remote {
+        // The active span is a Datadog one, so it is the last Datadog parent.
+        add_last_parent_id(&tracestate, context.span_id.unwrap_or(0))
+    } else {
+        match context.meta(LAST_DD_PARENT_ID_KEY)? {
+            Some(span_id) => {
+                let span_id = u64::from_str_radix(&span_id, 16).map_err(|_| {
+                    PyValueError::new_err(format!("invalid {LAST_DD_PARENT_ID_KEY}: {span_id}"))
+                })?;
+                add_last_parent_id(&tracestate, span_id)
+            }
+            None => tracestate,
+        }
+    };
+    headers.set_item(TRACESTATE_KEY, tracestate)?;
+    Ok(headers)
+}
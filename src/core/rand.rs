@@ -0,0 +1,95 @@
+// Generation of trace and span IDs, like `ddtrace.internal._rand`: a xorshift* generator with
+// the constants of the Ranq1 algorithm of Numerical Recipes, one per thread, so that generating an
+// ID takes no lock.
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use pyo3::prelude::*;
+use pyo3::types::IntoPyDict;
+
+const SEED_MASK: u64 = 4101842887655102017;
+const MULTIPLIER: u64 = 2685821657736338717;
+
+/// Incremented in the children after a fork, for the generators inherited from the parent to be
+/// reseeded instead of generating the same IDs as the parent.
+static FORK_GENERATION: AtomicU64 = AtomicU64::new(0);
+static FORK_HOOK: OnceLock<()> = OnceLock::new();
+
+thread_local! {
+    /// Fork generation the generator of the thread was seeded at, and its state.
+    static STATE: Cell<Option<(u64, u64)>> = const { Cell::new(None) };
+}
+
+fn register_fork_hook(py: Python<'_>) -> PyResult<()> {
+    if FORK_HOOK.get().is_some() {
+        return Ok(());
+    }
+    let os = py.import_bound("os")?;
+    if os.hasattr("register_at_fork")? {
+        let kwargs =
+            [("after_in_child", wrap_pyfunction!(reseed_after_fork, py)?)].into_py_dict_bound(py);
+        os.call_method("register_at_fork", (), Some(&kwargs))?;
+    }
+    FORK_HOOK.get_or_init(|| ());
+    Ok(())
+}
+
+#[pyfunction]
+fn reseed_after_fork() {
+    FORK_GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns a new seed. The keys of `RandomState` are inherited by forked children, so the process
+/// ID, the thread and the time are mixed in as well.
+fn seed(generation: u64) -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    now.as_nanos().hash(&mut hasher);
+    std::process::id().hash(&mut hasher);
+    std::thread::current().id().hash(&mut hasher);
+    generation.hash(&mut hasher);
+    // The state of a xorshift generator must never be zero.
+    match hasher.finish() ^ SEED_MASK {
+        0 => SEED_MASK,
+        state => state,
+    }
+}
+
+fn next_u64() -> u64 {
+    let generation = FORK_GENERATION.load(Ordering::Relaxed);
+    STATE.with(|cell| {
+        let mut state = match cell.get() {
+            Some((seeded_at, state)) if seeded_at == generation => state,
+            _ => seed(generation),
+        };
+        state ^= state >> 21;
+        state ^= state << 35;
+        state ^= state >> 4;
+        cell.set(Some((generation, state)));
+        state.wrapping_mul(MULTIPLIER)
+    })
+}
+
+/// Returns a random 64-bit span ID, like `rand64bits()`.
+#[pyfunction]
+pub fn gen_span_id(py: Python<'_>) -> PyResult<u64> {
+    register_fork_hook(py)?;
+    Ok(next_u64())
+}
+
+/// Returns a random 128-bit trace ID, like `rand128bits()`: the Unix time in seconds in the
+/// upper 32 bits, then 32 bits of zeroes, and 64 random bits.
+#[pyfunction]
+pub fn gen_trace_id_128(py: Python<'_>) -> PyResult<u128> {
+    register_fork_hook(py)?;
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as u32);
+    Ok((u128::from(seconds) << 96) | u128::from(next_u64()))
+}
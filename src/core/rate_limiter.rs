@@ -0,0 +1,1291 @@
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+use lru::LruCache;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{IntoPyDict, PyType};
+
+pub(crate) const SECOND_NS: u64 = 1_000_000_000;
+
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+/// Nanoseconds since the first use of the clock in this process. `Instant` is backed by the system
+/// monotonic clock, so forked children inherit the epoch and keep consistent timestamps, but
+/// timestamps are meaningless in any other process: pickled limiter state never includes them.
+pub(crate) fn monotonic_ns() -> u64 {
+    EPOCH.get_or_init(Instant::now).elapsed().as_nanos() as u64
+}
+
+const UNPINNED: u64 = u64::MAX;
+
+/// Monotonic clock that can be pinned to a fixed time through the `_now_ns` attribute of the
+/// limiters, so tests can advance time deterministically instead of sleeping.
+///
+/// `is_allowed` also accepts a caller-supplied `timestamp_ns` (e.g. to replay events with
+/// historical timestamps). Such timestamps must then be supplied on every call, since they are not
+/// comparable with the clock's. A timestamp of 0 means now, like in Python.
+struct Clock {
+    pinned_ns: AtomicU64,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock {
+            pinned_ns: AtomicU64::new(UNPINNED),
+        }
+    }
+}
+
+impl Clock {
+    fn now_ns(&self) -> u64 {
+        match self.pinned_ns.load(Ordering::Relaxed) {
+            UNPINNED => monotonic_ns(),
+            now_ns => now_ns,
+        }
+    }
+
+    fn pinned_ns(&self) -> Option<u64> {
+        match self.pinned_ns.load(Ordering::Relaxed) {
+            UNPINNED => None,
+            now_ns => Some(now_ns),
+        }
+    }
+
+    fn pin(&self, now_ns: Option<u64>) {
+        self.pinned_ns
+            .store(now_ns.unwrap_or(UNPINNED), Ordering::Relaxed);
+    }
+}
+
+fn window_ns(time_window: f64) -> PyResult<u64> {
+    if time_window.is_nan() || time_window < 1.0 {
+        return Err(PyValueError::new_err(
+            "time_window must be at least 1 nanosecond",
+        ));
+    }
+    Ok(time_window as u64)
+}
+
+/// Return type of `__reduce__`: the class, its constructor arguments and the `__setstate__` state.
+type Reduced<'py, Args, State> = (Bound<'py, PyType>, Args, State);
+
+/// Requests can ask for several permits at once, which are then granted or denied as a whole.
+fn check_permits(n: u64) -> PyResult<()> {
+    if n == 0 {
+        return Err(PyValueError::new_err("n must be at least 1"));
+    }
+    Ok(())
+}
+
+/// Tracks allowed vs. total decisions per window, and reports the effective rate exactly the way
+/// the Python `RateLimiter` does for the `_dd.limit_psr` tag:
+///
+/// - a window starts with the first decision made after the previous window is over, so windows
+///   are not aligned on multiples of the window duration, and a start time of 0 means that no
+///   window has started yet;
+/// - the rate of a window is allowed / total decisions, or 1.0 if no decision was made;
+/// - the effective rate is the current window's rate averaged with the previous window's, or the
+///   current window's rate alone until a window is over.
+#[derive(Clone)]
+pub(crate) struct EffectiveRate {
+    window_ns: u64,
+    current_window_ns: u64,
+    allowed: u64,
+    total: u64,
+    prev_window_rate: Option<f64>,
+}
+
+impl EffectiveRate {
+    pub(crate) fn new(window_ns: u64) -> Self {
+        EffectiveRate {
+            window_ns,
+            current_window_ns: 0,
+            allowed: 0,
+            total: 0,
+            prev_window_rate: None,
+        }
+    }
+
+    pub(crate) fn update(&mut self, allowed: bool, n: u64, now_ns: u64) {
+        if self.current_window_ns == 0 {
+            self.current_window_ns = now_ns;
+        } else if now_ns as i64 - self.current_window_ns as i64 >= self.window_ns as i64 {
+            self.prev_window_rate = Some(self.current_window_rate());
+            self.allowed = 0;
+            self.total = 0;
+            self.current_window_ns = now_ns;
+        }
+        if allowed {
+            self.allowed += n;
+        }
+        self.total += n;
+    }
+
+    fn current_window_rate(&self) -> f64 {
+        // No requests seen yet, effectively a 100% rate
+        if self.total == 0 {
+            return 1.0;
+        }
+        self.allowed as f64 / self.total as f64
+    }
+
+    pub(crate) fn effective_rate(&self) -> f64 {
+        match self.prev_window_rate {
+            None => self.current_window_rate(),
+            Some(prev) => (self.current_window_rate() + prev) / 2.0,
+        }
+    }
+}
+
+const COUNT_BITS: u32 = 21;
+const COUNT_MASK: u64 = (1 << COUNT_BITS) - 1;
+const INDEX_MASK: u64 = (1 << (64 - 2 * COUNT_BITS)) - 1;
+
+/// A window index and two counters packed in a single `u64`, so that they can be updated together
+/// with one compare-and-swap. Indexes wrap around after 2^22 windows and counters are limited to
+/// 2^21 - 1.
+#[derive(Clone, Copy)]
+struct PackedWindow {
+    index: u64,
+    first: u64,
+    second: u64,
+}
+
+impl PackedWindow {
+    fn unpack(bits: u64) -> Self {
+        PackedWindow {
+            index: bits >> (2 * COUNT_BITS),
+            first: (bits >> COUNT_BITS) & COUNT_MASK,
+            second: bits & COUNT_MASK,
+        }
+    }
+
+    fn pack(self) -> u64 {
+        (self.index & INDEX_MASK) << (2 * COUNT_BITS)
+            | (self.first & COUNT_MASK) << COUNT_BITS
+            | (self.second & COUNT_MASK)
+    }
+
+    /// Returns how many windows `index` is ahead of this window, or `None` if it is behind, which
+    /// happens when a thread read the clock before another one moved to a newer window.
+    fn windows_until(&self, index: u64) -> Option<u64> {
+        let ahead = index.wrapping_sub(self.index) & INDEX_MASK;
+        if ahead > INDEX_MASK / 2 {
+            None
+        } else {
+            Some(ahead)
+        }
+    }
+
+    /// Moves the (previous, current) counts of a sliding window forward to the window `index`.
+    fn rolled(self, index: u64) -> Self {
+        match self.windows_until(index) {
+            Some(0) | None => self,
+            Some(1) => PackedWindow {
+                index,
+                first: self.second,
+                second: 0,
+            },
+            Some(_) => PackedWindow {
+                index,
+                first: 0,
+                second: 0,
+            },
+        }
+    }
+}
+
+fn check_packed_rate_limit(rate_limit: i64) -> PyResult<()> {
+    if rate_limit > COUNT_MASK as i64 {
+        return Err(PyValueError::new_err(format!(
+            "rate_limit must be at most {COUNT_MASK}"
+        )));
+    }
+    Ok(())
+}
+
+fn window_index(now_ns: u64, window_ns: u64) -> u64 {
+    (now_ns / window_ns) & INDEX_MASK
+}
+
+/// Cumulative allowed/denied permit counts, with an optional Python callback invoked with both
+/// counts every `interval` decisions, e.g. to report spans dropped by a limiter to telemetry.
+struct Decisions {
+    allowed: AtomicU64,
+    denied: AtomicU64,
+    decisions: AtomicU64,
+    callback: Option<PyObject>,
+    interval: u64,
+}
+
+impl Decisions {
+    fn new(callback: Option<PyObject>, interval: u64) -> PyResult<Self> {
+        if interval == 0 {
+            return Err(PyValueError::new_err("metrics_interval must be at least 1"));
+        }
+        Ok(Decisions {
+            allowed: AtomicU64::new(0),
+            denied: AtomicU64::new(0),
+            decisions: AtomicU64::new(0),
+            callback,
+            interval,
+        })
+    }
+
+    fn allowed(&self) -> u64 {
+        self.allowed.load(Ordering::Relaxed)
+    }
+
+    fn denied(&self) -> u64 {
+        self.denied.load(Ordering::Relaxed)
+    }
+
+    fn record(&self, py: Python<'_>, allowed: bool, n: u64) {
+        let counter = if allowed { &self.allowed } else { &self.denied };
+        counter.fetch_add(n, Ordering::Relaxed);
+
+        let Some(callback) = &self.callback else {
+            return;
+        };
+        let decisions = self.decisions.fetch_add(1, Ordering::Relaxed) + 1;
+        if decisions % self.interval != 0 {
+            return;
+        }
+        // A failing metrics callback must not turn into a failing rate limiting decision
+        if let Err(err) = callback.call1(py, (self.allowed(), self.denied())) {
+            err.write_unraisable_bound(py, Some(callback.bind(py)));
+        }
+    }
+
+    fn reset(&self) {
+        self.allowed.store(0, Ordering::Relaxed);
+        self.denied.store(0, Ordering::Relaxed);
+        self.decisions.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Sliding window counter: the number of requests in the previous window is weighted by how much
+/// of it still overlaps the rolling window ending now, so bursts at window boundaries are limited
+/// to `rate_limit` instead of twice that.
+///
+/// The window index, previous count and current count live in a single atomic word, and a request
+/// is only allowed by a successful compare-and-swap of that word, so concurrent callers never need
+/// a lock (or the GIL). Every allowed decision takes effect atomically at its compare-and-swap and
+/// every denied decision at the load it was based on, which makes `is_allowed` linearizable: the
+/// number of requests allowed in any window never exceeds what a serial execution would allow.
+struct SlidingWindow {
+    rate_limit: AtomicI64,
+    window_ns: u64,
+    state: AtomicU64,
+}
+
+impl SlidingWindow {
+    fn new(rate_limit: i64, window_ns: u64) -> Self {
+        SlidingWindow {
+            rate_limit: AtomicI64::new(rate_limit),
+            window_ns,
+            state: AtomicU64::new(0),
+        }
+    }
+
+    fn rate_limit(&self) -> i64 {
+        self.rate_limit.load(Ordering::Relaxed)
+    }
+
+    /// Changes the rate limit, keeping the counts of the current and previous windows so that the
+    /// requests already allowed are still accounted for under the new limit.
+    fn set_rate_limit(&self, rate_limit: i64) {
+        self.rate_limit.store(rate_limit, Ordering::Relaxed);
+    }
+
+    fn is_allowed(&self, now_ns: u64, n: u64) -> bool {
+        // Rate limit of 0 blocks everything, negative rate limit disables rate limiting
+        let rate_limit = self.rate_limit();
+        if rate_limit == 0 {
+            return false;
+        } else if rate_limit < 0 {
+            return true;
+        }
+
+        let index = window_index(now_ns, self.window_ns);
+        let mut bits = self.state.load(Ordering::Acquire);
+        loop {
+            // first: requests allowed in the previous window, second: in the current window
+            let mut window = PackedWindow::unpack(bits).rolled(index);
+            // If another thread already moved to a newer window, the request is counted at the
+            // start of that window, where the previous window weighs the most.
+            let elapsed = if window.index == index {
+                (now_ns % self.window_ns) as f64 / self.window_ns as f64
+            } else {
+                0.0
+            };
+            let estimated = window.first as f64 * (1.0 - elapsed) + window.second as f64;
+            if estimated + n as f64 > rate_limit as f64 {
+                return false;
+            }
+            window.second += n;
+            match self.state.compare_exchange_weak(
+                bits,
+                window.pack(),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => bits = actual,
+            }
+        }
+    }
+
+    /// Returns the (previous, current) window counts as seen at `now_ns`.
+    fn counts(&self, now_ns: u64) -> (u64, u64) {
+        let index = window_index(now_ns, self.window_ns);
+        let window = PackedWindow::unpack(self.state.load(Ordering::Acquire)).rolled(index);
+        (window.first, window.second)
+    }
+
+    fn restore_counts(&self, now_ns: u64, previous: u64, current: u64) {
+        let window = PackedWindow {
+            index: window_index(now_ns, self.window_ns),
+            first: previous.min(COUNT_MASK),
+            second: current.min(COUNT_MASK),
+        };
+        self.state.store(window.pack(), Ordering::Release);
+    }
+
+    fn reset(&self) {
+        self.state.store(0, Ordering::Release);
+    }
+}
+
+const RATE_FIELD_BITS: u32 = 16;
+const RATE_FIELD_MASK: u64 = (1 << RATE_FIELD_BITS) - 1;
+/// Fixed-point 1.0 of the previous window's rate, which is exact for sums of powers of two down to
+/// 2^-15, and within 2^-16 of the actual rate otherwise.
+const RATE_ONE: u64 = 1 << 15;
+/// Previous window's rate until a window is over.
+const NO_RATE: u64 = RATE_FIELD_MASK;
+
+/// State of `AtomicEffectiveRate` packed in a single `u64`: a window index, the previous window's
+/// rate, and the allowed and total requests of the current window, 16 bits each. Indexes wrap
+/// around after 2^16 windows, and counts are halved together when the total would overflow, which
+/// keeps their ratio.
+#[derive(Clone, Copy)]
+struct RateWindow {
+    index: u64,
+    prev_rate: u64,
+    allowed: u64,
+    total: u64,
+}
+
+impl RateWindow {
+    const EMPTY: RateWindow = RateWindow {
+        index: 0,
+        prev_rate: NO_RATE,
+        allowed: 0,
+        total: 0,
+    };
+
+    fn unpack(bits: u64) -> Self {
+        RateWindow {
+            index: bits >> (3 * RATE_FIELD_BITS),
+            prev_rate: (bits >> (2 * RATE_FIELD_BITS)) & RATE_FIELD_MASK,
+            allowed: (bits >> RATE_FIELD_BITS) & RATE_FIELD_MASK,
+            total: bits & RATE_FIELD_MASK,
+        }
+    }
+
+    fn pack(self) -> u64 {
+        (self.index & RATE_FIELD_MASK) << (3 * RATE_FIELD_BITS)
+            | (self.prev_rate & RATE_FIELD_MASK) << (2 * RATE_FIELD_BITS)
+            | (self.allowed & RATE_FIELD_MASK) << RATE_FIELD_BITS
+            | (self.total & RATE_FIELD_MASK)
+    }
+
+    fn current_window_rate(&self) -> f64 {
+        // No requests seen yet, effectively a 100% rate
+        if self.total == 0 {
+            return 1.0;
+        }
+        self.allowed as f64 / self.total as f64
+    }
+
+    /// Moves forward to the window `index`, keeping the rate of this window as the previous one
+    /// if it saw any request. A window that is behind, which happens when a thread read the clock
+    /// before another one moved to a newer window, is left as is, and counts the request.
+    fn rolled(self, index: u64) -> Self {
+        let ahead = index.wrapping_sub(self.index) & RATE_FIELD_MASK;
+        if ahead == 0 || ahead > RATE_FIELD_MASK / 2 {
+            return self;
+        }
+        let prev_rate = if self.total > 0 {
+            (self.current_window_rate() * RATE_ONE as f64).round() as u64
+        } else {
+            self.prev_rate
+        };
+        RateWindow {
+            index,
+            prev_rate,
+            allowed: 0,
+            total: 0,
+        }
+    }
+
+    fn counted(self, allowed: bool, n: u64) -> Self {
+        let mut total = self.total.saturating_add(n);
+        let mut allowed = if allowed {
+            self.allowed.saturating_add(n)
+        } else {
+            self.allowed
+        };
+        let shift = (u64::BITS - total.leading_zeros()).saturating_sub(RATE_FIELD_BITS);
+        total >>= shift;
+        allowed >>= shift;
+        RateWindow {
+            allowed,
+            total,
+            ..self
+        }
+    }
+}
+
+/// Lock-free counterpart of `EffectiveRate`, with windows aligned on the limiter's windows
+/// instead of starting at the first request. Rolling to a new window replaces the counts and the
+/// previous window's rate with a single compare-and-swap, so readers never see one without the
+/// other.
+struct AtomicEffectiveRate {
+    window_ns: u64,
+    state: AtomicU64,
+}
+
+impl AtomicEffectiveRate {
+    fn new(window_ns: u64) -> Self {
+        AtomicEffectiveRate {
+            window_ns,
+            state: AtomicU64::new(RateWindow::EMPTY.pack()),
+        }
+    }
+
+    fn update(&self, allowed: bool, n: u64, now_ns: u64) {
+        let index = (now_ns / self.window_ns) & RATE_FIELD_MASK;
+        let mut bits = self.state.load(Ordering::Acquire);
+        loop {
+            let window = RateWindow::unpack(bits).rolled(index).counted(allowed, n);
+            match self.state.compare_exchange_weak(
+                bits,
+                window.pack(),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return,
+                Err(actual) => bits = actual,
+            }
+        }
+    }
+
+    fn current_window_rate(&self) -> f64 {
+        RateWindow::unpack(self.state.load(Ordering::Acquire)).current_window_rate()
+    }
+
+    fn effective_rate(&self) -> f64 {
+        let window = RateWindow::unpack(self.state.load(Ordering::Acquire));
+        if window.prev_rate == NO_RATE {
+            return window.current_window_rate();
+        }
+        (window.current_window_rate() + window.prev_rate as f64 / RATE_ONE as f64) / 2.0
+    }
+
+    fn reset(&self) {
+        self.state
+            .store(RateWindow::EMPTY.pack(), Ordering::Release);
+    }
+}
+
+/// Returns the burst size of a bucket. By default, it holds `rate` tokens like the Python
+/// `RateLimiter`, so a rate below 1 never allows a request, as a request takes a whole token.
+pub(crate) fn bucket_size(rate: f64, burst: Option<f64>) -> PyResult<f64> {
+    if rate.is_nan() {
+        return Err(PyValueError::new_err("rate must be a number"));
+    }
+    match burst {
+        Some(burst) if burst.is_nan() || burst < 1.0 => {
+            Err(PyValueError::new_err("burst must be at least 1"))
+        }
+        Some(burst) => Ok(burst),
+        // Rates of 0 or less don't use the bucket
+        None => Ok(rate.max(0.0)),
+    }
+}
+
+/// Token bucket holding up to `burst` tokens and refilled at `rate` tokens per `window_ns`, so
+/// short bursts are allowed while the sustained throughput stays bounded by `rate`.
+#[derive(Clone)]
+pub(crate) struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    window_ns: u64,
+    tokens: f64,
+    last_update_ns: u64,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(rate: f64, burst: f64, window_ns: u64, now_ns: u64) -> Self {
+        TokenBucket {
+            rate,
+            burst,
+            window_ns,
+            tokens: burst,
+            last_update_ns: now_ns,
+        }
+    }
+
+    fn replenish(&mut self, now_ns: u64) {
+        let last_update_ns = std::mem::replace(&mut self.last_update_ns, now_ns);
+        // If we are at the max, we do not need to add any more
+        if self.tokens == self.burst {
+            return;
+        }
+        // Time going backwards takes tokens away, like in the Python limiter
+        let elapsed = (now_ns as i64 - last_update_ns as i64) as f64 / self.window_ns as f64;
+        self.tokens = self.burst.min(self.tokens + elapsed * self.rate);
+    }
+
+    pub(crate) fn is_allowed(&mut self, now_ns: u64, n: u64) -> bool {
+        // Rate of 0 blocks everything, negative rate disables rate limiting
+        if self.rate == 0.0 {
+            return false;
+        } else if self.rate < 0.0 {
+            return true;
+        }
+
+        self.replenish(now_ns);
+        if self.tokens < n as f64 {
+            return false;
+        }
+        self.tokens -= n as f64;
+        true
+    }
+
+    /// Changes the rate and burst size, keeping the number of tokens consumed from the bucket so
+    /// that a rate change neither grants a fresh burst nor starves the bucket.
+    fn set_rate(&mut self, now_ns: u64, rate: f64, burst: f64) {
+        // Tokens accumulated until now are earned at the previous rate
+        if self.rate > 0.0 {
+            self.replenish(now_ns);
+        } else {
+            self.last_update_ns = now_ns;
+        }
+        let consumed = (self.burst - self.tokens).max(0.0);
+        self.rate = rate;
+        self.burst = burst;
+        self.tokens = (burst - consumed).clamp(0.0, burst);
+    }
+
+    fn restore_tokens(&mut self, now_ns: u64, tokens: f64) {
+        self.tokens = tokens.clamp(0.0, self.burst);
+        self.last_update_ns = now_ns;
+    }
+}
+
+#[pyclass(
+    frozen,
+    name = "SlidingWindowRateLimiter",
+    module = "ddtrace.internal.core._core"
+)]
+pub struct SlidingWindowRateLimiterPy {
+    limiter: SlidingWindow,
+    rates: AtomicEffectiveRate,
+    clock: Clock,
+    decisions: Decisions,
+}
+
+impl SlidingWindowRateLimiterPy {
+    fn reset(&self) {
+        self.limiter.reset();
+        self.rates.reset();
+        self.decisions.reset();
+    }
+}
+
+#[pymethods]
+impl SlidingWindowRateLimiterPy {
+    #[new]
+    #[pyo3(signature = (
+        rate_limit,
+        time_window = 1e9,
+        metrics_callback = None,
+        metrics_interval = 1000
+    ))]
+    fn new(
+        rate_limit: i64,
+        time_window: f64,
+        metrics_callback: Option<PyObject>,
+        metrics_interval: u64,
+    ) -> PyResult<Self> {
+        check_packed_rate_limit(rate_limit)?;
+        let window_ns = window_ns(time_window)?;
+        Ok(SlidingWindowRateLimiterPy {
+            limiter: SlidingWindow::new(rate_limit, window_ns),
+            rates: AtomicEffectiveRate::new(window_ns),
+            clock: Clock::default(),
+            decisions: Decisions::new(metrics_callback, metrics_interval)?,
+        })
+    }
+
+    #[getter]
+    fn rate_limit(&self) -> i64 {
+        self.limiter.rate_limit()
+    }
+
+    fn set_rate(&self, rate_limit: i64) -> PyResult<()> {
+        check_packed_rate_limit(rate_limit)?;
+        self.limiter.set_rate_limit(rate_limit);
+        Ok(())
+    }
+
+    #[getter]
+    fn time_window(&self) -> f64 {
+        self.limiter.window_ns as f64
+    }
+
+    #[pyo3(signature = (n = 1, timestamp_ns = None))]
+    fn is_allowed(&self, py: Python<'_>, n: u64, timestamp_ns: Option<u64>) -> PyResult<bool> {
+        check_permits(n)?;
+        let now_ns = timestamp_ns
+            .filter(|&ts| ts != 0)
+            .unwrap_or_else(|| self.clock.now_ns());
+        let allowed = self.limiter.is_allowed(now_ns, n);
+        self.rates.update(allowed, n, now_ns);
+        self.decisions.record(py, allowed, n);
+        Ok(allowed)
+    }
+
+    fn __call__(&self, py: Python<'_>) -> PyResult<bool> {
+        self.is_allowed(py, 1, None)
+    }
+
+    /// Makes `count` single-permit decisions at once, and returns how many of them were allowed.
+    fn allow_many(&self, py: Python<'_>, count: u64) -> u64 {
+        let now_ns = self.clock.now_ns();
+        let mut allowed_count = 0;
+        for _ in 0..count {
+            let allowed = self.limiter.is_allowed(now_ns, 1);
+            self.rates.update(allowed, 1, now_ns);
+            self.decisions.record(py, allowed, 1);
+            allowed_count += allowed as u64;
+        }
+        allowed_count
+    }
+
+    #[getter]
+    fn effective_rate(&self) -> f64 {
+        self.rates.effective_rate()
+    }
+
+    #[getter]
+    fn current_window_rate(&self) -> f64 {
+        self.rates.current_window_rate()
+    }
+
+    #[getter]
+    fn allowed(&self) -> u64 {
+        self.decisions.allowed()
+    }
+
+    #[getter]
+    fn denied(&self) -> u64 {
+        self.decisions.denied()
+    }
+
+    #[getter(_now_ns)]
+    fn get_now_ns(&self) -> Option<u64> {
+        self.clock.pinned_ns()
+    }
+
+    #[setter(_now_ns)]
+    fn set_now_ns(&self, now_ns: Option<u64>) {
+        self.clock.pin(now_ns);
+    }
+
+    fn __reduce__<'py>(slf: &Bound<'py, Self>) -> Reduced<'py, (i64, f64), (u64, u64)> {
+        let limiter = slf.get();
+        (
+            slf.get_type(),
+            (limiter.rate_limit(), limiter.time_window()),
+            limiter.limiter.counts(limiter.clock.now_ns()),
+        )
+    }
+
+    fn __setstate__(&self, state: (u64, u64)) {
+        let (previous, current) = state;
+        self.limiter
+            .restore_counts(self.clock.now_ns(), previous, current);
+    }
+}
+
+/// With the default burst size, decisions and effective rates are identical to the ones of
+/// `ddtrace.internal.rate_limiter.RateLimiter(rate_limit, time_window)` for the same sequence of
+/// timestamps, so this limiter can replace it without changing the sampling math.
+#[pyclass(
+    name = "TokenBucketRateLimiter",
+    module = "ddtrace.internal.core._core"
+)]
+pub struct TokenBucketRateLimiterPy {
+    limiter: TokenBucket,
+    rates: EffectiveRate,
+    clock: Clock,
+    decisions: Decisions,
+}
+
+#[pymethods]
+impl TokenBucketRateLimiterPy {
+    #[new]
+    #[pyo3(signature = (
+        rate,
+        burst = None,
+        time_window = 1e9,
+        metrics_callback = None,
+        metrics_interval = 1000
+    ))]
+    fn new(
+        rate: f64,
+        burst: Option<f64>,
+        time_window: f64,
+        metrics_callback: Option<PyObject>,
+        metrics_interval: u64,
+    ) -> PyResult<Self> {
+        let burst = bucket_size(rate, burst)?;
+        let window_ns = window_ns(time_window)?;
+        Ok(TokenBucketRateLimiterPy {
+            limiter: TokenBucket::new(rate, burst, window_ns, monotonic_ns()),
+            rates: EffectiveRate::new(window_ns),
+            clock: Clock::default(),
+            decisions: Decisions::new(metrics_callback, metrics_interval)?,
+        })
+    }
+
+    #[getter]
+    fn rate(&self) -> f64 {
+        self.limiter.rate
+    }
+
+    #[getter]
+    fn burst(&self) -> f64 {
+        self.limiter.burst
+    }
+
+    #[getter]
+    fn time_window(&self) -> f64 {
+        self.limiter.window_ns as f64
+    }
+
+    #[pyo3(signature = (rate, burst = None))]
+    fn set_rate(&mut self, rate: f64, burst: Option<f64>) -> PyResult<()> {
+        let burst = bucket_size(rate, burst)?;
+        let now_ns = self.clock.now_ns();
+        self.limiter.set_rate(now_ns, rate, burst);
+        Ok(())
+    }
+
+    #[getter]
+    fn tokens(&self) -> f64 {
+        self.limiter.tokens
+    }
+
+    #[pyo3(signature = (n = 1, timestamp_ns = None))]
+    fn is_allowed(&mut self, py: Python<'_>, n: u64, timestamp_ns: Option<u64>) -> PyResult<bool> {
+        check_permits(n)?;
+        let now_ns = timestamp_ns
+            .filter(|&ts| ts != 0)
+            .unwrap_or_else(|| self.clock.now_ns());
+        let allowed = self.limiter.is_allowed(now_ns, n);
+        self.rates.update(allowed, n, now_ns);
+        self.decisions.record(py, allowed, n);
+        Ok(allowed)
+    }
+
+    fn __call__(&mut self, py: Python<'_>) -> PyResult<bool> {
+        self.is_allowed(py, 1, None)
+    }
+
+    /// Makes `count` single-permit decisions at once, and returns how many of them were allowed.
+    fn allow_many(&mut self, py: Python<'_>, count: u64) -> u64 {
+        let now_ns = self.clock.now_ns();
+        let mut allowed_count = 0;
+        for _ in 0..count {
+            let allowed = self.limiter.is_allowed(now_ns, 1);
+            self.rates.update(allowed, 1, now_ns);
+            self.decisions.record(py, allowed, 1);
+            allowed_count += allowed as u64;
+        }
+        allowed_count
+    }
+
+    #[getter]
+    fn effective_rate(&self) -> f64 {
+        self.rates.effective_rate()
+    }
+
+    #[getter]
+    fn current_window_rate(&self) -> f64 {
+        self.rates.current_window_rate()
+    }
+
+    #[getter]
+    fn allowed(&self) -> u64 {
+        self.decisions.allowed()
+    }
+
+    #[getter]
+    fn denied(&self) -> u64 {
+        self.decisions.denied()
+    }
+
+    #[getter(_now_ns)]
+    fn get_now_ns(&self) -> Option<u64> {
+        self.clock.pinned_ns()
+    }
+
+    #[setter(_now_ns)]
+    fn set_now_ns(&self, now_ns: Option<u64>) {
+        self.clock.pin(now_ns);
+    }
+
+    fn __reduce__<'py>(slf: &Bound<'py, Self>) -> Reduced<'py, (f64, f64, f64), f64> {
+        let limiter = slf.borrow();
+        (
+            slf.get_type(),
+            (limiter.rate(), limiter.burst(), limiter.time_window()),
+            limiter.tokens(),
+        )
+    }
+
+    fn __setstate__(&mut self, tokens: f64) {
+        let now_ns = self.clock.now_ns();
+        self.limiter.restore_tokens(now_ns, tokens);
+    }
+}
+
+/// Independent token buckets per key (e.g. probe id or endpoint). Buckets are kept in an LRU cache
+/// bounded by `max_keys`: when a new key is seen and the cache is full, the least recently used
+/// bucket is evicted, and the key starts again with a full bucket if it comes back.
+#[pyclass(name = "KeyedRateLimiter", module = "ddtrace.internal.core._core")]
+pub struct KeyedRateLimiterPy {
+    rate: f64,
+    burst: f64,
+    window_ns: u64,
+    buckets: LruCache<String, TokenBucket>,
+    clock: Clock,
+    decisions: Decisions,
+}
+
+#[pymethods]
+impl KeyedRateLimiterPy {
+    #[new]
+    #[pyo3(signature = (
+        rate,
+        burst = None,
+        time_window = 1e9,
+        max_keys = 1024,
+        metrics_callback = None,
+        metrics_interval = 1000
+    ))]
+    fn new(
+        rate: f64,
+        burst: Option<f64>,
+        time_window: f64,
+        max_keys: usize,
+        metrics_callback: Option<PyObject>,
+        metrics_interval: u64,
+    ) -> PyResult<Self> {
+        let burst = bucket_size(rate, burst)?;
+        let max_keys = NonZeroUsize::new(max_keys)
+            .ok_or_else(|| PyValueError::new_err("max_keys must be greater than 0"))?;
+        Ok(KeyedRateLimiterPy {
+            rate,
+            burst,
+            window_ns: window_ns(time_window)?,
+            buckets: LruCache::new(max_keys),
+            clock: Clock::default(),
+            decisions: Decisions::new(metrics_callback, metrics_interval)?,
+        })
+    }
+
+    #[getter]
+    fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    #[getter]
+    fn burst(&self) -> f64 {
+        self.burst
+    }
+
+    #[getter]
+    fn time_window(&self) -> f64 {
+        self.window_ns as f64
+    }
+
+    /// Changes the rate and burst size of every bucket, see `TokenBucketRateLimiter.set_rate`.
+    #[pyo3(signature = (rate, burst = None))]
+    fn set_rate(&mut self, rate: f64, burst: Option<f64>) -> PyResult<()> {
+        let burst = bucket_size(rate, burst)?;
+        let now_ns = self.clock.now_ns();
+        for (_, bucket) in self.buckets.iter_mut() {
+            bucket.set_rate(now_ns, rate, burst);
+        }
+        self.rate = rate;
+        self.burst = burst;
+        Ok(())
+    }
+
+    #[getter]
+    fn max_keys(&self) -> usize {
+        self.buckets.cap().get()
+    }
+
+    fn __len__(&self) -> usize {
+        self.buckets.len()
+    }
+
+    fn __contains__(&self, key: &str) -> bool {
+        self.buckets.contains(key)
+    }
+
+    #[pyo3(signature = (key, n = 1, timestamp_ns = None))]
+    fn is_allowed(
+        &mut self,
+        py: Python<'_>,
+        key: &str,
+        n: u64,
+        timestamp_ns: Option<u64>,
+    ) -> PyResult<bool> {
+        check_permits(n)?;
+        let now_ns = timestamp_ns
+            .filter(|&ts| ts != 0)
+            .unwrap_or_else(|| self.clock.now_ns());
+        let allowed = match self.buckets.get_mut(key) {
+            Some(bucket) => bucket.is_allowed(now_ns, n),
+            None => {
+                let mut bucket = TokenBucket::new(self.rate, self.burst, self.window_ns, now_ns);
+                let allowed = bucket.is_allowed(now_ns, n);
+                self.buckets.put(key.to_owned(), bucket);
+                allowed
+            }
+        };
+        self.decisions.record(py, allowed, n);
+        Ok(allowed)
+    }
+
+    fn forget(&mut self, key: &str) {
+        self.buckets.pop(key);
+    }
+
+    #[getter]
+    fn allowed(&self) -> u64 {
+        self.decisions.allowed()
+    }
+
+    #[getter]
+    fn denied(&self) -> u64 {
+        self.decisions.denied()
+    }
+
+    #[getter(_now_ns)]
+    fn get_now_ns(&self) -> Option<u64> {
+        self.clock.pinned_ns()
+    }
+
+    #[setter(_now_ns)]
+    fn set_now_ns(&self, now_ns: Option<u64>) {
+        self.clock.pin(now_ns);
+    }
+
+    /// Buckets are pickled from least to most recently used, so that restoring them in order
+    /// preserves the eviction order.
+    fn __reduce__<'py>(
+        slf: &Bound<'py, Self>,
+    ) -> Reduced<'py, (f64, f64, f64, usize), Vec<(String, f64)>> {
+        let limiter = slf.borrow();
+        let buckets = limiter
+            .buckets
+            .iter()
+            .rev()
+            .map(|(key, bucket)| (key.clone(), bucket.tokens))
+            .collect();
+        (
+            slf.get_type(),
+            (
+                limiter.rate,
+                limiter.burst,
+                limiter.time_window(),
+                limiter.max_keys(),
+            ),
+            buckets,
+        )
+    }
+
+    fn __setstate__(&mut self, buckets: Vec<(String, f64)>) {
+        let now_ns = self.clock.now_ns();
+        for (key, tokens) in buckets {
+            let mut bucket = TokenBucket::new(self.rate, self.burst, self.window_ns, now_ns);
+            bucket.restore_tokens(now_ns, tokens);
+            self.buckets.put(key, bucket);
+        }
+    }
+}
+
+/// Token bucket whose rate converges towards a target rate set from a feedback signal (e.g. the
+/// agent's `rate_by_service` or drop statistics reported by the writer). Every elapsed second
+/// moves the current rate by `smoothing` of the remaining distance to the target, bounded by
+/// `min_rate` and `max_rate`.
+#[pyclass(name = "AdaptiveRateLimiter", module = "ddtrace.internal.core._core")]
+pub struct AdaptiveRateLimiterPy {
+    limiter: TokenBucket,
+    rates: EffectiveRate,
+    clock: Clock,
+    decisions: Decisions,
+    target_rate: f64,
+    min_rate: f64,
+    max_rate: f64,
+    smoothing: f64,
+    last_adjust_ns: u64,
+}
+
+impl AdaptiveRateLimiterPy {
+    fn adjust(&mut self, now_ns: u64) {
+        if now_ns < self.last_adjust_ns {
+            // The clock was pinned to an earlier time, start counting from there
+            self.last_adjust_ns = now_ns;
+        }
+        let seconds = now_ns.saturating_sub(self.last_adjust_ns) / SECOND_NS;
+        if seconds == 0 {
+            return;
+        }
+        self.last_adjust_ns += seconds * SECOND_NS;
+
+        let remaining = (1.0 - self.smoothing).powi(seconds.min(i32::MAX as u64) as i32);
+        let rate = self.target_rate + (self.limiter.rate - self.target_rate) * remaining;
+        self.limiter.rate = rate.clamp(self.min_rate, self.max_rate);
+        self.limiter.burst = self.limiter.rate.max(1.0);
+        self.limiter.tokens = self.limiter.tokens.min(self.limiter.burst);
+    }
+}
+
+#[pymethods]
+impl AdaptiveRateLimiterPy {
+    #[new]
+    #[pyo3(signature = (
+        rate,
+        min_rate = 1.0,
+        max_rate = None,
+        smoothing = 0.2,
+        metrics_callback = None,
+        metrics_interval = 1000
+    ))]
+    fn new(
+        rate: f64,
+        min_rate: f64,
+        max_rate: Option<f64>,
+        smoothing: f64,
+        metrics_callback: Option<PyObject>,
+        metrics_interval: u64,
+    ) -> PyResult<Self> {
+        let max_rate = max_rate.unwrap_or(f64::INFINITY);
+        if min_rate.is_nan() || max_rate.is_nan() || min_rate <= 0.0 || min_rate > max_rate {
+            return Err(PyValueError::new_err(
+                "min_rate must be positive and not greater than max_rate",
+            ));
+        }
+        if smoothing.is_nan() || smoothing <= 0.0 || smoothing > 1.0 {
+            return Err(PyValueError::new_err("smoothing must be in (0, 1]"));
+        }
+        if rate.is_nan() {
+            return Err(PyValueError::new_err("rate must be a number"));
+        }
+        let rate = rate.clamp(min_rate, max_rate);
+        let now_ns = monotonic_ns();
+        Ok(AdaptiveRateLimiterPy {
+            limiter: TokenBucket::new(rate, rate.max(1.0), SECOND_NS, now_ns),
+            rates: EffectiveRate::new(SECOND_NS),
+            clock: Clock::default(),
+            decisions: Decisions::new(metrics_callback, metrics_interval)?,
+            target_rate: rate,
+            min_rate,
+            max_rate,
+            smoothing,
+            last_adjust_ns: now_ns,
+        })
+    }
+
+    #[getter]
+    fn rate(&self) -> f64 {
+        self.limiter.rate
+    }
+
+    #[getter]
+    fn target_rate(&self) -> f64 {
+        self.target_rate
+    }
+
+    #[getter]
+    fn min_rate(&self) -> f64 {
+        self.min_rate
+    }
+
+    #[getter]
+    fn max_rate(&self) -> f64 {
+        self.max_rate
+    }
+
+    fn set_target_rate(&mut self, rate: f64) -> PyResult<()> {
+        if rate.is_nan() {
+            return Err(PyValueError::new_err("rate must be a number"));
+        }
+        let now_ns = self.clock.now_ns();
+        // Settle the time elapsed under the previous target before switching to the new one
+        self.adjust(now_ns);
+        self.target_rate = rate.clamp(self.min_rate, self.max_rate);
+        Ok(())
+    }
+
+    #[pyo3(signature = (n = 1, timestamp_ns = None))]
+    fn is_allowed(&mut self, py: Python<'_>, n: u64, timestamp_ns: Option<u64>) -> PyResult<bool> {
+        check_permits(n)?;
+        let now_ns = timestamp_ns
+            .filter(|&ts| ts != 0)
+            .unwrap_or_else(|| self.clock.now_ns());
+        self.adjust(now_ns);
+        let allowed = self.limiter.is_allowed(now_ns, n);
+        self.rates.update(allowed, n, now_ns);
+        self.decisions.record(py, allowed, n);
+        Ok(allowed)
+    }
+
+    fn __call__(&mut self, py: Python<'_>) -> PyResult<bool> {
+        self.is_allowed(py, 1, None)
+    }
+
+    /// Makes `count` single-permit decisions at once, and returns how many of them were allowed.
+    fn allow_many(&mut self, py: Python<'_>, count: u64) -> u64 {
+        let now_ns = self.clock.now_ns();
+        self.adjust(now_ns);
+        let mut allowed_count = 0;
+        for _ in 0..count {
+            let allowed = self.limiter.is_allowed(now_ns, 1);
+            self.rates.update(allowed, 1, now_ns);
+            self.decisions.record(py, allowed, 1);
+            allowed_count += allowed as u64;
+        }
+        allowed_count
+    }
+
+    #[getter]
+    fn effective_rate(&self) -> f64 {
+        self.rates.effective_rate()
+    }
+
+    #[getter]
+    fn current_window_rate(&self) -> f64 {
+        self.rates.current_window_rate()
+    }
+
+    #[getter]
+    fn allowed(&self) -> u64 {
+        self.decisions.allowed()
+    }
+
+    #[getter]
+    fn denied(&self) -> u64 {
+        self.decisions.denied()
+    }
+
+    #[getter(_now_ns)]
+    fn get_now_ns(&self) -> Option<u64> {
+        self.clock.pinned_ns()
+    }
+
+    #[setter(_now_ns)]
+    fn set_now_ns(&self, now_ns: Option<u64>) {
+        self.clock.pin(now_ns);
+    }
+
+    fn __reduce__<'py>(slf: &Bound<'py, Self>) -> Reduced<'py, (f64, f64, f64, f64), (f64, f64)> {
+        let limiter = slf.borrow();
+        (
+            slf.get_type(),
+            (
+                limiter.limiter.rate,
+                limiter.min_rate,
+                limiter.max_rate,
+                limiter.smoothing,
+            ),
+            (limiter.target_rate, limiter.limiter.tokens),
+        )
+    }
+
+    fn __setstate__(&mut self, state: (f64, f64)) {
+        let (target_rate, tokens) = state;
+        let now_ns = self.clock.now_ns();
+        self.target_rate = target_rate.clamp(self.min_rate, self.max_rate);
+        self.limiter.restore_tokens(now_ns, tokens);
+        self.last_adjust_ns = now_ns;
+    }
+}
+
+type Registry = Mutex<HashMap<String, Py<SlidingWindowRateLimiterPy>>>;
+
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+fn registry(py: Python<'_>) -> PyResult<&'static Registry> {
+    if let Some(registry) = REGISTRY.get() {
+        return Ok(registry);
+    }
+    // Children forked after the first limiter is registered start over with fresh budgets
+    let os = py.import_bound("os")?;
+    if os.hasattr("register_at_fork")? {
+        let kwargs = [(
+            "after_in_child",
+            wrap_pyfunction!(reset_limiters_after_fork, py)?,
+        )]
+        .into_py_dict_bound(py);
+        os.call_method("register_at_fork", (), Some(&kwargs))?;
+    }
+    Ok(REGISTRY.get_or_init(Default::default))
+}
+
+#[pyfunction]
+fn reset_limiters_after_fork(py: Python<'_>) {
+    if let Some(registry) = REGISTRY.get() {
+        for limiter in registry.lock().unwrap().values() {
+            limiter.bind(py).get().reset();
+        }
+    }
+}
+
+/// Returns the process-wide limiter registered under `name`, creating it with the given rate
+/// limit and time window on first use. Later calls return the same instance regardless of the
+/// rate limit and time window they pass, so that modules imported from different places share
+/// one budget per purpose.
+#[pyfunction]
+#[pyo3(signature = (name, rate_limit, time_window = 1e9))]
+pub fn get_limiter(
+    py: Python<'_>,
+    name: &str,
+    rate_limit: i64,
+    time_window: f64,
+) -> PyResult<Py<SlidingWindowRateLimiterPy>> {
+    let mut limiters = registry(py)?.lock().unwrap();
+    if let Some(limiter) = limiters.get(name) {
+        return Ok(limiter.clone_ref(py));
+    }
+    let limiter = Py::new(
+        py,
+        SlidingWindowRateLimiterPy::new(rate_limit, time_window, None, 1000)?,
+    )?;
+    limiters.insert(name.to_owned(), limiter.clone_ref(py));
+    Ok(limiter)
+}
@@ -0,0 +1,79 @@
+use std::sync::{Arc, Mutex};
+
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use tokio::runtime::{Builder, Runtime};
+
+const DEFAULT_WORKER_THREADS: usize = 1;
+
+/// The shared runtime and the ID of the process that built it.
+struct SharedRuntime {
+    pid: u32,
+    runtime: Arc<Runtime>,
+}
+
+static RUNTIME: Mutex<Option<SharedRuntime>> = Mutex::new(None);
+static WORKER_THREADS: Mutex<usize> = Mutex::new(DEFAULT_WORKER_THREADS);
+
+/// Returns the runtime of the current process, forgetting the one inherited from the parent after
+/// a fork: the child doesn't have its worker threads, and dropping it would wait for them forever.
+fn current_runtime(shared: &mut Option<SharedRuntime>) -> Option<Arc<Runtime>> {
+    match shared {
+        Some(current) if current.pid == std::process::id() => Some(current.runtime.clone()),
+        Some(_) => {
+            std::mem::forget(shared.take());
+            None
+        }
+        None => None,
+    }
+}
+
+/// Returns the process-wide tokio runtime, building it on first use, and again in a forked child.
+///
+/// Every native component that needs to run async work (remote config, writers, ...) should spawn
+/// onto this runtime instead of creating its own, so that the process only pays for one set of
+/// worker threads.
+pub fn shared_runtime() -> Arc<Runtime> {
+    let mut shared = RUNTIME.lock().unwrap();
+    if let Some(runtime) = current_runtime(&mut shared) {
+        return runtime;
+    }
+    let worker_threads = *WORKER_THREADS.lock().unwrap();
+    let runtime = Builder::new_multi_thread()
+        .worker_threads(worker_threads)
+        .thread_name("ddtrace-native")
+        .enable_all()
+        .build()
+        .expect("failed to build the shared tokio runtime");
+    let runtime = Arc::new(runtime);
+    *shared = Some(SharedRuntime {
+        pid: std::process::id(),
+        runtime: runtime.clone(),
+    });
+    runtime
+}
+
+/// Sets the number of worker threads used by the shared runtime.
+///
+/// This must be called before any native component starts the runtime.
+#[pyfunction]
+pub fn set_runtime_worker_threads(worker_threads: usize) -> PyResult<()> {
+    if worker_threads == 0 {
+        return Err(PyValueError::new_err(
+            "worker_threads must be greater than 0",
+        ));
+    }
+    if is_runtime_started() {
+        return Err(PyRuntimeError::new_err(
+            "the shared runtime is already running",
+        ));
+    }
+    *WORKER_THREADS.lock().unwrap() = worker_threads;
+    Ok(())
+}
+
+/// Returns whether the shared runtime has been started in the current process.
+#[pyfunction]
+pub fn is_runtime_started() -> bool {
+    current_runtime(&mut RUNTIME.lock().unwrap()).is_some()
+}
@@ -0,0 +1,48 @@
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use super::{
+    MECHANISM_AGENT_RATE, MECHANISM_REMOTE_DYNAMIC_RULE, MECHANISM_REMOTE_USER_RULE,
+    MECHANISM_TRACE_SAMPLING_RULE,
+};
+
+/// Propagated tag of the mechanism of the sampling decision of a trace.
+pub const DECISION_MAKER_KEY: &str = "_dd.p.dm";
+const AGENT_RATE_KEY: &str = "_dd.agent_psr";
+const RULE_RATE_KEY: &str = "_dd.rule_psr";
+const LIMIT_RATE_KEY: &str = "_dd.limit_psr";
+
+/// Returns the `_dd.p.dm` value of a mechanism.
+pub fn decision_maker(mechanism: i32) -> String {
+    format!("-{mechanism}")
+}
+
+/// Returns the tags and metrics to set on the root span of a trace for a sampling decision, like
+/// `ddtrace.internal.sampling._set_sampling_tags()`: `_dd.p.dm`, along with the rate of the
+/// decision as `_dd.agent_psr` or `_dd.rule_psr` depending on the mechanism, and the effective rate
+/// of the rate limiter as `_dd.limit_psr` if it was applied.
+#[pyfunction]
+#[pyo3(signature = (mechanism, sample_rate = None, limit_rate = None))]
+pub fn sampling_decision_tags<'py>(
+    py: Python<'py>,
+    mechanism: i32,
+    sample_rate: Option<f64>,
+    limit_rate: Option<f64>,
+) -> PyResult<(Bound<'py, PyDict>, Bound<'py, PyDict>)> {
+    let meta = PyDict::new_bound(py);
+    meta.set_item(DECISION_MAKER_KEY, decision_maker(mechanism))?;
+    let metrics = PyDict::new_bound(py);
+    if let Some(sample_rate) = sample_rate {
+        match mechanism {
+            MECHANISM_AGENT_RATE => metrics.set_item(AGENT_RATE_KEY, sample_rate)?,
+            MECHANISM_TRACE_SAMPLING_RULE
+            | MECHANISM_REMOTE_USER_RULE
+            | MECHANISM_REMOTE_DYNAMIC_RULE => metrics.set_item(RULE_RATE_KEY, sample_rate)?,
+            _ => {}
+        }
+    }
+    if let Some(limit_rate) = limit_rate {
+        metrics.set_item(LIMIT_RATE_KEY, limit_rate)?;
+    }
+    Ok((meta, metrics))
+}
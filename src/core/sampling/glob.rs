@@ -0,0 +1,98 @@
+use pyo3::prelude::*;
+
+/// Glob pattern of the sampling rules, like `ddtrace.internal.glob_matching.GlobMatcher`: `*`
+/// matches any number of characters, `?` any single character, there are no escape sequences, and
+/// matching ignores case.
+pub struct Glob {
+    pattern: String,
+    chars: Vec<char>,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Self {
+        let pattern = pattern.to_lowercase();
+        Glob {
+            chars: pattern.chars().collect(),
+            pattern,
+        }
+    }
+
+    /// The pattern, in lower case.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Returns whether `subject` matches the pattern, backtracking to the last `*` on a mismatch.
+    pub fn matches(&self, subject: &str) -> bool {
+        if self.pattern == "*" {
+            return true;
+        }
+        let subject: Vec<char> = subject.to_lowercase().chars().collect();
+        let pattern = &self.chars;
+        let (mut px, mut sx) = (0, 0);
+        let (mut next_px, mut next_sx) = (0, 0);
+        while px < pattern.len() || sx < subject.len() {
+            if let Some(&char) = pattern.get(px) {
+                match char {
+                    '?' if sx < subject.len() => {
+                        px += 1;
+                        sx += 1;
+                        continue;
+                    }
+                    '*' => {
+                        next_px = px;
+                        next_sx = sx + 1;
+                        px += 1;
+                        continue;
+                    }
+                    '?' => {}
+                    _ if subject.get(sx) == Some(&char) => {
+                        px += 1;
+                        sx += 1;
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            if 0 < next_sx && next_sx <= subject.len() {
+                px = next_px;
+                sx = next_sx;
+                continue;
+            }
+            return false;
+        }
+        true
+    }
+}
+
+/// Glob pattern, precompiled, like `ddtrace.internal.glob_matching.GlobMatcher`: `*` matches any
+/// number of characters, `?` any single character, and matching ignores case.
+#[pyclass(frozen, name = "GlobMatcher", module = "ddtrace.internal.core._core")]
+pub struct GlobMatcherPy {
+    glob: Glob,
+}
+
+#[pymethods]
+impl GlobMatcherPy {
+    #[new]
+    fn new(pattern: &str) -> Self {
+        GlobMatcherPy {
+            glob: Glob::new(pattern),
+        }
+    }
+
+    /// The pattern, in lower case.
+    #[getter]
+    fn pattern(&self) -> &str {
+        self.glob.pattern()
+    }
+
+    #[pyo3(name = "match")]
+    fn matches(&self, subject: &str) -> bool {
+        self.glob.matches(subject)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("GlobMatcher({:?})", self.glob.pattern())
+    }
+}
@@ -0,0 +1,63 @@
+mod decision;
+mod glob;
+mod priority;
+mod rules;
+mod sampler;
+mod span_rules;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+pub use decision::sampling_decision_tags;
+pub use glob::GlobMatcherPy;
+pub use priority::PrioritySamplerPy;
+pub use rules::SamplingRulesPy;
+pub use sampler::TraceSamplerPy;
+pub use span_rules::SpanSamplingRulesPy;
+
+/// Has to be the same factor as the agent's, so that sampling decisions can be chained.
+const KNUTH_FACTOR: u128 = 1111111111111111111;
+
+/// Sampling priorities, like `ddtrace.constants`.
+const USER_REJECT: i32 = -1;
+const AUTO_REJECT: i32 = 0;
+const AUTO_KEEP: i32 = 1;
+const USER_KEEP: i32 = 2;
+
+/// Mechanisms of the sampling decisions, like `ddtrace.internal.sampling.SamplingMechanism`.
+const MECHANISM_DEFAULT: i32 = 0;
+const MECHANISM_AGENT_RATE: i32 = 1;
+const MECHANISM_TRACE_SAMPLING_RULE: i32 = 3;
+const MECHANISM_SPAN_SAMPLING_RULE: i32 = 8;
+const MECHANISM_REMOTE_USER_RULE: i32 = 11;
+const MECHANISM_REMOTE_DYNAMIC_RULE: i32 = 12;
+
+/// Returns whether a trace is kept at `rate`, from the lower 64 bits of its ID, like
+/// `ddtrace.sampler.RateSampler`: the hash is taken modulo `2**64 - 1`, and compared with the
+/// threshold as Python compares integers with floats.
+fn knuth_sampled(trace_id: u64, rate: f64) -> bool {
+    let max = u64::MAX as f64;
+    let threshold = rate.clamp(0.0, 1.0) * max;
+    if threshold >= max {
+        return true;
+    }
+    let hash = (trace_id as u128 * KNUTH_FACTOR) % u64::MAX as u128;
+    hash <= threshold.floor() as u128
+}
+
+/// Returns the lower 64 bits of a trace ID, like `_get_64_lowest_order_bits_as_int()`. Sampling
+/// decisions are made from those only, so that generating 128-bit trace IDs doesn't change them.
+#[pyfunction]
+pub fn trace_id_lower_64_bits(trace_id: u128) -> u64 {
+    trace_id as u64
+}
+
+/// Returns whether a trace is kept at `rate`, with the same hash of its ID as the samplers, so that
+/// other components sampling by trace make the same decisions.
+#[pyfunction]
+pub fn sampling_decision(trace_id: u128, rate: f64) -> PyResult<bool> {
+    if rate.is_nan() {
+        return Err(PyValueError::new_err("rate must be a number"));
+    }
+    Ok(knuth_sampled(trace_id_lower_64_bits(trace_id), rate))
+}
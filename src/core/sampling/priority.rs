@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use pyo3::prelude::*;
+
+use super::{
+    knuth_sampled, trace_id_lower_64_bits, AUTO_KEEP, AUTO_REJECT, MECHANISM_AGENT_RATE,
+    MECHANISM_DEFAULT,
+};
+
+/// Returns the key of the rates of a service and env, in the format of the agent.
+fn key(service: Option<&str>, env: Option<&str>) -> String {
+    format!(
+        "service:{},env:{}",
+        service.unwrap_or_default(),
+        env.unwrap_or_default()
+    )
+}
+
+/// Samples traces at the rate the agent returned for their service and env, or at `sample_rate`
+/// if it returned none, like `ddtrace.sampler.RateByServiceSampler`.
+///
+/// `sample()` returns the sampling priority of a trace along with the mechanism of the decision
+/// and the rate it was made at, for the tracer to tag the root span with, so that a decision is a
+/// single call.
+#[pyclass(
+    frozen,
+    name = "PrioritySampler",
+    module = "ddtrace.internal.core._core"
+)]
+pub struct PrioritySamplerPy {
+    sample_rate: f64,
+    /// Service and env the rates set without them apply to.
+    service: Option<String>,
+    env: Option<String>,
+    /// Replaced as a whole when the agent returns new rates.
+    rates: Mutex<Arc<HashMap<String, f64>>>,
+}
+
+impl PrioritySamplerPy {
+    /// Returns the rate of a service and env, and whether it was given by the agent.
+    pub fn rate(&self, service: Option<&str>, env: Option<&str>) -> (f64, bool) {
+        let rates = self.rates.lock().unwrap().clone();
+        match rates.get(&key(service, env)) {
+            Some(&rate) => (rate.clamp(0.0, 1.0), true),
+            None => (self.sample_rate, false),
+        }
+    }
+}
+
+#[pymethods]
+impl PrioritySamplerPy {
+    #[new]
+    #[pyo3(signature = (sample_rate = 1.0, service = None, env = None))]
+    pub fn new(sample_rate: f64, service: Option<String>, env: Option<String>) -> Self {
+        PrioritySamplerPy {
+            sample_rate: sample_rate.clamp(0.0, 1.0),
+            service,
+            env,
+            rates: Mutex::new(Arc::new(HashMap::new())),
+        }
+    }
+
+    #[getter]
+    fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    /// Rates by `service:<service>,env:<env>` key.
+    #[getter]
+    pub fn rates(&self) -> HashMap<String, f64> {
+        (**self.rates.lock().unwrap()).clone()
+    }
+
+    /// Sets the rate of a service and env, those of the sampler by default.
+    #[pyo3(signature = (sample_rate, service = None, env = None))]
+    pub fn set_sample_rate(&self, sample_rate: f64, service: Option<&str>, env: Option<&str>) {
+        let key = key(
+            service.or(self.service.as_deref()),
+            env.or(self.env.as_deref()),
+        );
+        let mut rates = self.rates.lock().unwrap();
+        Arc::make_mut(&mut rates).insert(key, sample_rate);
+    }
+
+    /// Replaces the rates with those returned by the agent.
+    pub fn update_rate_by_service_sample_rates(&self, rate_by_service: HashMap<String, f64>) {
+        *self.rates.lock().unwrap() = Arc::new(rate_by_service);
+    }
+
+    /// Returns the priority of a trace, from the lower 64 bits of its ID, with the mechanism and
+    /// the rate of the decision.
+    #[pyo3(signature = (trace_id, service = None, env = None))]
+    fn sample(&self, trace_id: u128, service: Option<&str>, env: Option<&str>) -> (i32, i32, f64) {
+        let (rate, from_agent) = self.rate(service, env);
+        let priority = if knuth_sampled(trace_id_lower_64_bits(trace_id), rate) {
+            AUTO_KEEP
+        } else {
+            AUTO_REJECT
+        };
+        let mechanism = if from_agent {
+            MECHANISM_AGENT_RATE
+        } else {
+            MECHANISM_DEFAULT
+        };
+        (priority, mechanism, rate)
+    }
+}
@@ -0,0 +1,472 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use super::glob::Glob;
+use super::{
+    knuth_sampled, trace_id_lower_64_bits, MECHANISM_REMOTE_DYNAMIC_RULE,
+    MECHANISM_REMOTE_USER_RULE, MECHANISM_TRACE_SAMPLING_RULE, USER_KEEP, USER_REJECT,
+};
+use crate::json::{self, Value};
+use crate::rate_limiter::{monotonic_ns, TokenBucket, SECOND_NS};
+
+/// Where a rule comes from, in order of precedence.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Provenance {
+    Customer,
+    Dynamic,
+    Default,
+}
+
+impl Provenance {
+    fn name(self) -> &'static str {
+        match self {
+            Provenance::Customer => "customer",
+            Provenance::Dynamic => "dynamic",
+            Provenance::Default => "default",
+        }
+    }
+
+    fn parse(provenance: &str) -> Option<Self> {
+        match provenance {
+            "customer" => Some(Provenance::Customer),
+            "dynamic" => Some(Provenance::Dynamic),
+            "default" => Some(Provenance::Default),
+            _ => None,
+        }
+    }
+
+    /// Mechanism of the decisions made by the rules of this provenance.
+    pub fn mechanism(self) -> i32 {
+        match self {
+            Provenance::Customer => MECHANISM_REMOTE_USER_RULE,
+            Provenance::Dynamic => MECHANISM_REMOTE_DYNAMIC_RULE,
+            Provenance::Default => MECHANISM_TRACE_SAMPLING_RULE,
+        }
+    }
+}
+
+/// Value of a tag of a span, from its meta or its metrics.
+#[derive(Default)]
+pub struct Tag {
+    pub meta: Option<String>,
+    pub metric: Option<f64>,
+}
+
+/// Fields of a span rules are matched against, with the tags the rules look at.
+pub struct SpanFields<'a> {
+    pub trace_id: u64,
+    pub service: Option<&'a str>,
+    pub name: Option<&'a str>,
+    pub resource: Option<&'a str>,
+    pub tags: HashMap<String, Tag>,
+}
+
+/// Rule of `DD_TRACE_SAMPLING_RULES`, like `ddtrace.sampling_rule.SamplingRule`. Rules can also
+/// set `max_per_second`, to keep at most that many traces per second.
+pub struct Rule {
+    pub sample_rate: f64,
+    service: Option<Glob>,
+    name: Option<Glob>,
+    resource: Option<Glob>,
+    tags: Vec<(String, Glob)>,
+    pub provenance: Provenance,
+    limiter: Option<Mutex<TokenBucket>>,
+}
+
+/// Returns the pattern of a rule property, converted to a string like Python's `str()` does.
+fn pattern(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) if !value.is_f64() => Some(value.to_string()),
+        Value::Number(value) => match value.as_f64()? {
+            value if value.fract() == 0.0 => Some((value as i64).to_string()),
+            value => Some(value.to_string()),
+        },
+        Value::Bool(true) => Some("True".to_owned()),
+        Value::Bool(false) => Some("False".to_owned()),
+        Value::Null => Some("None".to_owned()),
+        _ => None,
+    }
+}
+
+/// Matches a property of a span, `None` being matched as `"None"` like in Python.
+fn glob_matches(glob: &Option<Glob>, value: Option<&str>) -> bool {
+    glob.as_ref()
+        .map_or(true, |glob| glob.matches(value.unwrap_or("None")))
+}
+
+impl Rule {
+    pub fn new(
+        sample_rate: f64,
+        service: Option<&str>,
+        name: Option<&str>,
+        resource: Option<&str>,
+        tags: Vec<(String, String)>,
+        provenance: Provenance,
+        max_per_second: Option<f64>,
+    ) -> Self {
+        Rule {
+            sample_rate: sample_rate.clamp(0.0, 1.0),
+            service: service.map(Glob::new),
+            name: name.map(Glob::new),
+            resource: resource.map(Glob::new),
+            tags: tags
+                .into_iter()
+                .map(|(key, value)| (key, Glob::new(&value)))
+                .collect(),
+            provenance,
+            limiter: max_per_second.map(|rate| {
+                Mutex::new(TokenBucket::new(
+                    rate,
+                    rate.max(1.0),
+                    SECOND_NS,
+                    monotonic_ns(),
+                ))
+            }),
+        }
+    }
+
+    /// Parses a rule of the JSON document, or returns `None` if it is invalid, like
+    /// `DatadogSampler._parse_rules_from_str`, which skips those.
+    fn from_json(rule: &Value) -> Option<Self> {
+        let sample_rate = match rule.get("sample_rate")? {
+            Value::Number(rate) => rate.as_f64()?,
+            Value::String(rate) => rate.trim().parse().ok()?,
+            _ => return None,
+        };
+        let property = |key: &str| match rule.get(key) {
+            Some(value) => pattern(value).map(Some),
+            None => Some(None),
+        };
+        let service = property("service")?;
+        let name = property("name")?;
+        let resource = property("resource")?;
+        let tags = match rule.get("tags") {
+            // Remote configuration sends the tags as a list of `{"key": ..., "value_glob": ...}`.
+            Some(Value::Array(tags)) => tags
+                .iter()
+                .map(|tag| {
+                    let key = tag.get("key")?.as_str()?;
+                    Some((key.to_owned(), pattern(tag.get("value_glob")?)?))
+                })
+                .collect::<Option<Vec<_>>>()?,
+            Some(tags) => tags
+                .as_object()?
+                .iter()
+                .map(|(key, value)| Some((key.clone(), pattern(value)?)))
+                .collect::<Option<Vec<_>>>()?,
+            None => Vec::new(),
+        };
+        let provenance = match rule.get("provenance") {
+            Some(provenance) => Provenance::parse(provenance.as_str()?)?,
+            None => Provenance::Default,
+        };
+        let max_per_second = match rule.get("max_per_second") {
+            Some(max_per_second) => Some(max_per_second.as_f64()?),
+            None => None,
+        };
+        Some(Rule::new(
+            sample_rate,
+            service.as_deref(),
+            name.as_deref(),
+            resource.as_deref(),
+            tags,
+            provenance,
+            max_per_second,
+        ))
+    }
+
+    /// Rule matching every span, for the default sample rate.
+    pub fn catch_all(sample_rate: f64) -> Self {
+        Rule::new(
+            sample_rate,
+            None,
+            None,
+            None,
+            Vec::new(),
+            Provenance::Default,
+            None,
+        )
+    }
+
+    /// Returns whether the tags of a span match, like `SamplingRule.check_tags()`: integral metrics
+    /// are matched as integers, and other metrics only by `*`.
+    fn tags_match(&self, span: &SpanFields<'_>) -> bool {
+        for (key, glob) in &self.tags {
+            let tag = span.tags.get(key);
+            let meta = tag.and_then(|tag| tag.meta.as_deref());
+            if glob.matches(meta.unwrap_or("None")) {
+                continue;
+            }
+            let matches = match tag.and_then(|tag| tag.metric) {
+                Some(metric) if metric.fract() != 0.0 => glob.pattern() == "*",
+                Some(metric) => glob.matches(&(metric as i64).to_string()),
+                None => glob.matches("None"),
+            };
+            if !matches {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn matches(&self, span: &SpanFields<'_>) -> bool {
+        self.tags_match(span)
+            && glob_matches(&self.service, span.service)
+            && glob_matches(&self.name, span.name)
+            && glob_matches(&self.resource, span.resource)
+    }
+
+    /// Returns whether the rule keeps a trace, within its limit of traces per second if any.
+    pub fn sample(&self, trace_id: u64) -> bool {
+        self.sampled(trace_id) && self.is_allowed()
+    }
+
+    /// Returns whether the sample rate of the rule keeps a trace, regardless of its limit.
+    pub fn sampled(&self, trace_id: u64) -> bool {
+        match self.sample_rate {
+            rate if rate == 1.0 => true,
+            rate if rate == 0.0 => false,
+            rate => knuth_sampled(trace_id, rate),
+        }
+    }
+
+    /// Takes a trace from the limit of traces per second of the rule, if any.
+    pub fn is_allowed(&self) -> bool {
+        self.limiter.as_ref().map_or(true, |limiter| {
+            limiter.lock().unwrap().is_allowed(monotonic_ns(), 1)
+        })
+    }
+
+    /// Returns what `is_allowed()` would, without taking a trace from the limit.
+    pub fn peek_allowed(&self) -> bool {
+        self.limiter.as_ref().map_or(true, |limiter| {
+            limiter
+                .lock()
+                .unwrap()
+                .clone()
+                .is_allowed(monotonic_ns(), 1)
+        })
+    }
+
+    /// Keys of the tags the rule looks at.
+    pub fn tag_keys(&self) -> impl Iterator<Item = &str> {
+        self.tags.iter().map(|(key, _)| key.as_str())
+    }
+}
+
+/// Formats a rule like `SamplingRule.__repr__()`.
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let glob = |glob: &Option<Glob>| match glob {
+            Some(glob) => format!("'{}'", glob.pattern()),
+            None => "'NO_RULE'".to_owned(),
+        };
+        let tags = self
+            .tags
+            .iter()
+            .map(|(key, glob)| format!("'{key}': '{}'", glob.pattern()))
+            .collect::<Vec<_>>();
+        write!(
+            f,
+            concat!(
+                "SamplingRule(sample_rate={:?}, service={}, name={}, resource={}, tags={{{}}}, ",
+                "provenance='{}')"
+            ),
+            self.sample_rate,
+            glob(&self.service),
+            glob(&self.name),
+            glob(&self.resource),
+            tags.join(", "),
+            self.provenance.name(),
+        )
+    }
+}
+
+/// Parses the rules of a `DD_TRACE_SAMPLING_RULES` document, in order of precedence.
+pub fn parse(rules: &str) -> Result<Vec<Rule>, String> {
+    let document = json::parse(rules.as_bytes())
+        .map_err(|err| format!("Unable to parse DD_TRACE_SAMPLING_RULES={rules}: {err}"))?;
+    let rules = document
+        .as_array()
+        .ok_or_else(|| format!("DD_TRACE_SAMPLING_RULES isn't a list of rules: {rules}"))?;
+    let mut rules: Vec<Rule> = rules.iter().filter_map(Rule::from_json).collect();
+    // Sorting is stable, so rules of the same provenance keep their order.
+    rules.sort_by_key(|rule| rule.provenance);
+    Ok(rules)
+}
+
+/// Parses `rules`, or `DD_TRACE_SAMPLING_RULES` if `None`, followed by a rule matching every span
+/// if there is a `default_sample_rate`.
+pub fn load(rules: Option<String>, default_sample_rate: Option<f64>) -> PyResult<Vec<Rule>> {
+    let rules = rules.or_else(|| std::env::var("DD_TRACE_SAMPLING_RULES").ok());
+    let mut rules = match rules.as_deref().map(str::trim) {
+        Some(rules) if !rules.is_empty() => parse(rules).map_err(PyValueError::new_err)?,
+        _ => Vec::new(),
+    };
+    rules.extend(default_sample_rate.map(Rule::catch_all));
+    Ok(rules)
+}
+
+/// Sampling configuration of an `APM_TRACING` remote configuration.
+pub struct RemoteConfig {
+    /// `None` if the configuration sets no rules, for the local ones to apply.
+    pub rules: Option<Vec<Rule>>,
+    pub sample_rate: Option<f64>,
+}
+
+impl RemoteConfig {
+    /// Parses the `lib_config` of an `APM_TRACING` configuration, or the configuration itself.
+    /// Like `Config.convert_rc_trace_sampling_rules`, rules are skipped unless they have a sample
+    /// rate, a provenance and something to match.
+    pub fn parse(payload: &[u8]) -> Result<Self, String> {
+        let document = json::parse(payload)
+            .map_err(|err| format!("Unable to parse the remote configuration: {err}"))?;
+        let config = document.get("lib_config").unwrap_or(&document);
+        // A deleted configuration reverts to the local one.
+        if config.as_object().is_none() {
+            return Ok(RemoteConfig {
+                rules: None,
+                sample_rate: None,
+            });
+        }
+        let rules = match config
+            .get("tracing_sampling_rules")
+            .and_then(Value::as_array)
+        {
+            Some(rules) => {
+                let mut rules: Vec<Rule> = rules
+                    .iter()
+                    .filter(|rule| {
+                        ["service", "name", "resource", "tags"]
+                            .iter()
+                            .any(|key| rule.get(key).is_some())
+                            && rule.get("sample_rate").is_some()
+                            && rule.get("provenance").is_some()
+                    })
+                    .filter_map(Rule::from_json)
+                    .collect();
+                rules.sort_by_key(|rule| rule.provenance);
+                Some(rules).filter(|rules| !rules.is_empty())
+            }
+            None => None,
+        };
+        Ok(RemoteConfig {
+            rules,
+            sample_rate: config.get("tracing_sampling_rate").and_then(Value::as_f64),
+        })
+    }
+}
+
+/// Returns the first rule matching a span, in order of precedence.
+pub fn find<'r>(rules: &'r [Rule], span: &SpanFields<'_>) -> Option<&'r Rule> {
+    rules.iter().find(|rule| rule.matches(span))
+}
+
+/// Extracts the tags of a span that rules look at, from its meta and its metrics.
+pub fn extract_tags<'a>(
+    keys: impl Iterator<Item = &'a str>,
+    meta: Option<&Bound<'_, PyDict>>,
+    metrics: Option<&Bound<'_, PyDict>>,
+) -> PyResult<HashMap<String, Tag>> {
+    let mut tags = HashMap::new();
+    for key in keys {
+        if tags.contains_key(key) {
+            continue;
+        }
+        let mut tag = Tag::default();
+        if let Some(value) = meta.map(|meta| meta.get_item(key)).transpose()?.flatten() {
+            tag.meta = Some(value.str()?.to_string());
+        }
+        if let Some(value) = metrics
+            .map(|metrics| metrics.get_item(key))
+            .transpose()?
+            .flatten()
+        {
+            tag.metric = value.extract().ok();
+        }
+        tags.insert(key.to_owned(), tag);
+    }
+    Ok(tags)
+}
+
+/// Trace sampling rules of `DD_TRACE_SAMPLING_RULES`, like those of `ddtrace.sampler.DatadogSampler`:
+/// the first rule matching the root span of a trace, in order of precedence (customer rules, then
+/// dynamic ones, then the others), decides whether it is kept. `default_sample_rate` adds a last
+/// rule matching every span.
+///
+/// `sample()` returns the sampling priority along with the mechanism and the rate of the decision,
+/// or `None` if no rule matched, for the agent rates to apply.
+#[pyclass(frozen, name = "SamplingRules", module = "ddtrace.internal.core._core")]
+pub struct SamplingRulesPy {
+    rules: Mutex<Arc<Vec<Rule>>>,
+}
+
+impl SamplingRulesPy {
+    fn rules(&self) -> Arc<Vec<Rule>> {
+        self.rules.lock().unwrap().clone()
+    }
+}
+
+#[pymethods]
+impl SamplingRulesPy {
+    /// Parses `rules`, or `DD_TRACE_SAMPLING_RULES` if `None`. Rules that aren't valid are skipped.
+    #[new]
+    #[pyo3(signature = (rules = None, default_sample_rate = None))]
+    fn new(rules: Option<String>, default_sample_rate: Option<f64>) -> PyResult<Self> {
+        Ok(SamplingRulesPy {
+            rules: Mutex::new(Arc::new(load(rules, default_sample_rate)?)),
+        })
+    }
+
+    fn __len__(&self) -> usize {
+        self.rules().len()
+    }
+
+    /// Returns the priority of a trace from the fields of its root span, with the mechanism and the
+    /// rate of the decision, or `None` if no rule matched.
+    #[pyo3(signature = (
+        trace_id,
+        service = None,
+        name = None,
+        resource = None,
+        meta = None,
+        metrics = None
+    ))]
+    fn sample(
+        &self,
+        trace_id: u128,
+        service: Option<&str>,
+        name: Option<&str>,
+        resource: Option<&str>,
+        meta: Option<&Bound<'_, PyDict>>,
+        metrics: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Option<(i32, i32, f64)>> {
+        let rules = self.rules();
+        let span = SpanFields {
+            trace_id: trace_id_lower_64_bits(trace_id),
+            service,
+            name,
+            resource,
+            tags: extract_tags(rules.iter().flat_map(Rule::tag_keys), meta, metrics)?,
+        };
+        let Some(rule) = find(&rules, &span) else {
+            return Ok(None);
+        };
+        let priority = if rule.sample(span.trace_id) {
+            USER_KEEP
+        } else {
+            USER_REJECT
+        };
+        Ok(Some((
+            priority,
+            rule.provenance.mechanism(),
+            rule.sample_rate,
+        )))
+    }
+}
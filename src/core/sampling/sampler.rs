@@ -0,0 +1,555 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use super::decision::{decision_maker, DECISION_MAKER_KEY};
+use super::priority::PrioritySamplerPy;
+use super::rules::{self, RemoteConfig, Rule, SpanFields};
+use super::{
+    knuth_sampled, trace_id_lower_64_bits, AUTO_KEEP, AUTO_REJECT, MECHANISM_AGENT_RATE,
+    MECHANISM_DEFAULT, MECHANISM_TRACE_SAMPLING_RULE, USER_KEEP, USER_REJECT,
+};
+use crate::rate_limiter::{bucket_size, monotonic_ns, EffectiveRate, TokenBucket, SECOND_NS};
+
+/// Same default as `DD_TRACE_RATE_LIMIT`.
+const DEFAULT_RATE_LIMIT: f64 = 100.0;
+
+/// Limit of the traces kept per window, with the effective rate reported as `_dd.limit_psr`, like
+/// `ddtrace.internal.rate_limiter.RateLimiter`.
+#[derive(Clone)]
+struct Limiter {
+    bucket: TokenBucket,
+    rates: EffectiveRate,
+}
+
+impl Limiter {
+    fn new(rate: f64, window_ns: u64) -> PyResult<Self> {
+        Ok(Limiter {
+            bucket: TokenBucket::new(rate, bucket_size(rate, None)?, window_ns, monotonic_ns()),
+            rates: EffectiveRate::new(window_ns),
+        })
+    }
+
+    /// Returns whether a trace is allowed, with the effective rate after the decision.
+    fn is_allowed(&mut self) -> (bool, f64) {
+        let now_ns = monotonic_ns();
+        let allowed = self.bucket.is_allowed(now_ns, 1);
+        self.rates.update(allowed, 1, now_ns);
+        (allowed, self.rates.effective_rate())
+    }
+
+    /// Returns what `is_allowed()` would, without taking a token or counting the decision.
+    fn peek(&self) -> (bool, f64) {
+        self.clone().is_allowed()
+    }
+}
+
+/// Lowest factor backpressure reduces the sample rates to.
+const MIN_BACKPRESSURE_FACTOR: f64 = 1.0 / 64.0;
+/// How much of the sample rates is recovered per second without backpressure.
+const BACKPRESSURE_RECOVERY: f64 = 0.05;
+
+/// Factor of the sample rates in adaptive mode: halved at most once per second while the writer
+/// reports backpressure, and recovered linearly once it stops.
+struct Backpressure {
+    factor: f64,
+    reduced_ns: Option<u64>,
+}
+
+impl Backpressure {
+    fn factor(&self, now_ns: u64) -> f64 {
+        match self.reduced_ns {
+            Some(reduced_ns) => {
+                let elapsed = now_ns.saturating_sub(reduced_ns) as f64 / SECOND_NS as f64;
+                (self.factor + elapsed * BACKPRESSURE_RECOVERY).min(1.0)
+            }
+            None => 1.0,
+        }
+    }
+
+    fn report(&mut self, now_ns: u64) {
+        if let Some(reduced_ns) = self.reduced_ns {
+            if now_ns.saturating_sub(reduced_ns) < SECOND_NS {
+                return;
+            }
+        }
+        self.factor = (self.factor(now_ns) / 2.0).max(MIN_BACKPRESSURE_FACTOR);
+        self.reduced_ns = Some(now_ns);
+    }
+}
+
+/// What decided whether a trace is kept, for telemetry.
+#[derive(Clone, Copy)]
+enum Category {
+    Default,
+    Agent,
+    Rule,
+    /// Traces kept by a rule, but dropped by the rate limiter.
+    Limiter,
+    /// Decisions set on the trace by the user, recorded with `record_manual()`.
+    Manual,
+}
+
+impl Category {
+    const ALL: [Category; 5] = [
+        Category::Default,
+        Category::Agent,
+        Category::Rule,
+        Category::Limiter,
+        Category::Manual,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Category::Default => "default",
+            Category::Agent => "agent",
+            Category::Rule => "rule",
+            Category::Limiter => "limiter",
+            Category::Manual => "manual",
+        }
+    }
+}
+
+/// Numbers of traces kept and dropped by category of decision.
+struct Counters {
+    kept: [AtomicU64; Category::ALL.len()],
+    dropped: [AtomicU64; Category::ALL.len()],
+}
+
+impl Counters {
+    fn new() -> Self {
+        Counters {
+            kept: Default::default(),
+            dropped: Default::default(),
+        }
+    }
+
+    fn record(&self, category: Category, sampled: bool) {
+        let counters = if sampled { &self.kept } else { &self.dropped };
+        counters[category as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the numbers of traces kept and dropped by category, resetting them if `reset`.
+    fn snapshot(&self, reset: bool) -> HashMap<&'static str, (u64, u64)> {
+        let load = |counter: &AtomicU64| {
+            if reset {
+                counter.swap(0, Ordering::Relaxed)
+            } else {
+                counter.load(Ordering::Relaxed)
+            }
+        };
+        Category::ALL
+            .iter()
+            .map(|&category| {
+                let index = category as usize;
+                (
+                    category.name(),
+                    (load(&self.kept[index]), load(&self.dropped[index])),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Sampling decision of a trace.
+pub struct Decision {
+    pub sampled: bool,
+    pub priority: i32,
+    pub mechanism: i32,
+    /// Rate of the rule or of the agent the decision was made at.
+    pub sample_rate: f64,
+    /// Effective rate of the rate limiter, if it applied.
+    pub limit_rate: Option<f64>,
+    /// Whether the rate limiter dropped the trace.
+    pub rate_limited: bool,
+    /// Index of the rule that matched, if any.
+    pub rule: Option<usize>,
+}
+
+fn rate_limit_from_env() -> PyResult<f64> {
+    match std::env::var("DD_TRACE_RATE_LIMIT") {
+        Ok(rate_limit) => rate_limit.trim().parse().map_err(|_| {
+            PyValueError::new_err(format!("invalid DD_TRACE_RATE_LIMIT: {rate_limit}"))
+        }),
+        Err(_) => Ok(DEFAULT_RATE_LIMIT),
+    }
+}
+
+/// Returns the fields of a root span the sampler looks at.
+fn span_fields<'a>(
+    rules: &[Rule],
+    trace_id: u128,
+    service: Option<&'a str>,
+    name: Option<&'a str>,
+    resource: Option<&'a str>,
+    meta: Option<&Bound<'_, PyDict>>,
+    metrics: Option<&Bound<'_, PyDict>>,
+) -> PyResult<SpanFields<'a>> {
+    Ok(SpanFields {
+        trace_id: trace_id_lower_64_bits(trace_id),
+        service,
+        name,
+        resource,
+        tags: rules::extract_tags(rules.iter().flat_map(Rule::tag_keys), meta, metrics)?,
+    })
+}
+
+/// Samples traces from their root span like `ddtrace.sampler.DatadogSampler`: the first matching
+/// rule of `rules` (`DD_TRACE_SAMPLING_RULES` by default) decides, or the rates by service of the
+/// agent if none matches. Traces kept by a rule are then limited to `rate_limit` per
+/// `rate_limit_window` nanoseconds (`DD_TRACE_RATE_LIMIT` per second by default), as are those kept
+/// by the agent rates with `rate_limit_always_on`, whose decisions are then made as a user's.
+/// An `adaptive` sampler also reduces its rates while the writer reports backpressure.
+///
+/// `sample()` returns whether a trace is kept, its sampling priority, the mechanism and the rate of
+/// the decision, and the effective rate of the limiter for `_dd.limit_psr` if it applied.
+#[pyclass(frozen, name = "TraceSampler", module = "ddtrace.internal.core._core")]
+pub struct TraceSamplerPy {
+    rules: Mutex<Arc<Vec<Rule>>>,
+    /// Rules and default rate the sampler was created with, for remote configuration to revert to.
+    local_rules: Option<String>,
+    default_sample_rate: Option<f64>,
+    agent: PrioritySamplerPy,
+    limiter: Mutex<Limiter>,
+    rate_limit: f64,
+    rate_limit_always_on: bool,
+    counters: Counters,
+    /// `None` unless the sampler is adaptive.
+    backpressure: Option<Mutex<Backpressure>>,
+}
+
+impl TraceSamplerPy {
+    fn rules(&self) -> Arc<Vec<Rule>> {
+        self.rules.lock().unwrap().clone()
+    }
+
+    /// Factor of the sample rates, below 1 while the writer reports backpressure in adaptive mode.
+    fn rate_factor(&self) -> f64 {
+        self.backpressure.as_ref().map_or(1.0, |backpressure| {
+            backpressure.lock().unwrap().factor(monotonic_ns())
+        })
+    }
+
+    /// Decides whether a trace is kept, from its root span and the env of the tracer.
+    pub fn decide(&self, rules: &[Rule], span: &SpanFields<'_>, env: Option<&str>) -> Decision {
+        self.decide_at(rules, span, env, self.rate_factor(), false)
+    }
+
+    /// Decides whether a trace is kept with the sample rates reduced by `factor`. A `dry_run`
+    /// leaves the rate limiters, of the sampler and of the rules, and the counters of decisions
+    /// untouched.
+    fn decide_at(
+        &self,
+        rules: &[Rule],
+        span: &SpanFields<'_>,
+        env: Option<&str>,
+        factor: f64,
+        dry_run: bool,
+    ) -> Decision {
+        let (mut decision, mut category) = match rules.iter().position(|rule| rule.matches(span)) {
+            Some(index) => {
+                let rule = &rules[index];
+                let sample_rate = rule.sample_rate * factor;
+                // Hashes under the reduced rate are under the rate of the rule, so the traces kept
+                // under backpressure are a subset of those kept otherwise. Only those take from the
+                // limit of traces per second of the rule.
+                let sampled = rule.sampled(span.trace_id)
+                    && (factor == 1.0 || knuth_sampled(span.trace_id, sample_rate))
+                    && if dry_run {
+                        rule.peek_allowed()
+                    } else {
+                        rule.is_allowed()
+                    };
+                let decision = Decision {
+                    sampled,
+                    priority: if sampled { USER_KEEP } else { USER_REJECT },
+                    mechanism: rule.provenance.mechanism(),
+                    sample_rate,
+                    limit_rate: None,
+                    rate_limited: false,
+                    rule: Some(index),
+                };
+                (decision, Category::Rule)
+            }
+            // Like a manual decision, for the agent not to adjust its rates to those traces.
+            None if self.rate_limit_always_on => {
+                let (sample_rate, from_agent) = self.agent.rate(span.service, env);
+                let sample_rate = sample_rate * factor;
+                let sampled = knuth_sampled(span.trace_id, sample_rate);
+                let decision = Decision {
+                    sampled,
+                    priority: if sampled { USER_KEEP } else { USER_REJECT },
+                    mechanism: MECHANISM_TRACE_SAMPLING_RULE,
+                    sample_rate,
+                    limit_rate: None,
+                    rate_limited: false,
+                    rule: None,
+                };
+                let category = if from_agent {
+                    Category::Agent
+                } else {
+                    Category::Default
+                };
+                (decision, category)
+            }
+            None => {
+                let (sample_rate, from_agent) = self.agent.rate(span.service, env);
+                let sample_rate = sample_rate * factor;
+                let sampled = knuth_sampled(span.trace_id, sample_rate);
+                let (mechanism, category) = if from_agent {
+                    (MECHANISM_AGENT_RATE, Category::Agent)
+                } else {
+                    (MECHANISM_DEFAULT, Category::Default)
+                };
+                if !dry_run {
+                    self.counters.record(category, sampled);
+                }
+                return Decision {
+                    sampled,
+                    priority: if sampled { AUTO_KEEP } else { AUTO_REJECT },
+                    mechanism,
+                    sample_rate,
+                    limit_rate: None,
+                    rate_limited: false,
+                    rule: None,
+                };
+            }
+        };
+        if decision.sampled {
+            let mut limiter = self.limiter.lock().unwrap();
+            let (allowed, effective_rate) = if dry_run {
+                limiter.peek()
+            } else {
+                limiter.is_allowed()
+            };
+            decision.limit_rate = Some(effective_rate);
+            if !allowed {
+                decision.rate_limited = true;
+                decision.sampled = false;
+                decision.priority = USER_REJECT;
+                category = Category::Limiter;
+            }
+        }
+        if !dry_run {
+            self.counters.record(category, decision.sampled);
+        }
+        decision
+    }
+}
+
+#[pymethods]
+impl TraceSamplerPy {
+    #[new]
+    #[pyo3(signature = (
+        rules = None,
+        default_sample_rate = None,
+        rate_limit = None,
+        rate_limit_window = 1e9,
+        rate_limit_always_on = false,
+        service = None,
+        env = None,
+        adaptive = false
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        rules: Option<String>,
+        default_sample_rate: Option<f64>,
+        rate_limit: Option<f64>,
+        rate_limit_window: f64,
+        rate_limit_always_on: bool,
+        service: Option<String>,
+        env: Option<String>,
+        adaptive: bool,
+    ) -> PyResult<Self> {
+        let rate_limit = match rate_limit {
+            Some(rate_limit) => rate_limit,
+            None => rate_limit_from_env()?,
+        };
+        if rate_limit.is_nan() {
+            return Err(PyValueError::new_err("rate_limit must be a number"));
+        }
+        if rate_limit_window.is_nan() || rate_limit_window < 1.0 {
+            return Err(PyValueError::new_err(
+                "rate_limit_window must be at least 1 nanosecond",
+            ));
+        }
+        Ok(TraceSamplerPy {
+            rules: Mutex::new(Arc::new(rules::load(rules.clone(), default_sample_rate)?)),
+            local_rules: rules,
+            default_sample_rate,
+            agent: PrioritySamplerPy::new(1.0, service, env),
+            limiter: Mutex::new(Limiter::new(rate_limit, rate_limit_window as u64)?),
+            rate_limit,
+            rate_limit_always_on,
+            counters: Counters::new(),
+            backpressure: adaptive.then(|| {
+                Mutex::new(Backpressure {
+                    factor: 1.0,
+                    reduced_ns: None,
+                })
+            }),
+        })
+    }
+
+    #[getter]
+    fn rate_limit(&self) -> f64 {
+        self.rate_limit
+    }
+
+    /// Rates by service of the agent.
+    #[getter]
+    fn agent_rates(&self) -> HashMap<String, f64> {
+        self.agent.rates()
+    }
+
+    /// Sets the rate of a service and env, those of the sampler by default, until the agent
+    /// returns new rates.
+    #[pyo3(signature = (sample_rate, service = None, env = None))]
+    fn set_sample_rate(&self, sample_rate: f64, service: Option<&str>, env: Option<&str>) {
+        self.agent.set_sample_rate(sample_rate, service, env);
+    }
+
+    fn update_rate_by_service_sample_rates(&self, rate_by_service: HashMap<String, f64>) {
+        self.agent
+            .update_rate_by_service_sample_rates(rate_by_service);
+    }
+
+    fn __len__(&self) -> usize {
+        self.rules().len()
+    }
+
+    /// Applies the sampling rules and rate of an `APM_TRACING` remote configuration, as the raw
+    /// JSON document, in place of the local ones, or reverts to those if `None`. Remote rules keep
+    /// their provenance, for `_dd.p.dm` to tell customer rules from dynamic ones.
+    #[pyo3(signature = (rc_payload = None))]
+    fn update_rules(&self, rc_payload: Option<&[u8]>) -> PyResult<()> {
+        let remote = match rc_payload {
+            Some(payload) => RemoteConfig::parse(payload).map_err(PyValueError::new_err)?,
+            None => RemoteConfig {
+                rules: None,
+                sample_rate: None,
+            },
+        };
+        let mut rules = match remote.rules {
+            Some(rules) => rules,
+            None => rules::load(self.local_rules.clone(), None)?,
+        };
+        rules.extend(
+            remote
+                .sample_rate
+                .or(self.default_sample_rate)
+                .map(Rule::catch_all),
+        );
+        *self.rules.lock().unwrap() = Arc::new(rules);
+        Ok(())
+    }
+
+    /// Factor the sample rates are reduced by because of backpressure, 1 unless adaptive.
+    #[getter]
+    fn backpressure_factor(&self) -> f64 {
+        self.rate_factor()
+    }
+
+    /// Reports that the agent rejected a payload with a 429 or that the queue of the writer is
+    /// saturated. In adaptive mode, the sample rates are halved, at most once per second, down to
+    /// 1/64 of the configured ones, and recovered by 5% of those per second once reports stop.
+    fn report_backpressure(&self) {
+        if let Some(backpressure) = &self.backpressure {
+            backpressure.lock().unwrap().report(monotonic_ns());
+        }
+    }
+
+    /// Records a decision set on a trace by the user, for it to be counted with the others.
+    fn record_manual(&self, sampled: bool) {
+        self.counters.record(Category::Manual, sampled);
+    }
+
+    /// Returns the numbers of traces kept and dropped by category of decision (`default`, `agent`,
+    /// `rule`, `limiter` or `manual`), for telemetry, resetting them if `reset`.
+    #[pyo3(signature = (reset = false))]
+    fn decisions(&self, reset: bool) -> HashMap<&'static str, (u64, u64)> {
+        self.counters.snapshot(reset)
+    }
+
+    /// Samples a trace from the fields of its root span, and returns whether it is kept, its
+    /// priority, the mechanism and the rate of the decision, and the effective rate of the limiter
+    /// if it applied.
+    #[pyo3(signature = (
+        trace_id,
+        service = None,
+        name = None,
+        resource = None,
+        env = None,
+        meta = None,
+        metrics = None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn sample(
+        &self,
+        trace_id: u128,
+        service: Option<&str>,
+        name: Option<&str>,
+        resource: Option<&str>,
+        env: Option<&str>,
+        meta: Option<&Bound<'_, PyDict>>,
+        metrics: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<(bool, i32, i32, f64, Option<f64>)> {
+        let rules = self.rules();
+        let span = span_fields(&rules, trace_id, service, name, resource, meta, metrics)?;
+        let decision = self.decide(&rules, &span, env);
+        Ok((
+            decision.sampled,
+            decision.priority,
+            decision.mechanism,
+            decision.sample_rate,
+            decision.limit_rate,
+        ))
+    }
+
+    /// Returns how `sample()` would decide for a trace, for debug logs: the rule that matched, the
+    /// rate applied, the state of the rate limiter and the final priority. The trace isn't counted,
+    /// and takes no token from the rate limiter.
+    #[pyo3(signature = (
+        trace_id,
+        service = None,
+        name = None,
+        resource = None,
+        env = None,
+        meta = None,
+        metrics = None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn explain<'py>(
+        &self,
+        py: Python<'py>,
+        trace_id: u128,
+        service: Option<&str>,
+        name: Option<&str>,
+        resource: Option<&str>,
+        env: Option<&str>,
+        meta: Option<&Bound<'_, PyDict>>,
+        metrics: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let rules = self.rules();
+        let span = span_fields(&rules, trace_id, service, name, resource, meta, metrics)?;
+        let factor = self.rate_factor();
+        let decision = self.decide_at(&rules, &span, env, factor, true);
+        let explanation = PyDict::new_bound(py);
+        let rule = decision.rule.map(|index| rules[index].to_string());
+        explanation.set_item("rule", rule)?;
+        explanation.set_item("sample_rate", decision.sample_rate)?;
+        explanation.set_item("backpressure_factor", factor)?;
+        explanation.set_item("rate_limit", self.rate_limit)?;
+        explanation.set_item("limit_psr", decision.limit_rate)?;
+        explanation.set_item("rate_limited", decision.rate_limited)?;
+        explanation.set_item("sampled", decision.sampled)?;
+        explanation.set_item("priority", decision.priority)?;
+        explanation.set_item("mechanism", decision.mechanism)?;
+        explanation.set_item(DECISION_MAKER_KEY, decision_maker(decision.mechanism))?;
+        Ok(explanation)
+    }
+}
@@ -0,0 +1,167 @@
+use std::sync::Mutex;
+
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use super::glob::Glob;
+use super::MECHANISM_SPAN_SAMPLING_RULE;
+use crate::json::{self, Value};
+use crate::rate_limiter::{bucket_size, monotonic_ns, TokenBucket, SECOND_NS};
+
+const SPAN_SAMPLING_MECHANISM: &str = "_dd.span_sampling.mechanism";
+const SPAN_SAMPLING_RATE: &str = "_dd.span_sampling.rule_rate";
+const SPAN_SAMPLING_MAX_PER_SECOND: &str = "_dd.span_sampling.max_per_second";
+/// `max_per_second` of the rules without a limit.
+const NO_LIMIT: f64 = -1.0;
+
+/// Rule of `DD_SPAN_SAMPLING_RULES`, like `ddtrace.internal.sampling.SpanSamplingRule`.
+struct SpanRule {
+    sample_rate: f64,
+    max_per_second: f64,
+    service: Option<Glob>,
+    name: Option<Glob>,
+    limiter: Mutex<TokenBucket>,
+}
+
+impl SpanRule {
+    /// Parses a rule, or returns `None` if it is invalid.
+    fn from_json(rule: &Value) -> Option<Self> {
+        let number = |key: &str, default: f64| match rule.get(key) {
+            Some(value) => value.as_f64(),
+            None => Some(default),
+        };
+        let glob = |key: &str| match rule.get(key) {
+            Some(Value::Null) | None => Some(None),
+            Some(value) => value.as_str().map(|pattern| Some(Glob::new(pattern))),
+        };
+        let sample_rate = number("sample_rate", 1.0)?;
+        let max_per_second = number("max_per_second", NO_LIMIT)?;
+        Some(SpanRule {
+            sample_rate,
+            max_per_second,
+            service: glob("service")?,
+            name: glob("name")?,
+            limiter: Mutex::new(TokenBucket::new(
+                max_per_second,
+                bucket_size(max_per_second, None).ok()?,
+                SECOND_NS,
+                monotonic_ns(),
+            )),
+        })
+    }
+
+    fn matches(&self, service: Option<&str>, name: Option<&str>) -> bool {
+        if service.is_none() && name.is_none() {
+            return false;
+        }
+        let matches = |glob: &Option<Glob>, value: Option<&str>| match (glob, value) {
+            (None, _) => true,
+            (Some(glob), Some(value)) => glob.matches(value),
+            (Some(_), None) => false,
+        };
+        matches(&self.service, service) && matches(&self.name, name)
+    }
+
+    /// Returns whether a span is kept, from its ID, like `SpanSamplingRule.sample()`: unlike for
+    /// traces, the hash is taken modulo `2**64`.
+    fn sample(&self, span_id: u64) -> bool {
+        let sampled = match self.sample_rate {
+            rate if rate == 1.0 => true,
+            rate if rate == 0.0 => false,
+            rate => {
+                let threshold = rate * 2f64.powi(64);
+                let hash = span_id.wrapping_mul(super::KNUTH_FACTOR as u64);
+                threshold >= 2f64.powi(64) || (threshold >= 0.0 && hash <= threshold as u64)
+            }
+        };
+        sampled && self.limiter.lock().unwrap().is_allowed(monotonic_ns(), 1)
+    }
+}
+
+/// Parses the rules of a `DD_SPAN_SAMPLING_RULES` document. Like the Python tracer, a document
+/// that isn't a list gives no rules, and neither does a rule matching neither a service nor a name.
+fn parse(rules: &str) -> Vec<SpanRule> {
+    let Ok(document) = json::parse(rules.as_bytes()) else {
+        return Vec::new();
+    };
+    let Some(rules) = document.as_array() else {
+        return Vec::new();
+    };
+    let mut parsed = Vec::with_capacity(rules.len());
+    for rule in rules {
+        let pattern = |key: &str| rule.get(key).and_then(Value::as_str).unwrap_or_default();
+        if pattern("service").is_empty() && pattern("name").is_empty() {
+            return Vec::new();
+        }
+        parsed.extend(SpanRule::from_json(rule));
+    }
+    parsed
+}
+
+/// Returns the rules set in the environment, with `DD_SPAN_SAMPLING_RULES` taking precedence over
+/// the file at `DD_SPAN_SAMPLING_RULES_FILE`.
+fn rules_from_env() -> String {
+    match std::env::var("DD_SPAN_SAMPLING_RULES") {
+        Ok(rules) if !rules.is_empty() => rules,
+        _ => std::env::var("DD_SPAN_SAMPLING_RULES_FILE")
+            .ok()
+            .filter(|path| !path.is_empty())
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .unwrap_or_default(),
+    }
+}
+
+/// Single span sampling rules of `DD_SPAN_SAMPLING_RULES`, evaluated on the spans of the traces
+/// that aren't kept: the first rule matching the service and name of a span decides whether it is
+/// kept anyway, at its sample rate and within its limit of spans per second.
+///
+/// `sample()` returns the `_dd.span_sampling.*` metrics to set on a kept span, or `None`.
+#[pyclass(
+    frozen,
+    name = "SpanSamplingRules",
+    module = "ddtrace.internal.core._core"
+)]
+pub struct SpanSamplingRulesPy {
+    rules: Vec<SpanRule>,
+}
+
+#[pymethods]
+impl SpanSamplingRulesPy {
+    /// Parses `rules`, or those set in the environment if `None`. Rules that aren't valid are
+    /// skipped.
+    #[new]
+    #[pyo3(signature = (rules = None))]
+    fn new(rules: Option<String>) -> Self {
+        let rules = rules.unwrap_or_else(rules_from_env);
+        SpanSamplingRulesPy {
+            rules: parse(&rules),
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.rules.len()
+    }
+
+    #[pyo3(signature = (span_id, service = None, name = None))]
+    fn sample<'py>(
+        &self,
+        py: Python<'py>,
+        span_id: u64,
+        service: Option<&str>,
+        name: Option<&str>,
+    ) -> PyResult<Option<Bound<'py, PyDict>>> {
+        let Some(rule) = self.rules.iter().find(|rule| rule.matches(service, name)) else {
+            return Ok(None);
+        };
+        if !rule.sample(span_id) {
+            return Ok(None);
+        }
+        let tags = PyDict::new_bound(py);
+        tags.set_item(SPAN_SAMPLING_MECHANISM, MECHANISM_SPAN_SAMPLING_RULE)?;
+        tags.set_item(SPAN_SAMPLING_RATE, rule.sample_rate)?;
+        if rule.max_per_second != NO_LIMIT {
+            tags.set_item(SPAN_SAMPLING_MAX_PER_SECOND, rule.max_per_second)?;
+        }
+        Ok(Some(tags))
+    }
+}
@@ -0,0 +1,266 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use super::duration;
+use super::transport::{Client, Endpoint, Proxy};
+use crate::json::{self, Value};
+use crate::runtime::shared_runtime;
+
+/// Capabilities of the agent, read from its `/info` endpoint.
+pub struct AgentInfo {
+    document: Value,
+    version: Option<String>,
+    endpoints: Vec<String>,
+    feature_flags: Vec<String>,
+    client_drop_p0s: bool,
+    span_events: bool,
+    peer_tags: Vec<String>,
+    obfuscation_version: Option<u64>,
+}
+
+/// Returns the strings of an array, skipping anything else.
+fn strings(value: Option<&Value>) -> Vec<String> {
+    value
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|item| item.as_str().map(str::to_owned))
+        .collect()
+}
+
+impl AgentInfo {
+    fn parse(body: &[u8]) -> Result<Self, String> {
+        let document = json::parse(body)?;
+        if document.as_object().is_none() {
+            return Err("expected an object".to_owned());
+        }
+        Ok(AgentInfo {
+            version: document
+                .get("version")
+                .and_then(Value::as_str)
+                .map(str::to_owned),
+            endpoints: strings(document.get("endpoints")),
+            feature_flags: strings(document.get("feature_flags")),
+            client_drop_p0s: document
+                .get("client_drop_p0s")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            span_events: document
+                .get("span_events")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            peer_tags: strings(document.get("peer_tags")),
+            obfuscation_version: document
+                .get("obfuscation_version")
+                .and_then(Value::as_f64)
+                .map(|version| version as u64),
+            document,
+        })
+    }
+
+    /// Returns whether the agent serves `path`, e.g. `/v0.5/traces`.
+    pub fn supports_endpoint(&self, path: &str) -> bool {
+        let path = path.trim_end_matches('/');
+        self.endpoints
+            .iter()
+            .any(|endpoint| endpoint.trim_end_matches('/') == path)
+    }
+
+    /// Returns whether the agent accepts stats computed by the tracer, and lets it drop the
+    /// traces that aren't sampled.
+    pub fn supports_stats(&self) -> bool {
+        self.client_drop_p0s && self.supports_endpoint("/v0.6/stats")
+    }
+}
+
+/// Capabilities of the agent, as returned by [`AgentInfoFetcherPy::fetch`].
+#[pyclass(frozen, name = "AgentInfo", module = "ddtrace.internal.core._core")]
+pub struct AgentInfoPy {
+    info: Arc<AgentInfo>,
+}
+
+#[pymethods]
+impl AgentInfoPy {
+    #[getter]
+    fn version(&self) -> Option<&str> {
+        self.info.version.as_deref()
+    }
+
+    /// Paths served by the agent.
+    #[getter]
+    fn endpoints(&self) -> Vec<String> {
+        self.info.endpoints.clone()
+    }
+
+    #[getter]
+    fn feature_flags(&self) -> Vec<String> {
+        self.info.feature_flags.clone()
+    }
+
+    /// Whether the agent lets the tracer drop unsampled traces once it computed their stats.
+    #[getter]
+    fn client_drop_p0s(&self) -> bool {
+        self.info.client_drop_p0s
+    }
+
+    /// Whether the agent accepts span events in the `span_events` field of spans.
+    #[getter]
+    fn span_events(&self) -> bool {
+        self.info.span_events
+    }
+
+    /// Tags the agent aggregates stats of client spans by.
+    #[getter]
+    fn peer_tags(&self) -> Vec<String> {
+        self.info.peer_tags.clone()
+    }
+
+    /// Version of the obfuscation done by the agent, if it supports obfuscation by the tracer.
+    #[getter]
+    fn obfuscation_version(&self) -> Option<u64> {
+        self.info.obfuscation_version
+    }
+
+    #[getter]
+    fn supports_v05(&self) -> bool {
+        self.info.supports_endpoint("/v0.5/traces")
+    }
+
+    #[getter]
+    fn supports_stats(&self) -> bool {
+        self.info.supports_stats()
+    }
+
+    fn supports_endpoint(&self, path: &str) -> bool {
+        self.info.supports_endpoint(path)
+    }
+
+    /// Returns the whole document returned by the agent, like `ddtrace.internal.agent.info()`.
+    fn to_dict(&self, py: Python<'_>) -> PyResult<PyObject> {
+        json::to_python(&self.info.document, py)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "AgentInfo(version={:?}, endpoints={:?}, client_drop_p0s={})",
+            self.info.version, self.info.endpoints, self.info.client_drop_p0s
+        )
+    }
+}
+
+/// Cached outcome of the last query, `None` if the agent didn't answer with its capabilities.
+struct Cached {
+    fetched_at: Instant,
+    info: Option<Arc<AgentInfo>>,
+}
+
+/// Queries the agent's `/info` endpoint, caching the result for `ttl`.
+pub struct Fetcher {
+    client: Client,
+    path: String,
+    ttl: Duration,
+    cached: Mutex<Option<Cached>>,
+    last_error: Mutex<Option<String>>,
+}
+
+impl Fetcher {
+    pub fn new(client: Client, base_path: &str, ttl: Duration) -> Self {
+        Fetcher {
+            client,
+            path: format!("{base_path}/info"),
+            ttl,
+            cached: Mutex::new(None),
+            last_error: Mutex::new(None),
+        }
+    }
+
+    async fn query(&self) -> Result<Option<AgentInfo>, String> {
+        let response = self
+            .client
+            .send("GET", &self.path, &[], b"")
+            .await
+            .map_err(|err| err.to_string())?;
+        match response.status {
+            // Older agents don't serve `/info`.
+            404 => Ok(None),
+            200..=299 => AgentInfo::parse(&response.body)
+                .map(Some)
+                .map_err(|err| format!("invalid agent info: {err}")),
+            status => Err(format!("HTTP error status {status}")),
+        }
+    }
+
+    /// Returns the capabilities of the agent, or `None` if it couldn't tell them. The agent is
+    /// only queried if the last result is older than `ttl`, or if `force` is set.
+    pub async fn fetch(&self, force: bool) -> Option<Arc<AgentInfo>> {
+        if let Some(cached) = self
+            .cached
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|cached| !force && cached.fetched_at.elapsed() < self.ttl)
+        {
+            return cached.info.clone();
+        }
+        let info = match self.query().await {
+            Ok(info) => info.map(Arc::new),
+            Err(err) => {
+                *self.last_error.lock().unwrap() = Some(err);
+                None
+            }
+        };
+        *self.cached.lock().unwrap() = Some(Cached {
+            fetched_at: Instant::now(),
+            info: info.clone(),
+        });
+        info
+    }
+
+    pub fn last_error(&self) -> Option<String> {
+        self.last_error.lock().unwrap().clone()
+    }
+}
+
+/// Queries the agent's `/info` endpoint and caches the result for `ttl` seconds, so that
+/// components negotiating capabilities with the agent don't each query it. Requests go through
+/// the HTTP proxy at `proxy`, or the one set with `HTTP_PROXY` or `HTTPS_PROXY` if `None`, like
+/// the native writer.
+#[pyclass(
+    frozen,
+    name = "AgentInfoFetcher",
+    module = "ddtrace.internal.core._core"
+)]
+pub struct AgentInfoFetcherPy {
+    fetcher: Fetcher,
+}
+
+#[pymethods]
+impl AgentInfoFetcherPy {
+    #[new]
+    #[pyo3(signature = (agent_url, timeout = 2.0, ttl = 300.0, proxy = None))]
+    fn new(agent_url: &str, timeout: f64, ttl: f64, proxy: Option<&str>) -> PyResult<Self> {
+        let (endpoint, base_path) = Endpoint::parse(agent_url).map_err(PyValueError::new_err)?;
+        let proxy = Proxy::resolve(&endpoint, proxy).map_err(PyValueError::new_err)?;
+        let client = Client::new(endpoint, duration(timeout)?).with_proxy(proxy);
+        Ok(AgentInfoFetcherPy {
+            fetcher: Fetcher::new(client, &base_path, duration(ttl)?),
+        })
+    }
+
+    /// Returns the capabilities of the agent, or `None` if it couldn't tell them. The agent is
+    /// only queried if the last result is older than `ttl`, or if `force` is set.
+    #[pyo3(signature = (force = false))]
+    fn fetch(&self, py: Python<'_>, force: bool) -> Option<AgentInfoPy> {
+        py.allow_threads(|| shared_runtime().block_on(self.fetcher.fetch(force)))
+            .map(|info| AgentInfoPy { info })
+    }
+
+    /// Description of the last error, if any.
+    #[getter]
+    fn last_error(&self) -> Option<String> {
+        self.fetcher.last_error()
+    }
+}
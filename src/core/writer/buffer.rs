@@ -0,0 +1,120 @@
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::time::Duration;
+
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use tokio::time::{Instant, MissedTickBehavior};
+
+use super::queue::{Payload, Queue};
+use super::stats::{DropReason, Stats};
+use crate::encoding::{OverflowPy, OverflowReasonPy};
+
+/// Encoder a writer buffers traces in, flushed into its queue periodically and on demand. Any of
+/// the native encoders can be used.
+pub struct Buffer {
+    encoder: PyObject,
+    /// Number of spans buffered in the encoder, locked while it is used.
+    spans: Mutex<usize>,
+    stats: Arc<Mutex<Stats>>,
+}
+
+impl Buffer {
+    pub fn new(encoder: PyObject, stats: Arc<Mutex<Stats>>) -> Self {
+        Buffer {
+            encoder,
+            spans: Mutex::new(0),
+            stats,
+        }
+    }
+
+    pub fn encoder(&self) -> &PyObject {
+        &self.encoder
+    }
+
+    /// Locks the encoder. Encoders release the GIL while compressing payloads, so it is released
+    /// while waiting as well.
+    fn lock(&self, py: Python<'_>) -> MutexGuard<'_, usize> {
+        loop {
+            match self.spans.try_lock() {
+                Ok(guard) => return guard,
+                Err(TryLockError::Poisoned(err)) => return err.into_inner(),
+                Err(TryLockError::WouldBlock) => py.allow_threads(|| drop(self.spans.lock())),
+            }
+        }
+    }
+
+    fn take_payload(encoder: &Bound<'_, PyAny>, spans: &mut usize) -> PyResult<Option<Payload>> {
+        let (data, traces): (Option<Bound<'_, PyBytes>>, usize) =
+            encoder.call_method0("encode")?.extract()?;
+        let Some(data) = data else {
+            return Ok(None);
+        };
+        Ok(Some(Payload {
+            data: data.as_bytes().to_vec(),
+            traces,
+            spans: std::mem::take(spans),
+            content_encoding: encoder.getattr("content_encoding")?.extract()?,
+        }))
+    }
+
+    /// Buffers a trace, flushing the encoder first if it is full, and returns whether the trace
+    /// was buffered.
+    pub fn write(&self, py: Python<'_>, trace: &Bound<'_, PyAny>, queue: &Queue) -> PyResult<bool> {
+        let mut spans = self.lock(py);
+        let encoder = self.encoder.bind(py);
+        let mut overflow = encoder.call_method1("put", (trace,))?;
+        let full = matches!(
+            overflow.downcast::<OverflowPy>(),
+            Ok(overflow) if overflow.get().reason() == OverflowReasonPy::BufferFull
+        );
+        if full {
+            if let Some(payload) = Self::take_payload(encoder, &mut spans)? {
+                queue.push(payload);
+            }
+            overflow = encoder.call_method1("put", (trace,))?;
+        }
+        if overflow.is_none() {
+            *spans += trace.len()?;
+            return Ok(true);
+        }
+        let overflow = overflow.downcast::<OverflowPy>()?.get();
+        let reason = match overflow.reason() {
+            OverflowReasonPy::TraceTooBig => DropReason::TooLarge,
+            OverflowReasonPy::BufferFull => DropReason::BufferFull,
+        };
+        self.stats
+            .lock()
+            .unwrap()
+            .record_dropped_trace(reason, overflow.spans(), overflow.size());
+        Ok(false)
+    }
+
+    /// Queues the payload of the buffered traces, if any.
+    pub fn flush(&self, py: Python<'_>, queue: &Queue) -> PyResult<()> {
+        let mut spans = self.lock(py);
+        if let Some(payload) = Self::take_payload(self.encoder.bind(py), &mut spans)? {
+            queue.push(payload);
+        }
+        Ok(())
+    }
+}
+
+/// Flushes a buffer into a queue every `interval`, until the task is aborted.
+pub async fn flush_periodically(buffer: Arc<Buffer>, queue: Arc<Queue>, interval: Duration) {
+    let mut ticks = tokio::time::interval_at(Instant::now() + interval, interval);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        let buffer = buffer.clone();
+        let queue = queue.clone();
+        // Encoders need the GIL, which must not be waited for on a thread of the runtime.
+        let _ = tokio::task::spawn_blocking(move || {
+            Python::with_gil(|py| {
+                if let Err(err) = buffer.flush(py, &queue) {
+                    err.write_unraisable_bound(py, Some(buffer.encoder.bind(py)));
+                }
+            })
+        })
+        .await;
+    }
+}
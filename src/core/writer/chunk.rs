@@ -0,0 +1,159 @@
+// Splitting of v0.4 and v0.5 payloads larger than the agent accepts into several payloads, at
+// trace boundaries. v0.5 payloads each get the whole string table, which the traces index into.
+
+use super::queue::Payload;
+use crate::encoding::msgpack::write_array_len;
+
+/// Largest header of an array, which is assumed for the arrays of traces of the chunks.
+const MAX_ARRAY_HEADER_SIZE: usize = 5;
+/// Header of the `[string table, traces]` array of v0.5 payloads.
+const V05_HEADER: u8 = 0x92;
+
+/// Reads a big-endian unsigned integer of `size` bytes at `pos`, and moves past it.
+fn read_uint(data: &[u8], pos: &mut usize, size: usize) -> Option<usize> {
+    let bytes = data.get(*pos..*pos + size)?;
+    *pos += size;
+    Some(
+        bytes
+            .iter()
+            .fold(0usize, |value, &byte| value << 8 | byte as usize),
+    )
+}
+
+/// Reads the header of the array at `pos`, and returns its length with the position of its first
+/// item.
+fn read_array_len(data: &[u8], pos: usize) -> Option<(usize, usize)> {
+    let mut pos = pos;
+    let len = match *data.get(pos)? {
+        marker @ 0x90..=0x9f => {
+            pos += 1;
+            (marker & 0x0f) as usize
+        }
+        0xdc => {
+            pos += 1;
+            read_uint(data, &mut pos, 2)?
+        }
+        0xdd => {
+            pos += 1;
+            read_uint(data, &mut pos, 4)?
+        }
+        _ => return None,
+    };
+    Some((len, pos))
+}
+
+/// Returns the position after the value at `pos`, without recursing into nested values.
+fn skip(data: &[u8], mut pos: usize) -> Option<usize> {
+    let mut remaining = 1usize;
+    while remaining > 0 {
+        remaining -= 1;
+        let marker = *data.get(pos)?;
+        pos += 1;
+        let (size, items) = match marker {
+            0x00..=0x7f | 0xc0 | 0xc2 | 0xc3 | 0xe0..=0xff => (0, 0),
+            0x80..=0x8f => (0, 2 * (marker & 0x0f) as usize),
+            0x90..=0x9f => (0, (marker & 0x0f) as usize),
+            0xa0..=0xbf => ((marker & 0x1f) as usize, 0),
+            0xc4 | 0xd9 => (read_uint(data, &mut pos, 1)?, 0),
+            0xc5 | 0xda => (read_uint(data, &mut pos, 2)?, 0),
+            0xc6 | 0xdb => (read_uint(data, &mut pos, 4)?, 0),
+            0xc7 => (read_uint(data, &mut pos, 1)? + 1, 0),
+            0xc8 => (read_uint(data, &mut pos, 2)? + 1, 0),
+            0xc9 => (read_uint(data, &mut pos, 4)? + 1, 0),
+            0xcc | 0xd0 => (1, 0),
+            0xcd | 0xd1 => (2, 0),
+            0xca | 0xce | 0xd2 => (4, 0),
+            0xcb | 0xcf | 0xd3 => (8, 0),
+            0xd4 => (2, 0),
+            0xd5 => (3, 0),
+            0xd6 => (5, 0),
+            0xd7 => (9, 0),
+            0xd8 => (17, 0),
+            0xdc => (0, read_uint(data, &mut pos, 2)?),
+            0xdd => (0, read_uint(data, &mut pos, 4)?),
+            0xde => (0, 2 * read_uint(data, &mut pos, 2)?),
+            0xdf => (0, 2 * read_uint(data, &mut pos, 4)?),
+            // 0xc1 is never used.
+            _ => return None,
+        };
+        pos = pos.checked_add(size)?;
+        remaining = remaining.checked_add(items)?;
+    }
+    (pos <= data.len()).then_some(pos)
+}
+
+/// Traces of a chunk, as a range of the payload.
+struct Chunk {
+    start: usize,
+    end: usize,
+    traces: usize,
+    spans: usize,
+}
+
+/// Splits an uncompressed v0.4 or v0.5 payload into payloads of at most `max_size` bytes each,
+/// with as many whole traces as fit, in order. Traces larger than `max_size` get a payload of
+/// their own. Returns `None` if the payload isn't a valid one of `api_version`.
+pub fn split(payload: &Payload, api_version: &str, max_size: usize) -> Option<Vec<Payload>> {
+    let data = &payload.data;
+    let (prefix, traces_start) = match api_version {
+        "v0.4" => (&data[..0], 0),
+        "v0.5" => {
+            let (2, strings_start) = read_array_len(data, 0)? else {
+                return None;
+            };
+            let strings_end = skip(data, strings_start)?;
+            (&data[strings_start..strings_end], strings_end)
+        }
+        _ => return None,
+    };
+    let overhead = MAX_ARRAY_HEADER_SIZE
+        + if prefix.is_empty() {
+            0
+        } else {
+            1 + prefix.len()
+        };
+
+    let (len, mut pos) = read_array_len(data, traces_start)?;
+    let mut chunks: Vec<Chunk> = Vec::new();
+    for _ in 0..len {
+        let (spans, _) = read_array_len(data, pos)?;
+        let end = skip(data, pos)?;
+        match chunks.last_mut() {
+            Some(chunk) if overhead + end - chunk.start <= max_size => {
+                chunk.end = end;
+                chunk.traces += 1;
+                chunk.spans += spans;
+            }
+            _ => chunks.push(Chunk {
+                start: pos,
+                end,
+                traces: 1,
+                spans,
+            }),
+        }
+        pos = end;
+    }
+    if pos != data.len() {
+        return None;
+    }
+
+    let payloads = chunks
+        .into_iter()
+        .map(|chunk| {
+            let mut chunk_data = Vec::with_capacity(overhead + chunk.end - chunk.start);
+            if !prefix.is_empty() {
+                chunk_data.push(V05_HEADER);
+                chunk_data.extend_from_slice(prefix);
+            }
+            write_array_len(&mut chunk_data, chunk.traces);
+            chunk_data.extend_from_slice(&data[chunk.start..chunk.end]);
+            Payload {
+                data: chunk_data,
+                traces: chunk.traces,
+                spans: chunk.spans,
+                content_encoding: None,
+            }
+        })
+        .collect();
+    Some(payloads)
+}
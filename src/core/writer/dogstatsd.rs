@@ -0,0 +1,154 @@
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::runtime::shared_runtime;
+
+/// Namespace of the health metrics of the tracer.
+const PREFIX: &str = "datadog.tracer.";
+/// How often connecting to DogStatsD is attempted again after failing.
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Socket metrics are sent on, without waiting: they are dropped when it would block.
+enum Socket {
+    Udp(UdpSocket),
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+}
+
+/// Client sending the health metrics of a writer to DogStatsD. Connecting is best-effort: metrics
+/// are dropped until the socket is connected, which is attempted again every `RETRY_INTERVAL`.
+pub struct DogStatsd {
+    url: Arc<str>,
+    socket: Arc<OnceLock<Socket>>,
+    /// When a connection may be attempted again.
+    next_attempt: Mutex<Instant>,
+}
+
+/// Returns the DogStatsD URL set in the environment, like `ddtrace.internal.agent.get_stats_url`.
+pub fn url_from_env() -> String {
+    if let Ok(url) = std::env::var("DD_DOGSTATSD_URL") {
+        return url;
+    }
+    let host = std::env::var("DD_AGENT_HOST").unwrap_or_else(|_| "localhost".to_owned());
+    let port = std::env::var("DD_DOGSTATSD_PORT").unwrap_or_else(|_| "8125".to_owned());
+    if host.contains(':') {
+        format!("udp://[{host}]:{port}")
+    } else {
+        format!("udp://{host}:{port}")
+    }
+}
+
+/// Opens a socket to DogStatsD at a `udp://host:port` or `unix:///path` URL.
+fn connect(url: &str) -> Result<Socket, String> {
+    let error = |err: std::io::Error| format!("can't connect to DogStatsD at {url}: {err}");
+    if let Some(path) = url.strip_prefix("unix://") {
+        #[cfg(unix)]
+        {
+            let socket = UnixDatagram::unbound().map_err(error)?;
+            socket.connect(path).map_err(error)?;
+            socket.set_nonblocking(true).map_err(error)?;
+            return Ok(Socket::Unix(socket));
+        }
+        #[cfg(not(unix))]
+        return Err(format!(
+            "Unix domain sockets aren't supported on this platform: {path}"
+        ));
+    }
+    let Some(authority) = url.strip_prefix("udp://") else {
+        return Err(format!("unsupported DogStatsD URL scheme: {url}"));
+    };
+    let address = authority
+        .trim_end_matches('/')
+        .to_socket_addrs()
+        .map_err(error)?
+        .next()
+        .ok_or_else(|| format!("can't resolve DogStatsD host: {url}"))?;
+    let local = if address.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(local).map_err(error)?;
+    socket.connect(address).map_err(error)?;
+    socket.set_nonblocking(true).map_err(error)?;
+    Ok(Socket::Udp(socket))
+}
+
+/// Returns whether connecting to `url` involves resolving a host name, which may block.
+fn needs_resolution(url: &str) -> bool {
+    url.strip_prefix("udp://").is_some_and(|authority| {
+        authority
+            .trim_end_matches('/')
+            .parse::<SocketAddr>()
+            .is_err()
+    })
+}
+
+impl DogStatsd {
+    /// Returns a client for DogStatsD at a `udp://host:port` or `unix:///path` URL, without
+    /// waiting for a host name to be resolved: that happens on a blocking thread of the shared
+    /// runtime.
+    pub fn new(url: &str) -> Self {
+        let dogstatsd = DogStatsd {
+            url: url.into(),
+            socket: Arc::new(OnceLock::new()),
+            next_attempt: Mutex::new(Instant::now()),
+        };
+        dogstatsd.try_connect();
+        dogstatsd
+    }
+
+    /// Attempts to connect, unless an attempt was made less than `RETRY_INTERVAL` ago.
+    fn try_connect(&self) {
+        {
+            let mut next_attempt = self.next_attempt.lock().unwrap();
+            let now = Instant::now();
+            if now < *next_attempt {
+                return;
+            }
+            *next_attempt = now + RETRY_INTERVAL;
+        }
+        let (url, socket) = (self.url.clone(), self.socket.clone());
+        let attempt = move || {
+            // Like the Python client, metrics are lost while DogStatsD can't be reached.
+            if let Ok(connected) = connect(&url) {
+                let _ = socket.set(connected);
+            }
+        };
+        if needs_resolution(&self.url) {
+            shared_runtime().spawn_blocking(attempt);
+        } else {
+            attempt();
+        }
+    }
+
+    fn send(&self, name: &str, value: u64, kind: &str, tags: &[&str]) {
+        let Some(socket) = self.socket.get() else {
+            self.try_connect();
+            return;
+        };
+        let mut datagram = format!("{PREFIX}{name}:{value}|{kind}");
+        if !tags.is_empty() {
+            datagram.push_str("|#");
+            datagram.push_str(&tags.join(","));
+        }
+        // Like the Python client, metrics that can't be sent are lost.
+        let _ = match socket {
+            Socket::Udp(socket) => socket.send(datagram.as_bytes()),
+            #[cfg(unix)]
+            Socket::Unix(socket) => socket.send(datagram.as_bytes()),
+        };
+    }
+
+    /// Sends a distribution, the type of the health metrics of the Python writer.
+    pub fn distribution(&self, name: &str, value: u64, tags: &[&str]) {
+        self.send(name, value, "d", tags);
+    }
+
+    pub fn gauge(&self, name: &str, value: u64) {
+        self.send(name, value, "g", &[]);
+    }
+}
@@ -0,0 +1,28 @@
+mod agent_info;
+mod buffer;
+mod chunk;
+mod dogstatsd;
+mod pipeline;
+mod queue;
+mod rates;
+mod retry;
+mod span_stats;
+mod stats;
+mod trace_writer;
+mod transport;
+
+use std::time::Duration;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+pub use agent_info::{AgentInfoFetcherPy, AgentInfoPy};
+pub use retry::RetryPolicyPy;
+pub use span_stats::NativeSpanStatsProcessorPy;
+pub use stats::WriterStatsPy;
+pub use trace_writer::NativeTraceWriterPy;
+
+/// Converts a number of seconds given by Python, negative ones meaning no time.
+fn duration(seconds: f64) -> PyResult<Duration> {
+    Duration::try_from_secs_f64(seconds.max(0.0)).map_err(|e| PyValueError::new_err(e.to_string()))
+}
@@ -0,0 +1,102 @@
+use std::io::{self, ErrorKind};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use data_pipeline::trace_exporter::{
+    TraceExporter, TraceExporterInputFormat, TraceExporterOutputFormat,
+};
+
+use super::queue::Payload;
+use super::transport::Response;
+
+/// Size of the buckets the exporter aggregates stats in, like the Python span aggregator.
+const STATS_BUCKET_SIZE: Duration = Duration::from_secs(10);
+
+/// libdatadog's trace exporter, given v0.4 payloads to deserialize, compute stats on when
+/// enabled, and send to the agent itself.
+pub struct Pipeline {
+    /// Taken when the exporter is shut down.
+    exporter: Mutex<Option<Arc<TraceExporter>>>,
+    /// How long the stats left are given to be sent on shutdown.
+    timeout: Duration,
+}
+
+/// Returns the value of the header named `name` in `headers`, ignoring case.
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> &'a str {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map_or("", |(_, value)| value.as_str())
+}
+
+impl Pipeline {
+    /// Builds an exporter to `agent_url`, describing the tracer with the `Datadog-Meta-*` headers
+    /// sent by the writer.
+    pub fn new(
+        agent_url: &str,
+        headers: &[(String, String)],
+        timeout: Duration,
+        compute_stats: bool,
+    ) -> Result<Self, String> {
+        let mut builder = TraceExporter::builder()
+            .set_url(agent_url)
+            .set_language(header(headers, "Datadog-Meta-Lang"))
+            .set_language_version(header(headers, "Datadog-Meta-Lang-Version"))
+            .set_language_interpreter(header(headers, "Datadog-Meta-Lang-Interpreter"))
+            .set_tracer_version(header(headers, "Datadog-Meta-Tracer-Version"))
+            .set_input_format(TraceExporterInputFormat::V04)
+            .set_output_format(TraceExporterOutputFormat::V04);
+        if compute_stats {
+            builder = builder.enable_stats(STATS_BUCKET_SIZE);
+        }
+        let exporter = builder.build().map_err(|err| err.to_string())?;
+        Ok(Pipeline {
+            exporter: Mutex::new(Some(Arc::new(exporter))),
+            timeout,
+        })
+    }
+
+    fn exporter(&self) -> Option<Arc<TraceExporter>> {
+        self.exporter.lock().unwrap().clone()
+    }
+
+    /// Exports a payload, and returns the response of the agent as if it were sent over HTTP. The
+    /// exporter doesn't tell failures apart, so they are all reported as connection errors.
+    pub async fn send(&self, payload: &Payload) -> io::Result<Response> {
+        let Some(exporter) = self.exporter() else {
+            return Err(io::Error::new(
+                ErrorKind::NotConnected,
+                "the exporter is shut down",
+            ));
+        };
+        let data = payload.data.clone();
+        let traces = payload.traces;
+        // The exporter blocks on a runtime of its own, which can't be done on a thread of this
+        // one.
+        let result = tokio::task::spawn_blocking(move || exporter.send(&data, traces))
+            .await
+            .map_err(|err| io::Error::new(ErrorKind::Other, err))?;
+        match result {
+            Ok(body) => Ok(Response {
+                status: 200,
+                body: body.into_bytes(),
+            }),
+            Err(err) => Err(io::Error::new(ErrorKind::Other, err.to_string())),
+        }
+    }
+
+    /// Flushes the stats computed by the exporter, and stops it.
+    pub async fn shutdown(&self) {
+        let Some(exporter) = self.exporter.lock().unwrap().take() else {
+            return;
+        };
+        let timeout = self.timeout;
+        let _ = tokio::task::spawn_blocking(move || {
+            // Sends still in flight hold the exporter until they are done.
+            if let Ok(exporter) = Arc::try_unwrap(exporter) {
+                let _ = exporter.shutdown(Some(timeout));
+            }
+        })
+        .await;
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot};
+
+use super::stats::{DropReason, Stats};
+
+/// Encoded payload waiting to be sent.
+pub struct Payload {
+    pub data: Vec<u8>,
+    pub traces: usize,
+    /// Number of spans in the traces, if known.
+    pub spans: usize,
+    pub content_encoding: Option<String>,
+}
+
+pub enum Message {
+    Payload(Payload),
+    /// Answered once the payloads queued before it are sent, with whether they all were.
+    Flush(oneshot::Sender<bool>),
+}
+
+/// Sending end of the worker's queue, shared by the writer and its flush loop until the writer is
+/// stopped.
+///
+/// The worker doesn't survive a fork, so in a forked child the queue acts as if it was closed, and
+/// never touches the channel, whose receiver belongs to the runtime of the parent.
+pub struct Queue {
+    messages: Mutex<Option<mpsc::Sender<Message>>>,
+    stats: Arc<Mutex<Stats>>,
+    /// ID of the process the worker runs in.
+    pid: u32,
+}
+
+impl Queue {
+    pub fn new(messages: mpsc::Sender<Message>, stats: Arc<Mutex<Stats>>) -> Self {
+        Queue {
+            messages: Mutex::new(Some(messages)),
+            stats,
+            pid: std::process::id(),
+        }
+    }
+
+    /// Whether this is a child forked after the worker was spawned.
+    pub fn is_forked(&self) -> bool {
+        self.pid != std::process::id()
+    }
+
+    /// Queues a payload without waiting, and returns whether it was accepted. Payloads are dropped
+    /// when the queue is full or closed.
+    pub fn push(&self, payload: Payload) -> bool {
+        // Accounted for before it is sent, so that the worker can't see it first.
+        self.stats.lock().unwrap().record_queued(&payload);
+        let message = Message::Payload(payload);
+        let result = match self.messages.lock().unwrap().as_ref() {
+            Some(messages) if !self.is_forked() => messages.try_send(message),
+            _ => Err(TrySendError::Closed(message)),
+        };
+        let (reason, message) = match result {
+            Ok(()) => return true,
+            Err(TrySendError::Full(message)) => (DropReason::BufferFull, message),
+            Err(TrySendError::Closed(message)) => (DropReason::Shutdown, message),
+        };
+        if let Message::Payload(payload) = message {
+            self.stats.lock().unwrap().record_dropped(reason, &payload);
+        }
+        false
+    }
+
+    /// Returns a sender to the queue, unless it is closed.
+    pub fn sender(&self) -> Option<mpsc::Sender<Message>> {
+        if self.is_forked() {
+            return None;
+        }
+        self.messages.lock().unwrap().clone()
+    }
+
+    /// Closes the queue, so that the worker stops once it sent the queued payloads.
+    pub fn close(&self) {
+        let messages = self.messages.lock().unwrap().take();
+        if self.is_forked() {
+            // Dropping the last sender would wake the worker up on the runtime of the parent.
+            std::mem::forget(messages);
+        }
+    }
+}
+
+/// Payloads that failed to send, kept in order until the agent is back, within a byte budget.
+pub struct Requeue {
+    payloads: VecDeque<Payload>,
+    bytes: usize,
+    max_bytes: usize,
+}
+
+impl Requeue {
+    pub fn new(max_bytes: usize) -> Self {
+        Requeue {
+            payloads: VecDeque::new(),
+            bytes: 0,
+            max_bytes,
+        }
+    }
+
+    /// Returns whether a payload fits in the budget on its own.
+    pub fn fits(&self, payload: &Payload) -> bool {
+        payload.data.len() <= self.max_bytes
+    }
+
+    /// Queues a payload after the others, and returns the payloads dropped to stay within the
+    /// budget: the oldest ones, or the payload itself if it doesn't fit on its own.
+    pub fn push(&mut self, payload: Payload) -> Vec<Payload> {
+        if !self.fits(&payload) {
+            return vec![payload];
+        }
+        self.bytes += payload.data.len();
+        self.payloads.push_back(payload);
+        let mut dropped = Vec::new();
+        while self.bytes > self.max_bytes {
+            let Some(oldest) = self.pop() else {
+                break;
+            };
+            dropped.push(oldest);
+        }
+        dropped
+    }
+
+    /// Puts back a payload taken with [`Requeue::pop`], as the oldest one.
+    pub fn push_front(&mut self, payload: Payload) {
+        self.bytes += payload.data.len();
+        self.payloads.push_front(payload);
+    }
+
+    /// Takes the oldest payload.
+    pub fn pop(&mut self) -> Option<Payload> {
+        let payload = self.payloads.pop_front()?;
+        self.bytes -= payload.data.len();
+        Some(payload)
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use pyo3::prelude::*;
+
+use crate::json;
+
+/// Sample rates by service the agent returns with every trace response, keyed by
+/// `service:<service>,env:<env>`, as expected by the priority sampler.
+pub struct RateByService {
+    rates: Mutex<HashMap<String, f64>>,
+    /// Called with the new rates when they change.
+    on_update: Option<Arc<PyObject>>,
+}
+
+/// Reads the `rate_by_service` of a trace response, ignoring the rates that aren't numbers.
+fn parse(body: &[u8]) -> Option<HashMap<String, f64>> {
+    let response = json::parse(body).ok()?;
+    let rates = response.get("rate_by_service")?.as_object()?;
+    Some(
+        rates
+            .iter()
+            .filter_map(|(key, rate)| Some((key.clone(), rate.as_f64()?)))
+            .collect(),
+    )
+}
+
+impl RateByService {
+    pub fn new(on_update: Option<PyObject>) -> Self {
+        RateByService {
+            rates: Mutex::new(HashMap::new()),
+            on_update: on_update.map(Arc::new),
+        }
+    }
+
+    pub fn get(&self) -> HashMap<String, f64> {
+        self.rates.lock().unwrap().clone()
+    }
+
+    /// Updates the rates from the body of a trace response, and calls `on_update` if they changed.
+    pub async fn update(&self, body: &[u8]) {
+        let Some(rates) = parse(body) else {
+            return;
+        };
+        {
+            let mut current = self.rates.lock().unwrap();
+            if *current == rates {
+                return;
+            }
+            current.clone_from(&rates);
+        }
+        if let Some(on_update) = self.on_update.clone() {
+            // The GIL must not be waited for on a thread of the runtime.
+            let _ = tokio::task::spawn_blocking(move || {
+                Python::with_gil(|py| {
+                    if let Err(err) = on_update.call1(py, (rates,)) {
+                        err.write_unraisable_bound(py, Some(on_update.bind(py)));
+                    }
+                })
+            })
+            .await;
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use super::duration;
+
+/// How a writer retries the payloads that failed with a server or connection error: up to
+/// `max_attempts` attempts per payload, waiting `initial_backoff` seconds after the first one and
+/// twice as long after each next one, up to `max_backoff`. Payloads still failing are requeued
+/// until the agent is back, keeping up to `max_requeued_bytes` of them and dropping the oldest
+/// ones past that.
+#[pyclass(frozen, name = "RetryPolicy", module = "ddtrace.internal.core._core")]
+#[derive(Clone)]
+pub struct RetryPolicyPy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    max_requeued_bytes: usize,
+}
+
+impl Default for RetryPolicyPy {
+    fn default() -> Self {
+        RetryPolicyPy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+            max_requeued_bytes: 20 << 20,
+        }
+    }
+}
+
+impl RetryPolicyPy {
+    /// Returns the time to wait after the failed attempt `attempt`, starting from 0.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.max_backoff)
+    }
+
+    /// Returns whether another attempt can follow the failed attempt `attempt`.
+    pub fn can_retry(&self, attempt: u32) -> bool {
+        attempt + 1 < self.max_attempts
+    }
+}
+
+#[pymethods]
+impl RetryPolicyPy {
+    #[new]
+    #[pyo3(signature = (
+        max_attempts = 3,
+        initial_backoff = 0.1,
+        max_backoff = 2.0,
+        max_requeued_bytes = 20 << 20
+    ))]
+    fn new(
+        max_attempts: u32,
+        initial_backoff: f64,
+        max_backoff: f64,
+        max_requeued_bytes: usize,
+    ) -> PyResult<Self> {
+        if max_attempts == 0 {
+            return Err(PyValueError::new_err("max_attempts must be greater than 0"));
+        }
+        Ok(RetryPolicyPy {
+            max_attempts,
+            initial_backoff: duration(initial_backoff)?,
+            max_backoff: duration(max_backoff)?,
+            max_requeued_bytes,
+        })
+    }
+
+    /// Number of attempts to send a payload before requeuing it, counting the first one.
+    #[getter]
+    fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    #[getter]
+    fn initial_backoff(&self) -> f64 {
+        self.initial_backoff.as_secs_f64()
+    }
+
+    #[getter]
+    fn max_backoff(&self) -> f64 {
+        self.max_backoff.as_secs_f64()
+    }
+
+    /// Total size of the payloads kept for later when the agent can't be reached.
+    #[getter]
+    pub fn max_requeued_bytes(&self) -> usize {
+        self.max_requeued_bytes
+    }
+}
@@ -0,0 +1,461 @@
+// Stats computed by the tracer on the spans it finishes, sent to the agent's `/v0.6/stats`
+// endpoint. Spans are aggregated by the same key as `ddtrace.internal.processor.stats`, in buckets
+// aligned to the export interval.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use datadog_ddsketch::DDSketch;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+
+use super::duration;
+use super::retry::RetryPolicyPy;
+use super::transport::{Client, Endpoint, Proxy};
+use crate::encoding::msgpack::{
+    write_array_len, write_bin, write_bool, write_map_len, write_str, write_uint,
+};
+use crate::runtime::shared_runtime;
+
+/// Same default as `_DD_TRACE_STATS_WRITER_INTERVAL`.
+const DEFAULT_INTERVAL: f64 = 10.0;
+/// Metric set on the spans to compute stats on even if they aren't top level.
+const SPAN_MEASURED_KEY: &str = "_dd.measured";
+
+/// Spans aggregated together.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Key {
+    name: String,
+    service: String,
+    resource: String,
+    span_type: String,
+    http_status_code: u32,
+    synthetics: bool,
+}
+
+/// Aggregated stats of the spans of a key.
+#[derive(Default)]
+struct Group {
+    hits: u64,
+    top_level_hits: u64,
+    errors: u64,
+    duration: u64,
+    ok_summary: DDSketch,
+    error_summary: DDSketch,
+}
+
+/// Buckets of aggregated stats, by start time.
+struct Concentrator {
+    bucket_size_ns: u64,
+    buckets: BTreeMap<u64, HashMap<Key, Group>>,
+}
+
+impl Concentrator {
+    fn add(&mut self, key: Key, end_ns: u64, duration_ns: u64, error: bool, top_level: bool) {
+        let start = end_ns - end_ns % self.bucket_size_ns;
+        let group = self
+            .buckets
+            .entry(start)
+            .or_default()
+            .entry(key)
+            .or_default();
+        group.hits += 1;
+        group.duration += duration_ns;
+        if top_level {
+            group.top_level_hits += 1;
+        }
+        // Only non-finite values are rejected by the sketches, which durations never are.
+        if error {
+            group.errors += 1;
+            let _ = group.error_summary.add(duration_ns as f64);
+        } else {
+            let _ = group.ok_summary.add(duration_ns as f64);
+        }
+    }
+
+    /// Takes the buckets that ended by `now_ns`, or all of them with `force`. The current bucket
+    /// is otherwise left for the spans still to be added to it.
+    fn take(&mut self, now_ns: u64, force: bool) -> BTreeMap<u64, HashMap<Key, Group>> {
+        if force {
+            return std::mem::take(&mut self.buckets);
+        }
+        let current = now_ns - now_ns % self.bucket_size_ns;
+        let current = self.buckets.split_off(&current);
+        std::mem::replace(&mut self.buckets, current)
+    }
+}
+
+fn now_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_nanos() as u64)
+}
+
+/// Sends the buckets of the concentrator to the agent.
+struct Exporter {
+    client: Client,
+    path: String,
+    headers: Vec<(String, String)>,
+    hostname: String,
+    env: Option<String>,
+    version: Option<String>,
+    retry: RetryPolicyPy,
+    concentrator: Mutex<Concentrator>,
+    /// Cleared when the agent doesn't support stats computed by the tracer.
+    enabled: AtomicBool,
+    last_error: Mutex<Option<String>>,
+}
+
+impl Exporter {
+    /// Encodes buckets as a `/v0.6/stats` payload, like the Python processor.
+    fn encode(&self, buckets: BTreeMap<u64, HashMap<Key, Group>>, bucket_size_ns: u64) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_map_len(
+            &mut buf,
+            2 + self.env.is_some() as usize + self.version.is_some() as usize,
+        );
+        write_str(&mut buf, "Stats");
+        write_array_len(&mut buf, buckets.len());
+        for (start, groups) in buckets {
+            write_map_len(&mut buf, 3);
+            write_str(&mut buf, "Start");
+            write_uint(&mut buf, start);
+            write_str(&mut buf, "Duration");
+            write_uint(&mut buf, bucket_size_ns);
+            write_str(&mut buf, "Stats");
+            write_array_len(&mut buf, groups.len());
+            for (key, group) in groups {
+                let optional =
+                    !key.service.is_empty() as usize + !key.span_type.is_empty() as usize;
+                write_map_len(&mut buf, 10 + optional);
+                write_str(&mut buf, "Name");
+                write_str(&mut buf, &key.name);
+                write_str(&mut buf, "Resource");
+                write_str(&mut buf, &key.resource);
+                write_str(&mut buf, "Synthetics");
+                write_bool(&mut buf, key.synthetics);
+                write_str(&mut buf, "HTTPStatusCode");
+                write_uint(&mut buf, key.http_status_code as u64);
+                write_str(&mut buf, "Hits");
+                write_uint(&mut buf, group.hits);
+                write_str(&mut buf, "TopLevelHits");
+                write_uint(&mut buf, group.top_level_hits);
+                write_str(&mut buf, "Duration");
+                write_uint(&mut buf, group.duration);
+                write_str(&mut buf, "Errors");
+                write_uint(&mut buf, group.errors);
+                write_str(&mut buf, "OkSummary");
+                write_bin(&mut buf, &group.ok_summary.encode_to_vec());
+                write_str(&mut buf, "ErrorSummary");
+                write_bin(&mut buf, &group.error_summary.encode_to_vec());
+                if !key.service.is_empty() {
+                    write_str(&mut buf, "Service");
+                    write_str(&mut buf, &key.service);
+                }
+                if !key.span_type.is_empty() {
+                    write_str(&mut buf, "Type");
+                    write_str(&mut buf, &key.span_type);
+                }
+            }
+        }
+        write_str(&mut buf, "Hostname");
+        write_str(&mut buf, &self.hostname);
+        if let Some(env) = &self.env {
+            write_str(&mut buf, "Env");
+            write_str(&mut buf, env);
+        }
+        if let Some(version) = &self.version {
+            write_str(&mut buf, "Version");
+            write_str(&mut buf, version);
+        }
+        buf
+    }
+
+    fn set_error(&self, error: String) {
+        *self.last_error.lock().unwrap() = Some(error);
+    }
+
+    /// Sends the buckets that ended, or all of them with `force`, and returns whether they were
+    /// accepted. Failed payloads are retried with backoff, and dropped after the last attempt.
+    async fn export(&self, force: bool) -> bool {
+        let (buckets, bucket_size_ns) = {
+            let mut concentrator = self.concentrator.lock().unwrap();
+            (
+                concentrator.take(now_ns(), force),
+                concentrator.bucket_size_ns,
+            )
+        };
+        if buckets.is_empty() || !self.enabled.load(Ordering::Relaxed) {
+            return true;
+        }
+        let payload = self.encode(buckets, bucket_size_ns);
+        let mut attempt = 0;
+        loop {
+            let retriable = match self
+                .client
+                .send("PUT", &self.path, &self.headers, &payload)
+                .await
+            {
+                Ok(response) if response.status == 404 => {
+                    self.set_error("the agent doesn't support stats computed by the tracer".into());
+                    self.enabled.store(false, Ordering::Relaxed);
+                    return false;
+                }
+                Ok(response) if response.status < 400 => return true,
+                Ok(response) => {
+                    self.set_error(format!("the agent responded with {}", response.status));
+                    response.status >= 500
+                }
+                Err(err) => {
+                    self.set_error(err.to_string());
+                    true
+                }
+            };
+            if !retriable || !self.retry.can_retry(attempt) {
+                return false;
+            }
+            tokio::time::sleep(self.retry.backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+}
+
+async fn export_periodically(exporter: Arc<Exporter>, interval: Duration) {
+    let mut ticks = tokio::time::interval_at(Instant::now() + interval, interval);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        exporter.export(false).await;
+    }
+}
+
+fn interval_from_env() -> PyResult<f64> {
+    match std::env::var("_DD_TRACE_STATS_WRITER_INTERVAL") {
+        Ok(interval) if !interval.is_empty() => interval.trim().parse().map_err(|_| {
+            PyValueError::new_err(format!(
+                "invalid _DD_TRACE_STATS_WRITER_INTERVAL: {interval}"
+            ))
+        }),
+        _ => Ok(DEFAULT_INTERVAL),
+    }
+}
+
+/// Returns whether a span is the root of its trace, or a child of a span of another service.
+fn is_top_level(span: &Bound<'_, PyAny>, service: &Option<String>) -> PyResult<bool> {
+    if span.getattr("_local_root")?.is(span) {
+        return Ok(true);
+    }
+    let parent = span.getattr("_parent")?;
+    if parent.is_none() || service.is_none() {
+        return Ok(false);
+    }
+    Ok(parent.getattr("service")?.extract::<Option<String>>()? != *service)
+}
+
+/// Computes stats on the spans of the tracer, and sends them to the agent's `/v0.6/stats` endpoint
+/// every `interval` seconds (`_DD_TRACE_STATS_WRITER_INTERVAL` by default), in place of
+/// `SpanStatsProcessorV06`.
+///
+/// Spans are counted when they finish if they are top level or measured, in buckets of
+/// `interval` seconds by end time. Only the buckets that ended are sent on the timer, the current
+/// one being sent by `flush()` and `shutdown()`. Stats are no longer computed once the agent
+/// responds that it doesn't support them.
+#[pyclass(
+    frozen,
+    name = "NativeSpanStatsProcessor",
+    module = "ddtrace.internal.core._core"
+)]
+pub struct NativeSpanStatsProcessorPy {
+    agent_url: String,
+    interval: Duration,
+    exporter: Arc<Exporter>,
+    /// Task exporting the buckets, until the processor is shut down.
+    export_loop: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl NativeSpanStatsProcessorPy {
+    fn export(&self, py: Python<'_>, timeout: Option<Duration>) -> bool {
+        let exporter = self.exporter.clone();
+        py.allow_threads(|| {
+            shared_runtime().block_on(async move {
+                match timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, exporter.export(true))
+                        .await
+                        .unwrap_or(false),
+                    None => exporter.export(true).await,
+                }
+            })
+        })
+    }
+}
+
+#[pymethods]
+impl NativeSpanStatsProcessorPy {
+    #[new]
+    #[pyo3(signature = (
+        agent_url,
+        interval = None,
+        timeout = 1.0,
+        hostname = "",
+        env = None,
+        version = None,
+        retry = None,
+        proxy = None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        py: Python<'_>,
+        agent_url: String,
+        interval: Option<f64>,
+        timeout: f64,
+        hostname: &str,
+        env: Option<String>,
+        version: Option<String>,
+        retry: Option<RetryPolicyPy>,
+        proxy: Option<&str>,
+    ) -> PyResult<Self> {
+        let interval = match interval {
+            Some(interval) => interval,
+            None => interval_from_env()?,
+        };
+        if interval <= 0.0 {
+            return Err(PyValueError::new_err("interval must be greater than 0"));
+        }
+        let interval = duration(interval)?;
+        let (endpoint, base_path) = Endpoint::parse(&agent_url).map_err(PyValueError::new_err)?;
+        let proxy = Proxy::resolve(&endpoint, proxy).map_err(PyValueError::new_err)?;
+        let tracer_version = py.import_bound("ddtrace")?.getattr("__version__")?;
+        let headers = vec![
+            ("Datadog-Meta-Lang".to_owned(), "python".to_owned()),
+            (
+                "Datadog-Meta-Tracer-Version".to_owned(),
+                tracer_version.extract()?,
+            ),
+            ("Content-Type".to_owned(), "application/msgpack".to_owned()),
+        ];
+        let exporter = Arc::new(Exporter {
+            client: Client::new(endpoint, duration(timeout)?).with_proxy(proxy),
+            path: format!("{base_path}/v0.6/stats"),
+            headers,
+            hostname: hostname.to_owned(),
+            // Like the Python processor, empty values aren't sent.
+            env: env.filter(|env| !env.is_empty()),
+            version: version.filter(|version| !version.is_empty()),
+            retry: retry.unwrap_or_default(),
+            concentrator: Mutex::new(Concentrator {
+                bucket_size_ns: interval.as_nanos().max(1) as u64,
+                buckets: BTreeMap::new(),
+            }),
+            enabled: AtomicBool::new(true),
+            last_error: Mutex::new(None),
+        });
+        let export_loop = shared_runtime().spawn(export_periodically(exporter.clone(), interval));
+        Ok(NativeSpanStatsProcessorPy {
+            agent_url,
+            interval,
+            exporter,
+            export_loop: Mutex::new(Some(export_loop)),
+        })
+    }
+
+    #[getter]
+    fn agent_url(&self) -> &str {
+        &self.agent_url
+    }
+
+    #[getter]
+    fn interval(&self) -> f64 {
+        self.interval.as_secs_f64()
+    }
+
+    /// Whether stats are computed, which stops once the agent responds that it doesn't support
+    /// them.
+    #[getter]
+    fn enabled(&self) -> bool {
+        self.exporter.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Error of the last payload that failed, if any.
+    #[getter]
+    fn last_error(&self) -> Option<String> {
+        self.exporter.last_error.lock().unwrap().clone()
+    }
+
+    fn on_span_start(&self, _span: &Bound<'_, PyAny>) {}
+
+    /// Adds a finished span to the stats, if it is top level or measured.
+    fn on_span_finish(&self, span: &Bound<'_, PyAny>) -> PyResult<()> {
+        if !self.enabled() {
+            return Ok(());
+        }
+        let service: Option<String> = span.getattr("service")?.extract()?;
+        let top_level = is_top_level(span, &service)?;
+        if !top_level {
+            let metrics = span.getattr("_metrics")?.downcast_into::<PyDict>()?;
+            let measured = match metrics.get_item(SPAN_MEASURED_KEY)? {
+                Some(measured) => measured.eq(1)?,
+                None => false,
+            };
+            if !measured {
+                return Ok(());
+            }
+        }
+        let Some(duration_ns) = span.getattr("duration_ns")?.extract::<Option<u64>>()? else {
+            return Ok(());
+        };
+        let start_ns: u64 = span.getattr("start_ns")?.extract()?;
+        let meta = span.getattr("_meta")?.downcast_into::<PyDict>()?;
+        let http_status_code = match meta.get_item("http.status_code")? {
+            Some(status) => status.extract::<String>()?.trim().parse().unwrap_or(0),
+            None => 0,
+        };
+        let origin: Option<String> = span.getattr("context")?.getattr("dd_origin")?.extract()?;
+        let key = Key {
+            name: span.getattr("name")?.extract()?,
+            service: service.unwrap_or_default(),
+            resource: span
+                .getattr("resource")?
+                .extract::<Option<String>>()?
+                .unwrap_or_default(),
+            span_type: span
+                .getattr("span_type")?
+                .extract::<Option<String>>()?
+                .unwrap_or_default(),
+            http_status_code,
+            synthetics: origin.as_deref() == Some("synthetics"),
+        };
+        let error = span.getattr("error")?.extract::<i64>()? != 0;
+        self.exporter.concentrator.lock().unwrap().add(
+            key,
+            start_ns + duration_ns,
+            duration_ns,
+            error,
+            top_level,
+        );
+        Ok(())
+    }
+
+    /// Sends the stats computed so far, including those of the current bucket, waiting up to
+    /// `timeout` seconds (forever if `None`). Returns whether the agent accepted them.
+    #[pyo3(signature = (timeout = None))]
+    fn flush(&self, py: Python<'_>, timeout: Option<f64>) -> PyResult<bool> {
+        let timeout = timeout.map(duration).transpose()?;
+        Ok(self.export(py, timeout))
+    }
+
+    /// Stops the timer and sends the stats left, like `flush()`. Spans finished afterwards are
+    /// still counted, to be sent by another `flush()`.
+    #[pyo3(signature = (timeout = None))]
+    fn shutdown(&self, py: Python<'_>, timeout: Option<f64>) -> PyResult<bool> {
+        let timeout = timeout.map(duration).transpose()?;
+        if let Some(export_loop) = self.export_loop.lock().unwrap().take() {
+            export_loop.abort();
+        }
+        Ok(self.export(py, timeout))
+    }
+}
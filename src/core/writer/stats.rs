@@ -0,0 +1,421 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use tokio::time::{Instant, MissedTickBehavior};
+
+use super::dogstatsd::DogStatsd;
+use super::queue::Payload;
+use super::transport::Response;
+
+/// Why payloads or traces weren't sent, named like the `reason` tags of the health metrics.
+#[derive(Clone, Copy)]
+pub enum DropReason {
+    /// The encoder or the queue was full.
+    BufferFull,
+    /// The trace was larger than the encoder accepts.
+    TooLarge,
+    /// The agent rejected the payload, or it failed until it was over the requeue budget.
+    SendFailure,
+    /// The writer was stopped, or shut down before the payload could be sent, or the process was
+    /// forked after the writer was created.
+    Shutdown,
+}
+
+impl DropReason {
+    const ALL: [DropReason; 4] = [
+        DropReason::BufferFull,
+        DropReason::TooLarge,
+        DropReason::SendFailure,
+        DropReason::Shutdown,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DropReason::BufferFull => "buffer_full",
+            DropReason::TooLarge => "too_large",
+            DropReason::SendFailure => "send_failure",
+            DropReason::Shutdown => "shutdown",
+        }
+    }
+}
+
+/// Amount of data dropped for a reason, or waiting to be sent.
+#[derive(Clone, Copy, Default)]
+pub struct Counts {
+    payloads: u64,
+    traces: u64,
+    spans: u64,
+    bytes: u64,
+}
+
+impl Counts {
+    fn add(&mut self, payload: &Payload) {
+        self.payloads += 1;
+        self.traces += payload.traces as u64;
+        self.spans += payload.spans as u64;
+        self.bytes += payload.data.len() as u64;
+    }
+
+    fn remove(&mut self, payload: &Payload) {
+        self.payloads = self.payloads.saturating_sub(1);
+        self.traces = self.traces.saturating_sub(payload.traces as u64);
+        self.spans = self.spans.saturating_sub(payload.spans as u64);
+        self.bytes = self.bytes.saturating_sub(payload.data.len() as u64);
+    }
+
+    fn to_dict<'py>(self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new_bound(py);
+        dict.set_item("payloads", self.payloads)?;
+        dict.set_item("traces", self.traces)?;
+        dict.set_item("spans", self.spans)?;
+        dict.set_item("bytes", self.bytes)?;
+        Ok(dict)
+    }
+}
+
+/// Counters kept by a writer over its lifetime, or since they were last reset.
+#[derive(Default)]
+pub struct Stats {
+    payloads: u64,
+    traces: u64,
+    bytes: u64,
+    http_errors: u64,
+    connection_errors: u64,
+    retries: u64,
+    requeued_payloads: u64,
+    split_payloads: u64,
+    dropped: [Counts; DropReason::ALL.len()],
+    /// Payloads queued and not yet sent or dropped, which aren't reset with the counters.
+    pending: Counts,
+    last_error: Option<String>,
+    /// Where the health metrics are sent as the counters change, if enabled.
+    metrics: Option<Arc<DogStatsd>>,
+}
+
+impl Stats {
+    pub fn new(metrics: Option<DogStatsd>) -> Self {
+        Stats {
+            metrics: metrics.map(Arc::new),
+            ..Stats::default()
+        }
+    }
+
+    /// Sends the depth of the queue, in payloads and bytes.
+    fn report_queue(&self) {
+        if let Some(metrics) = &self.metrics {
+            metrics.gauge("queue.payloads", self.pending.payloads);
+            metrics.gauge("queue.bytes", self.pending.bytes);
+        }
+    }
+
+    fn report_dropped(&self, reason: DropReason, dropped: Counts) {
+        if let Some(metrics) = &self.metrics {
+            let tag = format!("reason:{}", reason.name());
+            metrics.distribution("dropped.traces", dropped.traces, &[&tag]);
+            metrics.distribution("dropped.spans", dropped.spans, &[&tag]);
+            metrics.distribution("dropped.bytes", dropped.bytes, &[&tag]);
+        }
+    }
+
+    /// Accounts for a payload handed to the worker.
+    pub fn record_queued(&mut self, payload: &Payload) {
+        self.pending.add(payload);
+        self.report_queue();
+    }
+
+    pub fn record_sent(&mut self, payload: &Payload) {
+        self.pending.remove(payload);
+        self.payloads += 1;
+        self.traces += payload.traces as u64;
+        self.bytes += payload.data.len() as u64;
+        if let Some(metrics) = &self.metrics {
+            metrics.distribution("http.requests", 1, &[]);
+            metrics.distribution("flushes", 1, &[]);
+            metrics.distribution("flush.traces", payload.traces as u64, &[]);
+            metrics.distribution("flush.bytes", payload.data.len() as u64, &[]);
+        }
+        self.report_queue();
+    }
+
+    /// Accounts for an attempt the agent answered with an error status.
+    pub fn record_http_error(&mut self, response: &Response) {
+        if let Some(metrics) = &self.metrics {
+            let tag = format!("type:{}", response.status);
+            metrics.distribution("http.requests", 1, &[]);
+            metrics.distribution("http.errors", 1, &[&tag]);
+        }
+        self.http_errors += 1;
+        let mut error = format!("HTTP error status {}", response.status);
+        let body = String::from_utf8_lossy(&response.body);
+        if !body.trim().is_empty() {
+            error.push_str(": ");
+            error.push_str(body.trim());
+        }
+        self.last_error = Some(error);
+    }
+
+    /// Accounts for an attempt that failed because of a connection error or timeout.
+    pub fn record_connection_error(&mut self, error: &std::io::Error) {
+        if let Some(metrics) = &self.metrics {
+            metrics.distribution("http.requests", 1, &[]);
+            metrics.distribution("http.errors", 1, &["type:err"]);
+        }
+        self.connection_errors += 1;
+        self.last_error = Some(error.to_string());
+    }
+
+    pub fn record_retry(&mut self) {
+        self.retries += 1;
+    }
+
+    /// Accounts for a payload kept to be sent again once the agent is back.
+    pub fn record_requeued(&mut self) {
+        self.requeued_payloads += 1;
+    }
+
+    /// Accounts for a payload replaced by `chunks` because it was too large.
+    pub fn record_split(&mut self, payload: &Payload, chunks: &[Payload]) {
+        self.split_payloads += 1;
+        self.pending.remove(payload);
+        for chunk in chunks {
+            self.pending.add(chunk);
+        }
+    }
+
+    /// Accounts for a payload that won't be sent.
+    pub fn record_dropped(&mut self, reason: DropReason, payload: &Payload) {
+        self.pending.remove(payload);
+        self.dropped[reason as usize].add(payload);
+        let mut dropped = Counts::default();
+        dropped.add(payload);
+        self.report_dropped(reason, dropped);
+        self.report_queue();
+    }
+
+    /// Accounts for a trace the encoder didn't accept.
+    pub fn record_dropped_trace(&mut self, reason: DropReason, spans: usize, size: usize) {
+        let dropped = &mut self.dropped[reason as usize];
+        dropped.traces += 1;
+        dropped.spans += spans as u64;
+        dropped.bytes += size as u64;
+        let dropped = Counts {
+            payloads: 0,
+            traces: 1,
+            spans: spans as u64,
+            bytes: size as u64,
+        };
+        self.report_dropped(reason, dropped);
+    }
+
+    /// Accounts for the payloads left when the worker was cancelled.
+    pub fn record_pending_dropped(&mut self) {
+        let dropped = &mut self.dropped[DropReason::Shutdown as usize];
+        dropped.payloads += self.pending.payloads;
+        dropped.traces += self.pending.traces;
+        dropped.spans += self.pending.spans;
+        dropped.bytes += self.pending.bytes;
+        self.report_dropped(DropReason::Shutdown, self.pending);
+        self.pending = Counts::default();
+        self.report_queue();
+    }
+
+    /// Returns a snapshot of the counters, and resets them if `reset` is set.
+    pub fn snapshot(&mut self, reset: bool) -> WriterStatsPy {
+        let stats = WriterStatsPy {
+            payloads: self.payloads,
+            traces: self.traces,
+            bytes: self.bytes,
+            http_errors: self.http_errors,
+            connection_errors: self.connection_errors,
+            retries: self.retries,
+            requeued_payloads: self.requeued_payloads,
+            split_payloads: self.split_payloads,
+            dropped: self.dropped,
+            queued_payloads: self.pending.payloads,
+            last_error: self.last_error.clone(),
+        };
+        if reset {
+            *self = Stats {
+                pending: self.pending,
+                metrics: self.metrics.take(),
+                ..Stats::default()
+            };
+        }
+        stats
+    }
+}
+
+/// Passes the counters of a writer to `callback`, and resets them.
+pub fn report(py: Python<'_>, stats: &Mutex<Stats>, callback: &PyObject) {
+    let snapshot = stats.lock().unwrap().snapshot(true);
+    if let Err(err) = callback.call1(py, (snapshot,)) {
+        err.write_unraisable_bound(py, Some(callback.bind(py)));
+    }
+}
+
+/// Reports the counters of a writer to `callback` every `interval`, until the task is aborted.
+pub async fn report_periodically(
+    stats: Arc<Mutex<Stats>>,
+    callback: Arc<PyObject>,
+    interval: Duration,
+) {
+    let mut ticks = tokio::time::interval_at(Instant::now() + interval, interval);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        let stats = stats.clone();
+        let callback = callback.clone();
+        // The GIL must not be waited for on a thread of the runtime.
+        let _ = tokio::task::spawn_blocking(move || {
+            Python::with_gil(|py| report(py, &stats, &callback))
+        })
+        .await;
+    }
+}
+
+/// Snapshot of the counters of a writer, returned by its `stats()` method.
+#[pyclass(frozen, name = "WriterStats", module = "ddtrace.internal.core._core")]
+pub struct WriterStatsPy {
+    payloads: u64,
+    traces: u64,
+    bytes: u64,
+    http_errors: u64,
+    connection_errors: u64,
+    retries: u64,
+    requeued_payloads: u64,
+    split_payloads: u64,
+    dropped: [Counts; DropReason::ALL.len()],
+    queued_payloads: u64,
+    last_error: Option<String>,
+}
+
+impl WriterStatsPy {
+    fn total_dropped(&self) -> Counts {
+        let mut total = Counts::default();
+        for dropped in &self.dropped {
+            total.payloads += dropped.payloads;
+            total.traces += dropped.traces;
+            total.spans += dropped.spans;
+            total.bytes += dropped.bytes;
+        }
+        total
+    }
+}
+
+#[pymethods]
+impl WriterStatsPy {
+    /// Number of payloads accepted by the agent.
+    #[getter]
+    fn payloads(&self) -> u64 {
+        self.payloads
+    }
+
+    #[getter]
+    fn traces(&self) -> u64 {
+        self.traces
+    }
+
+    /// Size in bytes of the payloads accepted by the agent.
+    #[getter]
+    fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Number of attempts the agent answered with a 4xx or 5xx status.
+    #[getter]
+    fn http_errors(&self) -> u64 {
+        self.http_errors
+    }
+
+    /// Number of attempts that failed because the agent couldn't be reached or didn't answer in
+    /// time.
+    #[getter]
+    fn connection_errors(&self) -> u64 {
+        self.connection_errors
+    }
+
+    /// Number of attempts made after a payload failed with a server or connection error.
+    #[getter]
+    fn retries(&self) -> u64 {
+        self.retries
+    }
+
+    /// Number of payloads kept to be sent again after their last attempt failed.
+    #[getter]
+    fn requeued_payloads(&self) -> u64 {
+        self.requeued_payloads
+    }
+
+    /// Number of payloads larger than the agent accepts that were split, each of their chunks
+    /// being accounted for as a payload of its own.
+    #[getter]
+    fn split_payloads(&self) -> u64 {
+        self.split_payloads
+    }
+
+    /// Number of payloads that weren't accepted by the agent: rejected with a 4xx status, dropped
+    /// because the queue was full, because the oldest requeued payloads were over budget, or
+    /// because the writer was shut down.
+    #[getter]
+    fn dropped_payloads(&self) -> u64 {
+        self.total_dropped().payloads
+    }
+
+    /// Number of traces that weren't sent, whether in a dropped payload or rejected by the
+    /// encoder.
+    #[getter]
+    fn dropped_traces(&self) -> u64 {
+        self.total_dropped().traces
+    }
+
+    #[getter]
+    fn dropped_spans(&self) -> u64 {
+        self.total_dropped().spans
+    }
+
+    #[getter]
+    fn dropped_bytes(&self) -> u64 {
+        self.total_dropped().bytes
+    }
+
+    /// Payloads, traces, spans and bytes dropped by reason: `buffer_full`, `too_large`,
+    /// `send_failure` and `shutdown`.
+    #[getter]
+    fn dropped<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new_bound(py);
+        for reason in DropReason::ALL {
+            dict.set_item(reason.name(), self.dropped[reason as usize].to_dict(py)?)?;
+        }
+        Ok(dict)
+    }
+
+    /// Number of payloads waiting to be sent, or sent again, which isn't reset.
+    #[getter]
+    fn queued_payloads(&self) -> u64 {
+        self.queued_payloads
+    }
+
+    /// Description of the last error, if any.
+    #[getter]
+    fn last_error(&self) -> Option<String> {
+        self.last_error.clone()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "WriterStats(payloads={}, traces={}, bytes={}, http_errors={}, connection_errors={}, \
+             retries={}, requeued_payloads={}, dropped_payloads={}, dropped_traces={})",
+            self.payloads,
+            self.traces,
+            self.bytes,
+            self.http_errors,
+            self.connection_errors,
+            self.retries,
+            self.requeued_payloads,
+            self.dropped_payloads(),
+            self.dropped_traces()
+        )
+    }
+}
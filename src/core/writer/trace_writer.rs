@@ -0,0 +1,808 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use super::agent_info::Fetcher;
+use super::buffer::{self, Buffer};
+use super::chunk;
+use super::dogstatsd::{self, DogStatsd};
+use super::duration;
+use super::pipeline::Pipeline;
+use super::queue::{Message, Payload, Queue, Requeue};
+use super::rates::RateByService;
+use super::retry::RetryPolicyPy;
+use super::stats::{self, DropReason, Stats, WriterStatsPy};
+use super::transport::{self, Client, Endpoint, Proxy};
+use crate::runtime::shared_runtime;
+
+const API_VERSIONS: [&str; 2] = ["v0.4", "v0.5"];
+/// Same default as `DD_TRACE_WRITER_INTERVAL_SECONDS`.
+const DEFAULT_INTERVAL: f64 = 1.0;
+/// Same default as `DD_TRACE_WRITER_MAX_PAYLOAD_SIZE_BYTES`.
+const DEFAULT_MAX_PAYLOAD_SIZE: usize = 20 << 20;
+/// How long the capabilities of the agent are trusted before they are queried again.
+const AGENT_INFO_TTL: Duration = Duration::from_secs(300);
+
+/// Outcome of an attempt to send a payload.
+enum Attempt {
+    /// The payload was accepted, or rejected for good by the agent.
+    Done,
+    /// The payload failed with a server or connection error, and may be sent again.
+    Failed,
+}
+
+/// Traces and spans the tracer didn't send because they weren't sampled, once it computed their
+/// stats.
+#[derive(Clone, Copy, Default)]
+struct DroppedP0 {
+    traces: u64,
+    spans: u64,
+}
+
+/// State of the stats computed by the tracer, shared by the writer and its worker.
+#[derive(Default)]
+struct ClientStats {
+    /// Whether the agent accepted the stats computed by the tracer, as of the last payload.
+    enabled: AtomicBool,
+    /// Dropped since the last payload sent.
+    dropped_p0: Mutex<DroppedP0>,
+}
+
+impl ClientStats {
+    fn record_dropped_p0(&self, dropped: DroppedP0) {
+        let mut dropped_p0 = self.dropped_p0.lock().unwrap();
+        dropped_p0.traces += dropped.traces;
+        dropped_p0.spans += dropped.spans;
+    }
+}
+
+/// Where the worker sends payloads.
+enum Exporter {
+    /// Straight to the trace endpoint of the agent.
+    Agent(Client),
+    /// Through libdatadog's data pipeline, which sends them to the agent itself.
+    DataPipeline(Pipeline),
+}
+
+/// Task sending the queued payloads to the agent, one at a time.
+struct Worker {
+    exporter: Exporter,
+    path: String,
+    api_version: String,
+    max_payload_size: usize,
+    headers: Vec<(String, String)>,
+    retry: RetryPolicyPy,
+    requeue: Requeue,
+    stats: Arc<Mutex<Stats>>,
+    rate_by_service: Arc<RateByService>,
+    /// Queries the capabilities of the agent when the tracer computes stats.
+    agent_info: Option<Fetcher>,
+    client_stats: Arc<ClientStats>,
+}
+
+impl Worker {
+    /// Sends the queued payloads until the queue is closed, and returns whether they were all
+    /// sent.
+    async fn run(mut self, mut messages: mpsc::Receiver<Message>) -> bool {
+        while let Some(message) = messages.recv().await {
+            match message {
+                Message::Payload(payload) => {
+                    for payload in self.split(payload) {
+                        match self.send_with_retries(payload).await {
+                            // The agent is reachable: catch up with the payloads that failed
+                            // before.
+                            Ok(()) => {
+                                self.send_requeued().await;
+                            }
+                            Err(payload) => self.requeue(payload),
+                        }
+                    }
+                }
+                Message::Flush(done) => {
+                    let flushed = self.send_requeued().await;
+                    let _ = done.send(flushed);
+                }
+            }
+        }
+        // The writer is stopped: the requeued payloads get a last chance.
+        let sent = self.send_requeued().await;
+        if let Exporter::DataPipeline(pipeline) = &self.exporter {
+            pipeline.shutdown().await;
+        }
+        let mut stats = self.stats.lock().unwrap();
+        while let Some(payload) = self.requeue.pop() {
+            stats.record_dropped(DropReason::Shutdown, &payload);
+        }
+        sent
+    }
+
+    /// Splits a payload larger than the agent accepts at trace boundaries, into payloads sent and
+    /// retried separately. Compressed payloads, and ones that can't be read, are sent whole.
+    fn split(&self, payload: Payload) -> Vec<Payload> {
+        if payload.data.len() <= self.max_payload_size || payload.content_encoding.is_some() {
+            return vec![payload];
+        }
+        match chunk::split(&payload, &self.api_version, self.max_payload_size) {
+            Some(chunks) if chunks.len() > 1 => {
+                self.stats.lock().unwrap().record_split(&payload, &chunks);
+                chunks
+            }
+            _ => vec![payload],
+        }
+    }
+
+    async fn send(&self, payload: &Payload) -> Attempt {
+        let mut headers = self.headers.clone();
+        headers.push((
+            "X-Datadog-Trace-Count".to_owned(),
+            payload.traces.to_string(),
+        ));
+        if let Some(encoding) = &payload.content_encoding {
+            headers.push(("Content-Encoding".to_owned(), encoding.clone()));
+        }
+        let dropped_p0 = self.client_stats_headers(&mut headers).await;
+        let result = match &self.exporter {
+            Exporter::Agent(client) => {
+                client
+                    .send("PUT", &self.path, &headers, &payload.data)
+                    .await
+            }
+            Exporter::DataPipeline(pipeline) => pipeline.send(payload).await,
+        };
+        if let Ok(response) = &result {
+            if response.status < 400 {
+                self.rate_by_service.update(&response.body).await;
+            }
+        }
+        let mut stats = self.stats.lock().unwrap();
+        let attempt = match result {
+            Ok(response) if response.status < 400 => {
+                stats.record_sent(payload);
+                Attempt::Done
+            }
+            Ok(response) => {
+                stats.record_http_error(&response);
+                if response.status >= 500 {
+                    Attempt::Failed
+                } else {
+                    stats.record_dropped(DropReason::SendFailure, payload);
+                    Attempt::Done
+                }
+            }
+            Err(err) => {
+                stats.record_connection_error(&err);
+                Attempt::Failed
+            }
+        };
+        if let (Attempt::Failed, Some(dropped_p0)) = (&attempt, dropped_p0) {
+            // Reported with the next attempt instead.
+            self.client_stats.record_dropped_p0(dropped_p0);
+        }
+        attempt
+    }
+
+    /// Tells the agent that the tracer computed the stats of the payload, if it supports them,
+    /// along with the unsampled traces dropped since the last payload. Returns what was reported.
+    async fn client_stats_headers(&self, headers: &mut Vec<(String, String)>) -> Option<DroppedP0> {
+        let agent_info = self.agent_info.as_ref()?;
+        let enabled = agent_info
+            .fetch(false)
+            .await
+            .is_some_and(|info| info.supports_stats());
+        self.client_stats.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            return None;
+        }
+        let dropped_p0 = std::mem::take(&mut *self.client_stats.dropped_p0.lock().unwrap());
+        headers.push(("Datadog-Client-Computed-Stats".to_owned(), "yes".to_owned()));
+        headers.push((
+            "Datadog-Client-Dropped-P0-Traces".to_owned(),
+            dropped_p0.traces.to_string(),
+        ));
+        headers.push((
+            "Datadog-Client-Dropped-P0-Spans".to_owned(),
+            dropped_p0.spans.to_string(),
+        ));
+        Some(dropped_p0)
+    }
+
+    /// Sends a payload, retrying it with exponential backoff while it fails with server or
+    /// connection errors. Returns the payload if it still failed after the last attempt.
+    async fn send_with_retries(&self, payload: Payload) -> Result<(), Payload> {
+        let mut attempt = 0;
+        loop {
+            match self.send(&payload).await {
+                Attempt::Done => return Ok(()),
+                Attempt::Failed if self.retry.can_retry(attempt) => {
+                    tokio::time::sleep(self.retry.backoff(attempt)).await;
+                    self.stats.lock().unwrap().record_retry();
+                    attempt += 1;
+                }
+                Attempt::Failed => return Err(payload),
+            }
+        }
+    }
+
+    fn requeue(&mut self, payload: Payload) {
+        let requeued = self.requeue.fits(&payload);
+        let dropped = self.requeue.push(payload);
+        let mut stats = self.stats.lock().unwrap();
+        if requeued {
+            stats.record_requeued();
+        }
+        for payload in dropped {
+            stats.record_dropped(DropReason::SendFailure, &payload);
+        }
+    }
+
+    /// Sends the requeued payloads, oldest first, until one fails. Returns whether they were all
+    /// sent.
+    async fn send_requeued(&mut self) -> bool {
+        while let Some(payload) = self.requeue.pop() {
+            if let Attempt::Failed = self.send(&payload).await {
+                self.requeue.push_front(payload);
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Returns the headers the Python writer sends with every payload.
+fn standard_headers(py: Python<'_>) -> PyResult<Vec<(String, String)>> {
+    let platform = py.import_bound("platform")?;
+    let tracer_version = py.import_bound("ddtrace")?.getattr("__version__")?;
+    Ok(vec![
+        ("Datadog-Meta-Lang".to_owned(), "python".to_owned()),
+        (
+            "Datadog-Meta-Lang-Version".to_owned(),
+            platform.call_method0("python_version")?.extract()?,
+        ),
+        (
+            "Datadog-Meta-Lang-Interpreter".to_owned(),
+            platform.call_method0("python_implementation")?.extract()?,
+        ),
+        (
+            "Datadog-Meta-Tracer-Version".to_owned(),
+            tracer_version.extract()?,
+        ),
+        (
+            "Datadog-Client-Computed-Top-Level".to_owned(),
+            "yes".to_owned(),
+        ),
+    ])
+}
+
+/// Sets a header, replacing any header with the same name.
+fn set_header(headers: &mut Vec<(String, String)>, name: String, value: String) {
+    headers.retain(|(key, _)| !key.eq_ignore_ascii_case(&name));
+    headers.push((name, value));
+}
+
+/// Returns whether health metrics are enabled with `DD_TRACE_HEALTH_METRICS_ENABLED`.
+fn health_metrics_from_env() -> bool {
+    std::env::var("DD_TRACE_HEALTH_METRICS_ENABLED").is_ok_and(|enabled| {
+        matches!(
+            enabled.trim().to_ascii_lowercase().as_str(),
+            "1" | "true" | "yes" | "on"
+        )
+    })
+}
+
+/// Returns the flush interval set with `DD_TRACE_WRITER_INTERVAL_SECONDS`, if any.
+fn interval_from_env() -> PyResult<f64> {
+    match std::env::var("DD_TRACE_WRITER_INTERVAL_SECONDS") {
+        Ok(interval) => interval.trim().parse().map_err(|_| {
+            PyValueError::new_err(format!(
+                "invalid DD_TRACE_WRITER_INTERVAL_SECONDS: {interval}"
+            ))
+        }),
+        Err(_) => Ok(DEFAULT_INTERVAL),
+    }
+}
+
+/// Returns the largest payload size set with `DD_TRACE_WRITER_MAX_PAYLOAD_SIZE_BYTES`, if any.
+fn max_payload_size_from_env() -> PyResult<usize> {
+    match std::env::var("DD_TRACE_WRITER_MAX_PAYLOAD_SIZE_BYTES") {
+        Ok(size) => size.trim().parse().map_err(|_| {
+            PyValueError::new_err(format!(
+                "invalid DD_TRACE_WRITER_MAX_PAYLOAD_SIZE_BYTES: {size}"
+            ))
+        }),
+        Err(_) => Ok(DEFAULT_MAX_PAYLOAD_SIZE),
+    }
+}
+
+/// Sends encoded trace payloads to the agent's `/v0.4/traces` or `/v0.5/traces` endpoint.
+///
+/// Payloads are queued and sent from a task of the shared runtime, so `send()` returns without
+/// waiting for the agent and Python threads never block on the network. Uncompressed payloads
+/// larger than `max_payload_size` (`DD_TRACE_WRITER_MAX_PAYLOAD_SIZE_BYTES` by default) are split
+/// into several requests, between traces. With an `encoder`, traces can be given to `write()`
+/// instead, and are flushed every `interval` seconds by another task
+/// (`DD_TRACE_WRITER_INTERVAL_SECONDS` by default), or by `flush_queue()`. The agent is reached over
+/// TCP, optionally over TLS, a Unix domain socket or a Windows named pipe, depending on the scheme
+/// of `agent_url`, and connections are kept alive between payloads unless `max_idle_connections`
+/// is 0. Requests over TCP go through the HTTP proxy at `proxy` if given, or else the one set with
+/// `HTTP_PROXY` (`HTTPS_PROXY` for `https://` URLs) and `NO_PROXY`, except to loopback addresses.
+/// An empty `proxy` disables proxying.
+///
+/// With `compute_stats`, the tracer computes the stats of the traces, which the agent is told with
+/// every payload once its `/info` endpoint says it supports them. The tracer can then drop the
+/// unsampled traces, and report them with `record_dropped_p0()`. With `data_pipeline`, payloads
+/// are handed to libdatadog's trace exporter instead, which sends them to the agent itself, and
+/// computes their stats if `compute_stats` is set. It only takes uncompressed v0.4 payloads.
+///
+/// The sample rates by service returned by the agent are kept for the priority sampler, and passed
+/// to `on_rate_by_service` whenever they change, from a thread of the shared runtime. Likewise, the
+/// counters of `stats()` are passed to `on_stats` every `stats_interval` seconds, and reset, for
+/// the health metrics of the tracer. With `health_metrics` (`DD_TRACE_HEALTH_METRICS_ENABLED` by
+/// default), these are also sent to DogStatsD at `dogstatsd_url` as they change, along with the
+/// depth of the queue, or dropped while it can't be reached.
+///
+/// The background tasks don't survive a fork: in a forked child, payloads are dropped, and
+/// `flush()`, `stop()` and `shutdown()` return `False` right away, so the writer must be recreated
+/// there.
+#[pyclass(
+    frozen,
+    name = "NativeTraceWriter",
+    module = "ddtrace.internal.core._core"
+)]
+pub struct NativeTraceWriterPy {
+    agent_url: String,
+    api_version: String,
+    data_pipeline: bool,
+    retry: RetryPolicyPy,
+    interval: Duration,
+    queue: Arc<Queue>,
+    buffer: Option<Arc<Buffer>>,
+    worker: Mutex<Option<JoinHandle<bool>>>,
+    /// Task flushing the buffer, if any, until the writer is stopped.
+    flush_loop: Mutex<Option<JoinHandle<()>>>,
+    /// Task reporting the counters to `on_stats`, if any, until the writer is stopped.
+    report_loop: Mutex<Option<JoinHandle<()>>>,
+    on_stats: Option<Arc<PyObject>>,
+    stats: Arc<Mutex<Stats>>,
+    rate_by_service: Arc<RateByService>,
+    client_stats: Arc<ClientStats>,
+}
+
+impl NativeTraceWriterPy {
+    /// Forgets the background tasks in a child forked after the writer was created, and returns
+    /// whether it is one: they ran on the runtime of the parent, so they can neither be waited for
+    /// nor cancelled.
+    fn forget_tasks_if_forked(&self) -> bool {
+        if !self.queue.is_forked() {
+            return false;
+        }
+        std::mem::forget(self.worker.lock().unwrap().take());
+        std::mem::forget(self.flush_loop.lock().unwrap().take());
+        std::mem::forget(self.report_loop.lock().unwrap().take());
+        self.queue.close();
+        true
+    }
+
+    /// Cancels the flush loop, if any, and queues the buffered traces a last time.
+    fn stop_flush_loop(&self, py: Python<'_>) -> PyResult<()> {
+        if let Some(flush_loop) = self.flush_loop.lock().unwrap().take() {
+            flush_loop.abort();
+            if let Some(buffer) = &self.buffer {
+                buffer.flush(py, &self.queue)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Cancels the report loop, if any, and reports the counters a last time.
+    fn stop_report_loop(&self, py: Python<'_>) {
+        if let Some(report_loop) = self.report_loop.lock().unwrap().take() {
+            report_loop.abort();
+            if let Some(on_stats) = &self.on_stats {
+                stats::report(py, &self.stats, on_stats);
+            }
+        }
+    }
+
+    /// Waits up to `timeout` for the worker to finish, and returns whether it sent every payload,
+    /// or `None` if it is still running.
+    fn wait(
+        py: Python<'_>,
+        worker: &mut JoinHandle<bool>,
+        timeout: Option<Duration>,
+    ) -> Option<bool> {
+        py.allow_threads(|| {
+            shared_runtime().block_on(async {
+                match timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, worker).await.ok(),
+                    None => Some(worker.await),
+                }
+            })
+        })
+        .map(|sent| sent.unwrap_or(false))
+    }
+}
+
+#[pymethods]
+impl NativeTraceWriterPy {
+    #[new]
+    #[pyo3(signature = (
+        agent_url,
+        api_version = "v0.5",
+        content_type = "application/msgpack",
+        headers = None,
+        timeout = 2.0,
+        max_queued_payloads = 64,
+        retry = None,
+        on_rate_by_service = None,
+        max_idle_connections = 2,
+        idle_timeout = 10.0,
+        encoder = None,
+        interval = None,
+        on_stats = None,
+        stats_interval = 10.0,
+        data_pipeline = false,
+        compute_stats = false,
+        max_payload_size = None,
+        proxy = None,
+        health_metrics = None,
+        dogstatsd_url = None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        py: Python<'_>,
+        agent_url: String,
+        api_version: &str,
+        content_type: &str,
+        headers: Option<&Bound<'_, PyDict>>,
+        timeout: f64,
+        max_queued_payloads: usize,
+        retry: Option<RetryPolicyPy>,
+        on_rate_by_service: Option<PyObject>,
+        max_idle_connections: usize,
+        idle_timeout: f64,
+        encoder: Option<PyObject>,
+        interval: Option<f64>,
+        on_stats: Option<PyObject>,
+        stats_interval: f64,
+        data_pipeline: bool,
+        compute_stats: bool,
+        max_payload_size: Option<usize>,
+        proxy: Option<&str>,
+        health_metrics: Option<bool>,
+        dogstatsd_url: Option<String>,
+    ) -> PyResult<Self> {
+        if !API_VERSIONS.contains(&api_version) {
+            return Err(PyValueError::new_err(format!(
+                "unsupported API version: {api_version}"
+            )));
+        }
+        let max_payload_size = match max_payload_size {
+            Some(max_payload_size) => max_payload_size,
+            None => max_payload_size_from_env()?,
+        };
+        if max_payload_size == 0 {
+            return Err(PyValueError::new_err(
+                "max_payload_size must be greater than 0",
+            ));
+        }
+        if max_queued_payloads == 0 {
+            return Err(PyValueError::new_err(
+                "max_queued_payloads must be greater than 0",
+            ));
+        }
+        let interval = match interval {
+            Some(interval) => interval,
+            None => interval_from_env()?,
+        };
+        if interval <= 0.0 {
+            return Err(PyValueError::new_err("interval must be greater than 0"));
+        }
+        let interval = duration(interval)?;
+        if stats_interval <= 0.0 {
+            return Err(PyValueError::new_err(
+                "stats_interval must be greater than 0",
+            ));
+        }
+        let stats_interval = duration(stats_interval)?;
+        if data_pipeline && api_version != "v0.4" {
+            return Err(PyValueError::new_err(
+                "the data pipeline only takes v0.4 payloads",
+            ));
+        }
+        if let (true, Some(encoder)) = (data_pipeline, &encoder) {
+            let content_encoding: Option<String> =
+                encoder.getattr(py, "content_encoding")?.extract(py)?;
+            if content_encoding.is_some() {
+                return Err(PyValueError::new_err(
+                    "the data pipeline only takes uncompressed payloads",
+                ));
+            }
+        }
+        let (endpoint, base_path) = Endpoint::parse(&agent_url).map_err(PyValueError::new_err)?;
+        let proxy = Proxy::resolve(&endpoint, proxy).map_err(PyValueError::new_err)?;
+
+        let mut all_headers = standard_headers(py)?;
+        if let Some(headers) = headers {
+            for (name, value) in headers {
+                let (name, value): (String, String) = (name.extract()?, value.extract()?);
+                transport::check_header(&name, &value).map_err(PyValueError::new_err)?;
+                set_header(&mut all_headers, name, value);
+            }
+        }
+        set_header(
+            &mut all_headers,
+            "Content-Type".to_owned(),
+            content_type.to_owned(),
+        );
+
+        let retry = retry.unwrap_or_default();
+        let metrics = if health_metrics.unwrap_or_else(health_metrics_from_env) {
+            let url = dogstatsd_url.unwrap_or_else(dogstatsd::url_from_env);
+            Some(DogStatsd::new(&url))
+        } else {
+            None
+        };
+        let stats = Arc::new(Mutex::new(Stats::new(metrics)));
+        let rate_by_service = Arc::new(RateByService::new(on_rate_by_service));
+        // The exporter negotiates the stats it computes with the agent itself.
+        let agent_info = if compute_stats && !data_pipeline {
+            let client =
+                Client::new(endpoint.clone(), duration(timeout)?).with_proxy(proxy.clone());
+            Some(Fetcher::new(client, &base_path, AGENT_INFO_TTL))
+        } else {
+            None
+        };
+        let client_stats = Arc::new(ClientStats::default());
+        let exporter = if data_pipeline {
+            let pipeline =
+                Pipeline::new(&agent_url, &all_headers, duration(timeout)?, compute_stats)
+                    .map_err(PyValueError::new_err)?;
+            Exporter::DataPipeline(pipeline)
+        } else {
+            Exporter::Agent(
+                Client::new(endpoint, duration(timeout)?)
+                    .with_pool(max_idle_connections, duration(idle_timeout)?)
+                    .with_proxy(proxy),
+            )
+        };
+        let worker = Worker {
+            exporter,
+            path: format!("{base_path}/{api_version}/traces"),
+            api_version: api_version.to_owned(),
+            max_payload_size,
+            headers: all_headers,
+            requeue: Requeue::new(retry.max_requeued_bytes()),
+            retry: retry.clone(),
+            stats: stats.clone(),
+            rate_by_service: rate_by_service.clone(),
+            agent_info,
+            client_stats: client_stats.clone(),
+        };
+        let (sender, receiver) = mpsc::channel(max_queued_payloads);
+        let worker = shared_runtime().spawn(worker.run(receiver));
+        let queue = Arc::new(Queue::new(sender, stats.clone()));
+        let buffer = encoder.map(|encoder| Arc::new(Buffer::new(encoder, stats.clone())));
+        let flush_loop = buffer.as_ref().map(|buffer| {
+            shared_runtime().spawn(buffer::flush_periodically(
+                buffer.clone(),
+                queue.clone(),
+                interval,
+            ))
+        });
+        let on_stats = on_stats.map(Arc::new);
+        let report_loop = on_stats.as_ref().map(|on_stats| {
+            shared_runtime().spawn(stats::report_periodically(
+                stats.clone(),
+                on_stats.clone(),
+                stats_interval,
+            ))
+        });
+        Ok(NativeTraceWriterPy {
+            agent_url,
+            api_version: api_version.to_owned(),
+            data_pipeline,
+            retry,
+            interval,
+            queue,
+            buffer,
+            worker: Mutex::new(Some(worker)),
+            flush_loop: Mutex::new(flush_loop),
+            report_loop: Mutex::new(report_loop),
+            on_stats,
+            stats,
+            rate_by_service,
+            client_stats,
+        })
+    }
+
+    #[getter]
+    fn agent_url(&self) -> &str {
+        &self.agent_url
+    }
+
+    #[getter]
+    fn api_version(&self) -> &str {
+        &self.api_version
+    }
+
+    /// Whether payloads are sent through libdatadog's data pipeline.
+    #[getter]
+    fn data_pipeline(&self) -> bool {
+        self.data_pipeline
+    }
+
+    #[getter]
+    fn retry(&self) -> RetryPolicyPy {
+        self.retry.clone()
+    }
+
+    #[getter]
+    fn interval(&self) -> f64 {
+        self.interval.as_secs_f64()
+    }
+
+    #[getter]
+    fn encoder(&self, py: Python<'_>) -> Option<PyObject> {
+        self.buffer
+            .as_ref()
+            .map(|buffer| buffer.encoder().clone_ref(py))
+    }
+
+    /// Whether the agent accepted the stats computed by the tracer, as of the last payload, so
+    /// that unsampled traces can be dropped.
+    #[getter]
+    fn client_computed_stats(&self) -> bool {
+        self.client_stats.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Sample rates by service from the last response of the agent that had them.
+    #[getter]
+    fn rate_by_service(&self) -> HashMap<String, f64> {
+        self.rate_by_service.get()
+    }
+
+    /// Queues a payload of `traces` traces, as returned by an encoder, and returns whether it was
+    /// accepted. Payloads are dropped when the queue is full or the writer is stopped.
+    #[pyo3(signature = (payload, traces, content_encoding = None, spans = 0))]
+    fn send(
+        &self,
+        payload: &[u8],
+        traces: usize,
+        content_encoding: Option<String>,
+        spans: usize,
+    ) -> PyResult<bool> {
+        if self.data_pipeline && content_encoding.is_some() {
+            return Err(PyValueError::new_err(
+                "the data pipeline only takes uncompressed payloads",
+            ));
+        }
+        Ok(self.queue.push(Payload {
+            data: payload.to_vec(),
+            traces,
+            spans,
+            content_encoding,
+        }))
+    }
+
+    /// Accounts for unsampled traces dropped by the tracer once it computed their stats, to be
+    /// reported to the agent with the next payload.
+    fn record_dropped_p0(&self, traces: u64, spans: u64) {
+        self.client_stats
+            .record_dropped_p0(DroppedP0 { traces, spans });
+    }
+
+    /// Buffers a trace in the encoder, and returns whether it was buffered. The buffered traces
+    /// are flushed first if the trace doesn't fit with them.
+    fn write(&self, py: Python<'_>, trace: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let Some(buffer) = &self.buffer else {
+            return Err(PyRuntimeError::new_err("the writer has no encoder"));
+        };
+        buffer.write(py, trace, &self.queue)
+    }
+
+    /// Queues the payload of the buffered traces, and waits up to `timeout` seconds (forever if
+    /// `None`) for it to be sent along with the other queued payloads, like `flush()`.
+    #[pyo3(signature = (timeout = None))]
+    fn flush_queue(&self, py: Python<'_>, timeout: Option<f64>) -> PyResult<bool> {
+        if let Some(buffer) = &self.buffer {
+            buffer.flush(py, &self.queue)?;
+        }
+        self.flush(py, timeout)
+    }
+
+    /// Waits up to `timeout` seconds (forever if `None`) for the queued payloads to be sent, and
+    /// returns whether they were. Requeued payloads are sent again first.
+    #[pyo3(signature = (timeout = None))]
+    fn flush(&self, py: Python<'_>, timeout: Option<f64>) -> PyResult<bool> {
+        let timeout = timeout.map(duration).transpose()?;
+        let Some(messages) = self.queue.sender() else {
+            return Ok(false);
+        };
+        Ok(py.allow_threads(|| {
+            shared_runtime().block_on(async move {
+                let flushed = async {
+                    let (done, flushed) = oneshot::channel();
+                    messages.send(Message::Flush(done)).await.is_ok()
+                        && flushed.await.unwrap_or(false)
+                };
+                match timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, flushed)
+                        .await
+                        .unwrap_or(false),
+                    None => flushed.await,
+                }
+            })
+        }))
+    }
+
+    /// Stops accepting payloads and waits up to `timeout` seconds (forever if `None`) for the
+    /// queued ones to be sent, along with the buffered traces. Returns whether they were.
+    #[pyo3(signature = (timeout = None))]
+    fn stop(&self, py: Python<'_>, timeout: Option<f64>) -> PyResult<bool> {
+        let timeout = timeout.map(duration).transpose()?;
+        if self.forget_tasks_if_forked() {
+            return Ok(false);
+        }
+        self.stop_flush_loop(py)?;
+        self.queue.close();
+        let Some(mut worker) = self.worker.lock().unwrap().take() else {
+            return Ok(true);
+        };
+        match Self::wait(py, &mut worker, timeout) {
+            Some(sent) => {
+                self.stop_report_loop(py);
+                Ok(sent)
+            }
+            None => {
+                // Keep waiting for the queue to drain on the next call.
+                *self.worker.lock().unwrap() = Some(worker);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Shuts the writer down for the exit of the interpreter: the buffered traces are queued, and
+    /// the queued payloads are sent within `timeout` seconds (without a deadline if `None`), after
+    /// which the background tasks are cancelled and the payloads left are dropped. Returns whether
+    /// everything was sent.
+    ///
+    /// Unlike `stop()`, this never leaves a task running, so it can be registered with `atexit`.
+    #[pyo3(signature = (timeout = None))]
+    fn shutdown(&self, py: Python<'_>, timeout: Option<f64>) -> PyResult<bool> {
+        let timeout = timeout.map(duration).transpose()?;
+        if self.forget_tasks_if_forked() {
+            return Ok(false);
+        }
+        self.stop_flush_loop(py)?;
+        self.queue.close();
+        let Some(mut worker) = self.worker.lock().unwrap().take() else {
+            self.stop_report_loop(py);
+            return Ok(true);
+        };
+        let sent = match Self::wait(py, &mut worker, timeout) {
+            Some(sent) => sent,
+            None => {
+                worker.abort();
+                // Wait for the cancellation, so that nothing is accounted for after this.
+                let _ = py.allow_threads(|| shared_runtime().block_on(worker));
+                self.stats.lock().unwrap().record_pending_dropped();
+                false
+            }
+        };
+        self.stop_report_loop(py);
+        Ok(sent)
+    }
+
+    /// Returns the counters of the writer, and resets them if `reset` is set.
+    #[pyo3(signature = (reset = false))]
+    fn stats(&self, reset: bool) -> WriterStatsPy {
+        self.stats.lock().unwrap().snapshot(reset)
+    }
+}
@@ -10,8 +10,10 @@ from ddtrace.internal.ci_visibility.constants import SESSION_ID
 from ddtrace.internal.ci_visibility.constants import SUITE_ID
 from ddtrace.internal.ci_visibility.encoder import CIVisibilityCoverageEncoderV02
 from ddtrace.internal.ci_visibility.encoder import CIVisibilityEncoderV01
+from ddtrace.internal.core._core import CiTestCycleEncoder
 from ddtrace.internal.encoding import JSONEncoder
 from tests.contrib.pytest.test_pytest import PytestTestCaseBase
+from tests.tracer.test_encoders import span_to_dict
 
 
 def test_encode_traces_civisibility_v0():
@@ -76,6 +78,48 @@ def test_encode_traces_civisibility_v0():
         assert expected_event == received_event
 
 
+def test_native_encoder_matches_civisibility_v0():
+    session = Span(name="test.session", service="foo", span_type="test_session_end")
+    session.set_tag_str("type", "test_session_end")
+    session.set_tag_str(SESSION_ID, "11")
+    suite = Span(name="test.suite", service="foo", span_type="test_suite_end")
+    suite.set_tag_str("type", "test_suite_end")
+    suite.set_tag_str(SESSION_ID, "11")
+    suite.set_tag_str("test_module_id", "22")
+    suite.set_tag_str(SUITE_ID, "33")
+    test = Span(name="test", service="foo", span_type="test", span_id=0xAAAAAA, parent_id=5)
+    test.set_tag_str("type", "test")
+    test.set_tag_str(SESSION_ID, "11")
+    test.set_tag_str(SUITE_ID, "33")
+    test.set_tag_str(COVERAGE_TAG_NAME, "{}")
+    test.set_tag_str(ITR_CORRELATION_ID_TAG_NAME, "abc")
+    test.set_metric("b", 2)
+    test.set_metric("a", 0.5)
+    child = Span(name="client.testing", service="foo", parent_id=0xAAAAAA)
+    child.context.dd_origin = "ciapp-test"
+    for span in (session, suite, test, child):
+        span.finish()
+    traces = [[session], [suite], [test], [child]]
+
+    encoder = CIVisibilityEncoderV01(0, 0)
+    native = CiTestCycleEncoder()
+    for event_type, metadata in (("*", {"language": "python"}), ("test", {"test_session_name": "s"})):
+        encoder.set_metadata(event_type, metadata)
+        native.set_metadata(event_type, metadata)
+    for trace in traces:
+        encoder.put(trace)
+        assert native.put([span_to_dict(span) for span in trace], trace[0].context.dd_origin) is None
+    assert len(native) == 4
+
+    size = native.size
+    native_payload, count = native.encode()
+    assert count == 4
+    assert len(native_payload) == size
+    payload, _ = encoder.encode()
+    assert msgpack.unpackb(native_payload, strict_map_key=False) == msgpack.unpackb(payload, strict_map_key=False)
+    assert native.encode() == (None, 0)
+
+
 def test_encode_traces_civisibility_v0_no_traces():
     encoder = CIVisibilityEncoderV01(0, 0)
     encoder.set_metadata("*", {"language": "python"})
@@ -0,0 +1,87 @@
+import threading
+import time
+
+import pytest
+
+from ddtrace.internal.core._core import ConcurrencyLimiter
+
+
+def test_concurrency_limiter_acquire_release():
+    limiter = ConcurrencyLimiter(2)
+    assert limiter.max_concurrent == 2
+    assert limiter.in_use == 0
+
+    assert limiter.acquire()
+    assert limiter.acquire()
+    assert limiter.in_use == 2
+
+    # No slot left
+    assert not limiter.acquire(timeout=0)
+
+    limiter.release()
+    assert limiter.in_use == 1
+    assert limiter.acquire(timeout=0)
+    assert limiter.in_use == 2
+
+
+def test_concurrency_limiter_timeout():
+    limiter = ConcurrencyLimiter(1)
+    assert limiter.acquire()
+
+    start = time.monotonic()
+    assert not limiter.acquire(timeout=0.1)
+    assert time.monotonic() - start >= 0.1
+    assert limiter.in_use == 1
+
+
+def test_concurrency_limiter_wakes_up_waiter():
+    limiter = ConcurrencyLimiter(1)
+    assert limiter.acquire()
+    acquired = []
+
+    thread = threading.Thread(target=lambda: acquired.append(limiter.acquire(timeout=10)))
+    thread.start()
+    # The waiting thread doesn't hold the GIL
+    time.sleep(0.1)
+    assert acquired == []
+
+    limiter.release()
+    thread.join()
+    assert acquired == [True]
+    assert limiter.in_use == 1
+
+
+def test_concurrency_limiter_context_manager():
+    limiter = ConcurrencyLimiter(1)
+
+    with limiter as acquired:
+        assert acquired is limiter
+        assert limiter.in_use == 1
+        assert not limiter.acquire(timeout=0)
+
+    assert limiter.in_use == 0
+
+    # The slot is released when the block raises
+    with pytest.raises(RuntimeError):
+        with limiter:
+            raise RuntimeError("upload failed")
+
+    assert limiter.in_use == 0
+
+
+def test_concurrency_limiter_over_release():
+    limiter = ConcurrencyLimiter(1)
+
+    with pytest.raises(RuntimeError):
+        limiter.release()
+
+    assert limiter.acquire()
+    limiter.release()
+    with pytest.raises(RuntimeError):
+        limiter.release()
+    assert limiter.in_use == 0
+
+
+def test_concurrency_limiter_invalid_max_concurrent():
+    with pytest.raises(ValueError):
+        ConcurrencyLimiter(0)
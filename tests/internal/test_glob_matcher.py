@@ -1,8 +1,10 @@
 import pytest
 
+from ddtrace.internal.core._core import GlobMatcher as NativeGlobMatcher
 from ddtrace.internal.glob_matching import GlobMatcher
 
 
+@pytest.mark.parametrize("matcher_class", [GlobMatcher, NativeGlobMatcher])
 @pytest.mark.parametrize(
     "pattern,string,result",
     [
@@ -31,6 +33,12 @@ from ddtrace.internal.glob_matching import GlobMatcher
         ("*a*a*a*a*a*a", "aaaaaaaarrrrrrraaaraaarararaarararaarararaaa", True),
     ],
 )
-def test_matching(pattern, string, result):
-    glob_matcher = GlobMatcher(pattern)
+def test_matching(matcher_class, pattern, string, result):
+    glob_matcher = matcher_class(pattern)
     assert result == glob_matcher.match(string)
+
+
+def test_native_glob_matcher_pattern():
+    matcher = NativeGlobMatcher("Foo.*")
+    assert matcher.pattern == GlobMatcher("Foo.*").pattern == "foo.*"
+    assert repr(matcher) == 'GlobMatcher("foo.*")'
@@ -1,3 +1,7 @@
+import http.server
+import json
+import threading
+
 import mock
 import pytest
 
@@ -383,3 +387,121 @@ def test_info(mock_connection, request_response, read_response, status_response,
 
     mock_connection.return_value = MockConn()
     assert info() == expected
+
+
+_AGENT_INFO = {
+    "version": "7.58.0",
+    "endpoints": ["/v0.4/traces", "/v0.5/traces", "/v0.6/stats", "/info"],
+    "feature_flags": ["discovery"],
+    "client_drop_p0s": True,
+    "span_events": True,
+    "peer_tags": ["db.hostname", "peer.service"],
+    "obfuscation_version": 1,
+    "config": {"statsd_port": 8125, "analyzed_spans_by_service": {}},
+}
+
+
+@pytest.fixture
+def info_server():
+    class Handler(http.server.BaseHTTPRequestHandler):
+        status = 200
+
+        def do_GET(self):
+            self.server.requests.append(self.path)
+            body = json.dumps(_AGENT_INFO).encode()
+            self.send_response(self.status)
+            self.send_header("Content-Length", str(len(body)))
+            self.end_headers()
+            self.wfile.write(body)
+
+        def log_message(self, format, *args):  # noqa: A002
+            pass
+
+    server = http.server.HTTPServer(("127.0.0.1", 0), Handler)
+    server.requests = []
+    server.handler = Handler
+    t = threading.Thread(target=server.serve_forever)
+    t.daemon = True
+    t.start()
+    try:
+        yield server
+    finally:
+        server.shutdown()
+        t.join()
+
+
+def test_native_agent_info(info_server):
+    from ddtrace.internal.core._core import AgentInfoFetcher
+
+    fetcher = AgentInfoFetcher("http://127.0.0.1:%d/" % info_server.server_port)
+    agent_info = fetcher.fetch()
+    assert agent_info.version == "7.58.0"
+    assert agent_info.endpoints == _AGENT_INFO["endpoints"]
+    assert agent_info.feature_flags == ["discovery"]
+    assert agent_info.client_drop_p0s
+    assert agent_info.span_events
+    assert agent_info.peer_tags == ["db.hostname", "peer.service"]
+    assert agent_info.obfuscation_version == 1
+    assert agent_info.supports_v05
+    assert agent_info.supports_stats
+    assert agent_info.supports_endpoint("/v0.4/traces/")
+    assert not agent_info.supports_endpoint("/v0.7/traces")
+    assert agent_info.to_dict() == _AGENT_INFO
+
+    # Results are cached until they expire.
+    assert fetcher.fetch() is not None
+    assert info_server.requests == ["/info"]
+    assert fetcher.fetch(force=True) is not None
+    assert info_server.requests == ["/info", "/info"]
+
+
+def test_native_agent_info_unavailable(info_server):
+    from ddtrace.internal.core._core import AgentInfoFetcher
+
+    info_server.handler.status = 404
+    fetcher = AgentInfoFetcher("http://127.0.0.1:%d" % info_server.server_port, ttl=0)
+    assert fetcher.fetch() is None
+    assert fetcher.last_error is None
+
+    info_server.handler.status = 500
+    assert fetcher.fetch() is None
+    assert fetcher.last_error == "HTTP error status 500"
+    assert len(info_server.requests) == 2
+
+
+@pytest.mark.subprocess()
+def test_native_runtime_fork():
+    import os
+    import socket
+
+    from ddtrace.internal.core._core import AgentInfoFetcher
+    from ddtrace.internal.core._core import is_runtime_started
+    from ddtrace.internal.core._core import set_runtime_worker_threads
+
+    # A port nothing listens on, for the requests to fail right away
+    sock = socket.socket()
+    sock.bind(("127.0.0.1", 0))
+    port = sock.getsockname()[1]
+    sock.close()
+
+    fetcher = AgentInfoFetcher("http://127.0.0.1:%d" % port, ttl=0)
+    assert fetcher.fetch() is None
+    assert is_runtime_started()
+
+    pid = os.fork()
+
+    if pid == 0:
+        # The child builds a runtime of its own instead of using the parent's, whose worker
+        # threads weren't forked
+        assert not is_runtime_started()
+        set_runtime_worker_threads(2)
+        assert fetcher.fetch(force=True) is None
+        assert fetcher.last_error is not None
+        assert is_runtime_started()
+        os._exit(12)
+
+    _, status = os.waitpid(pid, 0)
+    assert os.WEXITSTATUS(status) == 12
+
+    assert is_runtime_started()
+    assert fetcher.fetch(force=True) is None
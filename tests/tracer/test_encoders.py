@@ -1,5 +1,6 @@
 # -*- coding: utf-8 -*-
 import contextlib
+import gzip
 import json
 import random
 import string
@@ -28,6 +29,14 @@ from ddtrace.internal._encoding import BufferFull
 from ddtrace.internal._encoding import BufferItemTooLarge
 from ddtrace.internal._encoding import ListStringTable
 from ddtrace.internal._encoding import MsgpackStringTable
+from ddtrace.internal.core._core import Compression
+from ddtrace.internal.core._core import InvalidUtf8
+from ddtrace.internal.core._core import JsonEncoder
+from ddtrace.internal.core._core import OtlpEncoder
+from ddtrace.internal.core._core import OverflowReason
+from ddtrace.internal.core._core import TagLimits
+from ddtrace.internal.core._core import TraceEncoderV04
+from ddtrace.internal.core._core import TraceEncoderV05
 from ddtrace.internal.encoding import MSGPACK_ENCODERS
 from ddtrace.internal.encoding import JSONEncoder
 from ddtrace.internal.encoding import JSONEncoderV2
@@ -630,6 +639,146 @@ def test_encoder_propagates_dd_origin(Encoder, item):
     assert all((_[item][_ORIGIN_KEY] == b"ciapp-test" for _ in decoded_trace[0]))
 
 
+@pytest.mark.parametrize(
+    "Encoder,NativeEncoder",
+    [
+        (MsgpackEncoderV04, TraceEncoderV04),
+        (MsgpackEncoderV05, TraceEncoderV05),
+    ],
+)
+def test_native_encoder_span_links(Encoder, NativeEncoder):
+    span = Span(
+        "s1",
+        links=[
+            SpanLink(trace_id=1, span_id=2),
+            SpanLink(trace_id=3, span_id=4, flags=0),
+            SpanLink(
+                trace_id=(123 << 64) + 456,
+                span_id=(2**64) - 1,
+                tracestate="congo=t61rcWkgMzE",
+                flags=1,
+                attributes={"moon": "ears", "drop_me": "bye", "key_other": [True, 2, ["hello", 4]], "ünï": "cødé"},
+            ),
+        ],
+    )
+    span._add_span_pointer(
+        pointer_kind="some-kind",
+        pointer_direction=_SpanPointerDirection.DOWNSTREAM,
+        pointer_hash="some-hash",
+    )
+    span._links[2]._drop_attribute("drop_me")
+    span.finish()
+
+    encoder = Encoder(1 << 20, 1 << 20)
+    encoder.put([span])
+    native = NativeEncoder()
+    native.put([span_to_dict(span)])
+    assert native.encode() == encoder.encode()
+
+
+def span_with_events():
+    span = Span("s1")
+    span._add_event("Something went so wrong", {"type": "error"}, 1)
+    span._add_event(
+        "I can sing!!! acbdefggnmdfsdv k 2e2ev;!|=xxx",
+        {"emotion": "happy", "rating": 9.8, "other": [1, 9.5, 1], "idol": False, "big": 1e16},
+        17353464354546,
+    )
+    span._add_event("We are going to the moon", timestamp=2234567890123456)
+    span.finish()
+    return span
+
+
+@pytest.mark.parametrize(
+    "Encoder,NativeEncoder",
+    [
+        (MsgpackEncoderV04, TraceEncoderV04),
+        (MsgpackEncoderV05, TraceEncoderV05),
+    ],
+)
+def test_native_encoder_span_events_tag(Encoder, NativeEncoder):
+    span = span_with_events()
+    encoder = Encoder(1 << 20, 1 << 20)
+    encoder.put([span])
+    native = NativeEncoder()
+    native.put([span_to_dict(span)])
+    assert native.encode() == encoder.encode()
+
+
+def test_native_encoder_v04_top_level_span_events():
+    native = TraceEncoderV04(top_level_span_events=True)
+    assert native.top_level_span_events
+    native.put([span_to_dict(span_with_events())])
+
+    decoded_span = decode(native.encode()[0])[0][0]
+    assert b"meta" not in decoded_span
+    assert decoded_span[b"span_events"] == [
+        {
+            b"name": b"Something went so wrong",
+            b"time_unix_nano": 1,
+            b"attributes": {b"type": {b"type": 0, b"string_value": b"error"}},
+        },
+        {
+            b"name": b"I can sing!!! acbdefggnmdfsdv k 2e2ev;!|=xxx",
+            b"time_unix_nano": 17353464354546,
+            b"attributes": {
+                b"emotion": {b"type": 0, b"string_value": b"happy"},
+                b"rating": {b"type": 3, b"double_value": 9.8},
+                b"other": {
+                    b"type": 4,
+                    b"array_value": {
+                        b"values": [
+                            {b"type": 2, b"int_value": 1},
+                            {b"type": 3, b"double_value": 9.5},
+                            {b"type": 2, b"int_value": 1},
+                        ]
+                    },
+                },
+                b"idol": {b"type": 1, b"bool_value": False},
+                b"big": {b"type": 3, b"double_value": 1e16},
+            },
+        },
+        {b"name": b"We are going to the moon", b"time_unix_nano": 2234567890123456},
+    ]
+
+
+@pytest.mark.parametrize(
+    "Encoder,NativeEncoder",
+    [
+        (MsgpackEncoderV04, TraceEncoderV04),
+        (MsgpackEncoderV05, TraceEncoderV05),
+    ],
+)
+def test_native_encoder_put_partial(Encoder, NativeEncoder):
+    root = Span("root", trace_id=1)
+    children = [Span("child", trace_id=1, parent_id=root.span_id) for _ in range(3)]
+    native = NativeEncoder()
+
+    # Not enough finished spans to flush yet
+    children[0].finish()
+    trace = [span_to_dict(span) for span in [root] + children]
+    assert native.put_partial(trace, 3) == (None, trace)
+    assert len(native) == 0
+
+    for child in children[1:]:
+        child.finish()
+    overflow, kept = native.put_partial([span_to_dict(span) for span in [root] + children], 3)
+    assert overflow is None
+    assert kept == [span_to_dict(root)]
+    assert "_dd.py.partial_flush" not in children[0].get_metrics()
+
+    encoder = Encoder(1 << 20, 1 << 20)
+    children[0].set_metric("_dd.py.partial_flush", 3)
+    encoder.put(children)
+    assert native.encode() == encoder.encode()
+
+    # The rest of the trace is flushed once finished
+    root.finish()
+    assert native.put_partial([span_to_dict(root)], 3) == (None, [])
+    encoder.put([root])
+    assert native.encode() == encoder.encode()
+
+
 @allencodings
 @given(
     trace_id=integers(min_value=1, max_value=2**128 - 1),
@@ -725,6 +874,440 @@ def test_custom_msgpack_encode_v05():
     ]
 
 
+def span_to_dict(span):
+    # type: (Span) -> dict
+    return {
+        "trace_id": span.trace_id,
+        "span_id": span.span_id,
+        "parent_id": span.parent_id,
+        "service": span.service,
+        "name": span.name,
+        "resource": span.resource,
+        "start": span.start_ns,
+        "duration": span.duration_ns,
+        "error": span.error,
+        "type": span.span_type,
+        "meta": span.get_tags(),
+        "metrics": span.get_metrics(),
+        "meta_struct": span._meta_struct,
+        "span_links": [link.to_dict() for link in span._links],
+        "span_events": [vars(event)() for event in span._events],
+    }
+
+
+@pytest.mark.parametrize("span_form", [span_to_tuple, span_to_dict])
+def test_native_encoder_v05_matches_msgpack_encoder(span_form):
+    traces = [gen_trace(nspans=50, ntags=5, nmetrics=3) for _ in range(3)]
+    traces[0][1].set_metric("float", 0.25)
+    traces[0][1].set_metric("negative", -(2**40))
+
+    encoder = MsgpackEncoderV05(2 << 20, 2 << 20)
+    native = TraceEncoderV05()
+    for trace in traces:
+        encoder.put(trace)
+        native.put([span_form(span) for span in trace])
+
+    assert len(native) == 3
+    assert native.size == encoder.size
+    assert native.encode() == encoder.encode()
+    assert native.encode() == (None, 0)
+
+
+@pytest.mark.parametrize("span_form", [span_to_tuple, span_to_dict])
+def test_native_encoder_v04_matches_msgpack_encoder(span_form):
+    traces = [gen_trace(nspans=50, ntags=5, nmetrics=3) for _ in range(3)]
+    traces[0][1].set_metric("float", 0.25)
+    traces[0][1].set_metric("negative", -(2**40))
+    traces[1][2].error = 1
+
+    encoder = MsgpackEncoderV04(2 << 20, 2 << 20)
+    native = TraceEncoderV04()
+    for trace in traces:
+        encoder.put(trace)
+        native.put([span_form(span) for span in trace])
+
+    assert len(native) == 3
+    assert native.size == encoder.size
+    assert native.encode() == encoder.encode()
+    assert native.encode() == (None, 0)
+
+
+def test_native_encoder_v04_meta_struct():
+    payload = {"tttt": {"iuopç": [{"abcd": 1, "bcde": True}, {}]}, "zzzz": b"\x93\x01\x02\x03", "ZZZZ": [1, 2, 3]}
+    span = Span(name="client.testing", trace_id=1)
+    span.set_struct_tag("payload", payload)
+    span.set_tag("payload", "meta_payload")
+    trace = [span, Span(name="client.testing", trace_id=1)]
+
+    encoder = MsgpackEncoderV04(2 << 10, 2 << 10)
+    encoder.put(trace)
+    native = TraceEncoderV04()
+    native.put([span_to_dict(span) for span in trace])
+    assert native.encode() == encoder.encode()
+
+    # Pre-encoded values are passed through as is
+    span_dict = span_to_dict(span)
+    span_dict["meta_struct"] = {"payload": msgpack.packb(payload)}
+    native.put([span_dict])
+    items = decode(native.encode()[0])
+    assert msgpack.unpackb(items[0][0][b"meta_struct"][b"payload"]) == payload
+
+
+@given(
+    trace_id=integers(min_value=1, max_value=2**128 - 1),
+    name=text(),
+    service=text(),
+    resource=text(),
+    meta=dictionaries(text(), text()),
+    metrics=dictionaries(text(), floats()),
+    error=integers(min_value=0, max_value=1),
+    span_type=text(),
+)
+@settings(max_examples=200)
+def test_native_encoder_v04_matches_msgpack_encoder_property(
+    trace_id, name, service, resource, meta, metrics, error, span_type
+):
+    span = Span(trace_id=trace_id, name=name, service=service, resource=resource)
+    span.set_tags(meta)
+    span.set_metrics(metrics)
+    span.error = error
+    span.span_type = span_type
+
+    encoder = MsgpackEncoderV04(1 << 20, 1 << 20)
+    encoder.put([span])
+    native = TraceEncoderV04()
+    native.put([span_to_dict(span)])
+
+    assert native.encode() == encoder.encode()
+
+
+@pytest.mark.parametrize("Encoder", [TraceEncoderV04, TraceEncoderV05])
+def test_native_encoder_overflow(Encoder):
+    dropped = []
+    encoder = Encoder(1 << 10, 1 << 9, on_drop=dropped.append)
+    assert encoder.max_size == 1 << 10
+    assert encoder.max_item_size == 1 << 9
+
+    trace = [span_to_dict(Span(name="test"))]
+    assert encoder.put(trace) is None
+
+    too_big = encoder.put(trace * 50)
+    assert too_big.reason == OverflowReason.TRACE_TOO_BIG
+    assert too_big.spans == 50
+    assert too_big.size > encoder.max_item_size
+    assert len(encoder) == 1
+
+    while True:
+        full = encoder.put(trace)
+        if full is not None:
+            break
+    assert full.reason == OverflowReason.BUFFER_FULL
+    assert full.spans == 1
+    assert encoder.size + full.size > encoder.max_size
+    assert dropped == [too_big, full]
+
+    size = encoder.size
+    assert size == len(encoder.encode()[0])
+
+
+@pytest.mark.parametrize("Encoder", [TraceEncoderV04, TraceEncoderV05, OtlpEncoder])
+def test_native_encoder_stats(Encoder):
+    encoder = Encoder(1 << 10, 1 << 9)
+    trace = [span_to_dict(Span(name="test"))]
+    assert encoder.put(trace * 2) is None
+    assert encoder.put(trace * 50) is not None
+    with pytest.raises(TypeError):
+        encoder.put([{"name": "test", "meta": {"key": 1}}])
+    payload, _ = encoder.encode()
+
+    stats = encoder.stats()
+    assert (stats.traces, stats.spans) == (1, 2)
+    assert (stats.dropped_traces, stats.dropped_spans) == (1, 50)
+    assert stats.errors == 1
+    assert (stats.payloads, stats.bytes) == (1, len(payload))
+    assert stats.encode_time_ns > 0
+    histogram = stats.encode_time_histogram
+    assert sum(count for _, count in histogram) == 2
+    assert histogram[0][0] == 1000 and histogram[-1][0] is None
+
+    assert encoder.stats(reset=True).traces == 1
+    assert encoder.stats().traces == 0
+
+
+INVALID_UTF8_SPAN = {
+    "name": "test",
+    "meta": {"ok": "yes", "bad\udce9": "value", "key": "bad\udce9"},
+    "metrics": {"m\udce9": 1.0, "n": 2},
+}
+
+
+@pytest.mark.parametrize("Encoder", [TraceEncoderV04, TraceEncoderV05])
+@pytest.mark.parametrize("policy", [InvalidUtf8.REPLACE, InvalidUtf8.DROP_TAG])
+def test_native_encoder_invalid_utf8(Encoder, policy):
+    encoder = Encoder(invalid_utf8=policy)
+    assert encoder.invalid_utf8 == policy
+    assert encoder.put([INVALID_UTF8_SPAN]) is None
+
+    [[span]] = decode(encoder.encode()[0])
+    meta, metrics = (span[b"meta"], span[b"metrics"]) if isinstance(span, dict) else span[9:11]
+    if policy == InvalidUtf8.REPLACE:
+        assert meta == {b"ok": b"yes", "bad\ufffd".encode(): b"value", b"key": "bad\ufffd".encode()}
+        assert metrics == {"m\ufffd".encode(): 1.0, b"n": 2}
+    else:
+        assert meta == {b"ok": b"yes"}
+        assert metrics == {b"n": 2}
+    assert encoder.stats().invalid_strings == 3
+
+
+@pytest.mark.parametrize("Encoder", [TraceEncoderV04, TraceEncoderV05])
+def test_native_encoder_tag_limits(Encoder):
+    limits = TagLimits(max_key_length=4, max_value_length=5, max_resource_length=6, max_meta_entries=2)
+    encoder = Encoder(tag_limits=limits)
+    assert encoder.tag_limits.max_meta_entries == 2
+    span = {
+        "name": "test",
+        "resource": "GET /users/123",
+        "meta": {"short": "é" * 4, "ok": "value", "dropped": "value"},
+        "metrics": {"metric": 1},
+    }
+    assert encoder.put([span]) is None
+
+    [[span]] = decode(encoder.encode()[0])
+    if isinstance(span, dict):
+        resource, meta, metrics = span[b"resource"], span[b"meta"], span[b"metrics"]
+    else:
+        resource, meta, metrics = span[2], span[9], span[10]
+    assert resource == b"GET /u"
+    # Values are cut at a character boundary
+    assert meta == {b"shor...": "éé...".encode(), b"ok": b"value"}
+    assert metrics == {b"metr...": 1}
+
+    stats = encoder.stats()
+    assert (stats.truncated_strings, stats.dropped_tags) == (4, 1)
+
+
+def test_native_encoder_default_tag_limits():
+    encoder = TraceEncoderV04()
+    assert encoder.tag_limits.max_value_length == 25000
+    assert encoder.put([{"name": "test", "meta": {"key": "a" * 25001}}]) is None
+
+    [[span]] = decode(encoder.encode()[0])
+    assert span[b"meta"][b"key"] == b"a" * 25000 + b"..."
+
+
+@pytest.mark.parametrize("Encoder", [TraceEncoderV04, TraceEncoderV05])
+def test_native_encoder_invalid_utf8_error(Encoder):
+    encoder = Encoder(invalid_utf8=InvalidUtf8.ERROR)
+    with pytest.raises(UnicodeEncodeError):
+        encoder.put([INVALID_UTF8_SPAN])
+    assert len(encoder) == 0
+
+    stats = encoder.stats()
+    assert (stats.errors, stats.invalid_strings) == (1, 1)
+
+
+@pytest.mark.parametrize("Encoder", [TraceEncoderV04, TraceEncoderV05])
+def test_native_encoder_estimate(Encoder):
+    link_span = Span("s1", links=[SpanLink(trace_id=1, span_id=2, attributes={"ünï": "cødé\n"})])
+    link_span.set_struct_tag("payload", {"a": [1, 2.5, None]})
+    traces = [
+        [span_to_dict(span) for span in gen_trace(nspans=50, ntags=5, nmetrics=3)],
+        [span_to_dict(link_span), span_to_dict(span_with_events())],
+        [{}],
+    ]
+    native = Encoder()
+    assert native.size_in_bytes() == native.size
+    for trace in traces:
+        estimate = native.estimate(trace)
+        size = native.size
+        assert native.put(trace) is None
+        assert size < native.size <= size + estimate
+        assert native.size_in_bytes() == native.size
+
+
+@pytest.mark.parametrize("Encoder", [TraceEncoderV04, TraceEncoderV05])
+def test_native_encoder_compression(Encoder):
+    trace = [span_to_dict(span) for span in gen_trace(nspans=50, ntags=5, nmetrics=3)]
+    plain = Encoder()
+    plain.put(trace)
+    assert plain.content_encoding is None
+    payload, _ = plain.encode()
+
+    gzipped = Encoder(compression=Compression.GZIP)
+    gzipped.put(trace)
+    assert gzipped.content_encoding == "gzip"
+    assert gzipped.size == len(payload)
+    compressed, count = gzipped.encode()
+    assert count == 1
+    assert len(compressed) < len(payload)
+    assert gzip.decompress(compressed) == payload
+
+    zstd = Encoder(compression=Compression.ZSTD)
+    zstd.put(trace)
+    assert zstd.content_encoding == "zstd"
+    assert zstd.encode()[0].startswith(b"\x28\xb5\x2f\xfd")
+
+
+@pytest.mark.parametrize(
+    "accept_encoding,compression",
+    [
+        ("", Compression.NONE),
+        ("identity", Compression.NONE),
+        ("gzip", Compression.GZIP),
+        ("deflate, gzip;q=0.5", Compression.GZIP),
+        ("gzip, zstd", Compression.ZSTD),
+        ("ZSTD;q=0, gzip", Compression.GZIP),
+        ("*", Compression.ZSTD),
+    ],
+)
+def test_compression_negotiate(accept_encoding, compression):
+    assert Compression.negotiate(accept_encoding) == compression
+
+
+def test_native_json_encoder():
+    encoder = JsonEncoder()
+    assert encoder.content_type == "application/json"
+    assert encoder.encode() == (None, 0)
+
+    span = span_to_dict(gen_trace(nspans=1, ntags=5, nmetrics=3)[0])
+    span["trace_id"] = 2**64 - 1
+    items = [
+        span,
+        {"float": 1e16, "small": 1.5e-7, "nan": float("nan"), "inf": float("-inf"), "neg": -(2**63)},
+        {2: "int key", None: "none key", True: "bool key", "bytes": b"caf\xc3\xa9", "tuple": (1, [2])},
+        "ünïcødé \"quoted\"\n",
+    ]
+    for item in items:
+        assert encoder.put(item) is None
+    assert len(encoder) == len(items)
+
+    size = encoder.size
+    payload, count = encoder.flush()
+    assert count == len(items)
+    assert len(payload) == size
+    assert json.loads(payload) == [
+        json.loads(json.dumps(span)),
+        {"float": 1e16, "small": 1.5e-7, "nan": None, "inf": None, "neg": -(2**63)},
+        {"2": "int key", "null": "none key", "true": "bool key", "bytes": "café", "tuple": [1, [2]]},
+        "ünïcødé \"quoted\"\n",
+    ]
+    # Strings are sent as UTF-8
+    assert "ünïcødé".encode() in payload
+
+
+def test_native_json_encoder_sanitization_and_errors():
+    encoder = JsonEncoder()
+    encoder.put({"surrogate": "a\udc80b"})
+    with pytest.raises(OverflowError):
+        encoder.put({"big": 2**64})
+    with pytest.raises(TypeError):
+        encoder.put({"object": object()})
+    assert len(encoder) == 1
+    assert json.loads(encoder.encode()[0]) == [{"surrogate": "a\ufffdb"}]
+
+
+def test_native_json_encoder_overflow():
+    dropped = []
+    encoder = JsonEncoder(64, 32, on_drop=dropped.append)
+    assert encoder.put("a" * 40).reason == OverflowReason.TRACE_TOO_BIG
+    assert encoder.put("a" * 20) is None
+    assert encoder.put("a" * 20) is None
+    full = encoder.put("a" * 20)
+    assert full.reason == OverflowReason.BUFFER_FULL
+    assert full.spans == 1
+    assert [overflow.reason for overflow in dropped] == [OverflowReason.TRACE_TOO_BIG, OverflowReason.BUFFER_FULL]
+    assert json.loads(encoder.encode()[0]) == ["a" * 20] * 2
+
+
+def test_native_otlp_encoder():
+    trace_service_pb2 = pytest.importorskip("opentelemetry.proto.collector.trace.v1.trace_service_pb2")
+
+    root = Span("root", service="web", resource="GET /", span_type="web", trace_id=(5 << 64) + 7, span_id=1)
+    root.set_tag("span.kind", "server")
+    root.set_tag("_dd.p.tid", "0000000000000005")
+    root.set_metric("count", 3)
+    root.set_metric("ratio", 0.5)
+    child = Span(
+        "db.query",
+        service="db",
+        trace_id=(5 << 64) + 7,
+        span_id=2,
+        parent_id=1,
+        links=[SpanLink(trace_id=9, span_id=10, flags=1, attributes={"a": "b"})],
+    )
+    child.error = 1
+    child.set_tag("error.message", "boom")
+    child._add_event("retry", {"attempts": [1, 2]}, 5)
+    child.finish()
+    root.finish()
+
+    encoder = OtlpEncoder(resource_attributes={"deployment.environment": "prod"}, scope_version="1.0")
+    assert encoder.content_type == "application/x-protobuf"
+    assert encoder.put([span_to_dict(root), span_to_dict(child)]) is None
+    size = encoder.size
+    payload, count = encoder.encode()
+    assert count == 1
+    assert len(payload) == size
+
+    request = trace_service_pb2.ExportTraceServiceRequest.FromString(payload)
+    assert len(request.resource_spans) == 2
+    web, db = request.resource_spans
+    assert [(kv.key, kv.value.string_value) for kv in web.resource.attributes] == [
+        ("service.name", "web"),
+        ("deployment.environment", "prod"),
+    ]
+    assert (web.scope_spans[0].scope.name, web.scope_spans[0].scope.version) == ("ddtrace", "1.0")
+
+    [otel_root] = web.scope_spans[0].spans
+    assert otel_root.trace_id == ((5 << 64) + 7).to_bytes(16, "big")
+    assert otel_root.span_id == (1).to_bytes(8, "big")
+    assert otel_root.parent_span_id == b""
+    assert otel_root.name == "root"
+    assert otel_root.kind == 2
+    assert otel_root.start_time_unix_nano == root.start_ns
+    assert otel_root.end_time_unix_nano == root.start_ns + root.duration_ns
+    attributes = {kv.key: kv.value for kv in otel_root.attributes}
+    assert attributes["resource.name"].string_value == "GET /"
+    assert attributes["span.type"].string_value == "web"
+    assert attributes["count"].int_value == 3
+    assert attributes["ratio"].double_value == 0.5
+
+    [otel_child] = db.scope_spans[0].spans
+    assert otel_child.parent_span_id == (1).to_bytes(8, "big")
+    assert otel_child.kind == 1
+    assert (otel_child.status.code, otel_child.status.message) == (2, "boom")
+    [event] = otel_child.events
+    assert (event.name, event.time_unix_nano) == ("retry", 5)
+    assert [v.int_value for v in event.attributes[0].value.array_value.values] == [1, 2]
+    [link] = otel_child.links
+    assert link.trace_id == (9).to_bytes(16, "big")
+    assert link.span_id == (10).to_bytes(8, "big")
+    assert link.flags == 1
+    assert [(kv.key, kv.value.string_value) for kv in link.attributes] == [("a", "b")]
+
+
+def test_native_encoder_v05_rollback():
+    native = TraceEncoderV05()
+    native.put([span_to_dict(Span(name="ok", service="foo"))])
+    size = native.size
+
+    span = Span(name="bad", service="bar")
+    span.set_tag("unencodable", "value")
+    span._meta["unencodable"] = 1
+    with pytest.raises(TypeError):
+        native.put([span_to_dict(span)])
+
+    assert len(native) == 1
+    assert native.size == size
+    st, _ = decode(native.flush()[0], reconstruct=False)
+    assert st == [b"", _ORIGIN_KEY, b"foo", b"ok"]
+
+    # Strings seen by the failed trace must not be looked up by identity anymore
+    native.put([{"name": span.name, "service": span.service}])
+    st, _ = decode(native.flush()[0], reconstruct=False)
+    assert st == [b"", _ORIGIN_KEY, b"bar", b"bad"]
+
+
 def string_table_test(t, origin_key=False):
     assert len(t) == 1 + origin_key
 
@@ -3306,3 +3306,374 @@ def test_baggage_malformedheader_extract(headers, expected_baggage):
 def test_http_propagator_baggage_extract(headers):
     context = HTTPPropagator.extract(headers)
     assert context._baggage == {"key1": "val1", "key2": "val2", "foo": "bar", "x": "y"}
+
+
+@pytest.mark.parametrize(
+    "traceparent",
+    [
+        "00-%s-00f067aa0ba902b7-01" % (TRACE_ID_HEX,),
+        "00-%s-00f067aa0ba902b7-00" % (TRACE_ID_HEX,),
+        "  00-%s-00f067aa0ba902b7-02 " % (TRACE_ID_HEX,),
+        "01-%s-00f067aa0ba902b7-01-what-the-future-looks-like" % (TRACE_ID_HEX,),
+        "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+        "00-%s-0000000000000000-01" % (TRACE_ID_HEX,),
+        "00-%s-00f067aa0ba902b7-01-v00-can-not-have-future-values" % (TRACE_ID_HEX,),
+        "01-%s-00f067aa0ba902b7-01-" % (TRACE_ID_HEX,),
+        "0-%s-00f067aa0ba902b7-01" % (TRACE_ID_HEX,),
+        "ff-%s-00f067aa0ba902b7-01" % (TRACE_ID_HEX,),
+        "00-4BF92K3577B34dA6C3ce929d0e0e4736-00f067aa0ba902b7-01",
+        "00-f92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        "",
+    ],
+)
+def test_native_w3c_parse_traceparent(traceparent):
+    from ddtrace.internal.core._core import w3c_parse_traceparent
+
+    try:
+        expected = _TraceContext._get_traceparent_values(traceparent)
+    except ValueError:
+        with pytest.raises(ValueError):
+            w3c_parse_traceparent(traceparent)
+    else:
+        assert w3c_parse_traceparent(traceparent) == expected
+
+
+@pytest.mark.parametrize(
+    "tracestate",
+    [
+        "dd=s:2;o:rum;t.dm:-4;t.usr.id:baz64;p:a0000000000000ff,congo=t61rcWkgMzE,mako=s:2;o:rum",
+        "congo=t61rcWkgMzE,mako=s:2;o:rum;",
+        "dd=s:2;t.dm:-4;t.usr.id:baz64 ,\t congo=t61rcWkgMzE",
+        "dd=s:-1;o:synthetics~web,congo=1",
+        "dd=o:rum",
+        "dd=s:0;o:rum",
+        "dd=invalid,congo=123",
+        "dd=s:x,congo=123",
+        "dd=s:2;o:rum;p:a0000000000000ff;t.dm:-4;t.usr.id:baz:6:4;t.dm:-3",
+        "dd=s:2,congo=l¢¢",
+    ],
+)
+@pytest.mark.parametrize("sampled", [0, 1])
+def test_native_w3c_parse_tracestate(tracestate, sampled):
+    from ddtrace.internal.core._core import w3c_parse_tracestate
+
+    context = _TraceContext._get_context(TRACE_ID, 67667974448284343, sampled, tracestate)
+    parsed = w3c_parse_tracestate(tracestate, bool(sampled))
+    if _HTTP_HEADER_TRACESTATE not in context._meta:
+        assert parsed is None
+        return
+
+    normalized, sampling_priority, tags, origin, last_parent_id = parsed
+    meta = {_HTTP_HEADER_TRACESTATE: normalized, **tags}
+    if last_parent_id:
+        meta[LAST_DD_PARENT_ID_KEY] = last_parent_id
+    assert meta == {k: v for k, v in context._meta.items() if k != "_dd.origin"}
+    assert sampling_priority == context.sampling_priority
+    assert origin == context.dd_origin
+
+
+@pytest.mark.parametrize(
+    "context",
+    [
+        Context(trace_id=1234, span_id=5678, sampling_priority=2, dd_origin="synthetics", meta={"_dd.p.dm": "-4"}),
+        Context(trace_id=2**127 + 1, span_id=5678, sampling_priority=-1, is_remote=False),
+        Context(
+            trace_id=1234,
+            span_id=5678,
+            sampling_priority=1,
+            dd_origin="rum=web,mobile",
+            meta={
+                "_dd.p.usr.id": "baz;64~",
+                "_dd.p.key=with spaces": "value\x00\x01end",
+                "_dd.p.long": "x" * 250,
+                "_dd.p.other": "value",
+                _HTTP_HEADER_TRACEPARENT: "00-%s-00f067aa0ba902b7-01" % (TRACE_ID_HEX,),
+                _HTTP_HEADER_TRACESTATE: "congo=1,dd=s:1;o:rum,xdd=2,mako=3",
+            },
+        ),
+        Context(
+            trace_id=1234,
+            span_id=5678,
+            meta={LAST_DD_PARENT_ID_KEY: "00f067aa0ba902b7", _HTTP_HEADER_TRACESTATE: "dd=s:1,congo=1"},
+        ),
+        Context(trace_id=1234, meta={_HTTP_HEADER_TRACEPARENT: "00-%s-00f067aa0ba902b7-01" % (TRACE_ID_HEX,)}),
+        Context(trace_id=1234),
+    ],
+)
+def test_native_w3c_build_headers(context):
+    from ddtrace.internal.core._core import w3c_build_headers
+
+    expected = {}
+    _TraceContext._inject(context, expected)
+    assert w3c_build_headers(context) == expected
+
+
+@pytest.mark.parametrize(
+    "headers",
+    [
+        {},
+        {HTTP_HEADER_TRACE_ID: "1234"},
+        {HTTP_HEADER_TRACE_ID: "0"},
+        {HTTP_HEADER_TRACE_ID: "-1"},
+        {HTTP_HEADER_TRACE_ID: str(2**64)},
+        {HTTP_HEADER_TRACE_ID: "abc"},
+        {HTTP_HEADER_TRACE_ID: "1234", HTTP_HEADER_PARENT_ID: "abc"},
+        {HTTP_HEADER_TRACE_ID: "1234", HTTP_HEADER_SAMPLING_PRIORITY: "1.5"},
+        {
+            HTTP_HEADER_TRACE_ID: " 1234 ",
+            HTTP_HEADER_PARENT_ID: "5678",
+            HTTP_HEADER_SAMPLING_PRIORITY: "-1",
+            HTTP_HEADER_ORIGIN: "synthetics",
+            _HTTP_HEADER_TAGS: "_dd.p.dm=-4,_dd.p.upstream_services=abc,other=1,_dd.p.usr.id= baz ",
+        },
+        {"http_x_datadog_trace_id": "1234", "http_x_datadog_parent_id": "0", "http_x_datadog_origin": b"rum"},
+        {HTTP_HEADER_TRACE_ID: "1234", _HTTP_HEADER_TAGS: "_dd.p.tid=640cfd8d00000000,_dd.p.dm=-0"},
+        {HTTP_HEADER_TRACE_ID: "1234", _HTTP_HEADER_TAGS: "_dd.p.tid=0000000000000000"},
+        {HTTP_HEADER_TRACE_ID: "1234", _HTTP_HEADER_TAGS: "_dd.p.tid=640cfd8d0000000Z,_dd.p.dm=-11"},
+        {HTTP_HEADER_TRACE_ID: "1234", _HTTP_HEADER_TAGS: "_dd.p.dm=-4,"},
+        {HTTP_HEADER_TRACE_ID: "1234", _HTTP_HEADER_TAGS: "_dd.p.dm"},
+        {HTTP_HEADER_TRACE_ID: "1234", _HTTP_HEADER_TAGS: "_dd.p.dm=-4," + "_dd.p.a=" + "x" * 512},
+    ],
+)
+def test_native_datadog_extract(headers):
+    from ddtrace.internal.core._core import datadog_extract
+    from ddtrace.propagation.http import _DatadogMultiHeader
+
+    expected = _DatadogMultiHeader._extract(headers)
+    context = datadog_extract(headers)
+    if expected is None:
+        assert context is None
+        return
+
+    assert context.trace_id == expected.trace_id
+    assert context.span_id == expected.span_id
+    assert context.sampling_priority == expected.sampling_priority
+    assert context.dd_origin == expected.dd_origin
+    assert context.meta == {k: v for k, v in expected._meta.items() if k != "_dd.origin"}
+
+
+def test_native_datadog_extract_64_bit_trace_id():
+    from ddtrace.internal.core._core import datadog_extract
+
+    headers = {HTTP_HEADER_TRACE_ID: "1234", _HTTP_HEADER_TAGS: "_dd.p.tid=640cfd8d00000000"}
+    assert datadog_extract(headers).trace_id == (0x640CFD8D00000000 << 64) + 1234
+    assert datadog_extract(headers, trace_id_128_bit=False).trace_id == 1234
+    assert datadog_extract(headers, tags_max_size=10).meta == {
+        "_dd.propagation_error": "extract_max_size",
+        "_dd.p.dm": "-3",
+    }
+
+
+@pytest.mark.parametrize(
+    "headers",
+    [
+        {},
+        {_HTTP_HEADER_B3_TRACE_ID: "80f198ee56343ba864fe8b2a57d3eff7"},
+        {_HTTP_HEADER_B3_TRACE_ID: "0", _HTTP_HEADER_B3_SPAN_ID: "0"},
+        {_HTTP_HEADER_B3_TRACE_ID: "xyz"},
+        {_HTTP_HEADER_B3_TRACE_ID: "a2fb4a1d1a96d312", _HTTP_HEADER_B3_SPAN_ID: "xyz"},
+        {
+            _HTTP_HEADER_B3_TRACE_ID: "a2fb4a1d1a96d312",
+            _HTTP_HEADER_B3_SPAN_ID: "e457b5a2e4d86bd1",
+            _HTTP_HEADER_B3_SAMPLED: "0",
+        },
+        {_HTTP_HEADER_B3_TRACE_ID: "a2fb4a1d1a96d312", _HTTP_HEADER_B3_SAMPLED: "1", _HTTP_HEADER_B3_FLAGS: "1"},
+        {_HTTP_HEADER_B3_TRACE_ID: "a2fb4a1d1a96d312", _HTTP_HEADER_B3_SAMPLED: "true"},
+        {"http_x_b3_traceid": b"a2fb4a1d1a96d312", "http_x_b3_flags": "1"},
+    ],
+)
+def test_native_b3multi_extract(headers):
+    from ddtrace.internal.core._core import b3multi_extract
+    from ddtrace.propagation.http import _B3MultiHeader
+
+    expected = _B3MultiHeader._extract(headers)
+    context = b3multi_extract(headers)
+    if expected is None:
+        assert context is None
+        return
+
+    assert context.trace_id == expected.trace_id
+    assert context.span_id == expected.span_id
+    assert context.sampling_priority == expected.sampling_priority
+
+
+@pytest.mark.parametrize(
+    "header",
+    [
+        "",
+        "1",
+        "d",
+        "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1",
+        "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-0",
+        "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-d-05e3ac9a4f6e3b90",
+        "a2fb4a1d1a96d312-0-1",
+        "xyz-e457b5a2e4d86bd1-1",
+        "a2fb4a1d1a96d312-e457b5a2e4d86bd1-true",
+    ],
+)
+def test_native_b3_extract(header):
+    from ddtrace.internal.core._core import b3_extract
+    from ddtrace.propagation.http import _B3SingleHeader
+
+    headers = {_HTTP_HEADER_B3_SINGLE: header}
+    expected = _B3SingleHeader._extract(headers)
+    context = b3_extract(headers)
+    if expected is None:
+        assert context is None
+        return
+
+    assert context.trace_id == expected.trace_id
+    assert context.span_id == expected.span_id
+    assert context.sampling_priority == expected.sampling_priority
+
+
+@pytest.mark.parametrize(
+    "context",
+    [
+        Context(trace_id=1234, span_id=5678),
+        Context(trace_id=(0x640CFD8D00000000 << 64) + 1234, span_id=5678, sampling_priority=-1),
+        Context(trace_id=1234, span_id=5678, sampling_priority=1),
+        Context(trace_id=1234, span_id=5678, sampling_priority=2),
+        Context(trace_id=1234),
+    ],
+)
+def test_native_b3_build_headers(context):
+    from ddtrace.internal.core._core import b3_build_headers
+    from ddtrace.internal.core._core import b3multi_build_headers
+    from ddtrace.propagation.http import _B3MultiHeader
+    from ddtrace.propagation.http import _B3SingleHeader
+
+    expected = {}
+    _B3MultiHeader._inject(context, expected)
+    assert b3multi_build_headers(context) == expected
+
+    expected = {}
+    _B3SingleHeader._inject(context, expected)
+    assert b3_build_headers(context) == expected
+
+
+@pytest.mark.parametrize(
+    "header",
+    [
+        "",
+        "key1=value1,key2=value2",
+        " key1 = value1 , key1=value2",
+        "user%20id=a%2Cb%3Dc,caf%C3%A9=%E2%82%AC,bad=%zz%4",
+        "key1=value1,key2",
+        "key1=value1,=value2",
+        "key1=",
+    ],
+)
+def test_native_baggage_extract(header):
+    from ddtrace.internal.core._core import baggage_extract
+
+    headers = {_HTTP_HEADER_BAGGAGE: header}
+    assert baggage_extract(headers) == _BaggageHeader._extract(headers)._baggage
+
+
+def test_native_baggage_extract_properties():
+    from ddtrace.internal.core._core import baggage_extract
+
+    headers = {_HTTP_HEADER_BAGGAGE: "key1=value1;prop1;prop2=x,key2=value2 ; prop"}
+    assert baggage_extract(headers) == {"key1": "value1", "key2": "value2"}
+    assert baggage_extract({_HTTP_HEADER_BAGGAGE: "key1=;prop"}) == {}
+
+
+@pytest.mark.parametrize(
+    "baggage",
+    [
+        {},
+        {"key1": "value1", "key2": 2},
+        {" user id ": "a,b=c", "café": "€ (euro);"},
+        {"key%d" % i: "value" for i in range(100)},
+        {"key%d" % i: "x" * 1000 for i in range(10)},
+    ],
+)
+def test_native_baggage_build_headers(baggage):
+    from ddtrace.internal.core._core import baggage_build_headers
+
+    context = Context(baggage=baggage)
+    expected = {}
+    _BaggageHeader._inject(context, expected)
+    assert baggage_build_headers(context) == expected
+
+
+def test_native_baggage_build_headers_limits():
+    from ddtrace.internal.core._core import baggage_build_headers
+
+    context = Context(baggage={"key1": "value1", "key2": "value2", "key3": "value3"})
+    assert baggage_build_headers(context, max_items=2) == {_HTTP_HEADER_BAGGAGE: "key1=value1,key2=value2"}
+    assert baggage_build_headers(context, max_bytes=22) == {_HTTP_HEADER_BAGGAGE: "key1=value1"}
+    assert baggage_build_headers(context, max_bytes=5) == {_HTTP_HEADER_BAGGAGE: ""}
+
+
+@pytest.mark.parametrize(
+    "tags",
+    [
+        "",
+        "_dd.p.dm=-4,_dd.p.upstream_services=abc,other=1,_dd.p.usr.id= baz ",
+        "_dd.p.dm=-4,",
+        "_dd.p.dm",
+        "_dd.p.dm=-4,_dd.p.key with spaces=1",
+        "_dd.p.dm=-4," + "_dd.p.a=" + "x" * 512,
+    ],
+)
+def test_native_datadog_tags_decode(tags):
+    from ddtrace.internal.core._core import datadog_tags_decode
+    from ddtrace.propagation.http import _DatadogMultiHeader
+
+    assert datadog_tags_decode(tags) == _DatadogMultiHeader._extract_meta(tags)
+
+
+@pytest.mark.parametrize(
+    "meta",
+    [
+        {},
+        {"_dd.origin": "synthetics", "other": "1"},
+        {"_dd.p.dm": "-4", "_dd.p.usr.id": " baz ", "other": "1"},
+        {"_dd.p.dm": "-4", "_dd.p.key": "value,with,commas"},
+        {"_dd.p.dm": "-4", "_dd.p.a": "x" * 512},
+        {"_dd.p.dm": "-4", "_dd.propagation_error": "decoding_error"},
+    ],
+)
+def test_native_datadog_tags_encode(meta):
+    from ddtrace.internal.core._core import datadog_tags_encode
+    from ddtrace.propagation.http import _DatadogMultiHeader
+
+    context = Context(trace_id=1234, span_id=5678, meta=dict(meta))
+    headers = {}
+    _DatadogMultiHeader._inject(context, headers)
+
+    native_meta = dict(meta)
+    assert datadog_tags_encode(native_meta) == headers.get(_HTTP_HEADER_TAGS)
+    assert native_meta == context._meta
+
+
+def test_native_build_headers_items_changed():
+    from ddtrace.internal.core._core import baggage_build_headers
+    from ddtrace.internal.core._core import datadog_tags_encode
+    from ddtrace.internal.core._core import w3c_build_headers
+
+    class Value(object):
+        """Adds an item to the dictionary it is in when converted to a string"""
+
+        def __init__(self, items):
+            self.items = items
+
+        def __str__(self):
+            self.items["_dd.p.added%d" % len(self.items)] = "1"
+            return "value"
+
+    meta = {}
+    meta["_dd.p.key"] = Value(meta)
+    assert datadog_tags_encode(meta) == "_dd.p.key=value"
+
+    context = Context(trace_id=1234, span_id=5678)
+    context._meta["_dd.p.key"] = Value(context._meta)
+    tracestate = w3c_build_headers(context)[_HTTP_HEADER_TRACESTATE]
+    assert "t.key:value" in tracestate
+    assert "t.added" not in tracestate
+
+    context = Context()
+    context._baggage["key"] = Value(context._baggage)
+    assert baggage_build_headers(context) == {_HTTP_HEADER_BAGGAGE: "key=value"}
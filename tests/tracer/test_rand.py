@@ -1,6 +1,7 @@
 from itertools import chain
 import multiprocessing as mp
 
+import mock
 import pytest
 
 
@@ -310,3 +311,92 @@ def test_span_api_fork():
             q.put(child_ids)
         finally:
             os._exit(0)
+
+
+def test_native_gen_span_id():
+    from ddtrace.internal.core import gen_span_id
+
+    ids = {gen_span_id() for _ in range(2**16)}
+    assert len(ids) == 2**16
+    assert all(0 <= n <= 2**64 - 1 for n in ids)
+
+
+def test_native_gen_trace_id_128():
+    from ddtrace.internal.core import gen_trace_id_128
+
+    t1 = int(time.time()) - 1
+    val1 = gen_trace_id_128()
+    val2 = gen_trace_id_128()
+    t2 = int(time.time()) + 1
+
+    assert val1 & (2**64 - 1) != val2 & (2**64 - 1)
+    assert (val1 >> 64) & (2**32 - 1) == (val2 >> 64) & (2**32 - 1) == 0
+    assert t1 <= val1 >> 96 <= t2
+    assert t1 <= val2 >> 96 <= t2
+
+
+def test_native_span_ids():
+    from ddtrace._trace.span import Span
+    from ddtrace.internal import core
+    from tests.utils import override_global_config
+
+    with override_global_config(dict(_128_bit_trace_id_enabled=False)):
+        with mock.patch.object(core, "gen_span_id", side_effect=[1, 2]):
+            span = Span(None)
+    assert (span.trace_id, span.span_id) == (1, 2)
+
+    with override_global_config(dict(_128_bit_trace_id_enabled=True)):
+        t1 = int(time.time()) - 1
+        span = Span(None)
+        t2 = int(time.time()) + 1
+
+    # <32-bit unix seconds><32 bits of zero><64 random bits>
+    assert t1 <= span.trace_id >> 96 <= t2
+    assert (span.trace_id >> 64) & (2**32 - 1) == 0
+    assert span.trace_id & (2**64 - 1) != span.span_id
+
+
+def test_native_gen_span_id_threads():
+    from ddtrace.internal.core import gen_span_id
+
+    q = Queue()
+
+    def target():
+        q.put([gen_span_id() for _ in range(1000)])
+
+    ts = [threading.Thread(target=target) for _ in range(10)]
+    for t in ts:
+        t.start()
+    for t in ts:
+        t.join()
+
+    ids = set(chain.from_iterable(q.get() for _ in ts))
+    assert len(ids) == 10 * 1000
+
+
+@pytest.mark.subprocess()
+def test_native_gen_span_id_fork():
+    import os
+
+    from ddtrace.internal.core import gen_span_id
+    from tests.tracer.test_rand import MPQueue
+
+    # Generate IDs before forking, for the child to inherit a seeded generator.
+    gen_span_id()
+
+    q = MPQueue()
+    pid = os.fork()
+
+    if pid > 0:
+        # parent
+        ids = {gen_span_id() for _ in range(100)}
+        child_ids = q.get()
+
+        assert ids & child_ids == set()
+
+    else:
+        # child
+        try:
+            q.put({gen_span_id() for _ in range(100)})
+        finally:
+            os._exit(0)
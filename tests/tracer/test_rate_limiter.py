@@ -1,10 +1,23 @@
 from __future__ import division
 
+import pickle
+import threading
 import time
 
+from hypothesis import given
+from hypothesis.strategies import floats
+from hypothesis.strategies import integers
+from hypothesis.strategies import lists
+from hypothesis.strategies import one_of
+from hypothesis.strategies import sampled_from
 import mock
 import pytest
 
+from ddtrace.internal.core._core import AdaptiveRateLimiter
+from ddtrace.internal.core._core import KeyedRateLimiter
+from ddtrace.internal.core._core import SlidingWindowRateLimiter
+from ddtrace.internal.core._core import TokenBucketRateLimiter
+from ddtrace.internal.core._core import get_limiter
 from ddtrace.internal.rate_limiter import BudgetRateLimiterWithJitter
 from ddtrace.internal.rate_limiter import RateLimiter
 from ddtrace.internal.rate_limiter import RateLimitExceeded
@@ -209,6 +222,26 @@ def test_rate_limiter_3():
             assert decision is False
 
 
+@given(
+    rate_limit=one_of(integers(min_value=-1, max_value=100), floats(min_value=-1, max_value=100)),
+    time_window=sampled_from([1e3, 1e6, 1e9]),
+    deltas=lists(integers(min_value=0, max_value=int(3e9)), min_size=1, max_size=200),
+)
+def test_native_token_bucket_rate_limiter_matches_rate_limiter(rate_limit, time_window, deltas):
+    start_ns = time.monotonic_ns()
+    with mock.patch("ddtrace.internal.rate_limiter.time.monotonic_ns", return_value=start_ns):
+        limiter = RateLimiter(rate_limit=rate_limit, time_window=time_window)
+    native_limiter = TokenBucketRateLimiter(rate_limit, time_window=time_window)
+
+    now_ns = start_ns
+    for delta in deltas:
+        now_ns += delta
+        with mock.patch("ddtrace.internal.rate_limiter.time.monotonic_ns", return_value=now_ns):
+            allowed = limiter.is_allowed()
+        assert native_limiter.is_allowed(timestamp_ns=now_ns) is allowed
+        assert native_limiter.effective_rate == limiter.effective_rate
+
+
 @pytest.mark.parametrize("rate_limit", list(range(10)))
 def test_rate_limiter_with_jitter_expected_calls(rate_limit):
     limiter = BudgetRateLimiterWithJitter(limit_rate=rate_limit)
@@ -280,3 +313,496 @@ def test_rate_limiter_with_jitter_not_raise():
     limiter = BudgetRateLimiterWithJitter(limit_rate=1, raise_on_exceed=False)
 
     assert [limiter.limit(lambda: None) for _ in range(10)][1:] == [RateLimitExceeded] * 9
+
+
+def test_native_sliding_window_rate_limiter():
+    limiter = SlidingWindowRateLimiter(10)
+
+    # Late in a window, the whole limit is available
+    limiter._now_ns = 5 * 10**9 + 9 * 10**8
+    assert [limiter.is_allowed() for _ in range(11)] == [True] * 10 + [False]
+
+    # Early in the next one, the previous window still weighs 90%, so there is no burst of 2x the limit
+    limiter._now_ns = 6 * 10**9 + 10**8
+    assert [limiter.is_allowed() for _ in range(2)] == [True, False]
+
+    # Later, it only weighs 40%
+    limiter._now_ns = 6 * 10**9 + 6 * 10**8
+    assert sum(limiter.is_allowed() for _ in range(10)) == 5
+
+    # After a whole window without requests, the limit is available again
+    limiter._now_ns = 8 * 10**9
+    assert sum(limiter.is_allowed() for _ in range(20)) == 10
+
+
+@pytest.mark.parametrize("rate_limit,allowed", [(0, False), (-1, True)])
+def test_native_sliding_window_rate_limiter_special_limits(rate_limit, allowed):
+    limiter = SlidingWindowRateLimiter(rate_limit)
+    limiter._now_ns = 10**9
+
+    assert all(limiter.is_allowed() is allowed for _ in range(100))
+    assert limiter.rate_limit == rate_limit
+
+
+def test_native_token_bucket_rate_limiter_burst():
+    limiter = TokenBucketRateLimiter(1, burst=5)
+    limiter._now_ns = 10**12
+    assert limiter.rate == 1
+    assert limiter.burst == 5
+
+    # The bucket starts full, so a burst is allowed
+    assert [limiter.is_allowed() for _ in range(6)] == [True] * 5 + [False]
+
+    # But the sustained throughput is bounded by the rate
+    limiter._now_ns += 10**9
+    assert [limiter.is_allowed() for _ in range(2)] == [True, False]
+
+    # And the bucket never holds more than the burst size
+    limiter._now_ns += 100 * 10**9
+    assert sum(limiter.is_allowed() for _ in range(10)) == 5
+
+
+@pytest.mark.parametrize("burst", [0, 0.5, float("nan")])
+def test_native_token_bucket_rate_limiter_invalid_burst(burst):
+    with pytest.raises(ValueError):
+        TokenBucketRateLimiter(1, burst=burst)
+
+
+def test_native_keyed_rate_limiter():
+    limiter = KeyedRateLimiter(1, max_keys=2)
+    limiter._now_ns = 10**12
+
+    # Every key has a budget of its own
+    assert [limiter.is_allowed("a"), limiter.is_allowed("a")] == [True, False]
+    assert [limiter.is_allowed("b"), limiter.is_allowed("b")] == [True, False]
+    assert len(limiter) == 2
+
+    # A new key evicts the least recently used one
+    assert limiter.is_allowed("c")
+    assert len(limiter) == 2
+    assert "a" not in limiter
+    assert "b" in limiter
+    assert "c" in limiter
+
+    # And an evicted key comes back with a full bucket
+    assert limiter.is_allowed("a")
+    assert "b" not in limiter
+
+    limiter.forget("a")
+    assert "a" not in limiter
+    assert len(limiter) == 1
+    assert limiter.max_keys == 2
+
+
+def test_native_keyed_rate_limiter_invalid_max_keys():
+    with pytest.raises(ValueError):
+        KeyedRateLimiter(1, max_keys=0)
+
+
+def test_native_token_bucket_rate_limiter_effective_rate():
+    limiter = TokenBucketRateLimiter(2)
+    limiter._now_ns = 10**12
+
+    # No decisions yet
+    assert limiter.effective_rate == 1.0
+
+    assert [limiter.is_allowed() for _ in range(4)] == [True, True, False, False]
+    assert limiter.current_window_rate == 0.5
+    assert limiter.effective_rate == 0.5
+
+    # The previous window's rate is averaged with the current one's
+    limiter._now_ns += 10**9
+    assert limiter.is_allowed()
+    assert limiter.current_window_rate == 1.0
+    assert limiter.effective_rate == 0.75
+
+
+def test_native_sliding_window_rate_limiter_effective_rate():
+    limiter = SlidingWindowRateLimiter(2)
+    limiter._now_ns = 10 * 10**9
+    assert limiter.effective_rate == 1.0
+
+    assert [limiter.is_allowed() for _ in range(4)] == [True, True, False, False]
+    assert limiter.current_window_rate == 0.5
+    assert limiter.effective_rate == 0.5
+
+    limiter._now_ns = 11 * 10**9 + 5 * 10**8
+    assert limiter.is_allowed()
+    assert limiter.current_window_rate == 1.0
+    assert limiter.effective_rate == 0.75
+
+
+@pytest.mark.parametrize("limiter_class", [SlidingWindowRateLimiter, TokenBucketRateLimiter])
+def test_native_rate_limiter_pinned_time(limiter_class):
+    limiter = limiter_class(1)
+    assert limiter._now_ns is None
+
+    limiter._now_ns = 10**12
+    assert limiter._now_ns == 10**12
+
+    # Time doesn't pass while it is pinned
+    assert limiter.is_allowed()
+    assert not limiter.is_allowed()
+    assert not limiter.is_allowed()
+
+    # Past the previous window, so that the sliding window doesn't count it either
+    limiter._now_ns += 2 * 10**9
+    assert limiter.is_allowed()
+    assert not limiter.is_allowed()
+
+    limiter._now_ns = None
+    assert limiter._now_ns is None
+
+
+def test_native_adaptive_rate_limiter_convergence():
+    limiter = AdaptiveRateLimiter(100, smoothing=0.5)
+    limiter._now_ns = 0
+    limiter.set_target_rate(20)
+    assert limiter.target_rate == 20
+    assert limiter.rate == 100
+
+    # Every second, the rate moves halfway to the target rate
+    limiter._now_ns = 10**9
+    assert limiter.is_allowed()
+    assert limiter.rate == 60
+
+    limiter._now_ns = 2 * 10**9
+    assert limiter.is_allowed()
+    assert limiter.rate == 40
+
+    limiter._now_ns = 22 * 10**9
+    assert limiter.is_allowed()
+    assert limiter.rate == pytest.approx(20, abs=1e-3)
+
+    # And back up when the target rate increases
+    limiter.set_target_rate(60)
+    limiter._now_ns = 23 * 10**9
+    assert limiter.is_allowed()
+    assert limiter.rate == pytest.approx(40, abs=1e-3)
+
+
+def test_native_adaptive_rate_limiter_bounds():
+    limiter = AdaptiveRateLimiter(10, min_rate=2, max_rate=50)
+    assert (limiter.min_rate, limiter.max_rate) == (2, 50)
+
+    limiter.set_target_rate(0.1)
+    assert limiter.target_rate == 2
+    limiter.set_target_rate(100)
+    assert limiter.target_rate == 50
+
+    assert AdaptiveRateLimiter(100, max_rate=50).rate == 50
+    assert AdaptiveRateLimiter(0.5).rate == 1
+
+
+@pytest.mark.parametrize(
+    "kwargs",
+    [dict(min_rate=0), dict(min_rate=10, max_rate=5), dict(smoothing=0), dict(smoothing=1.5)],
+)
+def test_native_adaptive_rate_limiter_invalid(kwargs):
+    with pytest.raises(ValueError):
+        AdaptiveRateLimiter(10, **kwargs)
+
+
+def test_native_sliding_window_rate_limiter_threads():
+    limiter = SlidingWindowRateLimiter(1000)
+    limiter._now_ns = 10**12
+    decisions = []
+
+    def target():
+        decisions.extend(limiter.is_allowed() for _ in range(500))
+
+    threads = [threading.Thread(target=target) for _ in range(8)]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join()
+
+    # Concurrent callers never exceed the rate limit
+    assert decisions.count(True) == 1000
+    assert decisions.count(False) == 3000
+    assert (limiter.allowed, limiter.denied) == (1000, 3000)
+    assert limiter.current_window_rate == 0.25
+
+
+def test_native_rate_limiter_permits():
+    limiter = TokenBucketRateLimiter(10)
+    limiter._now_ns = 10**12
+
+    # Permits are granted or denied as a whole
+    assert limiter.is_allowed(7)
+    assert not limiter.is_allowed(4)
+    assert limiter.tokens == 3
+    assert limiter.is_allowed(3)
+    assert (limiter.allowed, limiter.denied) == (10, 4)
+
+    limiter = SlidingWindowRateLimiter(10)
+    limiter._now_ns = 10**12
+    assert not limiter.is_allowed(11)
+    assert limiter.is_allowed(10)
+    assert not limiter.is_allowed()
+
+    limiter = KeyedRateLimiter(5)
+    limiter._now_ns = 10**12
+    assert limiter.is_allowed("a", 5)
+    assert not limiter.is_allowed("a")
+    assert limiter.is_allowed("b", 3)
+
+
+@pytest.mark.parametrize("limiter_class", [SlidingWindowRateLimiter, TokenBucketRateLimiter, AdaptiveRateLimiter])
+def test_native_rate_limiter_no_permits(limiter_class):
+    with pytest.raises(ValueError):
+        limiter_class(10).is_allowed(0)
+
+
+def test_native_rate_limiter_pickle():
+    limiter = TokenBucketRateLimiter(10, burst=20, time_window=2e9)
+    limiter._now_ns = 10**12
+    assert limiter.is_allowed(4)
+    restored = pickle.loads(pickle.dumps(limiter))
+    assert (restored.rate, restored.burst, restored.time_window) == (10, 20, 2e9)
+    assert restored.tokens == 16
+
+    limiter = SlidingWindowRateLimiter(10, time_window=1e12)
+    assert all(limiter.is_allowed() for _ in range(4))
+    restored = pickle.loads(pickle.dumps(limiter))
+    assert (restored.rate_limit, restored.time_window) == (10, 1e12)
+    assert sum(restored.is_allowed() for _ in range(20)) == 6
+
+    # Keys keep their tokens and their eviction order
+    limiter = KeyedRateLimiter(2, max_keys=2)
+    limiter._now_ns = 10**12
+    assert limiter.is_allowed("a", 2)
+    assert limiter.is_allowed("b")
+    restored = pickle.loads(pickle.dumps(limiter))
+    assert restored.max_keys == 2
+    assert len(restored) == 2
+    assert restored.is_allowed("c")
+    assert "a" not in restored
+    assert [restored.is_allowed("b"), restored.is_allowed("b")] == [True, False]
+
+    limiter = AdaptiveRateLimiter(50, min_rate=2, max_rate=100, smoothing=0.5)
+    limiter.set_target_rate(10)
+    restored = pickle.loads(pickle.dumps(limiter))
+    assert (restored.rate, restored.target_rate) == (50, 10)
+    assert (restored.min_rate, restored.max_rate) == (2, 100)
+
+
+def test_native_rate_limiter_metrics_callback():
+    calls = []
+    limiter = TokenBucketRateLimiter(
+        2, metrics_callback=lambda allowed, denied: calls.append((allowed, denied)), metrics_interval=2
+    )
+    limiter._now_ns = 10**12
+
+    assert [limiter.is_allowed() for _ in range(5)] == [True, True, False, False, False]
+    assert calls == [(2, 0), (2, 2)]
+    assert (limiter.allowed, limiter.denied) == (2, 3)
+
+
+def test_native_rate_limiter_metrics_callback_error():
+    def callback(allowed, denied):
+        raise RuntimeError("metrics are down")
+
+    limiter = SlidingWindowRateLimiter(1, metrics_callback=callback, metrics_interval=1)
+    limiter._now_ns = 10**12
+
+    # A failing callback doesn't fail the decision
+    with mock.patch("sys.unraisablehook") as unraisablehook:
+        assert limiter.is_allowed()
+        assert not limiter.is_allowed()
+    assert unraisablehook.call_count == 2
+
+
+@pytest.mark.parametrize("limiter_class", [SlidingWindowRateLimiter, TokenBucketRateLimiter, AdaptiveRateLimiter])
+def test_native_rate_limiter_invalid_metrics_interval(limiter_class):
+    with pytest.raises(ValueError):
+        limiter_class(10, metrics_interval=0)
+
+
+def test_native_rate_limiter_time_window():
+    limiter = TokenBucketRateLimiter(5, time_window=1e8)
+    limiter._now_ns = 10**12
+    assert limiter.time_window == 1e8
+    assert sum(limiter.is_allowed() for _ in range(10)) == 5
+
+    # 5 tokens every 100ms
+    limiter._now_ns += 5 * 10**7
+    assert sum(limiter.is_allowed() for _ in range(10)) == 2
+    limiter._now_ns += 10**8
+    assert sum(limiter.is_allowed() for _ in range(10)) == 5
+
+    limiter = SlidingWindowRateLimiter(5, time_window=1e8)
+    limiter._now_ns = 10**12
+    assert limiter.time_window == 1e8
+    assert sum(limiter.is_allowed() for _ in range(10)) == 5
+    limiter._now_ns += 2 * 10**8
+    assert sum(limiter.is_allowed() for _ in range(10)) == 5
+
+
+@pytest.mark.parametrize("limiter_class", [SlidingWindowRateLimiter, TokenBucketRateLimiter, KeyedRateLimiter])
+@pytest.mark.parametrize("time_window", [0, 0.5, float("nan")])
+def test_native_rate_limiter_invalid_time_window(limiter_class, time_window):
+    with pytest.raises(ValueError):
+        limiter_class(10, time_window=time_window)
+
+
+def test_native_rate_limiter_registry():
+    limiter = get_limiter("test_native_rate_limiter_registry", 2)
+    assert isinstance(limiter, SlidingWindowRateLimiter)
+
+    # The first registration sets the rate limit
+    assert get_limiter("test_native_rate_limiter_registry", 10, time_window=1e6) is limiter
+    assert (limiter.rate_limit, limiter.time_window) == (2, 1e9)
+    assert get_limiter("test_native_rate_limiter_registry_other", 2) is not limiter
+
+
+@pytest.mark.subprocess()
+def test_native_rate_limiter_registry_fork():
+    import os
+
+    from ddtrace.internal.core._core import get_limiter
+
+    limiter = get_limiter("test_native_rate_limiter_registry_fork", 1)
+    limiter._now_ns = 10**12
+    assert limiter.is_allowed()
+    assert not limiter.is_allowed()
+
+    pid = os.fork()
+
+    if pid == 0:
+        # The child starts over with a fresh budget
+        child_limiter = get_limiter("test_native_rate_limiter_registry_fork", 1)
+        assert child_limiter is limiter
+        assert (limiter.allowed, limiter.denied) == (0, 0)
+        assert limiter.is_allowed()
+        os._exit(12)
+
+    _, status = os.waitpid(pid, 0)
+    assert os.WEXITSTATUS(status) == 12
+
+    # The parent's budget is untouched
+    assert not limiter.is_allowed()
+    assert (limiter.allowed, limiter.denied) == (1, 2)
+
+
+@pytest.mark.parametrize("limiter_class", [SlidingWindowRateLimiter, TokenBucketRateLimiter])
+def test_native_rate_limiter_timestamp(limiter_class):
+    limiter = limiter_class(1)
+    # Explicit timestamps take precedence over the clock, pinned or not
+    limiter._now_ns = 10**15
+
+    assert limiter.is_allowed(timestamp_ns=10**12)
+    assert not limiter.is_allowed(timestamp_ns=10**12 + 5 * 10**8)
+    assert limiter.is_allowed(timestamp_ns=10**12 + 2 * 10**9)
+    assert (limiter.allowed, limiter.denied) == (2, 1)
+
+
+@pytest.mark.parametrize("limiter_class", [SlidingWindowRateLimiter, TokenBucketRateLimiter, AdaptiveRateLimiter])
+def test_native_rate_limiter_zero_timestamp(limiter_class):
+    limiter = limiter_class(1)
+    limiter._now_ns = 10**12
+
+    # A timestamp of 0 is the current time, like in Python
+    assert limiter.is_allowed(timestamp_ns=0)
+    assert not limiter.is_allowed(timestamp_ns=0)
+    limiter._now_ns += 10**9
+    assert limiter.is_allowed(timestamp_ns=0)
+
+
+def test_native_keyed_rate_limiter_zero_timestamp():
+    limiter = KeyedRateLimiter(1)
+    limiter._now_ns = 10**12
+    assert limiter.is_allowed("a", timestamp_ns=0)
+    assert not limiter.is_allowed("a", timestamp_ns=0)
+    limiter._now_ns += 10**9
+    assert limiter.is_allowed("a", timestamp_ns=0)
+
+def test_native_sliding_window_rate_limiter_stale_timestamp():
+    limiter = SlidingWindowRateLimiter(3)
+    assert limiter.is_allowed(2, timestamp_ns=10 * 10**9 + 9 * 10**8)
+    assert limiter.is_allowed(timestamp_ns=11 * 10**9)
+
+    # A request timed before the window moved is counted at the start of the newer window, where
+    # the previous window weighs the most
+    assert not limiter.is_allowed(timestamp_ns=10 * 10**9 + 95 * 10**7)
+    assert limiter.is_allowed(timestamp_ns=11 * 10**9 + 5 * 10**8)
+
+
+def test_native_keyed_rate_limiter_timestamp():
+    limiter = KeyedRateLimiter(1)
+    assert limiter.is_allowed("a", timestamp_ns=10**12)
+    assert not limiter.is_allowed("a", timestamp_ns=10**12)
+    assert limiter.is_allowed("a", timestamp_ns=10**12 + 10**9)
+
+
+def test_native_adaptive_rate_limiter_timestamp():
+    limiter = AdaptiveRateLimiter(100, smoothing=0.5)
+    limiter._now_ns = 0
+    limiter.set_target_rate(20)
+
+    # The rate converges with the timestamps of the decisions
+    assert limiter.is_allowed(timestamp_ns=10**9)
+    assert limiter.rate == 60
+
+
+def test_native_token_bucket_rate_limiter_set_rate():
+    limiter = TokenBucketRateLimiter(10)
+    limiter._now_ns = 10**12
+    assert limiter.is_allowed(4)
+
+    # The tokens already consumed stay consumed
+    limiter.set_rate(20)
+    assert (limiter.rate, limiter.burst) == (20, 20)
+    assert limiter.tokens == 16
+
+    limiter.set_rate(2)
+    assert (limiter.rate, limiter.burst) == (2, 2)
+    assert limiter.tokens == 0
+
+    limiter.set_rate(2, burst=10)
+    assert limiter.burst == 10
+    assert limiter.tokens == 8
+
+    with pytest.raises(ValueError):
+        limiter.set_rate(2, burst=0)
+
+
+def test_native_sliding_window_rate_limiter_set_rate():
+    limiter = SlidingWindowRateLimiter(10)
+    limiter._now_ns = 10**12
+    assert all(limiter.is_allowed() for _ in range(4))
+
+    # The requests already allowed count against the new rate limit
+    limiter.set_rate(5)
+    assert limiter.rate_limit == 5
+    assert sum(limiter.is_allowed() for _ in range(10)) == 1
+
+    with pytest.raises(ValueError):
+        limiter.set_rate(1 << 21)
+
+
+def test_native_keyed_rate_limiter_set_rate():
+    limiter = KeyedRateLimiter(2)
+    limiter._now_ns = 10**12
+    assert limiter.is_allowed("a", 2)
+
+    # Existing buckets are updated, and new ones use the new rate
+    limiter.set_rate(5)
+    assert (limiter.rate, limiter.burst) == (5, 5)
+    assert limiter.is_allowed("a", 3)
+    assert not limiter.is_allowed("a")
+    assert limiter.is_allowed("b", 5)
+
+
+@pytest.mark.parametrize("limiter_class", [SlidingWindowRateLimiter, TokenBucketRateLimiter, AdaptiveRateLimiter])
+def test_native_rate_limiter_call(limiter_class):
+    limiter = limiter_class(3)
+    limiter._now_ns = 10**12
+
+    assert limiter()
+    assert limiter.allow_many(5) == 2
+    assert not limiter()
+    assert limiter.allow_many(0) == 0
+    # Every decision is counted
+    assert (limiter.allowed, limiter.denied) == (3, 4)
+    assert limiter.current_window_rate == 3 / 7
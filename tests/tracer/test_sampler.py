@@ -1,5 +1,6 @@
 from __future__ import division
 
+import json
 import re
 import unittest
 
@@ -179,6 +180,328 @@ def test_key():
     ), "_key call with service and env name as positional args returns expected result"
 
 
+@pytest.mark.parametrize("sample_rate", [0, 0.1, 0.5, 1])
+def test_native_priority_sampler(sample_rate):
+    from ddtrace.internal.core._core import PrioritySampler
+
+    sampler = PrioritySampler(sample_rate=0.5, service="web", env="prod")
+    sampler.set_sample_rate(sample_rate)
+    assert sampler.rates == {"service:web,env:prod": sample_rate}
+    reference = RateSampler(sample_rate)
+    for trace_id in [0, 1, 2**63, 2**64 - 1, 2**127 + 12345] + [i * 7919**4 for i in range(1000)]:
+        span = Span("test", trace_id=trace_id)
+        priority = AUTO_KEEP if reference.sample(span) else AUTO_REJECT
+        assert sampler.sample(trace_id, "web", "prod") == (priority, SamplingMechanism.AGENT_RATE, sample_rate)
+
+    priority, mechanism, rate = sampler.sample(1, "other", "prod")
+    assert (mechanism, rate) == (SamplingMechanism.DEFAULT, 0.5)
+
+    sampler.update_rate_by_service_sample_rates({"service:other,env:": 2.0})
+    assert sampler.sample(1, "other") == (AUTO_KEEP, SamplingMechanism.AGENT_RATE, 1.0)
+    assert sampler.sample(1, "web", "prod")[1] == SamplingMechanism.DEFAULT
+
+
+@pytest.mark.parametrize("sample_rate", [0, 0.01, 0.5, 0.999, 1, -1, 2])
+def test_sampling_decision(sample_rate):
+    from ddtrace.internal.core import sampling_decision
+
+    reference = RateSampler(sample_rate)
+    for trace_id in [0, 2**64 - 1, 2**64, 2**128 - 1] + [i * 7919**4 for i in range(1000)]:
+        assert sampling_decision(trace_id, sample_rate) == reference.sample(Span("test", trace_id=trace_id))
+
+    with pytest.raises(ValueError):
+        sampling_decision(1, float("nan"))
+
+
+def test_trace_id_lower_64_bits():
+    from ddtrace._trace.span import _get_64_lowest_order_bits_as_int
+    from ddtrace.internal.core import trace_id_lower_64_bits
+    from ddtrace.internal.core._core import PrioritySampler
+    from ddtrace.internal.core._core import SamplingRules
+
+    for trace_id in [0, 1, 2**64 - 1, 2**64, 2**127 + 12345, 2**128 - 1]:
+        assert trace_id_lower_64_bits(trace_id) == _get_64_lowest_order_bits_as_int(trace_id)
+
+    # 128-bit trace ids are sampled like their lower 64 bits
+    sampler = PrioritySampler(0.5)
+    rules = SamplingRules(default_sample_rate=0.5)
+    for i in range(1000):
+        trace_id = i * 7919**4
+        for high in [0, 1, 2**63]:
+            trace_id_128 = (high << 64) | trace_id_lower_64_bits(trace_id)
+            assert sampler.sample(trace_id_128) == sampler.sample(trace_id_lower_64_bits(trace_id))
+            assert rules.sample(trace_id_128) == rules.sample(trace_id_lower_64_bits(trace_id))
+
+
+@pytest.mark.parametrize(
+    "mechanism,sample_rate,limit_rate,metrics",
+    [
+        (SamplingMechanism.DEFAULT, 1.0, None, {}),
+        (SamplingMechanism.AGENT_RATE, 0.5, None, {SAMPLING_AGENT_DECISION: 0.5}),
+        (
+            SamplingMechanism.TRACE_SAMPLING_RULE,
+            0.25,
+            0.75,
+            {SAMPLING_RULE_DECISION: 0.25, SAMPLING_LIMIT_DECISION: 0.75},
+        ),
+        (SamplingMechanism.REMOTE_USER_RULE, 0.25, None, {SAMPLING_RULE_DECISION: 0.25}),
+        (SamplingMechanism.REMOTE_DYNAMIC_RULE, 0.25, None, {SAMPLING_RULE_DECISION: 0.25}),
+        (SamplingMechanism.MANUAL, None, None, {}),
+        (SamplingMechanism.APPSEC, 1.0, None, {}),
+    ],
+)
+def test_sampling_decision_tags(mechanism, sample_rate, limit_rate, metrics):
+    from ddtrace.internal.core._core import sampling_decision_tags
+
+    context = Context()
+    expected_meta = {SAMPLING_DECISION_TRACE_TAG_KEY: set_sampling_decision_maker(context, mechanism)}
+    assert sampling_decision_tags(mechanism, sample_rate, limit_rate) == (expected_meta, metrics)
+
+
+def test_native_sampling_rules():
+    from ddtrace.internal.core._core import SamplingRules
+
+    rules = SamplingRules(
+        json.dumps(
+            [
+                {"sample_rate": 0, "service": "web", "name": "health*"},
+                {"sample_rate": 1, "tags": {"http.status_code": "5??"}},
+                {"sample_rate": "0.5", "service": "DB-?", "provenance": "dynamic"},
+                {"sample_rate": 1, "resource": "GET /users", "provenance": "customer"},
+                {"sample_rate": 1, "service": None},
+                {"service": "invalid"},
+            ]
+        ),
+        default_sample_rate=0.25,
+    )
+    assert len(rules) == 6
+    assert rules.sample(1, "web", "healthcheck") == (USER_REJECT, SamplingMechanism.TRACE_SAMPLING_RULE, 0.0)
+    assert rules.sample(1, "web", "healthcheck", "GET /users") == (
+        USER_KEEP,
+        SamplingMechanism.REMOTE_USER_RULE,
+        1.0,
+    )
+    assert rules.sample(1, "web", meta={"http.status_code": "503"}) == (
+        USER_KEEP,
+        SamplingMechanism.TRACE_SAMPLING_RULE,
+        1.0,
+    )
+    assert rules.sample(1, "web", metrics={"http.status_code": 500})[2] == 1.0
+    assert rules.sample(1, "web", metrics={"http.status_code": 500.5})[2] == 0.25
+    assert rules.sample(1, "db-1")[1:] == (SamplingMechanism.REMOTE_DYNAMIC_RULE, 0.5)
+    assert rules.sample(1)[2] == 1.0
+    assert rules.sample(1, "other")[2] == 0.25
+
+    for trace_id in [i * 7919**4 for i in range(1000)]:
+        span = Span("test", trace_id=trace_id)
+        priority = USER_KEEP if SamplingRule(0.25).sample(span) else USER_REJECT
+        assert rules.sample(trace_id, "other")[0] == priority
+
+    assert SamplingRules("[]").sample(1) is None
+    with pytest.raises(ValueError):
+        SamplingRules("{")
+
+
+def test_native_sampling_rules_max_per_second():
+    from ddtrace.internal.core._core import SamplingRules
+
+    rules = SamplingRules(json.dumps([{"sample_rate": 1, "max_per_second": 2}]))
+    priorities = [rules.sample(i)[0] for i in range(5)]
+    assert priorities == [USER_KEEP] * 2 + [USER_REJECT] * 3
+
+
+def test_native_trace_sampler_rate_limit():
+    from ddtrace.internal.core._core import TraceSampler
+
+    sampler = TraceSampler(json.dumps([{"service": "web", "sample_rate": 1}]), rate_limit=2)
+    decisions = [sampler.sample(i, service="web") for i in range(4)]
+    assert [d[:4] for d in decisions] == [(True, USER_KEEP, SamplingMechanism.TRACE_SAMPLING_RULE, 1.0)] * 2 + [
+        (False, USER_REJECT, SamplingMechanism.TRACE_SAMPLING_RULE, 1.0)
+    ] * 2
+    assert all(d[4] is not None for d in decisions)
+
+    # Agent rates aren't limited
+    sampler.update_rate_by_service_sample_rates({"service:db,env:": 1.0})
+    for i in range(4):
+        assert sampler.sample(i, service="db") == (True, AUTO_KEEP, SamplingMechanism.AGENT_RATE, 1.0, None)
+    assert sampler.sample(0, service="other") == (True, AUTO_KEEP, SamplingMechanism.DEFAULT, 1.0, None)
+
+
+def test_native_trace_sampler_rate_limit_always_on():
+    from ddtrace.internal.core._core import TraceSampler
+
+    sampler = TraceSampler(rate_limit=1, rate_limit_always_on=True)
+    assert len(sampler) == 0
+    assert sampler.sample(0)[:4] == (True, USER_KEEP, SamplingMechanism.TRACE_SAMPLING_RULE, 1.0)
+    assert sampler.sample(1)[:4] == (False, USER_REJECT, SamplingMechanism.TRACE_SAMPLING_RULE, 1.0)
+
+
+def test_native_trace_sampler_update_rules():
+    from ddtrace.internal.core._core import TraceSampler
+    from ddtrace.internal.core._core import sampling_decision_tags
+
+    sampler = TraceSampler(json.dumps([{"service": "web", "sample_rate": 0}]), default_sample_rate=0.5)
+    assert len(sampler) == 2
+
+    payload = {
+        "lib_config": {
+            "tracing_sampling_rate": 1.0,
+            "tracing_sampling_rules": [
+                {"service": "db", "sample_rate": 1, "provenance": "dynamic"},
+                {
+                    "service": "web",
+                    "sample_rate": 1,
+                    "provenance": "customer",
+                    "tags": [{"key": "region", "value_glob": "us-*"}],
+                },
+                # Invalid without a provenance
+                {"service": "web", "sample_rate": 0},
+            ],
+        }
+    }
+    sampler.update_rules(json.dumps(payload).encode())
+    assert len(sampler) == 3
+    _, priority, mechanism, _, _ = sampler.sample(1, service="web", meta={"region": "us-east"})
+    assert (priority, mechanism) == (USER_KEEP, SamplingMechanism.REMOTE_USER_RULE)
+    assert sampling_decision_tags(mechanism)[0] == {SAMPLING_DECISION_TRACE_TAG_KEY: "-11"}
+    _, priority, mechanism, _, _ = sampler.sample(1, service="db")
+    assert (priority, mechanism) == (USER_KEEP, SamplingMechanism.REMOTE_DYNAMIC_RULE)
+    assert sampler.sample(1, service="web")[1:4] == (USER_KEEP, SamplingMechanism.TRACE_SAMPLING_RULE, 1.0)
+
+    # Only the remote rate
+    sampler.update_rules(json.dumps({"lib_config": {"tracing_sampling_rate": 0.25}}).encode())
+    assert len(sampler) == 2
+    assert sampler.sample(1, service="web")[1:4] == (USER_REJECT, SamplingMechanism.TRACE_SAMPLING_RULE, 0.0)
+    assert sampler.sample(1, service="db")[3] == 0.25
+
+    # Deleted configuration
+    sampler.update_rules(None)
+    assert len(sampler) == 2
+    assert sampler.sample(1, service="db")[3] == 0.5
+
+    with pytest.raises(ValueError):
+        sampler.update_rules(b"{")
+
+
+def test_native_trace_sampler_decisions():
+    from ddtrace.internal.core._core import TraceSampler
+
+    sampler = TraceSampler(json.dumps([{"service": "web", "sample_rate": 1}]), rate_limit=1)
+    sampler.update_rate_by_service_sample_rates({"service:db,env:": 0.0})
+    for i in range(3):
+        sampler.sample(i, service="web")
+    sampler.sample(1, service="db")
+    sampler.sample(1, service="other")
+    sampler.record_manual(True)
+
+    expected = {"default": (1, 0), "agent": (0, 1), "rule": (1, 0), "limiter": (0, 2), "manual": (1, 0)}
+    assert sampler.decisions() == expected
+    assert sampler.decisions(reset=True) == expected
+    assert sampler.decisions() == {category: (0, 0) for category in expected}
+
+
+def test_native_trace_sampler_adaptive():
+    from ddtrace.internal.core._core import TraceSampler
+
+    sampler = TraceSampler(json.dumps([{"service": "web", "sample_rate": 0.5}]), rate_limit=-1, adaptive=True)
+    kept = {i for i in range(1000) if sampler.sample(i * 7919**4, service="web")[0]}
+
+    sampler.report_backpressure()
+    # Reported at most once per second
+    sampler.report_backpressure()
+    assert sampler.backpressure_factor == pytest.approx(0.5, abs=0.01)
+
+    decisions = [sampler.sample(i * 7919**4, service="web") for i in range(1000)]
+    assert {i for i, decision in enumerate(decisions) if decision[0]} < kept
+    assert decisions[0][3] == pytest.approx(0.25, abs=0.01)
+
+    # Not adaptive by default
+    sampler = TraceSampler()
+    sampler.report_backpressure()
+    assert sampler.backpressure_factor == 1.0
+
+
+def test_native_trace_sampler_adaptive_max_per_second():
+    from ddtrace.internal.core import sampling_decision
+    from ddtrace.internal.core._core import TraceSampler
+
+    sampler = TraceSampler(
+        json.dumps([{"service": "web", "sample_rate": 1, "max_per_second": 1}]), rate_limit=-1, adaptive=True
+    )
+    sampler.report_backpressure()
+    trace_ids = [i * 7919**4 for i in range(1000)]
+    dropped = next(trace_id for trace_id in trace_ids if not sampling_decision(trace_id, 0.75))
+    kept = next(trace_id for trace_id in trace_ids if sampling_decision(trace_id, 0.25))
+
+    # A trace dropped at the reduced rate doesn't take from the limit of the rule
+    assert not sampler.sample(dropped, service="web")[0]
+    assert sampler.sample(kept, service="web")[0]
+    assert not sampler.sample(kept, service="web")[0]
+
+
+def test_native_trace_sampler_explain():
+    from ddtrace.internal.core._core import TraceSampler
+
+    sampler = TraceSampler(
+        json.dumps([{"service": "web", "tags": {"region": "us-*"}, "sample_rate": 1, "provenance": "customer"}]),
+        rate_limit=1,
+    )
+    explanation = sampler.explain(1, service="web", meta={"region": "us-east"})
+    assert explanation == {
+        "rule": (
+            "SamplingRule(sample_rate=1.0, service='web', name='NO_RULE', resource='NO_RULE', "
+            "tags={'region': 'us-*'}, provenance='customer')"
+        ),
+        "sample_rate": 1.0,
+        "backpressure_factor": 1.0,
+        "rate_limit": 1.0,
+        "limit_psr": 1.0,
+        "rate_limited": False,
+        "sampled": True,
+        "priority": USER_KEEP,
+        "mechanism": SamplingMechanism.REMOTE_USER_RULE,
+        SAMPLING_DECISION_TRACE_TAG_KEY: "-11",
+    }
+
+    # Explaining is a dry run: it takes no token from the limiter and isn't counted
+    explanation = sampler.explain(1, service="web", meta={"region": "us-east"})
+    assert (explanation["rate_limited"], explanation["sampled"]) == (False, True)
+    assert sampler.decisions() == {category: (0, 0) for category in sampler.decisions()}
+
+    assert sampler.sample(1, service="web", meta={"region": "us-east"})[0]
+    decisions = sampler.decisions()
+    explanation = sampler.explain(1, service="web", meta={"region": "us-east"})
+    assert (explanation["rate_limited"], explanation["sampled"], explanation["priority"]) == (True, False, USER_REJECT)
+    assert sampler.decisions() == decisions
+    assert decisions["rule"] == (1, 0)
+
+    explanation = sampler.explain(1, service="db")
+    assert explanation["rule"] is None
+    assert explanation["limit_psr"] is None
+    assert (explanation["priority"], explanation["mechanism"]) == (AUTO_KEEP, SamplingMechanism.DEFAULT)
+
+
+def test_native_trace_sampler_explain_max_per_second():
+    from ddtrace.internal.core._core import TraceSampler
+
+    sampler = TraceSampler(json.dumps([{"service": "web", "sample_rate": 1, "max_per_second": 1}]), rate_limit=-1)
+
+    # Explaining takes nothing from the limit of the rule either
+    for _ in range(3):
+        assert sampler.explain(1, service="web")["sampled"]
+    assert sampler.sample(1, service="web")[0]
+    assert not sampler.explain(1, service="web")["sampled"]
+    assert not sampler.sample(1, service="web")[0]
+
+
+@run_in_subprocess(env=dict(DD_TRACE_RATE_LIMIT="7"))
+def test_native_trace_sampler_rate_limit_env():
+    from ddtrace.internal.core._core import TraceSampler
+
+    assert TraceSampler().rate_limit == 7
+    assert TraceSampler(rate_limit=3).rate_limit == 3
+
+
 @run_in_subprocess(env=dict(DD_TRACE_128_BIT_TRACEID_GENERATION_ENABLED="true"))
 def test_sample_rate_deviation_128bit_trace_id():
     _test_sample_rate_deviation()
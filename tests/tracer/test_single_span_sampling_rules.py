@@ -348,3 +348,43 @@ def test_max_per_sec_with_predetermined_number_of_spans():
             assert_sampling_decision_tags(span, limit=2)
         else:
             assert_sampling_decision_tags(span, sample_rate=None, mechanism=None, limit=None)
+
+
+def test_native_span_sampling_rules():
+    import json
+
+    from ddtrace._trace.span import Span
+    from ddtrace.internal.core._core import SpanSamplingRules
+
+    rules = SpanSamplingRules(
+        json.dumps(
+            [
+                {"service": "web", "name": "flask.*", "sample_rate": 0.5},
+                {"name": "db.query", "max_per_second": 2},
+                {"service": "*", "sample_rate": 0},
+            ]
+        )
+    )
+    assert len(rules) == 3
+    reference = SpanSamplingRule(sample_rate=0.5, max_per_second=-1, service="web", name="flask.*")
+    expected = {_SINGLE_SPAN_SAMPLING_MECHANISM: SamplingMechanism.SPAN_SAMPLING_RULE, _SINGLE_SPAN_SAMPLING_RATE: 0.5}
+    for span_id in [0, 2**64 - 1] + [i * 7919**4 % 2**64 for i in range(1000)]:
+        span = Span("flask.request", service="web", span_id=span_id)
+        assert rules.sample(span_id, "WEB", "flask.request") == (expected if reference._sample(span) else None)
+
+    tags = [rules.sample(1, "db", "db.query") for _ in range(3)]
+    assert tags[:2] == [
+        {
+            _SINGLE_SPAN_SAMPLING_MECHANISM: SamplingMechanism.SPAN_SAMPLING_RULE,
+            _SINGLE_SPAN_SAMPLING_RATE: 1.0,
+            _SINGLE_SPAN_SAMPLING_MAX_PER_SEC: 2,
+        }
+    ] * 2
+    assert tags[2] is None
+    # Only the catch-all rule matches, and its sample rate of 0 keeps nothing.
+    assert rules.sample(1, "web", "other") is None
+    # Spans without a service or name match no rule.
+    assert rules.sample(1) is None
+
+    assert len(SpanSamplingRules(json.dumps([{"sample_rate": 1}, {"service": "web"}]))) == 0
+    assert len(SpanSamplingRules("not json")) == 0